@@ -41,6 +41,16 @@ impl InsnId {
             InsnId::X86(id) => id.to_c(),
         }
     }
+
+    /// Converts a raw instruction ID for `arch` into a generic instruction ID.
+    /// Returns [`Option::None`] if the ID is not valid or if there is no typed
+    /// instruction ID for `arch` yet.
+    pub(crate) fn from_c(arch: crate::Arch, id: libc::c_uint) -> Option<InsnId> {
+        match arch {
+            crate::Arch::X86 => x86::InsnId::from_c(id).map(InsnId::X86),
+            _ => None,
+        }
+    }
 }
 
 impl core::cmp::Ord for InsnId {
@@ -64,10 +74,58 @@ impl core::cmp::PartialOrd for InsnId {
 pub struct InsnGroup(u8);
 
 impl InsnGroup {
+    // The generic groups have the same values for every architecture.
+
+    /// An invalid group.
+    pub const INVALID: InsnGroup = InsnGroup(0);
+    /// All jump instructions (conditional+direct+indirect jumps).
+    pub const JUMP: InsnGroup = InsnGroup(1);
+    /// All call instructions.
+    pub const CALL: InsnGroup = InsnGroup(2);
+    /// All return instructions.
+    pub const RET: InsnGroup = InsnGroup(3);
+    /// All interrupt instructions (int+syscall).
+    pub const INT: InsnGroup = InsnGroup(4);
+    /// All interrupt return instructions.
+    pub const IRET: InsnGroup = InsnGroup(5);
+    /// All privileged instructions.
+    pub const PRIVILEGE: InsnGroup = InsnGroup(6);
+    /// All relative branching instructions.
+    pub const BRANCH_RELATIVE: InsnGroup = InsnGroup(7);
+
     #[allow(dead_code)]
     pub(crate) fn to_primitive(self) -> u8 {
         self.0
     }
+
+    pub(crate) fn from_primitive(primitive: u8) -> InsnGroup {
+        InsnGroup(primitive)
+    }
+
+    /// Returns true if this is the generic jump group.
+    pub fn is_jump(self) -> bool {
+        self == Self::JUMP
+    }
+
+    /// Returns true if this is the generic call group.
+    pub fn is_call(self) -> bool {
+        self == Self::CALL
+    }
+
+    /// Returns true if this is the generic return group.
+    pub fn is_ret(self) -> bool {
+        self == Self::RET
+    }
+
+    /// Returns true if this is one of the generic groups of instructions that
+    /// transfer control: jumps, calls, returns, interrupt returns and relative
+    /// branches.
+    pub fn is_branch(self) -> bool {
+        matches!(
+            self,
+            Self::JUMP | Self::CALL | Self::RET | Self::IRET | Self::BRANCH_RELATIVE
+        )
+    }
 }
 
 /// A generic register that can be compared to any architecture specific register.
@@ -1,14 +1,133 @@
-use super::generated::cs_ppc;
+use super::generated::{cs_ppc, cs_ppc_op, ppc_op_crx, ppc_op_mem};
 use core::marker::PhantomData;
 
 #[repr(transparent)]
 #[derive(Clone, Copy)]
 pub struct Details<'c> {
-    #[allow(dead_code)]
     inner: cs_ppc,
     _phantom: PhantomData<&'c ()>,
 }
 
+impl<'c> Details<'c> {
+    /// Returns true if this instruction updates the CR0 register.
+    pub fn update_cr0(&self) -> bool {
+        self.inner.update_cr0
+    }
+
+    /// Returns the number of operands in this instruction.
+    pub fn op_count(&self) -> usize {
+        self.inner.op_count as usize
+    }
+
+    /// Returns the operands contained in this instruction. The length
+    /// of the returned slice will be the same as the value returned
+    /// by [`Details::op_count`].
+    pub fn operands(&self) -> &[Op] {
+        unsafe {
+            &*(&self.inner.operands[..self.inner.op_count as usize] as *const [cs_ppc_op]
+                as *const [Op])
+        }
+    }
+}
+
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct Op {
+    inner: cs_ppc_op,
+}
+
+impl Op {
+    /// Returns the type of this operand.
+    pub fn op_type(&self) -> OpType {
+        OpType::from_c(self.inner.type_).unwrap_or(OpType::Invalid)
+    }
+
+    /// Returns the value of this operand. Branch targets are immediates
+    /// that hold the target address, not the displacement from the instruction.
+    pub fn value(&self) -> OpValue {
+        match self.op_type() {
+            OpType::Invalid => OpValue::Imm(0),
+            OpType::Reg => OpValue::Reg(unsafe { self.inner.__bindgen_anon_1.reg }),
+            OpType::Imm => OpValue::Imm(unsafe { self.inner.__bindgen_anon_1.imm }),
+            OpType::Mem => OpValue::Mem(unsafe {
+                OpMem {
+                    inner: self.inner.__bindgen_anon_1.mem,
+                }
+            }),
+            OpType::Crx => OpValue::Crx(unsafe {
+                OpCrx {
+                    inner: self.inner.__bindgen_anon_1.crx,
+                }
+            }),
+        }
+    }
+}
+
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct OpMem {
+    inner: ppc_op_mem,
+}
+
+impl OpMem {
+    /// The raw Capstone ID of the base register.
+    pub fn base(&self) -> u32 {
+        self.inner.base
+    }
+
+    pub fn disp(&self) -> i32 {
+        self.inner.disp
+    }
+}
+
+/// A condition register field operand.
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct OpCrx {
+    inner: ppc_op_crx,
+}
+
+impl OpCrx {
+    pub fn scale(&self) -> u32 {
+        self.inner.scale
+    }
+
+    /// The raw Capstone ID of the condition register.
+    pub fn reg(&self) -> u32 {
+        self.inner.reg
+    }
+
+    /// The raw Capstone ID of the branch condition.
+    pub fn cond(&self) -> u32 {
+        self.inner.cond
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum OpValue {
+    /// The raw Capstone ID of a register.
+    Reg(u32),
+    Imm(i64),
+    Mem(OpMem),
+    Crx(OpCrx),
+}
+
+c_enum! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub enum OpType: u8 {
+        /// Uninitialized.
+        Invalid = 0,
+        /// Register operand.
+        Reg,
+        /// Immediate operand.
+        Imm,
+        /// Memory operand.
+        Mem,
+        /// Condition register operand.
+        Crx = 64,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -26,4 +145,17 @@ mod test {
             sys::get_test_val("alignof(cs_ppc)")
         );
     }
+
+    #[test]
+    fn ppc_op_size_and_alignment() {
+        assert_eq!(
+            core::mem::size_of::<Op>(),
+            sys::get_test_val("sizeof(cs_ppc_op)")
+        );
+
+        assert_eq!(
+            core::mem::align_of::<Op>(),
+            sys::get_test_val("alignof(cs_ppc_op)")
+        );
+    }
 }
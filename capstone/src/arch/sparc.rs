@@ -1,14 +1,102 @@
-use super::generated::cs_sparc;
+use super::generated::{cs_sparc, cs_sparc_op, sparc_op_mem};
 use core::marker::PhantomData;
 
 #[repr(transparent)]
 #[derive(Clone, Copy)]
 pub struct Details<'c> {
-    #[allow(dead_code)]
     inner: cs_sparc,
     _phantom: PhantomData<&'c ()>,
 }
 
+impl<'c> Details<'c> {
+    /// Returns the number of operands in this instruction.
+    pub fn op_count(&self) -> usize {
+        self.inner.op_count as usize
+    }
+
+    /// Returns the operands contained in this instruction. The length
+    /// of the returned slice will be the same as the value returned
+    /// by [`Details::op_count`].
+    pub fn operands(&self) -> &[Op] {
+        unsafe {
+            &*(&self.inner.operands[..self.inner.op_count as usize] as *const [cs_sparc_op]
+                as *const [Op])
+        }
+    }
+}
+
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct Op {
+    inner: cs_sparc_op,
+}
+
+impl Op {
+    /// Returns the type of this operand.
+    pub fn op_type(&self) -> OpType {
+        OpType::from_c(self.inner.type_).unwrap_or(OpType::Invalid)
+    }
+
+    /// Returns the value of this operand. Branch and call targets are immediates
+    /// that hold the target address, not the displacement from the instruction.
+    pub fn value(&self) -> OpValue {
+        match self.op_type() {
+            OpType::Invalid => OpValue::Imm(0),
+            OpType::Reg => OpValue::Reg(unsafe { self.inner.__bindgen_anon_1.reg }),
+            OpType::Imm => OpValue::Imm(unsafe { self.inner.__bindgen_anon_1.imm }),
+            OpType::Mem => OpValue::Mem(unsafe {
+                OpMem {
+                    inner: self.inner.__bindgen_anon_1.mem,
+                }
+            }),
+        }
+    }
+}
+
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct OpMem {
+    inner: sparc_op_mem,
+}
+
+impl OpMem {
+    /// The raw Capstone ID of the base register, or 0 if there is none.
+    pub fn base(&self) -> u32 {
+        self.inner.base as u32
+    }
+
+    /// The raw Capstone ID of the index register, or 0 if there is none.
+    pub fn index(&self) -> u32 {
+        self.inner.index as u32
+    }
+
+    pub fn disp(&self) -> i32 {
+        self.inner.disp
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum OpValue {
+    /// The raw Capstone ID of a register.
+    Reg(u32),
+    Imm(i64),
+    Mem(OpMem),
+}
+
+c_enum! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub enum OpType: u8 {
+        /// Uninitialized.
+        Invalid = 0,
+        /// Register operand.
+        Reg,
+        /// Immediate operand.
+        Imm,
+        /// Memory operand.
+        Mem,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -26,4 +114,17 @@ mod test {
             sys::get_test_val("alignof(cs_sparc)")
         );
     }
+
+    #[test]
+    fn sparc_op_size_and_alignment() {
+        assert_eq!(
+            core::mem::size_of::<Op>(),
+            sys::get_test_val("sizeof(cs_sparc_op)")
+        );
+
+        assert_eq!(
+            core::mem::align_of::<Op>(),
+            sys::get_test_val("alignof(cs_sparc_op)")
+        );
+    }
 }
@@ -10,19 +10,31 @@ pub struct Details<'c> {
 
 impl<'c> Details<'c> {
     /// Returns true if the instruction has the given prefix, or false otherwise.
-    pub fn has_prefix(&self, mut prefix: Prefix) -> bool {
-        if prefix == Prefix::RepE {
-            prefix = Prefix::Rep
+    ///
+    /// [`Prefix::Rep`] and [`Prefix::RepE`] are encoded as the same byte, which is a
+    /// REPE prefix for `cmps` and `scas` and a REP prefix for every other instruction.
+    pub fn has_prefix(&self, prefix: Prefix) -> bool {
+        match prefix {
+            Prefix::Rep => {
+                self.inner.prefix[0] == Prefix::Rep.to_primitive() && !self.is_repe_string_op()
+            }
+            Prefix::RepE => {
+                self.inner.prefix[0] == Prefix::Rep.to_primitive() && self.is_repe_string_op()
+            }
+            Prefix::Lock | Prefix::RepNE => self.inner.prefix[0] == prefix.to_primitive(),
+            Prefix::CS | Prefix::SS | Prefix::DS | Prefix::ES | Prefix::FS | Prefix::GS => {
+                self.inner.prefix[1] == prefix.to_primitive()
+            }
+            Prefix::OpSize => self.inner.prefix[2] == prefix.to_primitive(),
+            Prefix::AddrSize => self.inner.prefix[3] == prefix.to_primitive(),
         }
+    }
 
-        let idx = match prefix {
-            Prefix::Lock | Prefix::Rep | Prefix::RepNE | Prefix::RepE => 0,
-            Prefix::CS | Prefix::SS | Prefix::DS | Prefix::ES | Prefix::FS | Prefix::GS => 1,
-            Prefix::OpSize => 2,
-            Prefix::AddrSize => 3,
-        };
-
-        self.inner.prefix[idx] == prefix.to_primitive()
+    /// Returns true for `cmps` and `scas`, the only instructions that test the
+    /// REPE/REPZ condition instead of just repeating.
+    fn is_repe_string_op(&self) -> bool {
+        // cmpsb, cmps{w,d,q}, scasb and scas{w,d,q}
+        matches!(self.inner.opcode[0], 0xa6 | 0xa7 | 0xae | 0xaf)
     }
 
     /// Instruction opcode. This value can be from 1 to 4 bytes in size.
@@ -116,6 +128,31 @@ impl<'c> Details<'c> {
         }
     }
 
+    /// Returns the number of operands of the given type in this instruction.
+    /// This is the same as `cs_op_count`.
+    pub fn op_count_of(&self, op_type: OpType) -> usize {
+        self.operands()
+            .iter()
+            .filter(|op| op.op_type() == op_type)
+            .count()
+    }
+
+    /// Returns the index into [`Details::operands`] of the `n`th operand of
+    /// the given type, or `None` if there is no such operand. Like `cs_op_index`,
+    /// `n` starts at 1 for the first operand of the given type.
+    pub fn op_index(&self, op_type: OpType, n: usize) -> Option<usize> {
+        if n == 0 {
+            return None;
+        }
+
+        self.operands()
+            .iter()
+            .enumerate()
+            .filter(|(_, op)| op.op_type() == op_type)
+            .nth(n - 1)
+            .map(|(idx, _)| idx)
+    }
+
     /// Returns encoding information about this instruction.
     pub fn encoding(&self) -> &Encoding {
         unsafe { &*(&self.inner.encoding as *const cs_x86_encoding as *const Encoding) }
@@ -267,7 +304,7 @@ c_enum! {
         Rep = 0xf3,
 
         // NOTE: This is a special case (!!!).
-        // It is swapped out with `Rep` when used.
+        // REPE is encoded as the same byte as `Rep`, see `Details::has_prefix`.
         /// REPE/REPZ prefix
         RepE = 0x03,
 
@@ -770,6 +807,91 @@ c_enum_big! {
     }
 }
 
+impl Reg {
+    /// Returns the name of this register, the same name that [`Capstone::reg_name`]
+    /// returns, without going through Capstone.
+    ///
+    /// [`Capstone::reg_name`]: crate::Capstone::reg_name
+    pub fn name(self) -> &'static str {
+        // The other registers are named after their variants.
+        match self {
+            Reg::Invalid | Reg::Ending => "",
+            Reg::Eflags => "flags",
+            Reg::St0 => "st(0)",
+            Reg::St1 => "st(1)",
+            Reg::St2 => "st(2)",
+            Reg::St3 => "st(3)",
+            Reg::St4 => "st(4)",
+            Reg::St5 => "st(5)",
+            Reg::St6 => "st(6)",
+            Reg::St7 => "st(7)",
+            _ => self.lowercase_name(),
+        }
+    }
+
+    /// Returns the kind of register that this is.
+    pub fn class(self) -> RegClass {
+        let between = |first: Reg, last: Reg| {
+            (first.to_primitive()..=last.to_primitive()).contains(&self.to_primitive())
+        };
+
+        match self {
+            Reg::Invalid | Reg::Ending => RegClass::Invalid,
+            Reg::Cs | Reg::Ds | Reg::Es | Reg::Fs | Reg::Gs | Reg::Ss => RegClass::Segment,
+            Reg::Eflags | Reg::Fpsw => RegClass::Flags,
+            _ if between(Reg::Cr0, Reg::Cr15) => RegClass::Control,
+            _ if between(Reg::Dr0, Reg::Dr15) => RegClass::Debug,
+            _ if between(Reg::Fp0, Reg::Fp7) || between(Reg::St0, Reg::St7) => RegClass::X87,
+            _ if between(Reg::K0, Reg::K7) => RegClass::Mask,
+            _ if between(Reg::Mm0, Reg::Mm7) => RegClass::Mmx,
+            _ if between(Reg::Xmm0, Reg::Xmm31) => RegClass::Xmm,
+            _ if between(Reg::Ymm0, Reg::Ymm31) => RegClass::Ymm,
+            _ if between(Reg::Zmm0, Reg::Zmm31) => RegClass::Zmm,
+            _ => RegClass::General,
+        }
+    }
+}
+
+/// The kinds of x86 registers, which [`Reg::class`] returns.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RegClass {
+    Invalid,
+    /// The general purpose registers and their parts, the instruction pointer
+    /// and the zero index registers `eiz` and `riz`.
+    General,
+    Segment,
+    /// `flags` and the x87 status word `fpsw`.
+    Flags,
+    Control,
+    Debug,
+    /// The x87 stack `st(0)` to `st(7)` and `fp0` to `fp7`.
+    X87,
+    Mmx,
+    /// The AVX-512 opmask registers `k0` to `k7`.
+    Mask,
+    Xmm,
+    Ymm,
+    Zmm,
+}
+
+impl RegClass {
+    /// Returns true for the SIMD registers: MMX, XMM, YMM and ZMM.
+    pub fn is_vector(self) -> bool {
+        matches!(
+            self,
+            RegClass::Mmx | RegClass::Xmm | RegClass::Ymm | RegClass::Zmm
+        )
+    }
+
+    /// Returns true for the registers that only exist with AVX-512, ZMM and the
+    /// opmask registers. `xmm16` to `xmm31` and `ymm16` to `ymm31` are also only
+    /// available with AVX-512, but are in the same class as the other XMM and
+    /// YMM registers.
+    pub fn is_avx512(self) -> bool {
+        matches!(self, RegClass::Zmm | RegClass::Mask)
+    }
+}
+
 c_enum_big! {
     #[non_exhaustive]
     #[derive(Copy, Clone, PartialEq, Eq, Hash)]
@@ -2374,6 +2496,37 @@ mod test {
     use super::*;
     use crate::sys;
 
+    #[test]
+    fn x86_reg_names() {
+        assert_eq!(Reg::Invalid.name(), "");
+        assert_eq!(Reg::Rax.name(), "rax");
+        assert_eq!(Reg::R15w.name(), "r15w");
+        assert_eq!(Reg::Eflags.name(), "flags");
+        assert_eq!(Reg::St3.name(), "st(3)");
+        assert_eq!(Reg::Xmm31.name(), "xmm31");
+        assert_eq!(Reg::Ending.name(), "");
+    }
+
+    #[test]
+    fn x86_reg_classes() {
+        assert_eq!(Reg::Invalid.class(), RegClass::Invalid);
+        assert_eq!(Reg::Ah.class(), RegClass::General);
+        assert_eq!(Reg::Rip.class(), RegClass::General);
+        assert_eq!(Reg::R15w.class(), RegClass::General);
+        assert_eq!(Reg::Fs.class(), RegClass::Segment);
+        assert_eq!(Reg::Eflags.class(), RegClass::Flags);
+        assert_eq!(Reg::Cr15.class(), RegClass::Control);
+        assert_eq!(Reg::Dr0.class(), RegClass::Debug);
+        assert_eq!(Reg::St7.class(), RegClass::X87);
+        assert_eq!(Reg::K0.class(), RegClass::Mask);
+        assert_eq!(Reg::Mm7.class(), RegClass::Mmx);
+        assert_eq!(Reg::Xmm31.class(), RegClass::Xmm);
+        assert_eq!(Reg::Ymm0.class(), RegClass::Ymm);
+        assert_eq!(Reg::Zmm16.class(), RegClass::Zmm);
+        assert!(Reg::Zmm0.class().is_avx512());
+        assert!(!Reg::Ymm0.class().is_avx512());
+    }
+
     #[test]
     fn x86_size_and_alignment() {
         assert_eq!(
@@ -1,11 +1,13 @@
 use crate::arch::{
     arm, arm64, evm, m680x, m68k, mips, mos65xx, ppc, sparc, sysz, tms320c64x, x86, xcore,
-    InsnGroup, Reg,
+    InsnGroup, InsnId, Reg,
 };
 use crate::{sys, util, Arch};
 use core::marker::PhantomData;
+use core::ptr::NonNull;
 
 const MNEMONIC_SIZE: usize = 32;
+const OP_STR_SIZE: usize = 160;
 
 /// Information about a disassembled instruction.
 #[repr(C)]
@@ -36,7 +38,7 @@ pub struct Insn<'a> {
 
     /// Ascii text of instruction operands
     /// This information is available even when CS_OPT_DETAIL = CS_OPT_OFF
-    op_str: [libc::c_char; 160],
+    op_str: [libc::c_char; OP_STR_SIZE],
 
     /// Pointer to cs_detail.
     /// NOTE: detail pointer is only valid when both requirements below are met:
@@ -52,6 +54,14 @@ pub struct Insn<'a> {
 }
 
 impl<'a> Insn<'a> {
+    /// Returns the ID of this instruction for `arch`, which should be the
+    /// arch of the Capstone instance that disassembled it. Returns [`Option::None`]
+    /// for data in SKIPDATA mode or if there is no typed instruction ID for `arch`.
+    #[inline]
+    pub fn insn_id(&self, arch: Arch) -> Option<InsnId> {
+        InsnId::from_c(arch, self.id)
+    }
+
     /// Returns trhe address of this instruction.
     #[inline]
     pub fn address(&self) -> u64 {
@@ -72,28 +82,59 @@ impl<'a> Insn<'a> {
         unsafe { core::slice::from_raw_parts(self.bytes.as_ptr(), self.size()) }
     }
 
-    /// Returns the instruction mnemonic.
+    /// Returns the instruction mnemonic. A mnemonic that isn't valid UTF-8 is cut
+    /// off before the first invalid byte, see [`Insn::mnemonic_checked`].
     #[inline]
     pub fn mnemonic(&self) -> &str {
         unsafe { util::cstr(self.mnemonic.as_ptr(), MNEMONIC_SIZE) }
     }
 
-    /// Returns the instruction operands as a string.
+    /// Returns the instruction operands as a string. Operands that aren't valid
+    /// UTF-8 are cut off before the first invalid byte, see [`Insn::operands_checked`].
     #[inline]
     pub fn operands(&self) -> &str {
-        unsafe { util::cstr(self.op_str.as_ptr(), 160) }
+        unsafe { util::cstr(self.op_str.as_ptr(), OP_STR_SIZE) }
+    }
+
+    /// Returns the instruction mnemonic, or an error if Capstone
+    /// produced a mnemonic that isn't valid UTF-8.
+    #[inline]
+    pub fn mnemonic_checked(&self) -> Result<&str, core::str::Utf8Error> {
+        unsafe { util::cstr_checked(self.mnemonic.as_ptr(), MNEMONIC_SIZE) }
+    }
+
+    /// Returns the instruction operands as a string, or an error if Capstone
+    /// produced operands that aren't valid UTF-8.
+    #[inline]
+    pub fn operands_checked(&self) -> Result<&str, core::str::Utf8Error> {
+        unsafe { util::cstr_checked(self.op_str.as_ptr(), OP_STR_SIZE) }
+    }
+
+    /// Returns true if the operands filled Capstone's operand buffer, in which
+    /// case Capstone might have cut off the end of the operands.
+    #[inline]
+    pub fn operands_truncated(&self) -> bool {
+        // Capstone always leaves room for the terminator, so a full buffer
+        // holds `OP_STR_SIZE - 1` bytes.
+        unsafe { util::cstr_bytes(self.op_str.as_ptr(), OP_STR_SIZE) }.len() >= OP_STR_SIZE - 1
     }
 }
 
 /// A buffer of disassembled instructions.
+///
+/// `inner` is never null and always aligned for `Insn`, even if the buffer is empty,
+/// because `Deref` turns it into a slice. An empty buffer points at
+/// [`NonNull::dangling`] and doesn't own any memory.
 pub struct InsnBuffer<'a> {
-    inner: *mut Insn<'a>,
+    inner: NonNull<Insn<'a>>,
     count: usize,
     _phan: PhantomData<&'a Insn<'a>>,
 }
 
 impl<'a> InsnBuffer<'a> {
-    pub(crate) fn new(insn: *mut Insn<'a>, count: usize) -> InsnBuffer<'a> {
+    /// Creates a buffer that owns the `count` instructions at `insn`, which were
+    /// allocated by Capstone.
+    pub(crate) fn new(insn: NonNull<Insn<'a>>, count: usize) -> InsnBuffer<'a> {
         InsnBuffer {
             inner: insn,
             count,
@@ -101,14 +142,19 @@ impl<'a> InsnBuffer<'a> {
         }
     }
 
-    /// Frees the `Insn`(`cs_insn`) if it is not currently null
-    /// then clears the pointer and count.
+    /// Creates a buffer without any instructions.
+    pub(crate) fn empty() -> InsnBuffer<'a> {
+        InsnBuffer::new(NonNull::dangling(), 0)
+    }
+
+    /// Frees the `Insn`(`cs_insn`) if the buffer owns any, then leaves
+    /// the buffer empty.
     fn free(&mut self) {
-        if self.count == 0 || self.inner.is_null() {
+        if self.count == 0 {
             return;
         }
-        unsafe { sys::cs_free(self.inner as *mut Insn, self.count as libc::size_t) };
-        self.inner = core::ptr::null_mut();
+        unsafe { sys::cs_free(self.inner.as_ptr(), self.count as libc::size_t) };
+        self.inner = NonNull::dangling();
         self.count = 0;
     }
 }
@@ -118,7 +164,11 @@ impl<'a> core::ops::Deref for InsnBuffer<'a> {
 
     #[inline]
     fn deref(&self) -> &Self::Target {
-        unsafe { core::slice::from_raw_parts(self.inner, self.count) }
+        debug_assert_eq!(
+            self.inner.as_ptr() as usize % core::mem::align_of::<Insn>(),
+            0
+        );
+        unsafe { core::slice::from_raw_parts(self.inner.as_ptr(), self.count) }
     }
 }
 
@@ -200,6 +250,55 @@ impl<'a> Drop for InsnIter<'a> {
     }
 }
 
+/// An instruction allocated by [`crate::Capstone::alloc_insn`], which instructions
+/// are disassembled into one at a time by [`crate::Capstone::disasm_one_into`].
+///
+/// The slot borrows the Capstone instance that allocated it for `'a`, which is also
+/// the lifetime of the [`Insn`] that it dereferences to, so neither can be used after
+/// the instance is closed. It holds an empty instruction of size 0 until the first
+/// instruction is disassembled into it.
+pub struct InsnSlot<'a> {
+    insn: NonNull<Insn<'a>>,
+}
+
+impl<'a> InsnSlot<'a> {
+    pub(crate) fn new(insn: NonNull<Insn<'a>>) -> InsnSlot<'a> {
+        // `cs_malloc` only initializes the detail pointer of the instruction.
+        unsafe {
+            let insn = insn.as_ptr();
+            (*insn).id = 0;
+            (*insn).address = 0;
+            (*insn).size = 0;
+            (*insn).bytes = [0; 24];
+            (*insn).mnemonic = [0; MNEMONIC_SIZE];
+            (*insn).op_str = [0; OP_STR_SIZE];
+        }
+        InsnSlot { insn }
+    }
+}
+
+impl<'a> core::ops::Deref for InsnSlot<'a> {
+    type Target = Insn<'a>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.insn.as_ref() }
+    }
+}
+
+impl<'a> core::ops::DerefMut for InsnSlot<'a> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { self.insn.as_mut() }
+    }
+}
+
+impl<'a> Drop for InsnSlot<'a> {
+    fn drop(&mut self) {
+        unsafe { sys::cs_free(self.insn.as_ptr(), 1) };
+    }
+}
+
 /// Extra details about an isntruction.
 #[derive(Copy, Clone)]
 pub struct Details<'i> {
@@ -268,6 +367,71 @@ impl<'i> Details<'i> {
             None
         }
     }
+
+    /// If these are details for a Mips instruction, this will return
+    /// Mips specific details. If these are not details for a Mips instruction
+    /// this will return [`Option::None`].
+    pub fn mips(self) -> Option<&'i mips::Details<'i>> {
+        if self.arch == Arch::Mips {
+            Some(unsafe { &self.inner.arch.mips })
+        } else {
+            None
+        }
+    }
+
+    /// If these are details for a PowerPC instruction, this will return
+    /// PowerPC specific details. If these are not details for a PowerPC instruction
+    /// this will return [`Option::None`].
+    pub fn ppc(self) -> Option<&'i ppc::Details<'i>> {
+        if self.arch == Arch::PowerPc {
+            Some(unsafe { &self.inner.arch.ppc })
+        } else {
+            None
+        }
+    }
+
+    /// If these are details for a Sparc instruction, this will return
+    /// Sparc specific details. If these are not details for a Sparc instruction
+    /// this will return [`Option::None`].
+    pub fn sparc(self) -> Option<&'i sparc::Details<'i>> {
+        if self.arch == Arch::Sparc {
+            Some(unsafe { &self.inner.arch.sparc })
+        } else {
+            None
+        }
+    }
+
+    /// Copies these details so that they can outlive the instruction that they
+    /// belong to, such as the instruction returned by [`InsnIter`] which is reused
+    /// for every instruction.
+    pub fn into_owned(self) -> OwnedDetails {
+        OwnedDetails {
+            arch: self.arch,
+            // `cs_detail` is plain old data without any pointers.
+            inner: unsafe { core::ptr::read(self.inner) },
+        }
+    }
+}
+
+/// A copy of the [`Details`] of an instruction that does not borrow the instruction.
+/// This is created using [`Details::into_owned`].
+pub struct OwnedDetails {
+    arch: Arch,
+    inner: DetailsInner,
+}
+
+impl OwnedDetails {
+    /// Returns the details. The architecture specific details are available
+    /// through [`Details::arch`].
+    pub fn details(&self) -> Details<'_> {
+        Details::wrap(self.arch, &self.inner)
+    }
+}
+
+impl Clone for OwnedDetails {
+    fn clone(&self) -> Self {
+        self.details().into_owned()
+    }
 }
 
 /// Wrapper around cs_detail.
@@ -359,4 +523,52 @@ mod test {
             sys::get_test_val("alignof(cs_insn)")
         );
     }
+
+    #[test]
+    fn operands_truncated() {
+        use crate::{Capstone, Mode};
+
+        let caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        // mov qword ptr fs:[rax + rcx*8 + 0x12345678], 0x12345678
+        let insns = caps
+            .disasm(
+                &[
+                    0x64, 0x48, 0xc7, 0x84, 0xc8, 0x78, 0x56, 0x34, 0x12, 0x78, 0x56, 0x34, 0x12,
+                ],
+                0x1000,
+            )
+            .expect("failed to disassemble instructions");
+        let insn = &insns[0];
+        assert!(insn.operands().len() > 40);
+        assert_eq!(insn.operands_checked(), Ok(insn.operands()));
+        assert_eq!(insn.mnemonic_checked(), Ok(insn.mnemonic()));
+        assert!(!insn.operands_truncated());
+
+        // Real instructions rarely fill the buffer, so a copy of one is filled by hand.
+        let mut full: Insn = unsafe { core::ptr::read(insn) };
+        full.op_str = [b'a' as libc::c_char; OP_STR_SIZE];
+        full.op_str[OP_STR_SIZE - 1] = 0;
+        assert!(full.operands_truncated());
+        assert_eq!(full.operands().len(), OP_STR_SIZE - 1);
+
+        full.op_str[0] = 0xff_u8 as libc::c_char;
+        assert!(full.operands_checked().is_err());
+        assert_eq!(full.operands(), "");
+    }
+
+    #[test]
+    fn empty_buffer() {
+        use crate::{Capstone, Mode};
+
+        let empty = InsnBuffer::empty();
+        assert!(empty.is_empty());
+        drop(empty);
+
+        let caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        let insns = caps
+            .disasm_count(&[0x90, 0xc3], 0x1000, 0)
+            .expect("failed to disassemble instructions");
+        assert!(insns.is_empty());
+        assert_eq!(insns.iter().count(), 0);
+    }
 }
@@ -21,7 +21,7 @@ use std::{
 use alloc::{borrow::Cow, boxed::Box, collections::BTreeMap as Map};
 
 pub use arch::{InsnGroup, InsnId, Reg};
-pub use insn::{ArchDetails, Details, Insn, InsnBuffer, InsnIter};
+pub use insn::{ArchDetails, Details, Insn, InsnBuffer, InsnIter, InsnSlot, OwnedDetails};
 
 pub use arch::arm;
 pub use arch::arm64;
@@ -74,7 +74,11 @@ pub struct Capstone {
 
 impl Capstone {
     /// Initializes capstone with the given arch and mode.
+    ///
+    /// Returns [`Error::InvalidMode`] if `mode` has flags that are not valid for
+    /// `arch`, see [`Mode::check`].
     pub fn open(arch: Arch, mode: Mode) -> Result<Self, Error> {
+        mode.check(arch).map_err(Error::InvalidMode)?;
         let mut handle = sys::Handle(0);
 
         result! {
@@ -98,6 +102,26 @@ impl Capstone {
         }
     }
 
+    /// Initializes capstone for x86 with the given mode and the `preferred` syntax,
+    /// or with the first syntax that this build of capstone can print if it can't.
+    /// The syntaxes are tried in this order: `preferred`, Intel, MASM, AT&T, and
+    /// the engine's default syntax is kept if none of them work. Each syntax is
+    /// checked with [`Capstone::supports_syntax`] and then actually set, so builds
+    /// that reject a printer at runtime (e.g. with `x86-reduce`) fall back as well.
+    ///
+    /// Returns the engine with the syntax that was selected, which is different from
+    /// `preferred` if a fallback was used.
+    pub fn open_x86_best_effort(mode: Mode, preferred: Syntax) -> Result<(Self, Syntax), Error> {
+        let mut caps = Capstone::open(Arch::X86, mode)?;
+        let candidates = core::iter::once(preferred).chain(X86_SYNTAX_FALLBACKS.iter().copied());
+        for syntax in candidates {
+            if caps.supports_syntax(syntax) && caps.set_syntax(syntax).is_ok() {
+                return Ok((caps, syntax));
+            }
+        }
+        Ok((caps, Syntax::Default))
+    }
+
     /// Retrieves some general details about an instruction. This value is
     /// only available if the engine was not compiled in DIET mode and details
     /// mode is turned on for this instance of Capstone. If details about an
@@ -128,6 +152,13 @@ impl Capstone {
         }
     }
 
+    /// Returns the ID of an instruction disassembled by this instance. See
+    /// [`Insn::insn_id`].
+    #[inline]
+    pub fn insn_id(&self, insn: &Insn) -> Option<InsnId> {
+        insn.insn_id(self.packed.arch())
+    }
+
     /// Reports the last error that occurred in the API after a function
     /// has failed. Like glibc's errno, this might not retain its old value
     /// once it has been accessed.
@@ -144,7 +175,8 @@ impl Capstone {
 
     /// Disassembles at most `count` instructions from the buffer using
     /// the given starting address. This will dynamically allocate memory
-    /// to contain the disassembled instructions.
+    /// to contain the disassembled instructions, unless `count` is `0`, which
+    /// returns an empty buffer without disassembling or allocating anything.
     pub fn disasm_count<'s>(
         &'s self,
         code: &[u8],
@@ -152,7 +184,7 @@ impl Capstone {
         count: usize,
     ) -> Result<InsnBuffer<'s>, Error> {
         if count == 0 {
-            Ok(InsnBuffer::new(NonNull::dangling().as_ptr(), 0))
+            Ok(InsnBuffer::empty())
         } else {
             self.priv_disasm(code, address, count)
         }
@@ -190,6 +222,7 @@ impl Capstone {
             return Err(Error::Bindings);
         }
 
+        let insn = NonNull::new(insn).ok_or(Error::Memory)?;
         Ok(InsnBuffer::new(insn, count))
     }
 
@@ -208,6 +241,70 @@ impl Capstone {
         )
     }
 
+    /// Allocates an instruction that [`Capstone::disasm_one_into`] disassembles
+    /// instructions into. This is the only allocation, so a loop that decodes one
+    /// instruction at a time into the same slot doesn't use the heap, and it is
+    /// available without the `alloc` feature. The memory comes from Capstone's
+    /// allocator (`cs_malloc`).
+    ///
+    /// The slot borrows this instance, so it can't outlive it, and options such as the
+    /// detail mode can't be changed while it exists.
+    pub fn alloc_insn(&self) -> Result<InsnSlot<'_>, Error> {
+        let insn = NonNull::new(unsafe { sys::cs_malloc(self.handle) }).ok_or(Error::Memory)?;
+        Ok(InsnSlot::new(insn))
+    }
+
+    /// Disassembles the first instruction in `code`, which starts at `address`, into
+    /// `insn`, replacing the instruction that was in it. Returns the size of the
+    /// instruction in bytes, or `0` if `code` is empty or does not start with a
+    /// valid instruction.
+    ///
+    /// `insn` comes from an [`InsnSlot`] and has the lifetime of the borrow of the
+    /// instance that allocated it, so it can't be used after the instance is closed.
+    /// If details are enabled, the slot must have been allocated while they were
+    /// enabled, otherwise [`Error::Detail`] is returned.
+    pub fn disasm_one_into<'s>(
+        &'s self,
+        code: &[u8],
+        address: u64,
+        insn: &mut Insn<'s>,
+    ) -> Result<usize, Error> {
+        if self.details_enabled() && insn.detail.is_null() {
+            return Err(Error::Detail);
+        }
+
+        let mut code_ptr = code.as_ptr();
+        let mut size = code.len() as libc::size_t;
+        let mut address = address;
+        let success = unsafe {
+            sys::cs_disasm_iter(self.handle, &mut code_ptr, &mut size, &mut address, insn)
+        };
+
+        #[cfg(feature = "std")]
+        self.resume_panic();
+
+        if !success {
+            self.errno()?;
+            return Ok(0);
+        }
+
+        Ok(insn.size())
+    }
+
+    /// Returns the size in bytes of the first instruction in `code` without
+    /// handing out a full [`Insn`]. This does not change the engine's configured
+    /// detail mode.
+    ///
+    /// Returns [`Option::None`] if the bytes at the start of `code` are not a
+    /// valid instruction.
+    pub fn insn_length(&self, code: &[u8], address: u64) -> Option<usize> {
+        let mut iter = self.disasm_iter(code, address);
+        match iter.next() {
+            Some(Ok(insn)) => Some(insn.size()),
+            _ => None,
+        }
+    }
+
     /// Sets the assembly syntax for the disassembling engine at runtime.
     ///
     /// If the syntax is supported then [`Result::Ok`] is returned
@@ -225,14 +322,45 @@ impl Capstone {
         }
     }
 
+    /// Returns true if [`Capstone::set_syntax`] would accept `syntax` for
+    /// the current arch. This does not change the engine's configured syntax.
+    ///
+    /// Intel, AT&T and MASM syntaxes are only available for x86. AT&T syntax
+    /// is not available if capstone was built with the `x86-disable-att` or
+    /// `diet` features.
+    pub fn supports_syntax(&self, syntax: Syntax) -> bool {
+        let is_x86 = self.arch() == Arch::X86;
+        match syntax {
+            Syntax::Default => true,
+            Syntax::Intel | Syntax::Masm => is_x86,
+            Syntax::Att => {
+                is_x86 && !cfg!(feature = "x86-disable-att") && !supports(SupportQuery::Diet)
+            }
+            Syntax::NoRegName => matches!(self.arch(), Arch::Arm | Arch::PowerPc),
+        }
+    }
+
     /// Change the engine's mode at runtime after it has been initialized.
+    ///
+    /// Returns [`Error::InvalidMode`] if `mode` has flags that are not valid for
+    /// the current arch, see [`Mode::check`].
     pub fn set_mode(&mut self, mode: Mode) -> Result<(), Error> {
+        mode.check(self.arch()).map_err(Error::InvalidMode)?;
         self.set_option(sys::OptType::Mode, mode.bits() as libc::size_t)
     }
 
     /// Setting `detail` to true will make the disassembling engine break
     /// down instruction structure into details.
+    ///
+    /// Returns [`Error::Diet`] when enabling details in diet mode, which has no
+    /// instruction details, and details stay disabled.
     pub fn set_details_enabled(&mut self, detail: bool) -> Result<(), Error> {
+        // Capstone accepts the option in diet mode even though it never fills in
+        // the details, which would make `details_enabled` lie.
+        if detail && supports(SupportQuery::Diet) {
+            return Err(Error::Diet);
+        }
+
         self.set_option(
             sys::OptType::Detail,
             if detail {
@@ -533,11 +661,19 @@ impl Capstone {
 
     /// Returns the user friendly name of a register. This will return an empty string
     /// if the register is not valid for the current architecture.
+    ///
+    /// x86 register names are looked up in a table instead of asking Capstone.
     pub fn reg_name<R>(&self, reg: R) -> &str
     where
         R: Into<Reg>,
     {
         let reg = reg.into();
+        if self.arch() == Arch::X86 {
+            if let Some(x86_reg) = x86::Reg::from_c(reg.to_primitive()) {
+                return x86_reg.name();
+            }
+        }
+
         let name = unsafe { sys::cs_reg_name(self.handle, reg.to_primitive() as _) };
 
         if name.is_null() {
@@ -579,6 +715,14 @@ impl Capstone {
         }
     }
 
+    /// Returns the instruction groups of the current architecture, the groups that
+    /// [`Capstone::group_name`] returns a name for.
+    pub fn groups(&self) -> impl Iterator<Item = InsnGroup> + '_ {
+        (1..=u8::MAX)
+            .map(InsnGroup::from_primitive)
+            .filter(move |&group| !self.group_name(group).is_empty())
+    }
+
     /// Retrieves all of the registers read from and written to either
     /// implicitly or explicitly by an instruction and places them into
     /// the given buffer.
@@ -690,6 +834,31 @@ impl RegsUsed {
     pub fn write(&self) -> &[Reg] {
         &self.write
     }
+
+    /// Returns the x86 registers in [`RegsUsed::read`] that are in `class`.
+    pub fn x86_read_in(&self, class: x86::RegClass) -> impl Iterator<Item = x86::Reg> + '_ {
+        x86_regs_in(&self.read, class)
+    }
+
+    /// Returns the x86 registers in [`RegsUsed::write`] that are in `class`.
+    pub fn x86_write_in(&self, class: x86::RegClass) -> impl Iterator<Item = x86::Reg> + '_ {
+        x86_regs_in(&self.write, class)
+    }
+
+    /// Returns true if any x86 register read or written is in a class that
+    /// matches `predicate`, e.g. [`x86::RegClass::is_avx512`].
+    pub fn x86_uses_class(&self, predicate: impl Fn(x86::RegClass) -> bool) -> bool {
+        self.read
+            .iter()
+            .chain(self.write.iter())
+            .any(|reg| predicate(reg.x86().class()))
+    }
+}
+
+fn x86_regs_in(regs: &[Reg], class: x86::RegClass) -> impl Iterator<Item = x86::Reg> + '_ {
+    regs.iter()
+        .map(|reg| reg.x86())
+        .filter(move |reg| reg.class() == class)
 }
 
 /// A list of registers that are either read from or written to by an instruction.
@@ -716,6 +885,9 @@ impl core::ops::Deref for RegsBuffer {
     }
 }
 
+/// The syntaxes that [`Capstone::open_x86_best_effort`] falls back to, in order.
+const X86_SYNTAX_FALLBACKS: [Syntax; 3] = [Syntax::Intel, Syntax::Masm, Syntax::Att];
+
 /// Disassembling engine assembly syntax.
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum Syntax {
@@ -898,42 +1070,122 @@ impl From<Mode> for sys::Mode {
     }
 }
 
-c_enum! {
-    #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
-    pub enum Error: u8 {
-        /// Out of memory error.
-        Memory = 1,
-        /// Unsupported architecture.
-        Arch,
-        /// Invalid handle.
-        Handle,
-        /// Invalid Capstone handle argument.
-        ///
-        /// **NOTE**: This should not come up using the safe bindings. If
-        /// it does please file an issue.
-        Csh,
-        /// Invalid/unsupported mode.
-        Mode,
-        /// Invalid/unsupported option.
-        Option,
-        /// Information is unavailable because detail option is OFF.
-        Detail,
-        /// Dynamic memory management uninitialized.
-        MemSetup,
-        /// Unsupported version (bindings).
-        Version,
-        /// Accessed irrelevant data in "diet" engine.
-        Diet,
-        /// Accessed irrelevant data for "data" instruction in SKIPDATA mode.
-        Skipdata,
-        /// X86 AT&T syntax is unsupported (opted out at compile time).
-        X86Att,
-        /// X86 Intel syntex is unsupported (opted out at compile time).
-        X86Intel,
-        /// X86 MASM syntex is unsupported (opted out at compile time).
-        X86Masm,
-        /// An error occurred in the bindings. Truly terrible.
-        Bindings,
+/// Defines [`Mode::valid_for`] and [`Mode::check`] from one list of the flags that
+/// each architecture supports, so that the message of [`Error::InvalidMode`] always
+/// names the flags that are valid.
+macro_rules! arch_modes {
+    ($($arch:ident => [$($mode:ident),*],)*) => {
+        impl Mode {
+            /// Returns the flags that are valid modes for `arch`.
+            pub fn valid_for(arch: Arch) -> Mode {
+                match arch {
+                    $(Arch::$arch => Mode::empty() $(| Mode::$mode)*,)*
+                }
+            }
+
+            /// Checks that every flag of this mode is valid for `arch`, and returns a
+            /// description of the valid modes if it isn't.
+            ///
+            /// The flags of different architectures share bits (e.g. `Thumb`, `Micro`, `V9`
+            /// and `Qpx` are all the same bit), so a flag meant for another architecture is
+            /// only caught if its bit isn't used by any flag of `arch`.
+            pub fn check(self, arch: Arch) -> Result<(), &'static str> {
+                if self.bits() & !Mode::valid_for(arch).bits() == 0 {
+                    return Ok(());
+                }
+
+                Err(match arch {
+                    $(Arch::$arch => concat!(
+                        stringify!($arch),
+                        " only supports these modes:"
+                        $(, " ", stringify!($mode))*
+                    ),)*
+                })
+            }
+        }
+    };
+}
+
+arch_modes! {
+    Arm => [Thumb, MClass, V8, BigEndian],
+    Arm64 => [BigEndian],
+    Mips => [Mips32, Mips64, Micro, Mips2, Mips3, Mips32R6, BigEndian],
+    X86 => [Bits16, Bits32, Bits64],
+    PowerPc => [Bits32, Bits64, Qpx, BigEndian],
+    Sparc => [V9, BigEndian],
+    SystemZ => [BigEndian],
+    XCore => [BigEndian],
+    M68K => [M68K000, M68K010, M68K020, M68K030, M68K040, M68K060, BigEndian],
+    Tms320C64X => [BigEndian],
+    M680X => [
+        M680X6301, M680X6309, M680X6800, M680X6801, M680X6805, M680X6808, M680X6809,
+        M680X6811, M680XCPU12, M680XHCS08
+    ],
+    Evm => [LittleEndian],
+    Mos65xx => [LittleEndian],
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Error {
+    /// Out of memory error.
+    Memory,
+    /// Unsupported architecture.
+    Arch,
+    /// Invalid handle.
+    Handle,
+    /// Invalid Capstone handle argument.
+    ///
+    /// **NOTE**: This should not come up using the safe bindings. If
+    /// it does please file an issue.
+    Csh,
+    /// Invalid/unsupported mode.
+    Mode,
+    /// Invalid/unsupported option.
+    Option,
+    /// Information is unavailable because detail option is OFF.
+    Detail,
+    /// Dynamic memory management uninitialized.
+    MemSetup,
+    /// Unsupported version (bindings).
+    Version,
+    /// Accessed irrelevant data in "diet" engine.
+    Diet,
+    /// Accessed irrelevant data for "data" instruction in SKIPDATA mode.
+    Skipdata,
+    /// X86 AT&T syntax is unsupported (opted out at compile time).
+    X86Att,
+    /// X86 Intel syntex is unsupported (opted out at compile time).
+    X86Intel,
+    /// X86 MASM syntex is unsupported (opted out at compile time).
+    X86Masm,
+    /// An error occurred in the bindings. Truly terrible.
+    Bindings,
+    /// The mode has flags that are not valid for the architecture. This is checked
+    /// before calling Capstone, and the message from [`Mode::check`] lists the
+    /// valid modes.
+    InvalidMode(&'static str),
+}
+
+impl Error {
+    /// Converts an error code returned by Capstone into this.
+    pub(crate) fn from_c(err: libc::c_int) -> Option<Error> {
+        Some(match err {
+            1 => Error::Memory,
+            2 => Error::Arch,
+            3 => Error::Handle,
+            4 => Error::Csh,
+            5 => Error::Mode,
+            6 => Error::Option,
+            7 => Error::Detail,
+            8 => Error::MemSetup,
+            9 => Error::Version,
+            10 => Error::Diet,
+            11 => Error::Skipdata,
+            12 => Error::X86Att,
+            13 => Error::X86Intel,
+            14 => Error::X86Masm,
+            _ => return None,
+        })
     }
 }
 
@@ -955,6 +1207,7 @@ impl fmt::Display for Error {
             Error::X86Intel => "X86 Intel syntex is unsupported",
             Error::X86Masm => "X86 MASM syntex is unsupported",
             Error::Bindings => "bindings error (please file an issue)",
+            Error::InvalidMode(msg) => msg,
         };
 
         f.write_str(msg)
@@ -1105,6 +1358,326 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_insn_length() {
+        let caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+
+        // lea rcx, [rdx + rsi + 8]
+        assert_eq!(caps.insn_length(&[0x8d, 0x4c, 0x32, 0x08], 0x1000), Some(4));
+        // push rbp; mov rbp, rsp
+        assert_eq!(caps.insn_length(&[0x55, 0x48, 0x89, 0xe5], 0x1000), Some(1));
+        // truncated call rel32
+        assert_eq!(caps.insn_length(&[0xe8, 0xdf], 0x1000), None);
+        assert_eq!(caps.insn_length(&[], 0x1000), None);
+        assert!(!caps.details_enabled());
+    }
+
+    #[test]
+    fn test_non_ascii_mnemonic() {
+        let mut caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        // add eax, ebx
+        let code = [0x01, 0xd8];
+
+        caps.set_mnemonic(x86::InsnId::Add, "äddé")
+            .expect("failed to substitute instruction mnemonic");
+        {
+            let insns = caps.disasm(&code, 0x1000).expect("failed to disassemble");
+            assert_eq!(insns[0].mnemonic(), "äddé");
+            assert_eq!(insns[0].mnemonic_checked(), Ok("äddé"));
+        }
+
+        // Capstone cuts the mnemonic off after 31 bytes, in the middle of the 16th `é`.
+        caps.set_mnemonic(x86::InsnId::Add, "é".repeat(16))
+            .expect("failed to substitute instruction mnemonic");
+        let insns = caps.disasm(&code, 0x1000).expect("failed to disassemble");
+        assert_eq!(insns[0].mnemonic(), "é".repeat(15));
+        assert!(insns[0].mnemonic_checked().is_err());
+    }
+
+    #[test]
+    fn test_open_x86_best_effort() {
+        let (_, syntax) =
+            Capstone::open_x86_best_effort(Mode::Bits64, Syntax::Masm).expect("failed to open");
+        assert_eq!(syntax, Syntax::Masm);
+
+        let att_available = !cfg!(feature = "x86-disable-att") && !supports(SupportQuery::Diet);
+        let (_, syntax) =
+            Capstone::open_x86_best_effort(Mode::Bits64, Syntax::Att).expect("failed to open");
+        if att_available {
+            assert_eq!(syntax, Syntax::Att);
+        } else {
+            assert_eq!(syntax, Syntax::Intel);
+        }
+
+        // Register numbers are only printed for ARM and PowerPC.
+        let (_, syntax) = Capstone::open_x86_best_effort(Mode::Bits32, Syntax::NoRegName)
+            .expect("failed to open");
+        assert_eq!(syntax, Syntax::Intel);
+    }
+
+    #[test]
+    fn test_mode_check() {
+        assert!((Mode::Thumb | Mode::BigEndian).check(Arch::Arm).is_ok());
+        assert!(Mode::Mips64.check(Arch::Mips).is_ok());
+        assert!(Mode::LittleEndian.check(Arch::Mos65xx).is_ok());
+        assert!(Mode::V9.check(Arch::X86).is_err());
+        assert!(Mode::BigEndian.check(Arch::X86).is_err());
+        assert!(Mode::Bits64.check(Arch::Arm).is_err());
+        assert!(Mode::M680X6811.check(Arch::Mips).is_err());
+
+        assert_eq!(
+            Mode::V9.check(Arch::X86),
+            Err("X86 only supports these modes: Bits16 Bits32 Bits64")
+        );
+
+        assert_eq!(
+            Capstone::open(Arch::X86, Mode::Bits64 | Mode::V9).err(),
+            Some(Error::InvalidMode(
+                "X86 only supports these modes: Bits16 Bits32 Bits64"
+            ))
+        );
+        let mut caps = Capstone::open(Arch::Arm, Mode::Arm).expect("failed to open capstone");
+        assert_eq!(caps.set_mode(Mode::Thumb), Ok(()));
+        assert_eq!(
+            caps.set_mode(Mode::Bits64),
+            Err(Error::InvalidMode(
+                "Arm only supports these modes: Thumb MClass V8 BigEndian"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_disasm_one_into() {
+        let caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        let mut slot = caps.alloc_insn().expect("failed to allocate instruction");
+        assert_eq!(slot.size(), 0);
+
+        // push rbp; mov rbp, rsp; truncated call rel32
+        let code = [0x55, 0x48, 0x89, 0xe5, 0xe8, 0xdf];
+        let size = caps.disasm_one_into(&code, 0x1000, &mut slot);
+        assert_eq!(size, Ok(1));
+        assert_eq!((slot.mnemonic(), slot.address()), ("push", 0x1000));
+
+        let size = caps.disasm_one_into(&code[1..], 0x1001, &mut slot);
+        assert_eq!(size, Ok(3));
+        assert_eq!((slot.mnemonic(), slot.address()), ("mov", 0x1001));
+
+        assert_eq!(caps.disasm_one_into(&code[4..], 0x1004, &mut slot), Ok(0));
+        assert_eq!(caps.disasm_one_into(&[], 0x1006, &mut slot), Ok(0));
+    }
+
+    #[test]
+    fn test_insn_id() {
+        let caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+
+        // call rel32; ret
+        let insns = caps
+            .disasm(&[0xe8, 0x00, 0x00, 0x00, 0x00, 0xc3], 0x1000)
+            .expect("failed to disassemble instructions");
+        assert!(caps.insn_id(&insns[0]) == Some(InsnId::X86(x86::InsnId::Call)));
+        assert!(caps.insn_id(&insns[1]).unwrap() == x86::InsnId::Ret);
+    }
+
+    #[test]
+    fn test_generic_insn_groups() {
+        let caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        assert_eq!(caps.group_name(InsnGroup::JUMP), "jump");
+        assert_eq!(caps.group_name(InsnGroup::CALL), "call");
+        assert_eq!(caps.group_name(InsnGroup::RET), "ret");
+        assert_eq!(caps.group_name(InsnGroup::IRET), "iret");
+        assert_eq!(
+            caps.group_name(InsnGroup::BRANCH_RELATIVE),
+            "branch_relative"
+        );
+        assert!(InsnGroup::JUMP == x86::InsnGroup::Jump);
+        assert!(InsnGroup::from(x86::InsnGroup::Call).is_call());
+
+        let groups = caps.groups().collect::<Vec<InsnGroup>>();
+        assert!(groups.contains(&InsnGroup::JUMP));
+        assert!(groups.contains(&x86::InsnGroup::Sse2.into()));
+        assert!(!groups.contains(&InsnGroup::INVALID));
+
+        let caps = Capstone::open(Arch::Arm64, Mode::Arm).expect("failed to open capstone");
+        assert_eq!(caps.group_name(InsnGroup::JUMP), "jump");
+
+        assert!(InsnGroup::JUMP.is_jump() && InsnGroup::JUMP.is_branch());
+        assert!(InsnGroup::RET.is_ret() && InsnGroup::RET.is_branch());
+        assert!(!InsnGroup::INT.is_branch());
+        assert!(!InsnGroup::PRIVILEGE.is_branch());
+    }
+
+    #[test]
+    fn test_owned_details() {
+        let mut caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        caps.set_details_enabled(true)
+            .expect("failed to enable capstone instruction details");
+
+        // call rel32; ret
+        let code = [0xe8, 0x00, 0x00, 0x00, 0x00, 0xc3];
+        let details = caps
+            .disasm_iter(&code, 0x1000)
+            .map(|insn| {
+                caps.details(insn.expect("failed to disassemble"))
+                    .into_owned()
+            })
+            .collect::<Vec<OwnedDetails>>();
+
+        assert_eq!(details.len(), 2);
+        assert!(details[0]
+            .details()
+            .groups()
+            .iter()
+            .any(|&g| g == x86::InsnGroup::Call));
+        assert!(details[1]
+            .clone()
+            .details()
+            .groups()
+            .iter()
+            .any(|&g| g == x86::InsnGroup::Ret));
+        assert!(details[1].details().x86().is_some());
+    }
+
+    #[test]
+    fn test_x86_op_index() {
+        let mut caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        caps.set_details_enabled(true)
+            .expect("failed to enable capstone instruction details");
+
+        // mov dword ptr [rax + 8], 0x1234
+        let code = [0xc7, 0x40, 0x08, 0x34, 0x12, 0x00, 0x00];
+        let insn = caps
+            .disasm_iter(&code, 0x1000)
+            .next()
+            .expect("no instruction")
+            .expect("failed to disassemble instruction");
+        let details = caps.details(insn);
+        let details = details.x86().expect("no x86 details");
+
+        assert_eq!(details.op_count_of(x86::OpType::Mem), 1);
+        assert_eq!(details.op_count_of(x86::OpType::Imm), 1);
+        assert_eq!(details.op_count_of(x86::OpType::Reg), 0);
+        assert_eq!(details.op_index(x86::OpType::Mem, 1), Some(0));
+        assert_eq!(details.op_index(x86::OpType::Imm, 1), Some(1));
+        assert_eq!(details.op_index(x86::OpType::Imm, 2), None);
+        assert_eq!(details.op_index(x86::OpType::Imm, 0), None);
+    }
+
+    #[test]
+    #[cfg(feature = "diet")]
+    fn details_cannot_be_enabled_in_diet_mode() {
+        let mut caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        assert_eq!(caps.set_details_enabled(true), Err(Error::Diet));
+        assert!(!caps.details_enabled());
+        assert!(caps.set_details_enabled(false).is_ok());
+    }
+
+    #[test]
+    fn test_x86_reg_names() {
+        // Capstone doesn't have register names in diet mode.
+        if supports(SupportQuery::Diet) {
+            return;
+        }
+
+        let caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        for primitive in 0..x86::Reg::Ending as u8 {
+            let reg = x86::Reg::from_primitive(primitive).expect("invalid register");
+            let name = unsafe {
+                let name = sys::cs_reg_name(caps.handle, primitive as _);
+                if name.is_null() {
+                    ""
+                } else {
+                    util::cstr(name, 128)
+                }
+            };
+            assert_eq!(reg.name(), name, "register {}", primitive);
+        }
+        assert_eq!(caps.reg_name(x86::Reg::Rax), "rax");
+        assert_eq!(caps.reg_name(x86::Reg::St0), "st(0)");
+    }
+
+    #[test]
+    fn test_x86_regs_used_by_class() {
+        // Capstone doesn't track register accesses in diet mode.
+        if supports(SupportQuery::Diet) {
+            return;
+        }
+
+        let mut caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        caps.set_details_enabled(true)
+            .expect("failed to enable capstone instruction details");
+
+        // vaddps zmm0 {k1}, zmm1, zmm2
+        let code = [0x62, 0xf1, 0x74, 0x49, 0x58, 0xc2];
+        let insn = caps
+            .disasm_iter(&code, 0x1000)
+            .next()
+            .expect("no instruction")
+            .expect("failed to disassemble instruction");
+        let mut regs_used = RegsUsed::default();
+        caps.regs_used(insn, &mut regs_used)
+            .expect("failed to get registers accessed");
+
+        let read_zmm = regs_used
+            .x86_read_in(x86::RegClass::Zmm)
+            .collect::<Vec<_>>();
+        assert!(read_zmm.contains(&x86::Reg::Zmm1));
+        assert!(read_zmm.contains(&x86::Reg::Zmm2));
+        assert!(regs_used
+            .x86_read_in(x86::RegClass::Mask)
+            .eq(core::iter::once(x86::Reg::K1)));
+        assert!(regs_used
+            .x86_write_in(x86::RegClass::Zmm)
+            .eq(core::iter::once(x86::Reg::Zmm0)));
+        assert!(regs_used.x86_uses_class(x86::RegClass::is_avx512));
+        assert!(!regs_used.x86_uses_class(|class| class == x86::RegClass::Segment));
+    }
+
+    #[test]
+    fn test_x86_rep_prefixes() {
+        use x86::Prefix;
+
+        let mut caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        caps.set_details_enabled(true)
+            .expect("failed to enable capstone instruction details");
+
+        // (code, mnemonic, rep, repe, repne)
+        let cases: &[(&[u8], &str, bool, bool, bool)] = &[
+            (&[0xf3, 0xa4], "rep movsb", true, false, false),
+            (&[0xf3, 0xa6], "repe cmpsb", false, true, false),
+            (&[0xf2, 0xae], "repne scasb", false, false, true),
+            (&[0xa4], "movsb", false, false, false),
+        ];
+
+        // The prefixes must not depend on the syntax that the instructions are printed with.
+        let mut syntaxes = vec![Syntax::Intel];
+        if caps.supports_syntax(Syntax::Att) {
+            syntaxes.push(Syntax::Att);
+        }
+
+        for &syntax in syntaxes.iter() {
+            caps.set_syntax(syntax).expect("failed to set syntax");
+            for &(code, mnemonic, rep, repe, repne) in cases {
+                let insn = caps
+                    .disasm_iter(code, 0x1000)
+                    .next()
+                    .expect("no instruction")
+                    .expect("failed to disassemble instruction");
+                if syntax == Syntax::Intel {
+                    assert_eq!(insn.mnemonic(), mnemonic);
+                } else {
+                    assert!(insn.mnemonic().starts_with(mnemonic.trim_end_matches('b')));
+                }
+
+                let details = caps.details(insn);
+                let details = details.x86().expect("no x86 details");
+                assert_eq!(details.has_prefix(Prefix::Rep), rep, "{}", mnemonic);
+                assert_eq!(details.has_prefix(Prefix::RepE), repe, "{}", mnemonic);
+                assert_eq!(details.has_prefix(Prefix::RepNE), repne, "{}", mnemonic);
+                assert!(!details.has_prefix(Prefix::Lock), "{}", mnemonic);
+            }
+        }
+    }
+
     #[test]
     fn validate_packed_cs_info_states() {
         for arch in ALL_ARCHS.iter().copied() {
@@ -1130,6 +1703,21 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_supports_syntax() {
+        let mut caps = Capstone::open(Arch::X86, Mode::Bits64).expect("failed to open capstone");
+        for &syntax in &[Syntax::Intel, Syntax::Att, Syntax::Masm] {
+            let supported = caps.supports_syntax(syntax);
+            assert_eq!(supported, caps.set_syntax(syntax).is_ok());
+        }
+        caps.set_syntax(Syntax::Default)
+            .expect("failed to restore default syntax");
+
+        let caps = Capstone::open(Arch::Arm, Mode::Arm).expect("failed to open capstone");
+        assert!(!caps.supports_syntax(Syntax::Intel));
+        assert!(!caps.supports_syntax(Syntax::Att));
+    }
+
     #[test]
     fn test_version() {
         pub const EXPECTED_MAJOR_VERSION: u16 = 5;
@@ -122,6 +122,36 @@ macro_rules! c_enum_big {
                     None
                 }
             }
+
+            /// The name of this variant in lowercase, e.g. `rax` for `Rax`.
+            #[allow(dead_code)]
+            pub(crate) fn lowercase_name(self) -> &'static str {
+                match self {
+                    $(
+                        $EnumName::$Variant => {
+                            const NAME: &str = stringify!($Variant);
+                            const LOWER: [u8; NAME.len()] = $crate::macros::ascii_lowercase(NAME);
+                            // Variant names are ASCII, so they are still UTF-8 in lowercase.
+                            const LOWER_NAME: &str = unsafe { core::str::from_utf8_unchecked(&LOWER) };
+                            LOWER_NAME
+                        }
+                    )*
+                }
+            }
         }
     };
 }
+
+/// Returns the bytes of `s` with ASCII letters in lowercase, where `N` is the length
+/// of `s`. This is used by `c_enum_big!` to name variants at compile time.
+#[allow(dead_code)]
+pub(crate) const fn ascii_lowercase<const N: usize>(s: &str) -> [u8; N] {
+    let bytes = s.as_bytes();
+    let mut lower = [0; N];
+    let mut idx = 0;
+    while idx < N {
+        lower[idx] = bytes[idx].to_ascii_lowercase();
+        idx += 1;
+    }
+    lower
+}
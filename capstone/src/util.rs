@@ -1,5 +1,7 @@
+/// Returns the bytes of the 0 terminated string at `ptr`, not including the terminator.
+/// At most `max_size` bytes are read if there is no terminator.
 #[inline]
-pub unsafe fn cstr(ptr: *const libc::c_char, max_size: usize) -> &'static str {
+pub unsafe fn cstr_bytes<'a>(ptr: *const libc::c_char, max_size: usize) -> &'a [u8] {
     let mut len = 0;
 
     // strlen:
@@ -11,7 +13,34 @@ pub unsafe fn cstr(ptr: *const libc::c_char, max_size: usize) -> &'static str {
         }
     }
 
-    core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr as *const u8, len))
+    core::slice::from_raw_parts(ptr as *const u8, len)
+}
+
+/// Like [`cstr_bytes`] but only the part of the string before the first invalid
+/// UTF-8 sequence is returned. Capstone's own strings are ASCII, but a custom
+/// mnemonic can contain any character and Capstone cuts it off at a byte limit,
+/// which can split a character in half.
+#[inline]
+pub unsafe fn cstr(ptr: *const libc::c_char, max_size: usize) -> &'static str {
+    valid_prefix(cstr_bytes(ptr, max_size))
+}
+
+/// Returns the longest prefix of `bytes` that is valid UTF-8.
+fn valid_prefix(bytes: &[u8]) -> &str {
+    match core::str::from_utf8(bytes) {
+        Ok(s) => s,
+        // The bytes up to `valid_up_to` were just validated.
+        Err(err) => unsafe { core::str::from_utf8_unchecked(&bytes[..err.valid_up_to()]) },
+    }
+}
+
+/// Like [`cstr`] but the string is checked to be valid UTF-8.
+#[inline]
+pub unsafe fn cstr_checked<'a>(
+    ptr: *const libc::c_char,
+    max_size: usize,
+) -> Result<&'a str, core::str::Utf8Error> {
+    core::str::from_utf8(cstr_bytes(ptr, max_size))
 }
 
 /// Ensures that a string is 0 terminated.
@@ -32,3 +61,19 @@ pub fn ensure_c_string(s: &'static str) -> &'static str {
     assert!(s.ends_with('\0'), "not a valid 0 terminated string");
     s
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cstr_stops_at_invalid_utf8() {
+        let bytes = b"add\xc3\xa9\xc3\0";
+        let s = unsafe { cstr(bytes.as_ptr() as *const libc::c_char, bytes.len()) };
+        assert_eq!(s, "add\u{e9}");
+
+        let bytes = b"\xffadd\0";
+        let s = unsafe { cstr(bytes.as_ptr() as *const libc::c_char, bytes.len()) };
+        assert_eq!(s, "");
+    }
+}
@@ -37,12 +37,18 @@ struct ep_helper__entry ep_helper__entries[] = {
 
     { "sizeof(cs_mips)", sizeof(cs_mips) },
     { "alignof(cs_mips)", alignof(cs_mips) },
+    { "sizeof(cs_mips_op)", sizeof(cs_mips_op) },
+    { "alignof(cs_mips_op)", alignof(cs_mips_op) },
 
     { "sizeof(cs_ppc)", sizeof(cs_ppc) },
     { "alignof(cs_ppc)", alignof(cs_ppc) },
+    { "sizeof(cs_ppc_op)", sizeof(cs_ppc_op) },
+    { "alignof(cs_ppc_op)", alignof(cs_ppc_op) },
 
     { "sizeof(cs_sparc)", sizeof(cs_sparc) },
     { "alignof(cs_sparc)", alignof(cs_sparc) },
+    { "sizeof(cs_sparc_op)", sizeof(cs_sparc_op) },
+    { "alignof(cs_sparc_op)", alignof(cs_sparc_op) },
 
     { "sizeof(cs_sysz)", sizeof(cs_sysz) },
     { "alignof(cs_sysz)", alignof(cs_sysz) },
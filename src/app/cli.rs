@@ -1,3 +1,10 @@
+use super::dump::SymbolDumpFormat;
+use super::printer::{AddressFormat, BytesFormat, OutputFormat, SourcePaths, SymbolKindFilter};
+use crate::disasm::binary::{Arch, Bits};
+use crate::disasm::source::SourceMappings;
+use crate::disasm::symbol::Demangle;
+use crate::disasm::Traversal;
+use capstone::Syntax;
 use clap::Parser;
 use std::path::PathBuf;
 use termcolor::ColorChoice;
@@ -6,7 +13,125 @@ use termcolor::ColorChoice;
 #[clap(name = "cargo-disasm", version = env!("CARGO_PKG_VERSION"), author = "Marc C.")]
 pub struct Opts {
     /// The name of the symbol to match and disassemble.
-    pub symbol: String,
+    #[clap(required_unless_present_any = &["dump-symbols", "sections", "diagnostics", "all", "entry", "raw"])]
+    pub symbol: Option<String>,
+
+    /// Disassemble every function in the binary in the order of their addresses,
+    /// with the bytes between functions shown as data. The symbol name is not
+    /// used, so the first positional argument is the binary's path.
+    #[clap(
+        long = "all",
+        conflicts_with_all = &["list", "exact", "compare", "dump-symbols", "start-at", "stop-at"]
+    )]
+    pub all: bool,
+
+    /// Disassemble the function containing the entry point of the binary, starting at
+    /// the entry point. If no symbol covers it, the code from the entry point to the
+    /// end of its section is disassembled. The symbol name is not used, so the first
+    /// positional argument is the binary's path.
+    #[clap(
+        long = "entry",
+        conflicts_with_all = &["all", "list", "exact", "dump-symbols", "start-at"]
+    )]
+    pub entry: bool,
+
+    /// Disassemble the binary as a raw image of code (e.g. a ROM) instead of an
+    /// object file, starting at its first byte. `--arch` is required, and `--base`
+    /// sets the address the image is loaded at. The symbol name is only used with
+    /// `--symbols-from`, so a single positional argument is the binary's path.
+    #[clap(
+        long = "raw",
+        requires = "arch",
+        conflicts_with_all = &["all", "entry", "list", "exact", "compare", "dump-symbols"]
+    )]
+    pub raw: bool,
+
+    /// The architecture of a raw image. For other binaries, this disassembles them as
+    /// this architecture instead of the one detected from their headers.
+    ///
+    /// Possible values are: x86, x86_64, arm, arm64, ppc, ppc64, s390x, sparc, sparc64,
+    /// mips, mips64, mos6502
+    #[clap(long = "arch")]
+    pub arch: Option<Arch>,
+
+    /// Forces the word size of the architecture given with `--arch`, like `--arch x86
+    /// --bits 64` for x86_64: 32 or 64.
+    #[clap(long = "bits", requires = "arch", parse(try_from_str = parse_bits))]
+    pub bits: Option<Bits>,
+
+    /// After the symbol, also disassemble the functions that it calls, and the
+    /// functions that those call, up to N calls away. Each function is only shown
+    /// once, and at most 64 functions are followed.
+    #[clap(
+        long = "follow-calls",
+        value_name = "N",
+        conflicts_with_all = &["all", "list", "compare", "dump-symbols"]
+    )]
+    pub follow_calls: Option<usize>,
+
+    /// List the symbols matching `symbol` instead of disassembling
+    /// the closest match.
+    #[clap(short = 'l', long = "list")]
+    pub list: bool,
+
+    /// Match symbols listed by `--list` using `symbol` as a regular expression
+    /// instead of fuzzy matching.
+    #[clap(long = "regex", requires = "list")]
+    pub regex: bool,
+
+    /// The kind of symbols listed by `--list`: function, inlined (functions that
+    /// only exist in DWARF debug information, see `--dwarf-declarations`) or all.
+    /// `all` also shows the kind of each symbol.
+    #[clap(long = "kind", default_value = "function", requires = "list")]
+    pub kind: SymbolKindFilter,
+
+    /// Disassemble the symbol whose mangled (or demangled) name is exactly
+    /// `symbol`. Without this option a symbol with exactly that name is still
+    /// preferred, but the closest fuzzy match is used if there isn't one.
+    #[clap(short = 'e', long = "exact", conflicts_with = "list")]
+    pub exact: bool,
+
+    /// Disassemble the symbol in this binary as well and print a diff of the
+    /// instructions. The symbol is looked up by the name of the symbol found
+    /// in the main binary.
+    #[clap(
+        long = "compare",
+        value_name = "other-binary",
+        conflicts_with_all = &["list", "start-at", "stop-at"]
+    )]
+    pub compare: Option<PathBuf>,
+
+    /// Write every symbol of the binary to stdout in this format instead of
+    /// disassembling anything. The symbol name is not used, so the first
+    /// positional argument is the binary's path.
+    ///
+    /// Possible values are: json
+    #[clap(
+        long = "dump-symbols",
+        value_name = "format",
+        conflicts_with_all = &["list", "compare"]
+    )]
+    pub dump_symbols: Option<SymbolDumpFormat>,
+
+    /// List the sections of the binary with their address, file offset, size and
+    /// flags (`r`ead, `w`rite, e`x`ecute) instead of disassembling anything. Sections
+    /// that aren't loaded into memory have no flags. The symbol name is not used, so
+    /// the first positional argument is the binary's path.
+    #[clap(
+        long = "sections",
+        conflicts_with_all = &["all", "entry", "raw", "list", "compare", "dump-symbols", "diagnostics"]
+    )]
+    pub sections: bool,
+
+    /// Print the versions and features of the libraries that cargo-disasm was built
+    /// with and what was found in the binary (architecture, debug information and
+    /// symbols) instead of disassembling anything. Include this in bug reports. The
+    /// symbol name is not used, so the first positional argument is the binary's path.
+    #[clap(
+        long = "diagnostics",
+        conflicts_with_all = &["all", "entry", "list", "compare", "dump-symbols", "follow-calls"]
+    )]
+    pub diagnostics: bool,
 
     /// Path of the binary to disassemble. This can be left unspecified if the
     /// Cargo options are going to be used instead or if the current directory
@@ -17,8 +142,8 @@ pub struct Opts {
     /// By default this is `auto`.
     ///
     /// Possible values are: auto, dwarf, pdb, elf, pe, mach, archive,
-    /// obj (elf + pe + mach + archive), debug (dwarf + pdb),
-    /// all (use everything)
+    /// export (dynamic symbol tables), obj (elf + pe + mach + archive + export),
+    /// debug (dwarf + pdb), all (use everything)
     #[clap(
         long = "symsrc",
         multiple = true,
@@ -27,6 +152,60 @@ pub struct Opts {
     )]
     pub symbol_sources: Vec<String>,
 
+    /// With `--symsrc=auto`, the debug information is loaded first and then the
+    /// symbol tables of the object file, one source after another while fewer
+    /// than this many symbols have been found. Defaults to 131072.
+    #[clap(long = "auto-symbol-threshold", value_name = "N")]
+    pub auto_symbol_threshold: Option<usize>,
+
+    /// Also load functions from DWARF debug information that don't have any code
+    /// (declarations and functions that were only inlined). These can only be
+    /// shown by `--list --kind inlined` or `--list --kind all`.
+    #[clap(long = "dwarf-declarations")]
+    pub dwarf_declarations: bool,
+
+    /// Do not read or write the symbol cache. The symbols of a binary are cached
+    /// in `$CARGO_TARGET_DIR/disasm-cache` or the user's cache directory, and are
+    /// loaded again until the binary changes.
+    #[clap(long = "no-symbol-cache")]
+    pub no_symbol_cache: bool,
+
+    /// The demangler used for symbol names. By default this is `auto`, which
+    /// picks a demangler for each symbol based on its mangling scheme.
+    ///
+    /// Possible values are: auto, rust, cpp, none
+    #[clap(long = "demangle", default_value = "auto")]
+    pub demangle: Demangle,
+
+    /// The architecture of the slice to disassemble from a fat (universal) Mach-O binary.
+    /// By default the host architecture is used if it is present, otherwise the first slice.
+    ///
+    /// Possible values are: x86, x86_64, arm, arm64
+    #[clap(long = "mach-arch")]
+    pub mach_arch: Option<Arch>,
+
+    /// Path to the dSYM bundle of a Mach-O binary, or to the DWARF object file inside of it.
+    /// By default a dSYM bundle next to the binary is used if there is one.
+    #[clap(long = "dsym", visible_alias = "dsym-path")]
+    pub dsym: Option<PathBuf>,
+
+    /// Path to an object file with the DWARF debug information of an ELF or Mach-O
+    /// binary, like one created by `objcopy --only-keep-debug`. It is used instead of
+    /// the binary's own debug information.
+    #[clap(long = "dwarf-path", value_name = "PATH")]
+    pub dwarf_path: Option<PathBuf>,
+
+    /// Path to the PDB of a PE binary, instead of the PDB that the binary refers to.
+    #[clap(long = "pdb-path", value_name = "PATH")]
+    pub pdb_path: Option<PathBuf>,
+
+    /// Load the symbols and debug information from this file instead of the binary,
+    /// like the unstripped ELF of a stripped binary or a flashed raw image. Symbols are
+    /// matched to the binary by their address, so both must be linked at the same
+    /// addresses. The PDB of a PE binary is passed with --pdb-path instead.
+    #[clap(long = "symbols-from", value_name = "PATH")]
+    pub symbols_from: Option<PathBuf>,
+
     /// Path to Cargo.toml
     #[clap(long = "manifest-path")]
     pub manifest_path: Option<PathBuf>,
@@ -41,6 +220,10 @@ pub struct Opts {
     #[clap(short = 't', long = "target")]
     pub target_name: Option<String>,
 
+    /// Run `cargo metadata` without accessing the network.
+    #[clap(long = "offline")]
+    pub offline: bool,
+
     /// Disassemble the release mode build artifacts.
     #[clap(long = "release")]
     pub release: bool,
@@ -54,20 +237,213 @@ pub struct Opts {
     #[clap(short, long)]
     pub quiet: bool,
 
-    /// Coloring: auto, always, never, and always-ansi (only uses ansi color codes).
+    /// Print how long parsing the binary, sorting its symbols, searching for the
+    /// symbol and disassembling it took to stderr after the output.
+    #[clap(long = "timings")]
+    pub timings: bool,
+
+    /// Coloring: auto, always, never, and always-ansi (only uses ansi color codes, even
+    /// on Windows consoles, which is useful when the output is redirected to a log).
+    /// `auto` respects the NO_COLOR, CLICOLOR_FORCE, CLICOLOR and TERM environment variables.
     #[clap(long = "color", default_value = "auto", parse(try_from_str = parse_colorchoice))]
     pub color_choice: ColorChoice,
 
+    /// Writes the output to this file instead of stdout, replacing the file if it
+    /// already exists. The file is only colored with `--color always` or
+    /// `--color always-ansi`.
+    #[clap(long = "output-file", value_name = "PATH")]
+    pub output_file: Option<PathBuf>,
+
+    /// The runtime address that the binary was loaded at, for position independent
+    /// executables. This is added to every symbol's address, so printed addresses
+    /// match the addresses of a running process.
+    #[clap(long = "base", parse(try_from_str = parse_address))]
+    pub base: Option<u64>,
+
+    /// Only disassemble the instructions of the symbol starting at this address.
+    /// When `--base` is used, this is a runtime address and the base is subtracted
+    /// from it to find the instruction in the binary.
+    #[clap(long = "start-at", parse(try_from_str = parse_address))]
+    pub start_at: Option<u64>,
+
+    /// Only disassemble the instructions of the symbol before this address.
+    /// When `--base` is used, this is a runtime address like `--start-at`.
+    #[clap(long = "stop-at", parse(try_from_str = parse_address))]
+    pub stop_at: Option<u64>,
+
+    /// The maximum number of instructions to disassemble. This stops symbols
+    /// with bogus sizes from disassembling huge parts of the binary.
+    #[clap(long = "max-instructions", default_value = "100000")]
+    pub max_instructions: usize,
+
+    /// Only disassemble the first N instructions of the symbol, or of the
+    /// instructions after `--start-at`, e.g. to look at the next few instructions
+    /// at an address without disassembling the whole function around it.
+    #[clap(short = 'n', long = "count", value_name = "N")]
+    pub count: Option<usize>,
+
+    /// The format used to print the disassembly: text, gas for assembly that can
+    /// be reassembled by the GNU assembler, or plain for one tab separated line per
+    /// instruction that is printed as soon as it is disassembled. Plain output always
+    /// uses a linear sweep and doesn't fold anything.
+    #[clap(long = "format", default_value = "text")]
+    pub format: OutputFormat,
+
+    /// The assembly syntax: intel, att or masm for x86, or noregname to print
+    /// registers as numbers on ARM and PowerPC. By default this is Capstone's
+    /// default syntax for the architecture.
+    #[clap(long = "syntax", parse(try_from_str = parse_syntax))]
+    pub syntax: Option<Syntax>,
+
     /// If it is available, show source code alongside disassembly.
     #[clap(short = 'S', long = "show-source")]
     pub show_source: bool,
 
-    /// Show the bytes for each opcode alongside disassembly.
-    #[clap(short = 'B', long = "show-bytes")]
-    pub show_bytes: bool,
+    /// Show source code like `objdump -S`: each source line is shown once above the
+    /// run of instructions that were generated from it, instead of above every
+    /// instruction. Implies --show-source.
+    #[clap(long = "interleave-source")]
+    pub interleave_source: bool,
+
+    /// How the path of a source file is shown above its lines with --show-source:
+    /// full, relative (to the workspace root), name (only the file name) or none.
+    /// By default source lines are shown without their paths, and the declaration
+    /// of a symbol in its header is relative to the workspace root.
+    #[clap(long = "source-paths", value_name = "STYLE")]
+    pub source_paths: Option<SourcePaths>,
+
+    /// Which source lines are shown with --show-source for inlined code: the line in
+    /// the inlined function (innermost), the call in the function that it was inlined
+    /// into (outermost), or all of them including the calls in between (all).
+    #[clap(long = "source", value_name = "MAPPINGS", default_value = "innermost")]
+    pub source_mappings: SourceMappings,
+
+    /// Show which condition flags are modified, tested, set, reset or left
+    /// undefined by each instruction in a comment. Only supported for x86.
+    #[clap(long = "show-flags")]
+    pub show_flags: bool,
+
+    /// Show how each instruction is encoded (prefixes, ModR/M, SIB, displacement
+    /// and immediate) in a comment. Only supported for x86.
+    #[clap(long = "show-encoding")]
+    pub show_encoding: bool,
+
+    /// Show the immediate operands that are printable ASCII as characters in a
+    /// comment, e.g. `'A'` for `0x41` or `"Hello"` for `0x6f6c6c6548`. Only
+    /// supported for x86.
+    #[clap(long = "ascii-immediates")]
+    pub ascii_immediates: bool,
+
+    /// Color registers, immediates and memory operands differently. The kinds of
+    /// operands come from Capstone for x86 and are guessed for other architectures.
+    #[clap(long = "rich-operands")]
+    pub rich_operands: bool,
+
+    /// Keep the addresses of jump and call targets as operands and show the
+    /// symbol they point to in a comment, instead of replacing the address with
+    /// the symbol.
+    #[clap(long = "raw-targets")]
+    pub raw_targets: bool,
+
+    /// Leave the operands of jumps and calls the way Capstone printed them instead
+    /// of replacing their targets with symbols. This is faster for large dumps and
+    /// shows the instructions before cargo-disasm rewrites them.
+    #[clap(long = "no-jump-symbolication", conflicts_with = "raw-targets")]
+    pub no_jump_symbolication: bool,
+
+    /// How instructions are found: linear disassembles every byte of the function,
+    /// recursive only disassembles the instructions that are reachable by following
+    /// branches from the start of the function and shows the rest as data.
+    /// Recursive traversal is supported for x86 and PowerPC.
+    #[clap(long = "traversal", default_value = "linear")]
+    pub traversal: Traversal,
+
+    /// Fold the standard prologue at the start of the function into a single
+    /// `; prologue (N instructions)` line, and the epilogue before each return into
+    /// a `; epilogue (N instructions)` line. Only supported for x86 and arm64. Run
+    /// without this option to see the folded instructions.
+    #[clap(long = "skip-prologue")]
+    pub skip_prologue: bool,
+
+    /// Show each instruction of the `int3`, `nop` or zero bytes that pad the end
+    /// of a function, instead of a single `; padding (N bytes)` line.
+    #[clap(long = "no-trim-padding")]
+    pub no_trim_padding: bool,
+
+    /// Do not show comments after instructions, such as the addresses of
+    /// jump targets.
+    #[clap(long = "no-comments")]
+    pub no_comments: bool,
+
+    /// The column that comments start at. By default comments are aligned
+    /// after the longest operands of the symbol.
+    #[clap(long = "comment-column", value_name = "N")]
+    pub comment_column: Option<usize>,
+
+    /// Only show the instructions in a Capstone instruction group, e.g. `jump`,
+    /// `call`, `ret`, `int`, `privilege`, `branch-relative` or an architecture
+    /// specific group like x86's `vm` or `sse2`. The addresses are still shown.
+    #[clap(long = "only-group", value_name = "GROUP")]
+    pub only_group: Option<String>,
+
+    /// Show the number of instructions and bytes after the disassembly, and the
+    /// number of CET landing pads (`endbr32`/`endbr64`) in x86 functions.
+    #[clap(long = "stats")]
+    pub stats: bool,
+
+    /// How addresses are displayed: lowercase hex (`hex`), lowercase hex with
+    /// a `0x` prefix (`hex-prefixed`), uppercase hex (`HEX`), or decimal (`dec`).
+    #[clap(long = "addr-format", value_name = "FORMAT", default_value = "hex")]
+    pub addr_format: AddressFormat,
+
+    /// Show the address of each line, and the targets of jumps inside of the symbol,
+    /// as offsets from the start of the symbol (`+0x1f`). The output doesn't change
+    /// when a function is linked at a different address, which makes it easier to diff.
+    #[clap(long = "relative-addresses", conflicts_with = "raw-targets")]
+    pub relative_addresses: bool,
+
+    /// Show the bytes for each opcode alongside disassembly: grouped (`48 89 e5`),
+    /// which is the default if no format is given, or packed (`4889e5`).
+    #[clap(
+        short = 'B',
+        long = "bytes",
+        alias = "show-bytes",
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "grouped"
+    )]
+    pub bytes: Option<BytesFormat>,
+
+    /// Do not show the bytes for each opcode. This is the default.
+    #[clap(long = "no-bytes", overrides_with = "bytes")]
+    pub no_bytes: bool,
+
+    /// The maximum number of bytes shown for each opcode. Longer opcodes are
+    /// truncated with an ellipsis.
+    #[clap(long = "max-bytes")]
+    pub max_bytes: Option<usize>,
 }
 
 impl Opts {
+    /// The name of the symbol to match, or an empty string if there is none.
+    pub fn symbol(&self) -> &str {
+        self.symbol.as_deref().unwrap_or("")
+    }
+
+    /// Modes that don't use a symbol name take the binary's path as the
+    /// first positional argument instead.
+    pub fn resolve_positionals(&mut self) {
+        let no_symbol = self.dump_symbols.is_some()
+            || self.sections
+            || self.diagnostics
+            || self.all
+            || self.entry
+            || self.raw;
+        if no_symbol && self.binary_path.is_none() {
+            self.binary_path = self.symbol.take().map(PathBuf::from);
+        }
+    }
+
     pub fn log_level_filter(&self) -> log::LevelFilter {
         use log::LevelFilter;
 
@@ -84,6 +460,38 @@ impl Opts {
     }
 }
 
+/// Parses a hexadecimal address prefixed with `0x`, or a decimal address.
+pub fn parse_address(s: &str) -> Result<u64, String> {
+    let parsed = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else {
+        s.parse::<u64>()
+    };
+    parsed.map_err(|err| format!("`{}` is not a valid address: {}", s, err))
+}
+
+pub fn parse_bits(s: &str) -> Result<Bits, String> {
+    match s {
+        "32" => Ok(Bits::Bits32),
+        "64" => Ok(Bits::Bits64),
+        _ => Err(format!("{} is not a valid word size, expected 32 or 64", s)),
+    }
+}
+
+pub fn parse_syntax(s: &str) -> Result<Syntax, String> {
+    if s.eq_ignore_ascii_case("intel") {
+        Ok(Syntax::Intel)
+    } else if s.eq_ignore_ascii_case("att") || s.eq_ignore_ascii_case("at&t") {
+        Ok(Syntax::Att)
+    } else if s.eq_ignore_ascii_case("masm") {
+        Ok(Syntax::Masm)
+    } else if s.eq_ignore_ascii_case("noregname") {
+        Ok(Syntax::NoRegName)
+    } else {
+        Err(format!("{} is not a valid syntax", s))
+    }
+}
+
 pub fn parse_colorchoice(s: &str) -> Result<ColorChoice, String> {
     if s.eq_ignore_ascii_case("auto") {
         Ok(ColorChoice::Auto)
@@ -0,0 +1,78 @@
+use crate::disasm::binary::Binary;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// Every architecture that Capstone knows about, including the ones that
+/// cargo-disasm doesn't support yet.
+const CAPSTONE_ARCHS: [capstone::Arch; 13] = [
+    capstone::Arch::Arm,
+    capstone::Arch::Arm64,
+    capstone::Arch::Mips,
+    capstone::Arch::X86,
+    capstone::Arch::PowerPc,
+    capstone::Arch::Sparc,
+    capstone::Arch::SystemZ,
+    capstone::Arch::XCore,
+    capstone::Arch::M68K,
+    capstone::Arch::Tms320C64X,
+    capstone::Arch::M680X,
+    capstone::Arch::Evm,
+    capstone::Arch::Mos65xx,
+];
+
+/// Writes the versions and features of the libraries that cargo-disasm was
+/// built with and what was found in `binary`, for bug reports.
+pub fn print_diagnostics(out: &mut dyn Write, binary: &Binary) -> io::Result<()> {
+    writeln!(out, "cargo-disasm {}", env!("CARGO_PKG_VERSION"))?;
+
+    writeln!(out, "capstone {}", capstone::version())?;
+    let archs = CAPSTONE_ARCHS
+        .iter()
+        .filter(|&&arch| capstone::supports(arch))
+        .map(|arch| format!("{:?}", arch).to_lowercase())
+        .collect::<Vec<_>>();
+    writeln!(out, "  architectures: {}", archs.join(", "))?;
+    writeln!(
+        out,
+        "  diet: {}",
+        yes_no(capstone::supports(capstone::SupportQuery::Diet))
+    )?;
+    writeln!(
+        out,
+        "  x86 reduce: {}",
+        yes_no(capstone::supports(capstone::SupportQuery::X86Reduce))
+    )?;
+
+    writeln!(out, "binary {}", binary.path().display())?;
+    writeln!(out, "  arch: {}", binary.arch())?;
+    writeln!(out, "  endian: {}", binary.endian())?;
+    if binary.code_endian() != binary.endian() {
+        writeln!(out, "  code endian: {}", binary.code_endian())?;
+    }
+    writeln!(out, "  bits: {}", binary.bits())?;
+    match binary.entry_point() {
+        Some(entry) => writeln!(out, "  entry point: 0x{:x}", entry)?,
+        None => writeln!(out, "  entry point: none")?,
+    }
+    writeln!(out, "  dwarf: {}", yes_no(binary.has_dwarf()))?;
+    writeln!(out, "  pdb: {}", yes_no(binary.has_pdb()))?;
+
+    let mut sources = BTreeMap::new();
+    for symbol in binary.symbols() {
+        *sources.entry(symbol.source()).or_insert(0usize) += 1;
+    }
+    writeln!(out, "  symbols: {}", binary.symbols().len())?;
+    for (source, count) in sources {
+        writeln!(out, "    {}: {}", source, count)?;
+    }
+
+    Ok(())
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
@@ -0,0 +1,100 @@
+use crate::disasm::binary::Binary;
+use crate::disasm::source::SourceMappings;
+use crate::disasm::symbol::{Symbol, SymbolKind};
+use serde::Serialize;
+use std::io::Write;
+
+/// The version of the schema written by `--dump-symbols json`. This is increased
+/// whenever a field is removed or its meaning changes, but not when fields are added.
+pub const SYMBOLS_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum SymbolDumpFormat {
+    Json,
+}
+
+impl std::str::FromStr for SymbolDumpFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("json") {
+            Ok(SymbolDumpFormat::Json)
+        } else {
+            Err("invalid symbol dump format")
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonSymbol<'s> {
+    name: &'s str,
+    /// `None` if the name is not mangled.
+    demangled_name: Option<&'s str>,
+    address: u64,
+    size: usize,
+    source: String,
+    kind: &'static str,
+    /// The source file and line of the first instruction, if there is line information.
+    file: Option<String>,
+    line: Option<u32>,
+}
+
+impl<'s> JsonSymbol<'s> {
+    fn new(binary: &Binary, symbol: &'s Symbol) -> anyhow::Result<Self> {
+        let (file, line) = match symbol.kind() {
+            SymbolKind::Function => binary
+                .addr2line(symbol.address())?
+                .and_then(|lines| SourceMappings::Innermost.select(lines).pop())
+                .map(|location| {
+                    (
+                        Some(location.path().display().to_string()),
+                        Some(location.line()),
+                    )
+                })
+                .unwrap_or((None, None)),
+            SymbolKind::Declaration => (None, None),
+        };
+
+        Ok(JsonSymbol {
+            name: symbol.name(),
+            demangled_name: Some(symbol.display_name()).filter(|&name| name != symbol.name()),
+            address: symbol.address(),
+            size: symbol.size(),
+            source: symbol.source().to_string(),
+            kind: match symbol.kind() {
+                SymbolKind::Function => "function",
+                SymbolKind::Declaration => "declaration",
+            },
+            file,
+            line,
+        })
+    }
+}
+
+/// Writes all of the symbols in `binary`. Symbols are written one at a time so that
+/// the output for large binaries is never held in memory.
+pub fn dump_symbols(
+    out: &mut dyn Write,
+    binary: &Binary,
+    format: SymbolDumpFormat,
+) -> anyhow::Result<()> {
+    match format {
+        SymbolDumpFormat::Json => {
+            write!(
+                out,
+                "{{\"schema_version\":{},\"symbols\":[",
+                SYMBOLS_SCHEMA_VERSION
+            )?;
+            for (idx, symbol) in binary.symbols().iter().enumerate() {
+                if idx > 0 {
+                    write!(out, ",")?;
+                }
+                writeln!(out)?;
+                serde_json::to_writer(&mut *out, &JsonSymbol::new(binary, symbol)?)?;
+            }
+            writeln!(out, "\n]}}")?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,22 +1,63 @@
 pub mod cli;
+mod diagnostics;
+mod dump;
 pub mod logging;
 mod printer;
+mod progress;
 
 use crate::disasm::{
     self,
-    binary::{Binary, BinaryData, SearchOptions},
-    symbol::SymbolSource,
+    binary::{Arch, Binary, BinaryData, SearchOptions},
+    strmatch::{self, Tokenizer},
+    symbol::{Symbol, SymbolKind, SymbolSource},
+    DisasmError,
 };
 use anyhow::Context as _;
 use clap::Parser as _;
 use cli::Opts;
 use logging::AppLogger;
-use std::path::PathBuf;
-use termcolor::ColorChoice;
-use termcolor::StandardStream;
+use printer::OutputFormat;
+use progress::{Spinner, Timings};
+use regex::Regex;
+use std::collections::HashSet;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use termcolor::{Ansi, ColorChoice, NoColor, StandardStream, WriteColor};
+
+/// The most functions that are disassembled by `--follow-calls`, so that following
+/// the calls of a large function doesn't print most of the binary.
+const MAX_FOLLOWED_CALLS: usize = 64;
+
+/// Set by the Ctrl-C handler while the binary is being loaded.
+static CANCEL_LOAD: AtomicBool = AtomicBool::new(false);
+
+/// Set while Ctrl-C should set [`CANCEL_LOAD`] instead of terminating the process.
+static CANCEL_ON_INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+/// Makes Ctrl-C set [`CANCEL_LOAD`] instead of terminating the process, or restores
+/// the usual behavior. The handler is installed the first time that this is called.
+fn cancel_load_on_interrupt(enabled: bool) {
+    static INSTALL_HANDLER: Once = Once::new();
+    INSTALL_HANDLER.call_once(|| {
+        let result = ctrlc::set_handler(|| {
+            if CANCEL_ON_INTERRUPT.load(Ordering::Relaxed) {
+                CANCEL_LOAD.store(true, Ordering::Relaxed);
+            } else {
+                // The exit status of a process that was killed by SIGINT.
+                std::process::exit(130);
+            }
+        });
+        if let Err(err) = result {
+            log::debug!("failed to install the Ctrl-C handler: {}", err);
+        }
+    });
+    CANCEL_ON_INTERRUPT.store(enabled, Ordering::Relaxed);
+}
 
 fn parse_options() -> Opts {
-    if std::env::var("CARGO").is_ok() {
+    let mut opts = if std::env::var("CARGO").is_ok() {
         let mut args = std::env::args_os().collect::<Vec<_>>();
         if args.len() > 2 && args[1] == "disasm" {
             log::trace!("this is being run as a cargo subcommand");
@@ -27,42 +68,72 @@ fn parse_options() -> Opts {
         }
     } else {
         Opts::parse()
-    }
+    };
+    opts.resolve_positionals();
+    opts
 }
 
 pub fn run() -> anyhow::Result<()> {
     let opts = parse_options();
 
     unsafe { AppLogger::instance().set_level(opts.log_level_filter()) };
-    let color_choice = match opts.color_choice {
-        ColorChoice::Auto => unsafe {
-            let out_choice = if atty::is(atty::Stream::Stdout) {
-                ColorChoice::Always
-            } else {
-                ColorChoice::Never
+    let (color_choice, err_color_choice) = match opts.color_choice {
+        ColorChoice::Auto => {
+            let env_choice = color_choice_from_env();
+            let choose = |stream| {
+                env_choice.unwrap_or_else(|| {
+                    if atty::is(stream) {
+                        ColorChoice::Always
+                    } else {
+                        ColorChoice::Never
+                    }
+                })
             };
-            AppLogger::instance().set_color_choice_out(out_choice);
+            (choose(atty::Stream::Stdout), choose(atty::Stream::Stderr))
+        }
+        choice => (choice, choice),
+    };
+    unsafe {
+        AppLogger::instance().set_color_choice_out(color_choice);
+        AppLogger::instance().set_color_choice_err(err_color_choice);
+    }
 
-            AppLogger::instance().set_color_choice_err(if atty::is(atty::Stream::Stderr) {
-                ColorChoice::Always
-            } else {
-                ColorChoice::Never
-            });
+    // The spinners share stderr with the logs, so they follow the same settings and
+    // are never written into a file or pipe.
+    progress::set_enabled(
+        !opts.quiet && atty::is(atty::Stream::Stderr) && err_color_choice != ColorChoice::Never,
+    );
 
-            out_choice
-        },
+    // Check the syntax before spending time loading the binary.
+    if opts.syntax == Some(capstone::Syntax::Masm) && opts.format == OutputFormat::Gas {
+        return Err(anyhow::anyhow!(
+            "MASM syntax cannot be used with the gas output format"
+        ));
+    }
 
-        choice => unsafe {
-            AppLogger::instance().set_color_choice_out(choice);
-            AppLogger::instance().set_color_choice_err(choice);
-            choice
-        },
-    };
+    if opts.compare.is_some() && opts.format != OutputFormat::Text {
+        return Err(anyhow::anyhow!(
+            "the {} output format cannot be used with --compare",
+            opts.format.name()
+        ));
+    }
+
+    if opts.follow_calls.is_some() && opts.format != OutputFormat::Text {
+        return Err(anyhow::anyhow!(
+            "the {} output format cannot be used with --follow-calls",
+            opts.format.name()
+        ));
+    }
 
-    let binary_path = find_binary_path(&opts)?;
+    if opts.all && opts.format != OutputFormat::Text {
+        return Err(anyhow::anyhow!(
+            "the {} output format cannot be used with --all",
+            opts.format.name()
+        ));
+    }
+
+    let (binary_path, workspace_root) = find_binary_path(&opts)?;
     log::debug!("using binary {}", binary_path.display());
-    let data = BinaryData::from_path(&binary_path)
-        .with_context(|| format!("failed to load binary `{}`", binary_path.display()))?;
     let mut sources = Vec::new();
     for s in opts.symbol_sources.iter() {
         if s.eq_ignore_ascii_case("all") {
@@ -71,6 +142,7 @@ pub fn run() -> anyhow::Result<()> {
             sources.push(SymbolSource::Mach);
             sources.push(SymbolSource::Pe);
             sources.push(SymbolSource::Archive);
+            sources.push(SymbolSource::Export);
 
             // debug formats
             sources.push(SymbolSource::Dwarf);
@@ -88,11 +160,14 @@ pub fn run() -> anyhow::Result<()> {
             sources.push(SymbolSource::Pe);
         } else if s.eq_ignore_ascii_case("archive") {
             sources.push(SymbolSource::Archive);
+        } else if s.eq_ignore_ascii_case("export") {
+            sources.push(SymbolSource::Export);
         } else if s.eq_ignore_ascii_case("obj") {
             sources.push(SymbolSource::Elf);
             sources.push(SymbolSource::Mach);
             sources.push(SymbolSource::Pe);
             sources.push(SymbolSource::Archive);
+            sources.push(SymbolSource::Export);
         } else if s.eq_ignore_ascii_case("dwarf") {
             sources.push(SymbolSource::Dwarf);
         } else if s.eq_ignore_ascii_case("pdb") {
@@ -107,47 +182,601 @@ pub fn run() -> anyhow::Result<()> {
     sources.sort_unstable();
     sources.dedup();
 
+    let symbol_cache = if opts.no_symbol_cache {
+        None
+    } else {
+        symbol_cache_dir()
+    };
     let search_options = SearchOptions {
         sources: &sources,
-        dwarf_path: None,
-        dsym_path: None,
-        pdb_path: None,
+        auto_sources_threshold: opts
+            .auto_symbol_threshold
+            .unwrap_or(disasm::binary::AUTO_SOURCES_THRESHOLD),
+        demangle: opts.demangle,
+        dwarf_path: opts.dwarf_path.as_deref(),
+        dsym_path: opts.dsym.as_deref(),
+        pdb_path: opts.pdb_path.as_deref(),
+        mach_arch: opts.mach_arch,
+        cancel: Some(&CANCEL_LOAD),
+        dwarf_declarations: opts.dwarf_declarations,
+        symbol_cache: symbol_cache.as_deref(),
+    };
+
+    let arch = opts
+        .arch
+        .map(|arch| opts.bits.map_or(arch, |bits| arch.with_bits(bits)));
+    let mut bin = match arch {
+        Some(arch) if opts.raw => {
+            let data = BinaryData::from_path(&binary_path)
+                .with_context(|| format!("failed to load binary `{}`", binary_path.display()))?;
+            Binary::raw(data, arch)
+        }
+        _ => load_binary(&binary_path, search_options)?,
+    };
+    let mut timings = Timings::default();
+    timings.record("parse", bin.load_timings().parse);
+    timings.record("sort", bin.load_timings().sort);
+    match arch {
+        Some(arch) if !opts.raw && arch != bin.arch() => {
+            if bin.arch() != Arch::Unknown {
+                log::warn!(
+                    "disassembling as {} instead of the detected architecture {}",
+                    arch,
+                    bin.arch()
+                );
+            }
+            bin.set_arch(arch);
+        }
+        _ => {}
+    }
+    if let Some(base) = opts.base {
+        bin.set_base(base);
+    }
+    if let Some(ref path) = opts.pdb_path {
+        if !bin.has_pdb() {
+            log::warn!(
+                "`{}` was not used because PDBs are only used for PE binaries",
+                path.display()
+            );
+        }
+    }
+    if let Some(ref path) = opts.symbols_from {
+        import_symbols(&mut bin, path, search_options)?;
+    }
+
+    let mut out = open_output(&opts, &binary_path, color_choice)?;
+    print_output(
+        &mut *out,
+        &opts,
+        bin,
+        &binary_path,
+        workspace_root,
+        search_options,
+        &mut timings,
+    )?;
+    out.flush().with_context(|| match opts.output_file {
+        Some(ref path) => format!("failed to write output file `{}`", path.display()),
+        None => String::from("error occured while writing output"),
+    })?;
+
+    if opts.timings {
+        timings
+            .print(&mut std::io::stderr())
+            .context("failed to print timings")?;
+    }
+    Ok(())
+}
+
+/// Opens the file passed with `--output-file`, or stdout if there is none. Files
+/// are only colored with `--color always` or `--color always-ansi`, because
+/// `--color auto` is meant for terminals.
+fn open_output(
+    opts: &Opts,
+    binary_path: &Path,
+    color_choice: ColorChoice,
+) -> anyhow::Result<Box<dyn WriteColor>> {
+    let path = match opts.output_file {
+        Some(ref path) => path,
+        None => return Ok(Box::new(StandardStream::stdout(color_choice))),
     };
-    let mut bin = Binary::new(data, search_options)?;
 
-    if opts.show_source {
+    // Creating the file truncates it, which would destroy a binary that is being read.
+    if let Ok(output) = path.canonicalize() {
+        let inputs = std::iter::once(binary_path).chain(opts.compare.as_deref());
+        for input in inputs {
+            if input.canonicalize().ok().as_ref() == Some(&output) {
+                return Err(anyhow::anyhow!(
+                    "the output file `{}` is a binary being disassembled",
+                    path.display()
+                ));
+            }
+        }
+    }
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("failed to create output file `{}`", path.display()))?;
+    let file = std::io::BufWriter::new(file);
+    match opts.color_choice {
+        ColorChoice::Always | ColorChoice::AlwaysAnsi => Ok(Box::new(Ansi::new(file))),
+        _ => Ok(Box::new(NoColor::new(file))),
+    }
+}
+
+/// Prints the output selected by the options for the loaded binary.
+fn print_output(
+    mut out: &mut dyn WriteColor,
+    opts: &Opts,
+    mut bin: Binary,
+    binary_path: &Path,
+    workspace_root: Option<PathBuf>,
+    search_options: SearchOptions,
+    timings: &mut Timings,
+) -> anyhow::Result<()> {
+    if opts.diagnostics {
+        return diagnostics::print_diagnostics(&mut out, &bin)
+            .context("error occured while printing diagnostics");
+    }
+
+    if let Some(format) = opts.dump_symbols {
         bin.load_line_information()?;
+        let mut out = std::io::BufWriter::new(out);
+        dump::dump_symbols(&mut out, &bin, format)
+            .context("error occured while dumping symbols")?;
+        return out.flush().context("error occured while dumping symbols");
+    }
+
+    if opts.sections {
+        return printer::print_section_list(out, bin.sections(), opts.addr_format)
+            .context("error occured while printing sections");
     }
 
+    if opts.list {
+        return list_symbols(out, &bin, opts);
+    }
+
+    // Interleaving source lines implies showing them.
+    let show_source = opts.show_source || opts.interleave_source;
+    if show_source {
+        let _spinner = Spinner::start(String::from("loading line information"));
+        timings.time("lines", || bin.load_line_information())?;
+    }
+
+    let mut disasm_options = disasm::DisasmOptions {
+        start_at: opts.start_at,
+        stop_at: opts.stop_at,
+        load_source: show_source,
+        source_mappings: opts.source_mappings,
+        show_flags: opts.show_flags,
+        show_encoding: opts.show_encoding,
+        ascii_immediates: opts.ascii_immediates,
+        max_instructions: Some(opts.max_instructions),
+        count: opts.count,
+        syntax: disasm::select_syntax(&bin, opts.syntax)?,
+        raw_targets: opts.raw_targets,
+        symbolicate_jumps: !opts.no_jump_symbolication,
+        relative_targets: opts.relative_addresses,
+        address_format: opts.addr_format,
+        traversal: opts.traversal,
+        rich_operands: opts.rich_operands,
+        fold_prologue: opts.skip_prologue,
+        trim_padding: !opts.no_trim_padding,
+    };
+    let only_group = opts
+        .only_group
+        .as_deref()
+        .map(|name| disasm::find_group(&bin, name))
+        .transpose()?;
+    // Without a workspace, paths are shown relative to the working directory.
+    let source_root = workspace_root.or_else(|| std::env::current_dir().ok());
+    let print_options = printer::DisasmOptions {
+        show_source,
+        interleave_source: opts.interleave_source,
+        source_paths: opts.source_paths,
+        source_root: source_root.as_deref(),
+        bytes: if opts.no_bytes { None } else { opts.bytes },
+        max_bytes: opts.max_bytes,
+        show_comments: !opts.no_comments,
+        comment_column: opts.comment_column,
+        show_stats: opts.stats,
+        only_group,
+        address_format: opts.addr_format,
+        relative_addresses: opts.relative_addresses,
+    };
+
+    if opts.all {
+        // The functions are merged into one disassembly that is printed at the end,
+        // so this also includes printing it.
+        return timings
+            .time("disasm", || {
+                disasm_all(out, &bin, &disasm_options, print_options)
+            })
+            .context("error occured while printing disassembly");
+    }
+
+    let search_timer = std::time::Instant::now();
     // FIXME temporary test code
-    if let Some(symbol) = bin.fuzzy_find_symbol(&opts.symbol) {
-        let disassembly = disasm::disasm(&bin, symbol, opts.show_source)?;
-        let mut stdout = StandardStream::stdout(color_choice);
-        printer::print_disassembly(
-            &mut stdout,
-            symbol,
-            &disassembly,
-            printer::DisasmOptions {
-                show_source: opts.show_source,
-                show_bytes: opts.show_bytes,
-            },
-        )
-        .context("error occured while printing disassembly")?;
+    let symbol = if opts.raw && (opts.symbols_from.is_none() || opts.symbol.is_none()) {
+        bin.entry_symbol()
+    } else if opts.entry {
+        let entry = bin
+            .entry_point()
+            .ok_or_else(|| anyhow::anyhow!("the binary does not have an entry point"))?;
+        log::debug!("entry point at 0x{:x}", entry);
+        match bin.symbolicate(entry) {
+            Some((symbol, offset)) => {
+                if offset != 0 {
+                    disasm_options.start_at = Some(entry);
+                }
+                Some(symbol)
+            }
+            None => bin.entry_symbol(),
+        }
+    } else if opts.exact {
+        bin.find_symbol_exact(opts.symbol())
     } else {
+        // A full mangled name can be a close fuzzy match for other monomorphizations.
+        bin.find_symbol_exact(opts.symbol())
+            .or_else(|| bin.fuzzy_find_symbol(opts.symbol()))
+    };
+    timings.record("search", search_timer.elapsed());
+
+    if let Some(symbol) = symbol {
+        if opts.format == OutputFormat::Plain {
+            let mut stream = disasm::disasm_streaming(&bin, symbol, &disasm_options)?;
+            return timings
+                .time("disasm", || {
+                    printer::print_stream(out, symbol, stream.lines(), print_options)
+                })
+                .context("error occured while printing disassembly");
+        }
+
+        let disassembly = {
+            let _spinner = Spinner::start(format!("disassembling `{}`", symbol.display_name()));
+            timings.time("disasm", || disasm::disasm(&bin, symbol, &disasm_options))?
+        };
+
+        if let Some(ref other_path) = opts.compare {
+            // The debug information passed with the options belongs to the first binary.
+            let other_options = SearchOptions {
+                dwarf_path: None,
+                dsym_path: None,
+                pdb_path: None,
+                ..search_options
+            };
+            let other = load_binary(other_path, other_options)?;
+            let other_symbol = other.find_symbol_exact(symbol.name()).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "no symbol named `{}` was found in `{}`",
+                    symbol.display_name(),
+                    other_path.display()
+                )
+            })?;
+            let other_disassembly = disasm::disasm(&other, other_symbol, &disasm_options)?;
+
+            let diff = disasm::diff::diff(&disassembly, &other_disassembly);
+            return printer::print_diff(
+                out,
+                printer::DiffSide {
+                    path: binary_path,
+                    symbol,
+                    disassembly: &disassembly,
+                },
+                printer::DiffSide {
+                    path: other_path,
+                    symbol: other_symbol,
+                    disassembly: &other_disassembly,
+                },
+                &diff,
+                opts.addr_format,
+                opts.relative_addresses,
+            )
+            .context("error occured while printing diff");
+        }
+
+        if opts.format == OutputFormat::Gas {
+            write!(out, "{}", disassembly.to_gas_asm(symbol.name(), bin.arch()))
+                .context("error occured while printing disassembly")?;
+            return Ok(());
+        }
+
+        let disassembly = if let Some(depth) = opts.follow_calls {
+            // The range of the first symbol doesn't apply to the functions it calls.
+            let callee_options = disasm::DisasmOptions {
+                start_at: None,
+                stop_at: None,
+                count: None,
+                ..disasm_options
+            };
+            append_callees(&bin, symbol, disassembly, depth, &callee_options)
+        } else {
+            disassembly
+        };
+
+        let aliases = bin.aliases(symbol);
+        printer::print_disassembly(out, symbol, &aliases, &disassembly, print_options)
+            .context("error occured while printing disassembly")?;
+    } else {
+        return Err(DisasmError::SymbolNotFound(opts.symbol().to_string()).into());
+    }
+
+    Ok(())
+}
+
+/// Chooses colors for `--color auto` from the environment: `NO_COLOR` (with any value)
+/// disables colors, `CLICOLOR_FORCE` (not `0`) enables colors even if the output is not
+/// a terminal, and `TERM=dumb` or `CLICOLOR=0` disable colors. Returns `None` if colors
+/// should depend on whether the output is a terminal.
+fn color_choice_from_env() -> Option<ColorChoice> {
+    if std::env::var_os("NO_COLOR").is_some() {
+        return Some(ColorChoice::Never);
+    }
+
+    match std::env::var_os("CLICOLOR_FORCE") {
+        Some(force) if force != "0" => return Some(ColorChoice::Always),
+        _ => {}
+    }
+
+    // Dumb terminals don't understand escape codes even though they are terminals.
+    if std::env::var_os("TERM").is_some_and(|term| term == "dumb") {
+        return Some(ColorChoice::Never);
+    }
+
+    match std::env::var_os("CLICOLOR") {
+        Some(clicolor) if clicolor == "0" => Some(ColorChoice::Never),
+        _ => None,
+    }
+}
+
+/// Returns the directory that symbols are cached in: `disasm-cache` in
+/// `CARGO_TARGET_DIR` if it is set, or the user's cache directory.
+fn symbol_cache_dir() -> Option<PathBuf> {
+    if let Some(target_dir) = std::env::var_os("CARGO_TARGET_DIR") {
+        return Some(PathBuf::from(target_dir).join("disasm-cache"));
+    }
+    dirs::cache_dir().map(|dir| dir.join("cargo-disasm"))
+}
+
+/// Loads the binary at `path` and its symbols.
+fn load_binary(path: &Path, options: SearchOptions) -> anyhow::Result<Binary> {
+    let data = BinaryData::from_path(path)
+        .with_context(|| format!("failed to load binary `{}`", path.display()))?;
+
+    // Ctrl-C only cancels loading symbols, everything after that is quick.
+    cancel_load_on_interrupt(true);
+    let bin = {
+        let _spinner = Spinner::start(format!("loading `{}`", path.display()));
+        Binary::new(data, options)
+    };
+    cancel_load_on_interrupt(false);
+    Ok(bin?)
+}
+
+/// Loads the symbols and debug information for `bin` from the companion file passed
+/// with `--symbols-from`.
+fn import_symbols(bin: &mut Binary, path: &Path, options: SearchOptions) -> anyhow::Result<()> {
+    let is_pdb = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdb"));
+    if is_pdb {
         return Err(anyhow::anyhow!(
-            "no symbol matching `{}` was found",
-            opts.symbol
+            "`{}` is a PDB, pass it with --pdb-path instead",
+            path.display()
         ));
     }
 
+    // The debug information passed with the options belongs to the binary.
+    let companion_options = SearchOptions {
+        dwarf_path: None,
+        dsym_path: None,
+        pdb_path: None,
+        ..options
+    };
+    let companion = load_binary(path, companion_options)?;
+    let symbol_count = companion.symbols().len();
+    let dropped = bin.import_symbols(companion);
+    if dropped > symbol_count / 2 {
+        log::warn!(
+            "{} of the {} symbols from `{}` are outside of the binary, are both linked at the same addresses?",
+            dropped,
+            symbol_count,
+            path.display()
+        );
+    } else {
+        log::debug!(
+            "dropped {} symbols from `{}` that are outside of the binary",
+            dropped,
+            path.display()
+        );
+    }
+
     Ok(())
 }
 
+/// Disassembles every function in the binary in the order of their addresses,
+/// like `objdump -d`. The bytes between two functions are printed as data.
+fn disasm_all(
+    out: &mut dyn WriteColor,
+    bin: &Binary,
+    disasm_options: &disasm::DisasmOptions,
+    print_options: printer::DisasmOptions,
+) -> anyhow::Result<()> {
+    let mut symbols: Vec<&Symbol> = Vec::new();
+    for symbol in bin
+        .symbols()
+        .iter()
+        .filter(|sym| sym.kind() == SymbolKind::Function && sym.size() > 0)
+    {
+        match symbols.last() {
+            Some(prev) if symbol.address() < prev.end_address() => log::debug!(
+                "skipping {} because it overlaps {}",
+                symbol.display_name(),
+                prev.display_name()
+            ),
+            _ => symbols.push(symbol),
+        }
+    }
+
+    let mut all: Option<disasm::Disassembly> = None;
+    let mut prev: Option<&Symbol> = None;
+    let disassemblies = disasm::disasm_symbols(bin, &symbols, disasm_options);
+    for (&symbol, result) in symbols.iter().zip(disassemblies) {
+        let disassembly = match result {
+            Ok(disassembly) => disassembly,
+            Err(err) => {
+                log::warn!("failed to disassemble {}: {:#}", symbol.display_name(), err);
+                continue;
+            }
+        };
+        let all = all.get_or_insert_with(|| disasm::Disassembly::new(disassembly.syntax()));
+
+        // Symbols in different sections aren't next to each other in the file,
+        // so there are no bytes to show between them.
+        if let Some(prev) = prev {
+            let gap = (symbol.address() - prev.end_address()) as usize;
+            if gap > 0 && symbol.offset().checked_sub(prev.end()) == Some(gap) {
+                all.append_data(disasm::disasm_data(
+                    bin,
+                    prev.end_address(),
+                    prev.end(),
+                    gap,
+                ));
+            }
+        }
+
+        all.append_symbol(symbol, disassembly)
+            .set_aliases(&bin.aliases(symbol));
+        prev = Some(symbol);
+    }
+
+    if let Some(mut all) = all {
+        if disasm_options.symbolicate_jumps {
+            all.link_symbols();
+        }
+        printer::print_data(out, &all, print_options)?;
+    }
+    Ok(())
+}
+
+/// Appends the disassembly of the functions called by `symbol`, and the functions
+/// that they call, up to `depth` calls away, to `disassembly`. Functions closer to
+/// `symbol` come first and each function is only appended once.
+fn append_callees(
+    bin: &Binary,
+    symbol: &Symbol,
+    mut disassembly: disasm::Disassembly,
+    depth: usize,
+    disasm_options: &disasm::DisasmOptions,
+) -> disasm::Disassembly {
+    let mut visited = HashSet::new();
+    visited.insert(symbol.address());
+    let mut remaining = MAX_FOLLOWED_CALLS;
+
+    // Functions and their callers that are the same number of calls away from `symbol`.
+    let mut frontier = disasm::callees(bin, symbol, &disassembly)
+        .into_iter()
+        .map(|callee| (callee, symbol))
+        .collect::<Vec<_>>();
+
+    for _ in 0..depth {
+        frontier.retain(|(callee, _)| visited.insert(callee.address()));
+        if frontier.len() > remaining {
+            log::warn!(
+                "only the first {} called functions are shown",
+                MAX_FOLLOWED_CALLS
+            );
+            frontier.truncate(remaining);
+        }
+        if frontier.is_empty() {
+            break;
+        }
+        remaining -= frontier.len();
+
+        let callees = frontier
+            .iter()
+            .map(|&(callee, _)| callee)
+            .collect::<Vec<_>>();
+        let disassemblies = disasm::disasm_symbols(bin, &callees, disasm_options);
+
+        let mut next = Vec::new();
+        for (&(callee, caller), result) in frontier.iter().zip(disassemblies) {
+            let callee_disassembly = match result {
+                Ok(callee_disassembly) => callee_disassembly,
+                Err(err) => {
+                    log::warn!("failed to disassemble {}: {:#}", callee.display_name(), err);
+                    continue;
+                }
+            };
+
+            next.extend(
+                disasm::callees(bin, callee, &callee_disassembly)
+                    .into_iter()
+                    .map(|next_callee| (next_callee, callee)),
+            );
+            disassembly
+                .append_symbol(callee, callee_disassembly)
+                .set_caller(caller)
+                .set_aliases(&bin.aliases(callee));
+        }
+        frontier = next;
+    }
+
+    if disasm_options.symbolicate_jumps {
+        disassembly.link_symbols();
+    }
+    disassembly
+}
+
+/// Prints the symbols matching the symbol given in the options.
+fn list_symbols(out: &mut dyn WriteColor, bin: &Binary, opts: &Opts) -> anyhow::Result<()> {
+    let regex =
+        if opts.regex {
+            Some(Regex::new(opts.symbol()).with_context(|| {
+                format!("`{}` is not a valid regular expression", opts.symbol())
+            })?)
+        } else {
+            None
+        };
+
+    let symbols = if let Some(ref regex) = regex {
+        bin.list_symbols_regex(regex).collect::<Vec<_>>()
+    } else {
+        let mut matches = bin.fuzzy_list_symbols(opts.symbol()).collect::<Vec<_>>();
+        matches.sort_by(|lhs, rhs| {
+            lhs.0
+                .cmp(&rhs.0)
+                .then_with(|| lhs.1.address().cmp(&rhs.1.address()))
+        });
+        matches.into_iter().map(|(_, sym)| sym).collect::<Vec<_>>()
+    };
+    let symbols = symbols
+        .into_iter()
+        .filter(|sym| opts.kind.matches(sym.kind()))
+        .collect::<Vec<_>>();
+
+    if symbols.is_empty() {
+        return Err(anyhow::anyhow!(
+            "no symbols matching `{}` were found",
+            opts.symbol()
+        ));
+    }
+
+    let show_kind = opts.kind == printer::SymbolKindFilter::All;
+    let query = Tokenizer::new(opts.symbol()).collect::<Vec<&str>>();
+    let highlight = |name: &str| match regex {
+        Some(ref regex) => regex.find_iter(name).map(|m| m.range()).collect(),
+        None => strmatch::match_spans(&query, name),
+    };
+    printer::print_symbol_list(out, &symbols, show_kind, opts.addr_format, highlight)
+        .context("error occured while printing symbols")
+}
+
 /// Use options to find the binary to search for the symbol in.
-fn find_binary_path(opts: &Opts) -> anyhow::Result<PathBuf> {
+/// Returns the path of the binary to disassemble and the root of the workspace
+/// it was built in, if it was found using cargo metadata.
+fn find_binary_path(opts: &Opts) -> anyhow::Result<(PathBuf, Option<PathBuf>)> {
     use cargo_metadata::{MetadataCommand, Package, Target};
     if let Some(ref b) = opts.binary_path {
-        return Ok(b.clone());
+        return Ok((b.clone(), None));
     }
 
     log::trace!("running cargo_metadata");
@@ -156,10 +785,33 @@ fn find_binary_path(opts: &Opts) -> anyhow::Result<PathBuf> {
     if let Some(ref m) = opts.manifest_path {
         cmd.manifest_path(m);
     }
+    if opts.offline {
+        cmd.other_options(vec![String::from("--offline")]);
+    }
     let metadata = cmd
         .exec()
         .context("error occurred while running cargo_metadata")?;
 
+    // Like `cargo run`, the package of the manifest passed with `--manifest-path`
+    // or of the nearest manifest to the current directory is used by default. In
+    // the root of a virtual workspace this is none of them.
+    let current_manifest = match opts.manifest_path {
+        Some(ref m) => m.canonicalize().ok(),
+        None => std::env::current_dir()
+            .ok()
+            .and_then(|dir| find_nearest_manifest(&dir)),
+    };
+    let current_package = match (&opts.package, current_manifest) {
+        (None, Some(manifest)) => metadata.packages.iter().find(|package| {
+            metadata.workspace_members.contains(&package.id)
+                && package.manifest_path.canonicalize().ok().as_ref() == Some(&manifest)
+        }),
+        _ => None,
+    };
+    if let Some(package) = current_package {
+        log::debug!("using package `{}` in the current directory", package.name);
+    }
+
     let match_package = |package: &Package| {
         if !metadata.workspace_members.contains(&package.id) {
             return false;
@@ -168,6 +820,8 @@ fn find_binary_path(opts: &Opts) -> anyhow::Result<PathBuf> {
         if let Some(ref p) = opts.package {
             // FIXME use the pkgid scheme instead
             package.name.eq_ignore_ascii_case(p)
+        } else if let Some(current) = current_package {
+            package.id == current.id
         } else {
             true
         }
@@ -222,5 +876,14 @@ fn find_binary_path(opts: &Opts) -> anyhow::Result<PathBuf> {
         path.push(format!("{}.exe", target.name));
     }
 
-    Ok(path)
+    Ok((path, Some(metadata.workspace_root)))
+}
+
+/// Returns the canonical path of the `Cargo.toml` in `dir` or in the closest of
+/// its ancestors that has one.
+fn find_nearest_manifest(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|ancestor| ancestor.join("Cargo.toml"))
+        .find(|manifest| manifest.is_file())
+        .and_then(|manifest| manifest.canonicalize().ok())
 }
@@ -1,30 +1,96 @@
+use crate::disasm::binary::SectionInfo;
+use crate::disasm::diff::DiffLine;
+pub use crate::disasm::display::AddressFormat;
+use crate::disasm::source::SourceLine;
 use crate::disasm::strmatch::Tokenizer;
-use crate::disasm::{self, symbol::Symbol, Disassembly};
+use crate::disasm::symbol::{Symbol, SymbolKind};
+use crate::disasm::{self, DisasmError, DisasmLine, Disassembly, FoldKind, OperandKind};
+use capstone::InsnGroup;
+use std::ops::Range;
+use std::path::Path;
 use termcolor::{Color, ColorSpec, WriteColor};
 
 const MAX_OPERAND_LEN: usize = 72;
 
+/// Appended to the bytes of an instruction that were truncated by `--max-bytes`.
+const BYTES_ELLIPSIS: &str = "...";
+
+/// Prints the disassembly of `sym`. The names of symbols that share its code
+/// (`aliases`) are shown after its name.
 pub fn print_disassembly(
     out: &mut dyn WriteColor,
     sym: &Symbol,
+    aliases: &[&Symbol],
+    dis: &Disassembly,
+    opt: DisasmOptions<'_>,
+) -> anyhow::Result<()> {
+    let header = Header {
+        name: sym.display_name(),
+        decl: sym.decl_location(),
+        caller: None,
+        aliases: aliases.iter().map(|alias| alias.display_name()).collect(),
+        frame_size: dis.frame_size(),
+    };
+    print_lines(out, Some(header), Some(sym.address()), dis, opt)
+}
+
+/// The name of the symbol printed above its disassembly.
+struct Header<'a> {
+    name: &'a str,
+    /// The file and line that the symbol was declared at.
+    decl: Option<(&'a Path, u32)>,
+    /// The name of the function that called the symbol, for `--follow-calls`.
+    caller: Option<&'a str>,
+    aliases: Vec<&'a str>,
+    frame_size: Option<u64>,
+}
+
+/// Prints a disassembly that doesn't belong to a symbol without a header. The
+/// symbols that were appended to it are printed with their headers.
+pub fn print_data(
+    out: &mut dyn WriteColor,
+    dis: &Disassembly,
+    opt: DisasmOptions<'_>,
+) -> anyhow::Result<()> {
+    print_lines(out, None, None, dis, opt)
+}
+
+/// Prints the lines of a disassembly. With `relative_addresses` the addresses are
+/// shown as offsets from `start`, the address of the symbol, or from the symbol that
+/// was appended before each line.
+fn print_lines(
+    out: &mut dyn WriteColor,
+    header: Option<Header<'_>>,
+    start: Option<u64>,
     dis: &Disassembly,
-    opt: DisasmOptions,
+    opt: DisasmOptions<'_>,
 ) -> anyhow::Result<()> {
+    let mut base = start.filter(|_| opt.relative_addresses);
     let measure = disasm::display::measure(dis);
 
     let space_sm = Spacing(2);
     let space_lg = Spacing(4);
 
-    let max_addr = measure.max_address_width_hex(); // addr length
+    let max_addr = max_address_width(dis, start, opt); // addr length
     let max_mnem = measure.max_mnemonic_len(); // mnemonic length
     let mut max_oprn = measure.max_operands_len(); // operand length
-    let max_comm = measure.max_comments_len(); // comment length
-    let max_bytes = measure.max_bytes_width_hex(1); // bytes length
+    let max_comm = if opt.show_comments {
+        measure.max_comments_len() // comment length
+    } else {
+        0
+    };
+    let bytes_format = opt.bytes.unwrap_or(BytesFormat::Grouped);
+    let bytes_limit = opt.max_bytes.filter(|&n| measure.max_bytes_count() > n);
+    let max_bytes = if let Some(n) = bytes_limit {
+        (n * 2) + n.saturating_sub(1) * bytes_format.spacing() + BYTES_ELLIPSIS.len()
+    } else {
+        measure.max_bytes_width_hex(bytes_format.spacing()) // bytes length
+    };
 
     let addr_indent = space_sm;
     let bytes_indent = addr_indent + max_addr + space_lg;
     let mnem_indent = bytes_indent
-        + if opt.show_bytes {
+        + if opt.bytes.is_some() {
             space_sm + max_bytes // spacing comes after
         } else {
             Spacing(0)
@@ -48,27 +114,191 @@ pub fn print_disassembly(
     clr_source.set_fg(Some(Color::Magenta));
     clr_source.set_bold(true);
 
+    let mut clr_source_path = ColorSpec::new(); // source file path color
+    clr_source_path.set_fg(Some(Color::Magenta));
+    clr_source_path.set_dimmed(true);
+
     let mut clr_mnem = ColorSpec::new(); // mnemonic color
     clr_mnem.set_fg(Some(Color::Green));
     clr_mnem.set_bold(true);
 
+    let mut clr_mnem_pad = clr_mnem.clone(); // mnemonic color (for landing pads)
+    clr_mnem_pad.set_underline(true);
+
     let clr_oprn = ColorSpec::new(); // operands color
     let mut clr_oprn_sym = clr_oprn.clone(); // operands color (for jumps to symbols)
     clr_oprn_sym.set_fg(Some(Color::Cyan));
 
+    let mut clr_oprn_reg = clr_oprn.clone(); // register operand color
+    clr_oprn_reg.set_fg(Some(Color::Cyan));
+
+    let mut clr_oprn_imm = clr_oprn.clone(); // immediate operand color
+    clr_oprn_imm.set_fg(Some(Color::Yellow));
+
+    let mut clr_oprn_mem = clr_oprn.clone(); // memory operand color
+    clr_oprn_mem.set_fg(Some(Color::White));
+
     let mut clr_comm = ColorSpec::new(); // comment color
     clr_comm.set_italic(true);
     clr_comm.set_fg(Some(Color::Yellow));
 
-    out.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)).set_bold(true))?;
-    writeln!(out, "{}:", sym.name())?;
+    let mut clr_enc = ColorSpec::new(); // encoding color
+    clr_enc.set_dimmed(true);
+
+    let mut clr_header = ColorSpec::new(); // symbol name color
+    clr_header.set_fg(Some(Color::Cyan)).set_bold(true);
+
+    let print_header = |out: &mut dyn WriteColor, header: Header<'_>| -> anyhow::Result<()> {
+        out.set_color(&clr_header)?;
+        write!(out, "{}:", header.name)?;
+        let decl_paths = opt.source_paths.unwrap_or(SourcePaths::Relative);
+        if let Some((path, line)) = header.decl.filter(|_| decl_paths != SourcePaths::None) {
+            out.set_color(&clr_norm)?;
+            write!(out, "{}", space_sm)?;
+            out.set_color(&clr_source_path)?;
+            let path = decl_paths.render(path, opt.source_root);
+            write!(out, "({}:{})", path.display(), line)?;
+        }
+        if let Some(caller) = header.caller {
+            out.set_color(&clr_norm)?;
+            write!(out, "{}", space_sm)?;
+            out.set_color(&clr_comm)?;
+            write!(out, "; called by {}", caller)?;
+        }
+        if !header.aliases.is_empty() {
+            out.set_color(&clr_norm)?;
+            write!(out, "{}", space_sm)?;
+            out.set_color(&clr_comm)?;
+            write!(out, "; aka {}", header.aliases.join(", "))?;
+        }
+        if let Some(size) = header.frame_size {
+            out.set_color(&clr_norm)?;
+            write!(out, "{}", space_sm)?;
+            out.set_color(&clr_comm)?;
+            write!(out, "; frame: {} bytes", size)?;
+        }
+        out.set_color(&clr_norm)?;
+        writeln!(out)?;
+        Ok(())
+    };
+
+    if let Some(header) = header {
+        print_header(out, header)?;
+    }
     out.set_color(&clr_norm)?;
 
-    for line in dis.lines() {
-        if opt.show_source {
-            for source_line in line.source_lines() {
+    let mut last_source_path: Option<&Path> = None;
+    // The source lines that were shown last, for `interleave_source`.
+    let mut last_source_lines: &[SourceLine] = &[];
+    // Folds would hide the lines in the group that is shown.
+    let mut folds = dis
+        .folds()
+        .iter()
+        .filter(|_| opt.only_group.is_none())
+        .peekable();
+    let mut folded_until = 0;
+    let mut boundaries = dis.boundaries().iter().peekable();
+    for (idx, line) in dis.into_iter().enumerate() {
+        // The symbols of a disassembly that several symbols were appended to are
+        // separated by their headers, and the data between them by an empty line.
+        while let Some(boundary) = boundaries.next_if(|boundary| boundary.line() == idx) {
+            if idx > 0 {
+                writeln!(out)?;
+            }
+            if let Some(name) = boundary.name() {
+                let header = Header {
+                    name,
+                    decl: boundary.decl_location(),
+                    caller: boundary.caller(),
+                    aliases: boundary.aliases().collect(),
+                    frame_size: boundary.frame_size(),
+                };
+                print_header(out, header)?;
+            }
+            if opt.relative_addresses {
+                base = boundary.name().map(|_| boundary.address());
+            }
+        }
+
+        if idx < folded_until {
+            continue;
+        }
+
+        if let Some(group) = opt.only_group {
+            if !line.in_group(group) {
+                continue;
+            }
+        }
+
+        // A folded prologue or epilogue is replaced by a comment with the number of
+        // instructions in it, at the address of its first instruction. Padding is
+        // measured in bytes instead.
+        if let Some(fold) = folds.next_if(|fold| fold.lines().start == idx) {
+            folded_until = fold.lines().end;
+
+            out.set_color(&clr_norm)?;
+            write!(out, "{}", space_sm)?;
+
+            out.set_color(&clr_addr)?;
+            write!(
+                out,
+                "{:<1$}",
+                opt.address_format.display_from(line.address(), base),
+                max_addr
+            )?;
+
+            out.set_color(&clr_norm)?;
+            write!(out, "{}", space_lg)?;
+            if opt.bytes.is_some() {
+                write!(out, "{:1$}{2}", "", max_bytes, space_sm)?;
+            }
+
+            out.set_color(&clr_comm)?;
+            if fold.kind() == FoldKind::Padding {
+                let len: usize = dis.lines()[fold.lines()]
+                    .iter()
+                    .map(|line| line.bytes().len())
+                    .sum();
+                write!(out, "; {} ({} bytes)", fold.kind().name(), len)?;
+            } else {
+                write!(
+                    out,
+                    "; {} ({} instructions)",
+                    fold.kind().name(),
+                    fold.lines().len()
+                )?;
+            }
+
+            out.set_color(&clr_norm)?;
+            writeln!(out)?;
+            continue;
+        }
+
+        let source_lines = line.source_lines();
+        let repeated_source =
+            opt.interleave_source && same_source_lines(source_lines, last_source_lines);
+        if !source_lines.is_empty() {
+            last_source_lines = source_lines;
+        }
+
+        if opt.show_source && !repeated_source {
+            for source_line in source_lines {
+                // The path is only shown when the source file changes.
+                let paths = opt.source_paths.filter(|&paths| paths != SourcePaths::None);
+                if let Some(paths) = paths.filter(|_| last_source_path != Some(source_line.path()))
+                {
+                    out.set_color(&clr_source_path)?;
+                    writeln!(
+                        out,
+                        "{}{}:{}",
+                        source_indent,
+                        paths.render(source_line.path(), opt.source_root).display(),
+                        source_line.line()
+                    )?;
+                    last_source_path = Some(source_line.path());
+                }
                 out.set_color(&clr_source)?;
-                writeln!(out, "{}{}", source_indent, source_line)?;
+                writeln!(out, "{}{}", source_indent, source_line.text())?;
             }
         }
 
@@ -76,20 +306,34 @@ pub fn print_disassembly(
         write!(out, "{}", space_sm)?;
 
         out.set_color(&clr_addr)?;
-        write!(out, "{:<1$x}", line.address(), max_addr)?;
+        write!(
+            out,
+            "{:<1$}",
+            opt.address_format.display_from(line.address(), base),
+            max_addr
+        )?;
 
         out.set_color(&clr_norm)?;
         write!(out, "{}", space_lg)?;
 
-        if opt.show_bytes {
+        if opt.bytes.is_some() {
+            let hex = Hex {
+                bytes: line.bytes(),
+                format: bytes_format,
+                limit: bytes_limit,
+            };
             out.set_color(&clr_bytes)?;
-            write!(out, "{:>1$}", Hex(line.bytes()), max_bytes)?;
+            write!(out, "{:>1$}", hex, max_bytes)?;
 
             out.set_color(&clr_norm)?;
             write!(out, "{}", space_sm)?;
         }
 
-        out.set_color(&clr_mnem)?;
+        out.set_color(if line.is_landing_pad() {
+            &clr_mnem_pad
+        } else {
+            &clr_mnem
+        })?;
         write!(out, "{:<1$}", line.mnemonic(), max_mnem)?;
 
         out.set_color(&clr_norm)?;
@@ -105,11 +349,23 @@ pub fn print_disassembly(
         };
         out.set_color(oprn_color)?;
 
+        // With `--rich-operands` each token is colored by the kind of the operand it is in.
+        let token_color = |offset: usize| match line.operand_kind_at(offset) {
+            Some(OperandKind::Register) => &clr_oprn_reg,
+            Some(OperandKind::Immediate) => &clr_oprn_imm,
+            Some(OperandKind::Memory) => &clr_oprn_mem,
+            None => oprn_color,
+        };
+        let rich_operands = line.has_operand_kinds();
+
         let mut operands = WordWrapped::new(line.operands(), max_oprn);
         let mut has_more_operands = false;
         let mut operand_chars_printed = 0;
         for operand in operands.by_ref() {
             if let WrappedStr::Str(token) = operand {
+                if rich_operands {
+                    out.set_color(token_color(operand_chars_printed))?;
+                }
                 operand_chars_printed += token.len();
                 write!(out, "{}", token)?;
             } else {
@@ -119,15 +375,41 @@ pub fn print_disassembly(
         }
 
         // Write the comment after the first line of the operands:
-        if !line.comments().is_empty() {
+        let comments = if opt.show_comments {
+            line.comments()
+        } else {
+            ""
+        };
+        let encoding = line.encoding();
+        if !comments.is_empty() || !encoding.is_empty() {
             out.set_color(&clr_norm)?;
-            write!(
-                out,
-                "{}",
+            let comment_spacing = if let Some(column) = opt.comment_column {
+                // Always leave at least one space after operands that run past the column.
+                Spacing(std::cmp::max(
+                    column.saturating_sub(oprn_indent.0 + operand_chars_printed),
+                    1,
+                ))
+            } else {
                 Spacing(space_lg.0 + (max_oprn - operand_chars_printed))
-            )?;
-            out.set_color(&clr_comm)?;
-            write!(out, "; {:<1$}", line.comments(), max_comm)?;
+            };
+            write!(out, "{}", comment_spacing)?;
+
+            if !comments.is_empty() {
+                out.set_color(&clr_comm)?;
+                write!(out, "; {:<1$}", comments, max_comm)?;
+            } else if max_comm > 0 {
+                write!(out, "{:1$}", "", max_comm + 2)?;
+            }
+
+            // The encoding is written after all of the comments so that it stays out of the way.
+            if !encoding.is_empty() {
+                if !comments.is_empty() || max_comm > 0 {
+                    out.set_color(&clr_norm)?;
+                    write!(out, "{}", space_sm)?;
+                }
+                out.set_color(&clr_enc)?;
+                write!(out, "; {}", encoding)?;
+            }
         }
 
         // Write the remaining lines of the operands if there are any:
@@ -136,13 +418,18 @@ pub fn print_disassembly(
             writeln!(out)?;
             write!(out, "{}", oprn_indent)?;
             let mut in_oprn_color = false;
+            let mut operand_offset = operand_chars_printed;
             for w in operands {
                 match w {
                     WrappedStr::Str(s) => {
-                        if !in_oprn_color {
+                        if rich_operands {
+                            out.set_color(token_color(operand_offset))?;
+                            in_oprn_color = true;
+                        } else if !in_oprn_color {
                             out.set_color(oprn_color)?;
                             in_oprn_color = true;
                         }
+                        operand_offset += s.len();
                         write!(out, "{}", s)?;
                     }
 
@@ -162,10 +449,478 @@ pub fn print_disassembly(
         writeln!(out)?;
     }
 
+    if opt.show_stats {
+        write!(out, "{}", addr_indent)?;
+        out.set_color(&clr_comm)?;
+        write!(
+            out,
+            "; {} instructions, {} bytes",
+            dis.instruction_count(),
+            dis.byte_size()
+        )?;
+        let landing_pads = dis.landing_pad_count();
+        if landing_pads > 0 {
+            write!(out, ", {} CET landing pads", landing_pads)?;
+        }
+        out.set_color(&clr_norm)?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Prints the lines of `sym` as they are disassembled, for `--format plain`. Each
+/// line has the address, the bytes, the instruction and its comments separated by
+/// tabs, without colors or alignment since the widths of the lines that come later
+/// aren't known.
+pub fn print_stream(
+    out: &mut dyn WriteColor,
+    sym: &Symbol,
+    lines: impl Iterator<Item = Result<DisasmLine, DisasmError>>,
+    opt: DisasmOptions<'_>,
+) -> anyhow::Result<()> {
+    let base = Some(sym.address()).filter(|_| opt.relative_addresses);
+
+    writeln!(out, "{}:", sym.display_name())?;
+    for line in lines {
+        let line = line?;
+        if let Some(group) = opt.only_group {
+            if !line.in_group(group) {
+                continue;
+            }
+        }
+
+        write!(
+            out,
+            "{}",
+            opt.address_format.display_from(line.address(), base)
+        )?;
+        if let Some(format) = opt.bytes {
+            let hex = Hex {
+                bytes: line.bytes(),
+                format,
+                limit: opt.max_bytes,
+            };
+            write!(out, "\t{}", hex)?;
+        }
+        write!(out, "\t{}", line.mnemonic())?;
+        if !line.operands().is_empty() {
+            write!(out, " {}", line.operands())?;
+        }
+        if opt.show_comments && !line.comments().is_empty() {
+            write!(out, "\t; {}", line.comments())?;
+        }
+        writeln!(out)?;
+    }
     Ok(())
 }
 
-pub struct Hex<'b>(&'b [u8]);
+/// Returns the width of the address column. With `relative_addresses` the lines
+/// before the first appended symbol are offsets from `start`, and the lines of each
+/// appended symbol are offsets from that symbol, so each of them is measured on its
+/// own. Appended data is shown with its addresses.
+fn max_address_width(dis: &Disassembly, start: Option<u64>, opt: DisasmOptions<'_>) -> usize {
+    if !opt.relative_addresses {
+        return opt
+            .address_format
+            .max_width(&disasm::display::measure(dis), false);
+    }
+
+    // The first line of each segment and the address that it is relative to.
+    let mut segments = vec![(0, start)];
+    segments.extend(
+        dis.boundaries()
+            .iter()
+            .map(|boundary| (boundary.line(), boundary.name().map(|_| boundary.address()))),
+    );
+    segments.push((dis.len(), None));
+
+    segments
+        .windows(2)
+        .filter(|pair| pair[0].0 < pair[1].0)
+        .map(|pair| {
+            let (first, base) = pair[0];
+            let lines = &dis.lines()[first..pair[1].0];
+            let measure = disasm::display::measure_lines(lines, base.unwrap_or(0));
+            opt.address_format.max_width(&measure, base.is_some())
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Returns true if both slices have the same files and line numbers.
+fn same_source_lines(lhs: &[SourceLine], rhs: &[SourceLine]) -> bool {
+    lhs.len() == rhs.len()
+        && lhs
+            .iter()
+            .zip(rhs)
+            .all(|(lhs, rhs)| lhs.path() == rhs.path() && lhs.line() == rhs.line())
+}
+
+/// One side of a diff printed by [`print_diff`].
+pub struct DiffSide<'a> {
+    pub path: &'a Path,
+    pub symbol: &'a Symbol,
+    pub disassembly: &'a Disassembly,
+}
+
+/// Prints a unified diff of the instructions in two disassemblies.
+pub fn print_diff(
+    out: &mut dyn WriteColor,
+    old: DiffSide,
+    new: DiffSide,
+    lines: &[DiffLine],
+    address_format: AddressFormat,
+    relative_addresses: bool,
+) -> anyhow::Result<()> {
+    let old_base = Some(old.symbol.address()).filter(|_| relative_addresses);
+    let new_base = Some(new.symbol.address()).filter(|_| relative_addresses);
+    let old_measure = disasm::display::measure_relative(old.disassembly, old_base.unwrap_or(0));
+    let new_measure = disasm::display::measure_relative(new.disassembly, new_base.unwrap_or(0));
+    let max_old_addr = address_format.max_width(&old_measure, relative_addresses);
+    let max_new_addr = address_format.max_width(&new_measure, relative_addresses);
+    let max_mnem = std::cmp::max(
+        old_measure.max_mnemonic_len(),
+        new_measure.max_mnemonic_len(),
+    );
+
+    let space_sm = Spacing(2);
+
+    let clr_norm = ColorSpec::new();
+
+    let mut clr_addr = ColorSpec::new();
+    clr_addr.set_fg(Some(Color::Blue));
+
+    let mut clr_mnem = ColorSpec::new();
+    clr_mnem.set_fg(Some(Color::Green)).set_bold(true);
+
+    let mut clr_removed = ColorSpec::new();
+    clr_removed.set_fg(Some(Color::Red));
+
+    let mut clr_added = ColorSpec::new();
+    clr_added.set_fg(Some(Color::Green));
+
+    let mut clr_changed = ColorSpec::new();
+    clr_changed.set_fg(Some(Color::Yellow));
+
+    out.set_color(ColorSpec::new().set_bold(true))?;
+    writeln!(
+        out,
+        "--- {}: {}",
+        old.path.display(),
+        old.symbol.display_name()
+    )?;
+    writeln!(
+        out,
+        "+++ {}: {}",
+        new.path.display(),
+        new.symbol.display_name()
+    )?;
+    out.set_color(&clr_norm)?;
+
+    let mut write_line = |marker: char,
+                          color: Option<&ColorSpec>,
+                          old_line: Option<&DisasmLine>,
+                          new_line: Option<&DisasmLine>|
+     -> anyhow::Result<()> {
+        let line = old_line
+            .or(new_line)
+            .expect("diff line without instructions");
+
+        out.set_color(color.unwrap_or(&clr_norm))?;
+        write!(out, "{}", marker)?;
+
+        out.set_color(&clr_norm)?;
+        write!(out, "{}", space_sm)?;
+
+        out.set_color(&clr_addr)?;
+        match old_line {
+            Some(l) => write!(
+                out,
+                "{:<1$}",
+                address_format.display_from(l.address(), old_base),
+                max_old_addr
+            )?,
+            None => write!(out, "{:1$}", "", max_old_addr)?,
+        }
+        write!(out, "{}", space_sm)?;
+        match new_line {
+            Some(l) => write!(
+                out,
+                "{:<1$}",
+                address_format.display_from(l.address(), new_base),
+                max_new_addr
+            )?,
+            None => write!(out, "{:1$}", "", max_new_addr)?,
+        }
+
+        out.set_color(&clr_norm)?;
+        write!(out, "{}", space_sm)?;
+
+        out.set_color(color.unwrap_or(&clr_mnem))?;
+        write!(out, "{:<1$}", line.mnemonic(), max_mnem)?;
+
+        out.set_color(color.unwrap_or(&clr_norm))?;
+        write!(out, "{}{}", space_sm, line.operands())?;
+
+        out.set_color(&clr_norm)?;
+        writeln!(out)?;
+        Ok(())
+    };
+
+    for line in lines {
+        match *line {
+            DiffLine::Same(o, n) => write_line(' ', None, Some(o), Some(n))?,
+            DiffLine::Changed(o, n) => {
+                write_line('~', Some(&clr_changed), Some(o), None)?;
+                write_line('~', Some(&clr_changed), None, Some(n))?;
+            }
+            DiffLine::Removed(o) => write_line('-', Some(&clr_removed), Some(o), None)?,
+            DiffLine::Added(n) => write_line('+', Some(&clr_added), None, Some(n))?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints a line for each symbol. If `show_kind` is set, a column with the kind
+/// of each symbol is printed between the size and the name. The byte ranges of
+/// each name returned by `highlight` are underlined, to show what matched.
+pub fn print_symbol_list(
+    out: &mut dyn WriteColor,
+    symbols: &[&Symbol],
+    show_kind: bool,
+    address_format: AddressFormat,
+    highlight: impl Fn(&str) -> Vec<Range<usize>>,
+) -> anyhow::Result<()> {
+    let max_addr = symbols
+        .iter()
+        .map(|sym| address_format.display(sym.address()).to_string().len())
+        .max()
+        .unwrap_or(0);
+    let max_size = symbols
+        .iter()
+        .map(|sym| sym.size().to_string().len())
+        .max()
+        .unwrap_or(0);
+    let max_kind = symbols
+        .iter()
+        .map(|sym| kind_name(sym.kind()).len())
+        .max()
+        .unwrap_or(0);
+
+    let space_sm = Spacing(2);
+
+    let clr_norm = ColorSpec::new();
+
+    let mut clr_addr = ColorSpec::new();
+    clr_addr.set_fg(Some(Color::Blue));
+
+    let mut clr_size = ColorSpec::new();
+    clr_size.set_fg(Some(Color::Yellow));
+
+    let mut clr_name = ColorSpec::new();
+    clr_name.set_fg(Some(Color::Cyan)).set_bold(true);
+
+    let mut clr_match = clr_name.clone();
+    clr_match.set_underline(true);
+
+    let mut clr_decl = ColorSpec::new();
+    clr_decl.set_italic(true);
+
+    let mut clr_kind = ColorSpec::new();
+    clr_kind.set_fg(Some(Color::Green));
+
+    for sym in symbols {
+        out.set_color(&clr_addr)?;
+        write!(
+            out,
+            "{:<1$}",
+            address_format.display(sym.address()),
+            max_addr
+        )?;
+
+        out.set_color(&clr_norm)?;
+        write!(out, "{}", space_sm)?;
+
+        out.set_color(&clr_size)?;
+        write!(out, "{:>1$}", sym.size(), max_size)?;
+
+        out.set_color(&clr_norm)?;
+        write!(out, "{}", space_sm)?;
+
+        if show_kind {
+            out.set_color(&clr_kind)?;
+            write!(out, "{:<1$}", kind_name(sym.kind()), max_kind)?;
+
+            out.set_color(&clr_norm)?;
+            write!(out, "{}", space_sm)?;
+        }
+
+        let name = sym.display_name();
+        let mut pos = 0;
+        for span in highlight(name) {
+            let start = span.start.max(pos);
+            out.set_color(&clr_name)?;
+            write!(out, "{}", &name[pos..start])?;
+            out.set_color(&clr_match)?;
+            write!(out, "{}", &name[start..span.end])?;
+            pos = span.end;
+        }
+        out.set_color(&clr_name)?;
+        write!(out, "{}", &name[pos..])?;
+
+        if !show_kind && sym.kind() == SymbolKind::Declaration {
+            out.set_color(&clr_decl)?;
+            write!(out, " (declaration)")?;
+        }
+
+        out.set_color(&clr_norm)?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// Prints the sections of a binary for `--sections`, one per line.
+pub fn print_section_list(
+    out: &mut dyn WriteColor,
+    sections: &[SectionInfo],
+    address_format: AddressFormat,
+) -> anyhow::Result<()> {
+    let max_addr = sections
+        .iter()
+        .map(|section| address_format.display(section.address).to_string().len())
+        .max()
+        .unwrap_or(0);
+    let max_offset = sections
+        .iter()
+        .map(|section| format!("0x{:x}", section.offset).len())
+        .max()
+        .unwrap_or(0);
+    let max_size = sections
+        .iter()
+        .map(|section| section.size.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    let space_sm = Spacing(2);
+
+    let clr_norm = ColorSpec::new();
+
+    let mut clr_addr = ColorSpec::new();
+    clr_addr.set_fg(Some(Color::Blue));
+
+    let mut clr_size = ColorSpec::new();
+    clr_size.set_fg(Some(Color::Yellow));
+
+    let mut clr_flags = ColorSpec::new();
+    clr_flags.set_fg(Some(Color::Green));
+
+    let mut clr_name = ColorSpec::new();
+    clr_name.set_fg(Some(Color::Cyan)).set_bold(true);
+
+    for section in sections {
+        out.set_color(&clr_addr)?;
+        write!(
+            out,
+            "{:<1$}",
+            address_format.display(section.address),
+            max_addr
+        )?;
+
+        out.set_color(&clr_norm)?;
+        write!(out, "{}", space_sm)?;
+        write!(out, "{:<1$}", format!("0x{:x}", section.offset), max_offset)?;
+        write!(out, "{}", space_sm)?;
+
+        out.set_color(&clr_size)?;
+        write!(out, "{:>1$}", section.size, max_size)?;
+
+        out.set_color(&clr_norm)?;
+        write!(out, "{}", space_sm)?;
+
+        out.set_color(&clr_flags)?;
+        write!(out, "{}", section_flags(section))?;
+
+        out.set_color(&clr_norm)?;
+        write!(out, "{}", space_sm)?;
+
+        out.set_color(&clr_name)?;
+        write!(out, "{}", section.name)?;
+
+        out.set_color(&clr_norm)?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// The flags of a section in the flags column of the section list, like `r-x`.
+fn section_flags(section: &SectionInfo) -> String {
+    if !section.loaded {
+        return "---".to_string();
+    }
+    format!(
+        "r{}{}",
+        if section.writable { 'w' } else { '-' },
+        if section.executable { 'x' } else { '-' }
+    )
+}
+
+/// The name of a symbol kind in the kind column of the symbol list.
+fn kind_name(kind: SymbolKind) -> &'static str {
+    match kind {
+        SymbolKind::Function => "function",
+        SymbolKind::Declaration => "inlined",
+    }
+}
+
+/// The kinds of symbols that are listed by `--list`.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum SymbolKindFilter {
+    /// Functions with code that can be disassembled.
+    Function,
+    /// Functions that only exist in the debug information, usually because every
+    /// call to them was inlined ([`SymbolKind::Declaration`]).
+    Inlined,
+    /// Symbols of any kind.
+    All,
+}
+
+impl SymbolKindFilter {
+    pub fn matches(self, kind: SymbolKind) -> bool {
+        match self {
+            SymbolKindFilter::Function => kind == SymbolKind::Function,
+            SymbolKindFilter::Inlined => kind == SymbolKind::Declaration,
+            SymbolKindFilter::All => true,
+        }
+    }
+}
+
+impl std::str::FromStr for SymbolKindFilter {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("function") {
+            Ok(SymbolKindFilter::Function)
+        } else if s.eq_ignore_ascii_case("inlined") {
+            Ok(SymbolKindFilter::Inlined)
+        } else if s.eq_ignore_ascii_case("all") {
+            Ok(SymbolKindFilter::All)
+        } else {
+            Err("invalid symbol kind")
+        }
+    }
+}
+
+pub struct Hex<'b> {
+    bytes: &'b [u8],
+    format: BytesFormat,
+
+    /// The maximum number of bytes to write before truncating.
+    limit: Option<usize>,
+}
 
 impl std::fmt::Display for Hex<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -178,15 +933,20 @@ impl std::fmt::Display for Hex<'_> {
             b'E', b'F',
         ];
 
-        let output_width = if self.0.is_empty() {
+        let (bytes, truncated) = match self.limit {
+            Some(limit) if self.bytes.len() > limit => (&self.bytes[..limit], true),
+            _ => (self.bytes, false),
+        };
+
+        let output_width = if bytes.is_empty() {
             0
         } else {
-            (self.0.len() * 2) + (self.0.len() - 1)
+            (bytes.len() * 2) + (bytes.len() - 1) * self.format.spacing()
         };
-        let mut buffer = String::with_capacity(output_width);
+        let mut buffer = String::with_capacity(output_width + BYTES_ELLIPSIS.len());
 
-        for &byte in self.0 {
-            if !buffer.is_empty() {
+        for &byte in bytes {
+            if !buffer.is_empty() && self.format == BytesFormat::Grouped {
                 buffer.push(' ');
             }
 
@@ -199,6 +959,10 @@ impl std::fmt::Display for Hex<'_> {
             }
         }
 
+        if truncated {
+            buffer.push_str(BYTES_ELLIPSIS);
+        }
+
         f.pad(&buffer)
     }
 }
@@ -332,8 +1096,303 @@ pub enum WrappedStr<'s> {
     Break,
 }
 
+/// The format used for printing disassembly.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A colored table meant for reading.
+    Text,
+    /// Assembly source that can be reassembled by the GNU assembler.
+    Gas,
+    /// One line per instruction, printed as it is disassembled.
+    Plain,
+}
+
+impl OutputFormat {
+    /// The name of the format that is passed to `--format`.
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Gas => "gas",
+            OutputFormat::Plain => "plain",
+        }
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("text") {
+            Ok(OutputFormat::Text)
+        } else if s.eq_ignore_ascii_case("gas") {
+            Ok(OutputFormat::Gas)
+        } else if s.eq_ignore_ascii_case("plain") {
+            Ok(OutputFormat::Plain)
+        } else {
+            Err("invalid output format")
+        }
+    }
+}
+
+/// How the bytes of each instruction are displayed.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum BytesFormat {
+    /// Bytes are separated by a space (`48 89 e5`).
+    Grouped,
+    /// Bytes are written without any separator (`4889e5`).
+    Packed,
+}
+
+impl BytesFormat {
+    /// The number of space characters between each byte.
+    fn spacing(self) -> usize {
+        match self {
+            BytesFormat::Grouped => 1,
+            BytesFormat::Packed => 0,
+        }
+    }
+}
+
+impl std::str::FromStr for BytesFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("grouped") {
+            Ok(BytesFormat::Grouped)
+        } else if s.eq_ignore_ascii_case("packed") {
+            Ok(BytesFormat::Packed)
+        } else {
+            Err("invalid bytes format")
+        }
+    }
+}
+
+/// How the paths of source files are displayed above source lines.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum SourcePaths {
+    /// The path as it was recorded in the debug information.
+    Full,
+    /// The path relative to the workspace root, or the full path for files
+    /// outside of the workspace.
+    Relative,
+    /// Only the file name.
+    Name,
+    /// Paths are not shown.
+    None,
+}
+
+impl SourcePaths {
+    /// Returns the path that is displayed for the source file at `path`.
+    pub fn render<'p>(self, path: &'p Path, root: Option<&Path>) -> &'p Path {
+        match self {
+            SourcePaths::Full | SourcePaths::None => path,
+            SourcePaths::Relative => root
+                .and_then(|root| path.strip_prefix(root).ok())
+                .unwrap_or(path),
+            SourcePaths::Name => path.file_name().map(Path::new).unwrap_or(path),
+        }
+    }
+}
+
+impl std::str::FromStr for SourcePaths {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("full") {
+            Ok(SourcePaths::Full)
+        } else if s.eq_ignore_ascii_case("relative") {
+            Ok(SourcePaths::Relative)
+        } else if s.eq_ignore_ascii_case("name") {
+            Ok(SourcePaths::Name)
+        } else if s.eq_ignore_ascii_case("none") {
+            Ok(SourcePaths::None)
+        } else {
+            Err("invalid source path style")
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
-pub struct DisasmOptions {
-    pub show_bytes: bool,
+pub struct DisasmOptions<'p> {
+    /// How the address of each line is displayed.
+    pub address_format: AddressFormat,
+    /// Show the address of each line as an offset from the start of its symbol.
+    pub relative_addresses: bool,
+    /// The format used for the bytes of each instruction, or `None`
+    /// if bytes should not be shown.
+    pub bytes: Option<BytesFormat>,
+    /// The maximum number of bytes shown for each instruction.
+    pub max_bytes: Option<usize>,
     pub show_source: bool,
+    /// Only show the source lines of an instruction if they are different from
+    /// the source lines of the instruction before it.
+    pub interleave_source: bool,
+    /// How the paths of source files are displayed. Without a style source lines
+    /// are shown without their paths, and declarations are shown relative to
+    /// `source_root`.
+    pub source_paths: Option<SourcePaths>,
+    /// The directory that [`SourcePaths::Relative`] paths are relative to.
+    pub source_root: Option<&'p Path>,
+    /// Show the comments after each instruction.
+    pub show_comments: bool,
+    /// The column that comments start at, instead of the column after the
+    /// longest operands.
+    pub comment_column: Option<usize>,
+    /// Show the number of instructions, bytes and landing pads after the disassembly.
+    pub show_stats: bool,
+    /// Only show the instructions in this Capstone group, see [`disasm::find_group`].
+    pub only_group: Option<InsnGroup>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::disasm::binary::{Arch, Binary, BinaryData};
+    use crate::disasm::symbol::SymbolSource;
+    use termcolor::NoColor;
+
+    fn options(address_format: AddressFormat) -> DisasmOptions<'static> {
+        DisasmOptions {
+            address_format,
+            relative_addresses: true,
+            bytes: None,
+            max_bytes: None,
+            show_source: false,
+            interleave_source: false,
+            source_paths: Some(SourcePaths::None),
+            source_root: None,
+            show_comments: true,
+            comment_column: None,
+            show_stats: false,
+            only_group: None,
+        }
+    }
+
+    fn print(dis: &Disassembly, start: Option<u64>, opt: DisasmOptions) -> Vec<String> {
+        let mut out = NoColor::new(Vec::new());
+        print_lines(&mut out, None, start, dis, opt).unwrap();
+        String::from_utf8(out.into_inner())
+            .unwrap()
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn relative_address_formats() {
+        let offset = |format| AddressFormat::display_from(format, 0x101f, Some(0x1000));
+        assert_eq!(offset(AddressFormat::Hex).to_string(), "+0x1f");
+        assert_eq!(offset(AddressFormat::HexPrefixed).to_string(), "+0x1f");
+        assert_eq!(offset(AddressFormat::UpperHex).to_string(), "+0x1F");
+        assert_eq!(offset(AddressFormat::Dec).to_string(), "+31");
+        assert_eq!(format!("{:<6}|", offset(AddressFormat::Dec)), "+31   |");
+    }
+
+    #[test]
+    fn source_path_styles() {
+        let root = Path::new("/home/user/project");
+        let inside = Path::new("/home/user/project/src/main.rs");
+        let outside = Path::new("/rustc/library/core/src/ptr/mod.rs");
+
+        assert_eq!(SourcePaths::Full.render(inside, Some(root)), inside);
+        assert_eq!(
+            SourcePaths::Relative.render(inside, Some(root)),
+            Path::new("src/main.rs")
+        );
+        assert_eq!(
+            SourcePaths::Name.render(inside, Some(root)),
+            Path::new("main.rs")
+        );
+
+        // Files outside of the root, or without a root, keep their full path.
+        assert_eq!(SourcePaths::Relative.render(outside, Some(root)), outside);
+        assert_eq!(SourcePaths::Relative.render(inside, None), inside);
+        assert_eq!(SourcePaths::Name.render(outside, None), Path::new("mod.rs"));
+
+        // Relative paths from the debug information are left alone.
+        let relative = Path::new("src/lib.rs");
+        assert_eq!(SourcePaths::Relative.render(relative, Some(root)), relative);
+    }
+
+    #[test]
+    fn absolute_address_formats() {
+        let address = |format| AddressFormat::display(format, 0x4011a0).to_string();
+        assert_eq!(address(AddressFormat::Hex), "4011a0");
+        assert_eq!(address(AddressFormat::HexPrefixed), "0x4011a0");
+        assert_eq!(address(AddressFormat::UpperHex), "4011A0");
+        assert_eq!(address(AddressFormat::Dec), "4198816");
+        assert_eq!(
+            format!("{:>10}|", AddressFormat::Dec.display(42)),
+            "        42|"
+        );
+    }
+
+    #[test]
+    fn decimal_address_width() {
+        let width = |address| {
+            let binary = Binary::raw(BinaryData::from_bytes(&[0x90]), Arch::X86_64);
+            let symbol = Symbol::new_unmangled("foo".into(), address, 0, 1, SymbolSource::Elf);
+            let dis = disasm::disasm(&binary, &symbol, &disasm::DisasmOptions::default()).unwrap();
+            let opt = DisasmOptions {
+                relative_addresses: false,
+                ..options(AddressFormat::Dec)
+            };
+            max_address_width(&dis, None, opt)
+        };
+        assert_eq!(width(0), 0);
+        assert_eq!(width(9), 1);
+        assert_eq!(width(10), 2);
+        assert_eq!(width(99_999), 5);
+        assert_eq!(width(100_000), 6);
+    }
+
+    #[test]
+    fn stats_count_instructions_bytes_and_landing_pads() {
+        // endbr64; nop; ret
+        let binary = Binary::raw(
+            BinaryData::from_bytes(&[0xf3, 0x0f, 0x1e, 0xfa, 0x90, 0xc3]),
+            Arch::X86_64,
+        );
+        let symbol = Symbol::new_unmangled("f".into(), 0, 0, 6, SymbolSource::Elf);
+        let dis = disasm::disasm(&binary, &symbol, &disasm::DisasmOptions::default()).unwrap();
+
+        let lines = print(
+            &dis,
+            Some(0),
+            DisasmOptions {
+                show_stats: true,
+                ..options(AddressFormat::Hex)
+            },
+        );
+        assert_eq!(
+            lines.last().unwrap(),
+            "  ; 3 instructions, 6 bytes, 1 CET landing pads"
+        );
+    }
+
+    #[test]
+    fn relative_addresses_are_measured_per_symbol() {
+        // 0x100 nops in `first`, and a single nop in `second` further away.
+        let binary = Binary::raw(BinaryData::from_bytes(&[0x90; 0x1001]), Arch::X86_64);
+        let first = Symbol::new_unmangled("first".into(), 0, 0, 0x100, SymbolSource::Elf);
+        let second = Symbol::new_unmangled("second".into(), 0x1000, 0x1000, 1, SymbolSource::Elf);
+        let disasm_options = disasm::DisasmOptions::default();
+
+        let mut dis = disasm::disasm(&binary, &first, &disasm_options).unwrap();
+        let second_dis = disasm::disasm(&binary, &second, &disasm_options).unwrap();
+        dis.append_symbol(&second, second_dis);
+
+        // The widest offsets are the last ones in `first`, `second` starts over at zero.
+        let lines = print(&dis, Some(0), options(AddressFormat::Dec));
+        assert_eq!(lines[0], "  +0      nop");
+        assert_eq!(lines[255], "  +255    nop");
+        assert_eq!(lines[256], "");
+        assert_eq!(lines[257], "second:");
+        assert_eq!(lines[258], "  +0      nop");
+
+        let lines = print(&dis, Some(0), options(AddressFormat::UpperHex));
+        assert_eq!(lines[0], "  +0x0     nop");
+        assert_eq!(lines[255], "  +0xFF    nop");
+        assert_eq!(lines[258], "  +0x0     nop");
+    }
 }
@@ -0,0 +1,134 @@
+use crate::util::DurationDisplay;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long a phase has to run before its spinner is shown, so that quick runs
+/// don't flash a status line.
+const SPINNER_DELAY: Duration = Duration::from_millis(250);
+
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Set when stderr is a terminal that spinners can be drawn on.
+static SHOW_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables the spinners started after this.
+pub fn set_enabled(enabled: bool) {
+    SHOW_PROGRESS.store(enabled, Ordering::Relaxed);
+}
+
+/// A status line on stderr with a message and the time spent so far, shown while
+/// a slow phase runs. It is erased when the spinner is dropped.
+pub struct Spinner {
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    pub fn start(message: String) -> Spinner {
+        let done = Arc::new(AtomicBool::new(false));
+        let thread = if SHOW_PROGRESS.load(Ordering::Relaxed) {
+            let done = Arc::clone(&done);
+            Some(std::thread::spawn(move || spin(&message, &done)))
+        } else {
+            None
+        };
+        Spinner { done, thread }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.done.store(true, Ordering::Relaxed);
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn spin(message: &str, done: &AtomicBool) {
+    let start = Instant::now();
+    let mut frame = 0;
+    let mut width = 0;
+
+    loop {
+        std::thread::park_timeout(SPINNER_INTERVAL);
+        if done.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let elapsed = start.elapsed();
+        if elapsed < SPINNER_DELAY {
+            continue;
+        }
+
+        let line = format!(
+            "{} {} ({:.1}s)",
+            SPINNER_FRAMES[frame % SPINNER_FRAMES.len()],
+            message,
+            elapsed.as_secs_f64()
+        );
+        width = width.max(line.chars().count());
+        let mut err = io::stderr();
+        let _ = write!(err, "\r{}", line);
+        let _ = err.flush();
+        frame += 1;
+    }
+
+    if width > 0 {
+        let mut err = io::stderr();
+        let _ = write!(err, "\r{:width$}\r", "", width = width);
+        let _ = err.flush();
+    }
+}
+
+/// The time spent in each phase of a run, which is printed by `--timings`.
+#[derive(Debug, Default)]
+pub struct Timings {
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    /// Adds `duration` to the time spent in `phase`. Phases are printed in the order
+    /// that they were first recorded.
+    pub fn record(&mut self, phase: &'static str, duration: Duration) {
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += duration,
+            None => self.phases.push((phase, duration)),
+        }
+    }
+
+    /// Runs `f` and records the time that it took as part of `phase`.
+    pub fn time<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let timer = Instant::now();
+        let result = f();
+        self.record(phase, timer.elapsed());
+        result
+    }
+
+    pub fn print(&self, out: &mut dyn Write) -> io::Result<()> {
+        let width = self
+            .phases
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+
+        writeln!(out, "timings:")?;
+        for (name, duration) in self.phases.iter() {
+            writeln!(
+                out,
+                "  {:width$}  {}",
+                name,
+                DurationDisplay(*duration),
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
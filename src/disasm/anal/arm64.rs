@@ -0,0 +1,183 @@
+use crate::disasm::{DisasmLine, Jump};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Registers that are saved by the callee, including the frame pointer (`x29`)
+/// and the link register (`x30`).
+const CALLEE_SAVED: &[&str] = &[
+    "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27", "x28", "x29", "x30", "fp", "lr",
+    "d8", "d9", "d10", "d11", "d12", "d13", "d14", "d15",
+];
+
+/// Returns true if `line` is part of a standard function prologue: saving callee saved
+/// registers to the stack with `stp`/`str`, setting up the frame pointer with `mov x29, sp`
+/// and allocating stack space.
+pub fn is_prologue_line(line: &DisasmLine) -> bool {
+    let ops = operands(line);
+    match line.mnemonic() {
+        "paciasp" | "pacibsp" | "bti" => true,
+        "stp" | "str" => is_stack_transfer(&ops),
+        "mov" => ops == ["x29", "sp"],
+        "add" => ops.len() == 3 && ops[0] == "x29" && ops[1] == "sp" && is_imm(ops[2]),
+        "sub" => ops.len() == 3 && ops[0] == "sp" && ops[1] == "sp" && is_imm(ops[2]),
+        _ => false,
+    }
+}
+
+/// Returns the number of bytes that an instruction with `mnemonic` and `operands`
+/// allocates on the stack: `sub sp, sp, #N` or a store of callee saved registers
+/// that pre-decrements the stack pointer, e.g. `stp x29, x30, [sp, #-0x20]!`.
+pub fn stack_allocation(mnemonic: &str, operands: &str) -> Option<u64> {
+    let ops = split_operands(operands);
+    let size = match mnemonic {
+        "sub" if ops.len() == 3 && ops[0] == "sp" && ops[1] == "sp" => parse_imm(ops[2])?,
+        "stp" | "str" if is_stack_transfer(&ops) => {
+            let offset = ops.last()?.strip_suffix("]!")?;
+            -parse_imm(offset)?
+        }
+        _ => return None,
+    };
+    if size > 0 {
+        Some(size as u64)
+    } else {
+        None
+    }
+}
+
+/// Returns true if `line` is part of a standard function epilogue, which undoes
+/// the prologue before a return.
+pub fn is_epilogue_line(line: &DisasmLine) -> bool {
+    let ops = operands(line);
+    match line.mnemonic() {
+        "autiasp" | "autibsp" => true,
+        "ldp" | "ldr" => is_stack_transfer(&ops),
+        "mov" => ops == ["sp", "x29"],
+        "add" => ops.len() == 3 && ops[0] == "sp" && ops[1] == "sp" && is_imm(ops[2]),
+        _ => false,
+    }
+}
+
+pub fn is_return(line: &DisasmLine) -> bool {
+    matches!(line.mnemonic(), "ret" | "retaa" | "retab")
+}
+
+/// Finds the addresses computed by `adrp` and a following `add` or load/store that
+/// uses the page address as its base, e.g. `adrp x0, #0x411000` and `ldr x1, [x0, #0x18]`.
+/// The instructions don't have to be next to each other, but control flow between them
+/// isn't followed, so a register only holds a page address until the next branch.
+pub fn address_refs(lines: &[DisasmLine]) -> Vec<(usize, u64)> {
+    let mut pages: HashMap<&str, u64> = HashMap::new();
+    let mut refs = Vec::new();
+
+    for (idx, line) in lines.iter().enumerate() {
+        let ops = operands(line);
+        let mnemonic = line.mnemonic();
+
+        if mnemonic == "adrp" && ops.len() == 2 {
+            if let Some(page) = parse_imm(ops[1]) {
+                pages.insert(ops[0], page as u64);
+                continue;
+            }
+        }
+
+        match (mnemonic, ops.as_slice()) {
+            ("add", &[_, base, offset]) => {
+                if let (Some(&page), Some(offset)) = (pages.get(base), parse_imm(offset)) {
+                    refs.push((idx, page.wrapping_add(offset as u64)));
+                }
+            }
+            (m, &[_, base, ..]) if is_load_store(m) && base.starts_with('[') => {
+                let base = base.trim_start_matches('[').trim_end_matches(']');
+                // `[x1, #8]` accesses x1 + 8, and the pre-index form `[x1, #8]!` also
+                // writes that address back to x1. The post-index form `[x1], #8`
+                // accesses x1 and adds 8 to it afterwards.
+                let (offset, increment) = match ops.get(2) {
+                    Some(op) if op.ends_with("]!") => {
+                        let offset = parse_imm(op.trim_end_matches("]!"));
+                        (offset, offset)
+                    }
+                    Some(op) if op.ends_with(']') => (parse_imm(op.trim_end_matches(']')), Some(0)),
+                    Some(op) => (Some(0), parse_imm(op)),
+                    None => (Some(0), Some(0)),
+                };
+                if let (Some(&page), Some(offset)) = (pages.get(base), offset) {
+                    refs.push((idx, page.wrapping_add(offset as u64)));
+                    match increment {
+                        Some(0) => {}
+                        Some(increment) => {
+                            pages.insert(base, page.wrapping_add(increment as u64));
+                        }
+                        None => {
+                            pages.remove(base);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        // The first operand is the destination of everything except stores, so this
+        // forgets too much for stores, which is harmless. Writing `wN` clears `xN`.
+        if let Some(dest) = ops.first() {
+            pages.remove(full_register(dest).as_ref());
+        }
+        if !matches!(line.jump(), Jump::None) || is_return(line) {
+            pages.clear();
+        }
+    }
+
+    refs
+}
+
+/// Returns the 64-bit register that `reg` is the lower half of, e.g. `x3` for `w3`.
+fn full_register(reg: &str) -> Cow<'_, str> {
+    match reg.strip_prefix('w') {
+        Some(n) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => {
+            Cow::Owned(format!("x{}", n))
+        }
+        _ => Cow::Borrowed(reg),
+    }
+}
+
+fn is_load_store(mnemonic: &str) -> bool {
+    matches!(
+        mnemonic,
+        "ldr" | "ldrb" | "ldrh" | "ldrsb" | "ldrsh" | "ldrsw" | "str" | "strb" | "strh"
+    )
+}
+
+/// Parses an immediate operand such as `#0x18`, `#-0x10` or `#8`.
+fn parse_imm(op: &str) -> Option<i64> {
+    let op = op.strip_prefix('#')?;
+    let (negative, op) = match op.strip_prefix('-') {
+        Some(op) => (true, op),
+        None => (false, op),
+    };
+    let value = match op.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()? as i64,
+        None => op.parse::<i64>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Returns true for the operands of a load or store of callee saved registers
+/// relative to the stack pointer, e.g. `x29, x30, [sp, #-0x10]!`.
+fn is_stack_transfer(ops: &[&str]) -> bool {
+    let mut regs = ops.iter().take_while(|op| !op.starts_with('['));
+    let count = regs.clone().count();
+    count > 0
+        && regs.all(|reg| CALLEE_SAVED.contains(reg))
+        && ops.get(count).is_some_and(|mem| mem.starts_with("[sp"))
+}
+
+fn is_imm(op: &str) -> bool {
+    op.starts_with('#')
+}
+
+fn operands(line: &DisasmLine) -> Vec<&str> {
+    split_operands(line.operands())
+}
+
+fn split_operands(operands: &str) -> Vec<&str> {
+    operands.split(',').map(str::trim).collect()
+}
@@ -1,15 +1,253 @@
+mod arm64;
+mod ppc;
+mod sparc;
 mod x86;
 
-use capstone::{Arch, Capstone, Insn};
+use super::binary::Endian;
+use super::DisasmLine;
+use capstone::{Arch, Capstone, Insn, Syntax};
 
-pub fn identify_jump_target(insn: &Insn, caps: &Capstone) -> Jump {
+pub fn identify_jump_target(insn: &Insn, caps: &Capstone, endian: Endian) -> Jump {
     match caps.arch() {
         Arch::X86 => x86::identify_jump_target(insn, caps),
+        Arch::PowerPc => ppc::identify_jump_target(insn, endian),
+        Arch::Sparc => sparc::identify_jump_target(insn, caps),
         _ => Jump::None,
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Returns true if the instruction after `insn` is executed in its delay slot,
+/// before the control transfer takes effect.
+pub fn has_delay_slot(insn: &Insn, caps: &Capstone) -> bool {
+    match caps.arch() {
+        Arch::Sparc => sparc::has_delay_slot(insn, caps),
+        _ => false,
+    }
+}
+
+/// Returns true if the control flow analysis of the architecture finds the targets
+/// of branches and knows which instructions never fall through, which is required
+/// for recursive traversal. The x86 analysis needs instruction details.
+pub fn supports_control_flow(caps: &Capstone) -> bool {
+    match caps.arch() {
+        Arch::X86 => caps.details_enabled(),
+        Arch::PowerPc => true,
+        _ => false,
+    }
+}
+
+/// Returns true if `insn` calls a function.
+pub fn is_call(insn: &Insn, caps: &Capstone, endian: Endian) -> bool {
+    match caps.arch() {
+        Arch::PowerPc => ppc::is_call(insn, endian),
+        _ => caps
+            .try_details(insn)
+            .is_some_and(|details| details.groups().iter().any(|g| g.is_call())),
+    }
+}
+
+/// Returns false if execution never continues with the instruction after `insn`,
+/// e.g. for unconditional jumps and returns.
+pub fn falls_through(insn: &Insn, caps: &Capstone, endian: Endian) -> bool {
+    match caps.arch() {
+        Arch::X86 => x86::falls_through(insn, caps),
+        Arch::PowerPc => ppc::falls_through(insn, endian),
+        _ => !caps
+            .try_details(insn)
+            .is_some_and(|details| details.groups().iter().any(|g| g.is_ret())),
+    }
+}
+
+/// Returns true if `insn` marks a valid target for indirect jumps and calls,
+/// like x86's CET `endbr64`.
+pub fn is_landing_pad(insn: &Insn, caps: &Capstone) -> bool {
+    match caps.arch() {
+        Arch::X86 => x86::is_landing_pad(insn, caps),
+        _ => false,
+    }
+}
+
+/// Finds the tables that indexed indirect jumps, like the ones that switches are
+/// compiled to, jump through, if the architecture supports it. Some tables are only
+/// found by following the instructions before the jump, so instructions have to be
+/// passed in the order that they are executed, and [`JumpTableFinder::reset`] has
+/// to be called before an instruction that doesn't follow the previous one.
+#[derive(Default)]
+pub struct JumpTableFinder {
+    x86_relative: x86::RelativeJumpTable,
+}
+
+impl JumpTableFinder {
+    /// Returns the table that `insn` jumps through.
+    pub fn find(&mut self, insn: &Insn, caps: &Capstone) -> Option<JumpTable> {
+        match caps.arch() {
+            Arch::X86 => {
+                let relative = self.x86_relative.track(insn, caps);
+                x86::jump_table(insn, caps).or(relative)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = JumpTableFinder::default();
+    }
+}
+
+/// Returns the number of bytes of stack space that `insn` allocates if it is an
+/// instruction that a prologue allocates the stack frame with, e.g. `sub rsp, 0x28`,
+/// if the architecture supports it.
+pub fn stack_allocation(insn: &Insn, caps: &Capstone) -> Option<u64> {
+    match caps.arch() {
+        Arch::X86 => x86::stack_allocation(insn, caps),
+        Arch::Arm64 => arm64::stack_allocation(insn.mnemonic(), insn.operands()),
+        _ => None,
+    }
+}
+
+/// Finds the landing pads for indirect jumps and calls in `code`, which starts at
+/// `address`, if the architecture has them. These are only candidates, the bytes
+/// can also be part of another instruction or data.
+pub fn find_landing_pads(arch: Arch, code: &[u8], address: u64) -> Vec<u64> {
+    match arch {
+        Arch::X86 => x86::find_landing_pads(code, address),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns a comment describing the condition flags accessed by an instruction,
+/// if the architecture supports it.
+pub fn flags_comment(insn: &Insn, caps: &Capstone) -> Option<String> {
+    match caps.arch() {
+        Arch::X86 => x86::flags_comment(insn, caps),
+        _ => None,
+    }
+}
+
+/// Returns a comment with the characters of the immediate operands of an instruction
+/// that are printable ASCII, if the architecture supports it.
+pub fn immediate_chars_comment(insn: &Insn, caps: &Capstone) -> Option<String> {
+    match caps.arch() {
+        Arch::X86 => x86::immediate_chars_comment(insn, caps),
+        _ => None,
+    }
+}
+
+/// Formats the little endian bytes of an immediate that is `size` bytes long
+/// (8 if the size is unknown) as a character or string literal, if they are
+/// printable ASCII. Zero bytes at the end are ignored, so `0x41` in a 32-bit
+/// operand is `'A'`.
+fn immediate_chars(value: u64, size: usize) -> Option<String> {
+    let size = if size == 0 || size > 8 { 8 } else { size };
+    let bytes = value.to_le_bytes();
+    let len = bytes[..size].iter().rposition(|&b| b != 0)? + 1;
+    let bytes = &bytes[..len];
+
+    // A lone space is far more likely to be the number 32.
+    if bytes == b" " || !bytes.iter().all(|&b| (0x20..0x7f).contains(&b)) {
+        return None;
+    }
+
+    let text = std::str::from_utf8(bytes).ok()?;
+    if len == 1 {
+        Some(format!("{:?}", bytes[0] as char))
+    } else {
+        Some(format!("{:?}", text))
+    }
+}
+
+/// Returns a short description of how an instruction is encoded,
+/// if the architecture supports it.
+pub fn encoding_comment(insn: &Insn, caps: &Capstone) -> Option<String> {
+    match caps.arch() {
+        Arch::X86 => x86::encoding_comment(insn, caps),
+        _ => None,
+    }
+}
+
+/// Returns the kind of each operand of an instruction in the order that they are
+/// printed, if the architecture supports it.
+pub fn operand_kinds(insn: &Insn, caps: &Capstone) -> Option<Vec<OperandKind>> {
+    match caps.arch() {
+        Arch::X86 => x86::operand_kinds(insn, caps),
+        _ => None,
+    }
+}
+
+/// Finds the addresses that are computed by more than one instruction, e.g. a page
+/// address and an offset, and returns them with the index of the line that completes
+/// each address, if the architecture supports it.
+pub fn address_refs(arch: Arch, lines: &[DisasmLine]) -> Vec<(usize, u64)> {
+    match arch {
+        Arch::Arm64 => arm64::address_refs(lines),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns true if `line` can be part of a standard function prologue,
+/// if the architecture supports it.
+pub fn is_prologue_line(arch: Arch, syntax: Syntax, line: &DisasmLine) -> bool {
+    match arch {
+        Arch::X86 => x86::is_prologue_line(line, syntax),
+        Arch::Arm64 => arm64::is_prologue_line(line),
+        _ => false,
+    }
+}
+
+/// Returns true if `line` can be part of a standard function epilogue,
+/// if the architecture supports it.
+pub fn is_epilogue_line(arch: Arch, syntax: Syntax, line: &DisasmLine) -> bool {
+    match arch {
+        Arch::X86 => x86::is_epilogue_line(line, syntax),
+        Arch::Arm64 => arm64::is_epilogue_line(line),
+        _ => false,
+    }
+}
+
+/// Returns true if `line` is a return instruction that an epilogue comes before.
+pub fn is_return(arch: Arch, line: &DisasmLine) -> bool {
+    match arch {
+        Arch::X86 => x86::is_return(line),
+        Arch::Arm64 => arm64::is_return(line),
+        _ => false,
+    }
+}
+
+/// Returns true if `line` is padding that a compiler or linker could have added
+/// after a function to align the next one. Zero bytes are padding on every architecture.
+pub fn is_padding(arch: Arch, line: &DisasmLine) -> bool {
+    if line.bytes().iter().all(|&b| b == 0) {
+        return true;
+    }
+
+    match arch {
+        Arch::X86 => x86::is_padding(line),
+        _ => line.mnemonic() == "nop",
+    }
+}
+
+/// What an operand of an instruction refers to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OperandKind {
+    Register,
+    Immediate,
+    Memory,
+}
+
+/// A table that an indirect jump selects its target from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct JumpTable {
+    /// The address of the table. An absolute address from the instruction isn't
+    /// rebased, but one that is relative to the instruction is.
+    pub address: u64,
+    /// The size of each entry in the table in bytes.
+    pub entry_size: usize,
+    /// True if the entries are signed offsets from the start of the table instead
+    /// of absolute addresses.
+    pub relative: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Jump {
     /// This is a jump an internal instruction inside of the symbol's function.
     Internal(usize),
@@ -30,3 +268,268 @@ impl Jump {
         matches!(self, &Jump::External(..))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn immediate_chars_printable_ascii() {
+        assert_eq!(immediate_chars(0x41, 1), Some("'A'".to_string()));
+        assert_eq!(immediate_chars(0x41, 4), Some("'A'".to_string()));
+        assert_eq!(immediate_chars(0x27, 1), Some("'\\''".to_string()));
+        assert_eq!(
+            immediate_chars(0x6f6c6c6548, 8),
+            Some("\"Hello\"".to_string())
+        );
+        assert_eq!(
+            immediate_chars(0x6f6c6c6548, 0),
+            Some("\"Hello\"".to_string())
+        );
+        // Only the bytes of the operand count.
+        assert_eq!(immediate_chars(0x6f6c6c6548, 2), Some("\"He\"".to_string()));
+
+        assert_eq!(immediate_chars(0, 4), None);
+        assert_eq!(immediate_chars(0x20, 4), None);
+        assert_eq!(immediate_chars(0x0a, 1), None);
+        assert_eq!(immediate_chars(0x0041_0041, 4), None);
+        assert_eq!(immediate_chars(u64::MAX, 8), None);
+    }
+
+    #[test]
+    fn x86_flags_comment() {
+        let mut caps =
+            Capstone::open(Arch::X86, capstone::Mode::Bits64).expect("failed to open capstone");
+        caps.set_details_enabled(true)
+            .expect("failed to enable instruction details");
+        let comment = |code: &[u8]| {
+            let insns = caps
+                .disasm(code, 0x1000)
+                .expect("failed to disassemble instructions");
+            let insn = insns.iter().next().expect("no instructions");
+            flags_comment(insn, &caps)
+        };
+
+        // adc eax, ebx reads the carry flag and writes all of the arithmetic flags.
+        assert_eq!(
+            comment(&[0x11, 0xd8]),
+            Some("flags: M:CF,PF,AF,ZF,SF,OF T:CF".to_string())
+        );
+        // cmp eax, ebx
+        assert_eq!(
+            comment(&[0x39, 0xd8]),
+            Some("flags: M:CF,PF,AF,ZF,SF,OF".to_string())
+        );
+        // mov eax, ebx
+        assert_eq!(comment(&[0x89, 0xd8]), None);
+    }
+
+    #[test]
+    fn x86_jump_table() {
+        let mut caps =
+            Capstone::open(Arch::X86, capstone::Mode::Bits64).expect("failed to open capstone");
+        caps.set_details_enabled(true)
+            .expect("failed to enable instruction details");
+        // The table that the last instruction of `code` jumps through.
+        let table = |code: &[u8]| {
+            let insns = caps
+                .disasm(code, 0x1000)
+                .expect("failed to disassemble instructions");
+            let mut finder = JumpTableFinder::default();
+            insns
+                .iter()
+                .map(|insn| finder.find(insn, &caps))
+                .last()
+                .flatten()
+        };
+
+        // jmp qword ptr [rax*8 + 0x4020a0]
+        assert_eq!(
+            table(&[0xff, 0x24, 0xc5, 0xa0, 0x20, 0x40, 0x00]),
+            Some(JumpTable {
+                address: 0x4020a0,
+                entry_size: 8,
+                relative: false,
+            })
+        );
+        // jmp qword ptr [rbx + rax*8 + 0x10] has a base register.
+        assert_eq!(table(&[0xff, 0x64, 0xc3, 0x10]), None);
+        // jmp rax
+        assert_eq!(table(&[0xff, 0xe0]), None);
+
+        // lea rcx, [rip + 0x100]
+        let lea = [0x48, 0x8d, 0x0d, 0x00, 0x01, 0x00, 0x00];
+        // movsxd rax, dword ptr [rcx + rax*4]
+        let movsxd = [0x48, 0x63, 0x04, 0x81];
+        // add rax, rcx
+        let add = [0x48, 0x01, 0xc8];
+        // mov eax, edi
+        let mov_eax = [0x89, 0xf8];
+        // mov ecx, 5
+        let mov_ecx = [0xb9, 0x05, 0x00, 0x00, 0x00];
+        let jmp = [0xff, 0xe0];
+
+        assert_eq!(
+            table(&[&lea[..], &mov_eax, &movsxd, &add, &jmp].concat()),
+            Some(JumpTable {
+                address: 0x1107,
+                entry_size: 4,
+                relative: true,
+            })
+        );
+        // `mov ecx, 5` overwrites the address of the table.
+        assert_eq!(
+            table(&[&lea[..], &mov_ecx, &movsxd, &add, &jmp].concat()),
+            None
+        );
+        // The offset is never added to the table.
+        assert_eq!(table(&[&lea[..], &movsxd, &jmp].concat()), None);
+    }
+
+    #[test]
+    fn ppc_branch_targets() {
+        let caps = Capstone::open(
+            Arch::PowerPc,
+            capstone::Mode::Bits32 | capstone::Mode::BigEndian,
+        )
+        .expect("failed to open capstone");
+        // The jump target, whether it's a call and whether it falls through.
+        let branch = |code: &[u8], address: u64| {
+            let insns = caps
+                .disasm(code, address)
+                .expect("failed to disassemble instructions");
+            let insn = insns.iter().next().expect("no instructions");
+            (
+                identify_jump_target(insn, &caps, Endian::Big),
+                is_call(insn, &caps, Endian::Big),
+                falls_through(insn, &caps, Endian::Big),
+            )
+        };
+
+        // b 0x1000, backwards
+        assert_eq!(
+            branch(&[0x4b, 0xff, 0xff, 0xf8], 0x1008),
+            (Jump::External(0x1000), false, false)
+        );
+        // bl 0x1000, backwards
+        assert_eq!(
+            branch(&[0x4b, 0xff, 0xff, 0x01], 0x1100),
+            (Jump::External(0x1000), true, true)
+        );
+        // beq 0x1000, backwards
+        assert_eq!(
+            branch(&[0x41, 0x82, 0xff, 0xf0], 0x1010),
+            (Jump::External(0x1000), false, true)
+        );
+        // bne 0x1020, forwards
+        assert_eq!(
+            branch(&[0x40, 0x82, 0x00, 0x10], 0x1010),
+            (Jump::External(0x1020), false, true)
+        );
+        // ba 0x100 ignores the address of the branch.
+        assert_eq!(
+            branch(&[0x48, 0x00, 0x01, 0x02], 0x1000),
+            (Jump::External(0x100), false, false)
+        );
+        // blr
+        assert_eq!(
+            branch(&[0x4e, 0x80, 0x00, 0x20], 0x1000),
+            (Jump::None, false, false)
+        );
+    }
+
+    #[test]
+    fn sparc_jump_targets() {
+        let open = |mode| {
+            let mut caps = Capstone::open(Arch::Sparc, capstone::Mode::BigEndian | mode)
+                .expect("failed to open capstone");
+            caps.set_details_enabled(true)
+                .expect("failed to enable instruction details");
+            caps
+        };
+        let target = |caps: &Capstone, code: &[u8], address: u64| {
+            let insns = caps
+                .disasm(code, address)
+                .expect("failed to disassemble instructions");
+            let insn = insns.iter().next().expect("no instructions");
+            identify_jump_target(insn, caps, Endian::Big)
+        };
+
+        let caps = open(capstone::Mode::empty());
+        // call 0x1010
+        assert_eq!(
+            target(&caps, &[0x40, 0x00, 0x00, 0x04], 0x1000),
+            Jump::External(0x1010)
+        );
+        // ba 0x1008
+        assert_eq!(
+            target(&caps, &[0x10, 0x80, 0x00, 0x02], 0x1000),
+            Jump::External(0x1008)
+        );
+        // ba 0x1000, backwards
+        assert_eq!(
+            target(&caps, &[0x10, 0xbf, 0xff, 0xff], 0x1004),
+            Jump::External(0x1000)
+        );
+        // retl is indirect.
+        assert_eq!(target(&caps, &[0x81, 0xc3, 0xe0, 0x08], 0x1000), Jump::None);
+        // mov 1, %o0
+        assert_eq!(target(&caps, &[0x90, 0x10, 0x20, 0x01], 0x1000), Jump::None);
+
+        // SparcV9 targets use the full address, even across a 4GiB boundary.
+        let caps = open(capstone::Mode::V9);
+        assert_eq!(
+            target(&caps, &[0x10, 0x80, 0x00, 0x02], 0x2_0000_1000),
+            Jump::External(0x2_0000_1008)
+        );
+        assert_eq!(
+            target(&caps, &[0x10, 0xbf, 0xff, 0xff], 0x1_0000_0000),
+            Jump::External(0xffff_fffc)
+        );
+    }
+
+    #[test]
+    fn sparc_delay_slots() {
+        let mut caps = Capstone::open(Arch::Sparc, capstone::Mode::BigEndian)
+            .expect("failed to open capstone");
+        caps.set_details_enabled(true)
+            .expect("failed to enable instruction details");
+        let delay_slot = |code: &[u8]| {
+            let insns = caps
+                .disasm(code, 0x1000)
+                .expect("failed to disassemble instructions");
+            let insn = insns.iter().next().expect("no instructions");
+            has_delay_slot(insn, &caps)
+        };
+
+        // ba, call and retl all execute the next instruction first.
+        assert!(delay_slot(&[0x10, 0x80, 0x00, 0x02]));
+        assert!(delay_slot(&[0x40, 0x00, 0x00, 0x04]));
+        assert!(delay_slot(&[0x81, 0xc3, 0xe0, 0x08]));
+        // mov 1, %o0 and nop
+        assert!(!delay_slot(&[0x90, 0x10, 0x20, 0x01]));
+        assert!(!delay_slot(&[0x01, 0x00, 0x00, 0x00]));
+    }
+
+    #[test]
+    fn arm64_stack_allocation() {
+        assert_eq!(arm64::stack_allocation("sub", "sp, sp, #0x40"), Some(0x40));
+        assert_eq!(
+            arm64::stack_allocation("stp", "x29, x30, [sp, #-0x20]!"),
+            Some(0x20)
+        );
+        assert_eq!(arm64::stack_allocation("str", "x19, [sp, #-16]!"), Some(16));
+
+        // Stores without writeback and other registers don't allocate anything.
+        assert_eq!(
+            arm64::stack_allocation("stp", "x29, x30, [sp, #0x10]"),
+            None
+        );
+        assert_eq!(
+            arm64::stack_allocation("stp", "x0, x1, [sp, #-0x10]!"),
+            None
+        );
+        assert_eq!(arm64::stack_allocation("sub", "x0, x0, #8"), None);
+        assert_eq!(arm64::stack_allocation("add", "sp, sp, #0x40"), None);
+    }
+}
@@ -0,0 +1,100 @@
+use super::Jump;
+use crate::disasm::binary::Endian;
+use capstone::Insn;
+
+/// Primary opcode of `b`, `ba`, `bl` and `bla` (I-form).
+const OPCD_B: u32 = 18;
+/// Primary opcode of `bc`, `bca`, `bcl` and `bcla` (B-form).
+const OPCD_BC: u32 = 16;
+
+/// Primary opcode of `bclr` and `bcctr` (XL-form).
+const OPCD_XL: u32 = 19;
+/// Extended opcode of `bclr`.
+const XO_BCLR: u32 = 16;
+/// Extended opcode of `bcctr`.
+const XO_BCCTR: u32 = 528;
+
+/// The bits of the `BO` field that are set for branches that ignore both the
+/// condition register and the count register, i.e. that always branch.
+const BO_ALWAYS: u32 = 0b10100;
+
+/// Link bit, the address of the next instruction is saved in the link register.
+const LK: u32 = 0b1;
+
+/// Absolute address bit, the displacement is the target address instead of
+/// being relative to the address of the branch. The bit after this one is the
+/// link (`LK`) bit which is set for calls.
+const AA: u32 = 0b10;
+
+// Capstone doesn't decode PowerPC operands for us yet so the
+// branch displacements are decoded from the instruction word.
+pub fn identify_jump_target(insn: &Insn, endian: Endian) -> Jump {
+    let word = if let Some(word) = instruction_word(insn, endian) {
+        word
+    } else {
+        return Jump::None;
+    };
+
+    let displacement = match word >> 26 {
+        OPCD_B => sign_extend(word & 0x03ff_fffc, 26),
+        OPCD_BC => sign_extend(word & 0x0000_fffc, 16),
+
+        // `bclr` and `bcctr` branch to a register, so there is nothing to link.
+        _ => return Jump::None,
+    };
+
+    // Calls (`LK` set) are linked the same way as plain branches, the
+    // symbolication pass decides whether the target is inside of this function.
+    if word & AA != 0 {
+        Jump::External(displacement as u64)
+    } else {
+        Jump::External(insn.address().wrapping_add(displacement as u64))
+    }
+}
+
+/// Returns false for branches that always branch without linking. Calls return
+/// to the next instruction, so they fall through.
+pub fn falls_through(insn: &Insn, endian: Endian) -> bool {
+    let word = if let Some(word) = instruction_word(insn, endian) {
+        word
+    } else {
+        return true;
+    };
+
+    if word & LK != 0 {
+        return true;
+    }
+
+    let bo = (word >> 21) & 0x1f;
+    match word >> 26 {
+        OPCD_B => false,
+        OPCD_BC => bo & BO_ALWAYS != BO_ALWAYS,
+        OPCD_XL if matches!((word >> 1) & 0x3ff, XO_BCLR | XO_BCCTR) => bo & BO_ALWAYS != BO_ALWAYS,
+        _ => true,
+    }
+}
+
+/// Returns true for branches that save the return address in the link register.
+pub fn is_call(insn: &Insn, endian: Endian) -> bool {
+    instruction_word(insn, endian)
+        .is_some_and(|word| matches!(word >> 26, OPCD_B | OPCD_BC) && word & LK != 0)
+}
+
+fn instruction_word(insn: &Insn, endian: Endian) -> Option<u32> {
+    let bytes = insn.bytes();
+    if bytes.len() != 4 {
+        return None;
+    }
+
+    let word = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    Some(match endian {
+        Endian::Little => u32::from_le_bytes(word),
+        Endian::Big | Endian::Unknown => u32::from_be_bytes(word),
+    })
+}
+
+/// Sign extends the lower `bits` bits of `value`.
+fn sign_extend(value: u32, bits: u32) -> i64 {
+    let shift = 32 - bits;
+    (((value << shift) as i32) >> shift) as i64
+}
@@ -0,0 +1,72 @@
+use super::Jump;
+use capstone::{sparc, Capstone, Insn};
+
+/// Resolves the targets of `call`, `ba`, `bcc`, `bpcc` and `brz`-style branches.
+/// The branch forms encode their displacements differently, so the target is taken
+/// from the operand that Capstone decoded instead of from the instruction word.
+pub fn identify_jump_target(insn: &Insn, caps: &Capstone) -> Jump {
+    let generic_details = if let Some(details) = caps.try_details(insn) {
+        details
+    } else {
+        return Jump::None;
+    };
+
+    let transfers_control = generic_details
+        .groups()
+        .iter()
+        .any(|&g| g.is_jump() || g.is_call());
+    if !transfers_control {
+        return Jump::None;
+    }
+
+    let details = if let Some(details) = generic_details.sparc() {
+        details
+    } else {
+        log::error!("instruction did not have sparc details");
+        return Jump::None;
+    };
+
+    // Indirect jumps and calls (`jmpl`) have no immediate operand.
+    let target = details
+        .operands()
+        .iter()
+        .rev()
+        .find_map(|op| match op.value() {
+            sparc::OpValue::Imm(target) => Some(target),
+            _ => None,
+        });
+
+    match target {
+        Some(target) => Jump::External(branch_target(insn.address(), target)),
+        None => Jump::None,
+    }
+}
+
+/// Capstone adds the displacement to the low 32 bits of the address, which is
+/// wrong for SparcV9 code above 4GiB or branches that cross a 4GiB boundary.
+/// The displacement is recovered from the low 32 bits of the target and added to
+/// the full address instead.
+fn branch_target(address: u64, target: i64) -> u64 {
+    let displacement = (target as u32).wrapping_sub(address as u32) as i32;
+    address.wrapping_add(displacement as i64 as u64)
+}
+
+/// Returns true if the instruction after `insn` is in its delay slot, i.e. it is
+/// executed before control is transferred. Every control transfer instruction has
+/// one, including `call`, but an annulled branch (`,a`) only executes it if the
+/// branch is taken.
+pub fn has_delay_slot(insn: &Insn, caps: &Capstone) -> bool {
+    let transfers_control = caps.try_details(insn).is_some_and(|details| {
+        details
+            .groups()
+            .iter()
+            .any(|&g| g.is_jump() || g.is_call() || g.is_ret())
+    });
+
+    // Indirect jumps and returns aren't always in a group.
+    transfers_control
+        || matches!(
+            insn.mnemonic(),
+            "jmp" | "jmpl" | "ret" | "retl" | "rett" | "return"
+        )
+}
@@ -1,8 +1,13 @@
-use super::Jump;
-use capstone::{x86, Capstone, Insn};
+use super::{Jump, OperandKind};
+use crate::disasm::DisasmLine;
+use capstone::{x86, Capstone, Insn, InsnId, Syntax};
 
 pub fn identify_jump_target(insn: &Insn, caps: &Capstone) -> Jump {
-    let generic_details = caps.details(insn);
+    let generic_details = if let Some(details) = caps.try_details(insn) {
+        details
+    } else {
+        return Jump::None;
+    };
 
     let is_jump = generic_details.groups().iter().any(|&g| {
         g == x86::InsnGroup::Call
@@ -16,16 +21,573 @@ pub fn identify_jump_target(insn: &Insn, caps: &Capstone) -> Jump {
 
     if let Some(details) = generic_details.x86() {
         // Do these even exist?
-        if details.operands().len() != 1 {
+        if details.op_count() != 1 {
             return Jump::None;
         }
 
-        match details.operands()[0].value() {
-            x86::OpValue::Imm(addr) => Jump::External(addr as u64),
-            _ => Jump::None,
+        match details.op_index(x86::OpType::Imm, 1) {
+            Some(idx) => match details.operands()[idx].value() {
+                x86::OpValue::Imm(addr) => Jump::External(addr as u64),
+                _ => Jump::None,
+            },
+            None => Jump::None,
         }
     } else {
         log::error!("instruction did not have x86 details");
         Jump::None
     }
 }
+
+pub fn falls_through(insn: &Insn, caps: &Capstone) -> bool {
+    let returns = caps.try_details(insn).is_some_and(|details| {
+        details
+            .groups()
+            .iter()
+            .any(|&g| g == x86::InsnGroup::Ret || g == x86::InsnGroup::Iret)
+    });
+    if returns {
+        return false;
+    }
+
+    match caps.insn_id(insn) {
+        Some(InsnId::X86(id)) => !matches!(
+            id,
+            x86::InsnId::Jmp | x86::InsnId::Ljmp | x86::InsnId::Hlt | x86::InsnId::Ud2
+        ),
+        _ => true,
+    }
+}
+
+/// Returns true for `endbr32` and `endbr64`, which mark the addresses that indirect
+/// jumps and calls are allowed to land on when CET is enabled.
+pub fn is_landing_pad(insn: &Insn, caps: &Capstone) -> bool {
+    matches!(
+        caps.insn_id(insn),
+        Some(InsnId::X86(x86::InsnId::Endbr32)) | Some(InsnId::X86(x86::InsnId::Endbr64))
+    )
+}
+
+/// Finds the table of an indirect jump through a table of absolute addresses, which
+/// is how switches are compiled in non-PIC code: `jmp qword ptr [rax*8 + 0x4020a0]`.
+pub fn jump_table(insn: &Insn, caps: &Capstone) -> Option<super::JumpTable> {
+    if !matches!(caps.insn_id(insn), Some(InsnId::X86(x86::InsnId::Jmp))) {
+        return None;
+    }
+
+    let generic_details = caps.try_details(insn)?;
+    let details = generic_details.x86()?;
+    let op = match details.operands() {
+        [op] => op,
+        _ => return None,
+    };
+    match op.value() {
+        x86::OpValue::Mem(mem)
+            if mem.segment() == x86::Reg::Invalid
+                && mem.base() == x86::Reg::Invalid
+                && mem.index() != x86::Reg::Invalid
+                && mem.scale() as usize == op.size()
+                && (op.size() == 4 || op.size() == 8)
+                && mem.disp() > 0 =>
+        {
+            Some(super::JumpTable {
+                address: mem.disp() as u64,
+                entry_size: op.size(),
+                relative: false,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Follows the instructions that a switch in position independent code is compiled
+/// to, which jumps through a table of 32-bit offsets from the start of the table:
+///
+/// ```text
+/// lea rcx, [rip + table]
+/// movsxd rax, dword ptr [rcx + rax*4]
+/// add rax, rcx
+/// jmp rax
+/// ```
+///
+/// Each register holds the address of the table that it was computed from. Other
+/// instructions can be in between, as long as they don't overwrite these registers.
+#[derive(Default)]
+pub struct RelativeJumpTable {
+    /// The register that holds the address of the table.
+    table: Option<(x86::Reg, u64)>,
+    /// The register that holds an offset that was read from the table.
+    entry: Option<(x86::Reg, u64)>,
+    /// The register that holds the target, the sum of the offset and the table.
+    target: Option<(x86::Reg, u64)>,
+}
+
+impl RelativeJumpTable {
+    /// Returns the table that `insn` jumps through if it is the last instruction of
+    /// the sequence.
+    pub fn track(&mut self, insn: &Insn, caps: &Capstone) -> Option<super::JumpTable> {
+        let generic_details = match caps.try_details(insn) {
+            Some(details) => details,
+            None => {
+                *self = RelativeJumpTable::default();
+                return None;
+            }
+        };
+        let details = generic_details.x86()?;
+        let id = match caps.insn_id(insn) {
+            Some(InsnId::X86(id)) => id,
+            _ => x86::InsnId::Invalid,
+        };
+        let held = |slot: Option<(x86::Reg, u64)>, reg: x86::Reg| match slot {
+            Some((held, address)) if held == reg => Some(address),
+            _ => None,
+        };
+
+        let mut table = None;
+        let mut entry = None;
+        let mut target = None;
+        let mut jump_table = None;
+        match (id, details.operands()) {
+            (x86::InsnId::Lea, [dest, src]) => {
+                if let (x86::OpValue::Reg(dest), x86::OpValue::Mem(mem)) =
+                    (dest.value(), src.value())
+                {
+                    if mem.base() == x86::Reg::Rip && mem.index() == x86::Reg::Invalid {
+                        let next = insn.address().wrapping_add(insn.size() as u64);
+                        table = Some((dest, next.wrapping_add(mem.disp() as u64)));
+                    }
+                }
+            }
+            (x86::InsnId::Movsxd, [dest, src]) if src.size() == 4 => {
+                if let (x86::OpValue::Reg(dest), x86::OpValue::Mem(mem)) =
+                    (dest.value(), src.value())
+                {
+                    if mem.segment() == x86::Reg::Invalid
+                        && mem.index() != x86::Reg::Invalid
+                        && mem.scale() == 4
+                        && mem.disp() == 0
+                    {
+                        entry = held(self.table, mem.base()).map(|address| (dest, address));
+                    }
+                }
+            }
+            (x86::InsnId::Add, [dest, src]) => {
+                if let (x86::OpValue::Reg(dest), x86::OpValue::Reg(src)) =
+                    (dest.value(), src.value())
+                {
+                    let sum = match (held(self.entry, dest), held(self.table, src)) {
+                        (Some(a), Some(b)) if a == b => Some(a),
+                        _ => match (held(self.table, dest), held(self.entry, src)) {
+                            (Some(a), Some(b)) if a == b => Some(a),
+                            _ => None,
+                        },
+                    };
+                    target = sum.map(|address| (dest, address));
+                }
+            }
+            (x86::InsnId::Jmp, [op]) => {
+                if let x86::OpValue::Reg(reg) = op.value() {
+                    jump_table = held(self.target, reg).map(|address| super::JumpTable {
+                        address,
+                        entry_size: 4,
+                        relative: true,
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        // Forget the registers that this instruction overwrites.
+        let written = details
+            .operands()
+            .iter()
+            .filter(|op| op.access().contains(capstone::arch::Access::WRITE))
+            .filter_map(|op| match op.value() {
+                x86::OpValue::Reg(reg) => Some(full_register(reg)),
+                _ => None,
+            })
+            .chain(
+                generic_details
+                    .regs_write()
+                    .iter()
+                    .map(|&reg| full_register(reg.x86())),
+            )
+            .collect::<Vec<x86::Reg>>();
+        let kept = |slot: Option<(x86::Reg, u64)>| slot.filter(|(held, _)| !written.contains(held));
+        self.table = table.or_else(|| kept(self.table));
+        self.entry = entry.or_else(|| kept(self.entry));
+        self.target = target.or_else(|| kept(self.target));
+
+        jump_table
+    }
+}
+
+/// Returns the 64-bit register that `reg` is a part of, because writing to `eax`
+/// also overwrites `rax`.
+fn full_register(reg: x86::Reg) -> x86::Reg {
+    use x86::Reg::*;
+
+    match reg {
+        Al | Ah | Ax | Eax => Rax,
+        Bl | Bh | Bx | Ebx => Rbx,
+        Cl | Ch | Cx | Ecx => Rcx,
+        Dl | Dh | Dx | Edx => Rdx,
+        Sil | Si | Esi => Rsi,
+        Dil | Di | Edi => Rdi,
+        Bpl | Bp | Ebp => Rbp,
+        Spl | Sp | Esp => Rsp,
+        R8b | R8w | R8d => R8,
+        R9b | R9w | R9d => R9,
+        R10b | R10w | R10d => R10,
+        R11b | R11w | R11d => R11,
+        R12b | R12w | R12d => R12,
+        R13b | R13w | R13d => R13,
+        R14b | R14w | R14d => R14,
+        R15b | R15w | R15d => R15,
+        _ => reg,
+    }
+}
+
+/// Returns the number of bytes that `sub rsp, N` or `sub esp, N` allocates on the stack.
+pub fn stack_allocation(insn: &Insn, caps: &Capstone) -> Option<u64> {
+    if !matches!(caps.insn_id(insn), Some(InsnId::X86(x86::InsnId::Sub))) {
+        return None;
+    }
+
+    let generic_details = caps.try_details(insn)?;
+    let details = generic_details.x86()?;
+    match details.operands() {
+        [dest, src] => match (dest.value(), src.value()) {
+            (x86::OpValue::Reg(x86::Reg::Rsp), x86::OpValue::Imm(size))
+            | (x86::OpValue::Reg(x86::Reg::Esp), x86::OpValue::Imm(size))
+                if size > 0 =>
+            {
+                Some(size as u64)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The encodings of `endbr64` and `endbr32`.
+const ENDBR: &[[u8; 4]] = &[[0xf3, 0x0f, 0x1e, 0xfa], [0xf3, 0x0f, 0x1e, 0xfb]];
+
+/// Finds the `endbr32` and `endbr64` instructions in `code`, which starts at `address`.
+pub fn find_landing_pads(code: &[u8], address: u64) -> Vec<u64> {
+    code.windows(4)
+        .enumerate()
+        .filter(|(_, bytes)| ENDBR.iter().any(|endbr| endbr == bytes))
+        .map(|(offset, _)| address + offset as u64)
+        .collect()
+}
+
+pub fn operand_kinds(insn: &Insn, caps: &Capstone) -> Option<Vec<OperandKind>> {
+    let generic_details = caps.try_details(insn)?;
+    let details = generic_details.x86()?;
+    let kinds = details
+        .operands()
+        .iter()
+        .filter_map(|op| match op.op_type() {
+            x86::OpType::Reg => Some(OperandKind::Register),
+            x86::OpType::Imm => Some(OperandKind::Immediate),
+            x86::OpType::Mem => Some(OperandKind::Memory),
+            x86::OpType::Invalid => None,
+        })
+        .collect();
+    Some(kinds)
+}
+
+/// Describes the immediate operands of an instruction that are printable ASCII as
+/// characters, e.g. `'A'` for `mov al, 0x41` or `"Hello"` for `0x6f6c6c6548`.
+pub fn immediate_chars_comment(insn: &Insn, caps: &Capstone) -> Option<String> {
+    let generic_details = caps.try_details(insn)?;
+    let details = generic_details.x86()?;
+    let chars = details
+        .operands()
+        .iter()
+        .filter_map(|op| match op.value() {
+            x86::OpValue::Imm(value) => super::immediate_chars(value as u64, op.size()),
+            _ => None,
+        })
+        .collect::<Vec<String>>();
+
+    if chars.is_empty() {
+        None
+    } else {
+        Some(chars.join(", "))
+    }
+}
+
+/// The flags for each kind of access to EFLAGS, in the order that they are displayed.
+const EFLAGS_ACCESS: &[(&str, &[(x86::EFlags, &str)])] = &[
+    (
+        "M",
+        &[
+            (x86::EFlags::MODIFY_CF, "CF"),
+            (x86::EFlags::MODIFY_PF, "PF"),
+            (x86::EFlags::MODIFY_AF, "AF"),
+            (x86::EFlags::MODIFY_ZF, "ZF"),
+            (x86::EFlags::MODIFY_SF, "SF"),
+            (x86::EFlags::MODIFY_TF, "TF"),
+            (x86::EFlags::MODIFY_IF, "IF"),
+            (x86::EFlags::MODIFY_DF, "DF"),
+            (x86::EFlags::MODIFY_OF, "OF"),
+            (x86::EFlags::MODIFY_NT, "NT"),
+            (x86::EFlags::MODIFY_RF, "RF"),
+        ],
+    ),
+    (
+        "T",
+        &[
+            (x86::EFlags::TEST_CF, "CF"),
+            (x86::EFlags::TEST_PF, "PF"),
+            (x86::EFlags::TEST_AF, "AF"),
+            (x86::EFlags::TEST_ZF, "ZF"),
+            (x86::EFlags::TEST_SF, "SF"),
+            (x86::EFlags::TEST_TF, "TF"),
+            (x86::EFlags::TEST_IF, "IF"),
+            (x86::EFlags::TEST_DF, "DF"),
+            (x86::EFlags::TEST_OF, "OF"),
+            (x86::EFlags::TEST_NT, "NT"),
+            (x86::EFlags::TEST_RF, "RF"),
+        ],
+    ),
+    (
+        "S",
+        &[
+            (x86::EFlags::SET_CF, "CF"),
+            (x86::EFlags::SET_PF, "PF"),
+            (x86::EFlags::SET_AF, "AF"),
+            (x86::EFlags::SET_ZF, "ZF"),
+            (x86::EFlags::SET_SF, "SF"),
+            (x86::EFlags::SET_IF, "IF"),
+            (x86::EFlags::SET_DF, "DF"),
+            (x86::EFlags::SET_OF, "OF"),
+        ],
+    ),
+    (
+        "R",
+        &[
+            (x86::EFlags::RESET_CF, "CF"),
+            (x86::EFlags::RESET_PF, "PF"),
+            (x86::EFlags::RESET_AF, "AF"),
+            (x86::EFlags::RESET_ZF, "ZF"),
+            (x86::EFlags::RESET_SF, "SF"),
+            (x86::EFlags::RESET_TF, "TF"),
+            (x86::EFlags::RESET_IF, "IF"),
+            (x86::EFlags::RESET_DF, "DF"),
+            (x86::EFlags::RESET_OF, "OF"),
+            (x86::EFlags::RESET_NT, "NT"),
+            (x86::EFlags::RESET_RF, "RF"),
+            (x86::EFlags::RESET_AC, "AC"),
+        ],
+    ),
+    (
+        "U",
+        &[
+            (x86::EFlags::UNDEFINED_CF, "CF"),
+            (x86::EFlags::UNDEFINED_PF, "PF"),
+            (x86::EFlags::UNDEFINED_AF, "AF"),
+            (x86::EFlags::UNDEFINED_ZF, "ZF"),
+            (x86::EFlags::UNDEFINED_SF, "SF"),
+            (x86::EFlags::UNDEFINED_OF, "OF"),
+        ],
+    ),
+];
+
+/// Describes which EFLAGS are modified (`M`), tested (`T`), set (`S`), reset (`R`)
+/// or left undefined (`U`) by an instruction, e.g. `flags: M:ZF,SF T:CF`.
+pub fn flags_comment(insn: &Insn, caps: &Capstone) -> Option<String> {
+    let generic_details = caps.try_details(insn)?;
+
+    // The EFLAGS are not defined for FPU instructions.
+    if generic_details
+        .groups()
+        .iter()
+        .any(|&g| g == x86::InsnGroup::Fpu)
+    {
+        return None;
+    }
+
+    let eflags = generic_details.x86()?.eflags();
+    if eflags.is_empty() {
+        return None;
+    }
+
+    let mut comment = String::from("flags:");
+    for &(access, flags) in EFLAGS_ACCESS {
+        let mut names = flags
+            .iter()
+            .filter(|(flag, _)| eflags.contains(*flag))
+            .map(|&(_, name)| name)
+            .peekable();
+
+        if names.peek().is_none() {
+            continue;
+        }
+
+        comment.push(' ');
+        comment.push_str(access);
+        comment.push(':');
+        comment.push_str(&names.collect::<Vec<&str>>().join(","));
+    }
+
+    Some(comment)
+}
+
+/// Legacy prefixes that can come before a VEX or EVEX prefix.
+const LEGACY_PREFIXES: &[u8] = &[
+    0xf0, 0xf2, 0xf3, 0x2e, 0x36, 0x3e, 0x26, 0x64, 0x65, 0x66, 0x67,
+];
+
+/// Describes how an instruction is encoded, e.g. `REX.W modrm=0x89 disp32@+3 imm8@+4`.
+/// Offsets are relative to the start of the instruction.
+pub fn encoding_comment(insn: &Insn, caps: &Capstone) -> Option<String> {
+    let generic_details = caps.try_details(insn)?;
+    let details = generic_details.x86()?;
+    let encoding = details.encoding();
+    let mut parts: Vec<String> = Vec::new();
+
+    let rex = details.rex();
+    if rex != 0 {
+        let bits = [(0x8, 'W'), (0x4, 'R'), (0x2, 'X'), (0x1, 'B')]
+            .iter()
+            .filter(|&&(bit, _)| rex & bit != 0)
+            .map(|&(_, name)| name)
+            .collect::<String>();
+        if bits.is_empty() {
+            parts.push("REX".into());
+        } else {
+            parts.push(format!("REX.{}", bits));
+        }
+    }
+
+    // The VEX and EVEX prefixes are told apart from the instructions that share their
+    // first byte (`lds`, `les` and `bound`) by where the ModR/M byte ends up.
+    let bytes = insn.bytes();
+    if let Some(pos) = bytes.iter().position(|b| !LEGACY_PREFIXES.contains(b)) {
+        let modrm_offset = encoding.modrm_offset() as usize;
+        let prefix = match bytes[pos] {
+            0xc5 if modrm_offset == pos + 3 => Some("VEX2"),
+            0xc4 if modrm_offset == pos + 4 => Some("VEX3"),
+            0x8f if modrm_offset == pos + 4 => Some("XOP"),
+            0x62 if modrm_offset == pos + 5 => Some("EVEX"),
+            _ => None,
+        };
+        if let Some(prefix) = prefix {
+            parts.push(prefix.into());
+        }
+    }
+
+    if encoding.modrm_offset() != 0 {
+        parts.push(format!("modrm=0x{:02x}", details.modrm()));
+    }
+
+    if details.sib() != 0 {
+        parts.push(format!("sib=0x{:02x}", details.sib()));
+    }
+
+    if encoding.disp_offset() != 0 {
+        parts.push(format!(
+            "disp{}@+{}",
+            encoding.disp_size() as u32 * 8,
+            encoding.disp_offset()
+        ));
+    }
+
+    if encoding.imm_offset() != 0 {
+        parts.push(format!(
+            "imm{}@+{}",
+            encoding.imm_size() as u32 * 8,
+            encoding.imm_offset()
+        ));
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
+/// Registers that are saved by the callee in the System V and Windows calling conventions.
+const CALLEE_SAVED: &[&str] = &[
+    "rbx", "rbp", "rsi", "rdi", "r12", "r13", "r14", "r15", "ebx", "ebp", "esi", "edi",
+];
+
+/// Returns true if `line` is part of a standard function prologue: pushing callee saved
+/// registers, setting up the frame pointer with `mov rbp, rsp` and allocating stack space.
+pub fn is_prologue_line(line: &DisasmLine, syntax: Syntax) -> bool {
+    let ops = operands(line, syntax);
+    let mnemonic = line.mnemonic();
+    if mnemonic == "endbr64" || mnemonic == "endbr32" {
+        true
+    } else if is_mnemonic(mnemonic, "push") {
+        ops.len() == 1 && CALLEE_SAVED.contains(&ops[0])
+    } else if is_mnemonic(mnemonic, "mov") {
+        ops == ["rbp", "rsp"] || ops == ["ebp", "esp"]
+    } else if is_mnemonic(mnemonic, "sub") {
+        is_stack_adjustment(&ops)
+    } else {
+        false
+    }
+}
+
+/// Returns true if `line` is part of a standard function epilogue, which undoes
+/// the prologue before a return.
+pub fn is_epilogue_line(line: &DisasmLine, syntax: Syntax) -> bool {
+    let ops = operands(line, syntax);
+    let mnemonic = line.mnemonic();
+    if is_mnemonic(mnemonic, "leave") {
+        true
+    } else if is_mnemonic(mnemonic, "pop") {
+        ops.len() == 1 && CALLEE_SAVED.contains(&ops[0])
+    } else if is_mnemonic(mnemonic, "mov") {
+        ops == ["rsp", "rbp"] || ops == ["esp", "ebp"]
+    } else if is_mnemonic(mnemonic, "add") {
+        is_stack_adjustment(&ops)
+    } else {
+        false
+    }
+}
+
+pub fn is_return(line: &DisasmLine) -> bool {
+    is_mnemonic(line.mnemonic(), "ret")
+}
+
+/// Returns true for `int3` and the one and multi-byte forms of `nop`
+/// (`nopw`/`nop word ptr` in AT&T and Intel syntax).
+pub fn is_padding(line: &DisasmLine) -> bool {
+    line.mnemonic().starts_with("nop") || line.bytes().iter().all(|&b| b == 0xcc || b == 0x90)
+}
+
+/// Returns true if `mnemonic` is `name` or `name` with an AT&T operand size suffix.
+fn is_mnemonic(mnemonic: &str, name: &str) -> bool {
+    matches!(
+        mnemonic.strip_prefix(name),
+        Some("") | Some("q") | Some("l")
+    )
+}
+
+/// Returns true for the operands of an instruction that adds or subtracts an
+/// immediate from the stack pointer.
+fn is_stack_adjustment(ops: &[&str]) -> bool {
+    ops.len() == 2
+        && (ops[0] == "rsp" || ops[0] == "esp")
+        && ops[1].starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Returns the operands of a line in Intel order (destination first) without
+/// the AT&T register and immediate sigils.
+fn operands(line: &DisasmLine, syntax: Syntax) -> Vec<&str> {
+    let mut ops = line
+        .operands()
+        .split(',')
+        .map(|op| op.trim().trim_start_matches(['%', '$']))
+        .filter(|op| !op.is_empty())
+        .collect::<Vec<&str>>();
+    if syntax == Syntax::Att {
+        ops.reverse();
+    }
+    ops
+}
@@ -0,0 +1,206 @@
+use super::SearchOptions;
+use crate::disasm::symbol::Symbol;
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The version of the layout of cache entries. This has to be bumped whenever
+/// the key or the serialized symbols change, since bincode can't tell that an
+/// entry was written with a different layout.
+const CACHE_FORMAT_VERSION: u32 = 2;
+
+/// The symbols of a binary that were loaded by an earlier run, stored on disk
+/// so that they don't have to be gathered again.
+///
+/// A cache entry is only used while the binary has the same path, modification
+/// time and size, and the symbols were loaded with the same options. Changes to
+/// separate debug information (dSYM bundles and PDBs) are not detected.
+pub struct SymbolCache {
+    /// The file that the symbols are stored in.
+    path: PathBuf,
+    key: CacheKey,
+}
+
+/// Identifies the binary and options that the symbols in a cache entry were loaded from.
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+struct CacheKey {
+    /// The [`CACHE_FORMAT_VERSION`] that the entry was written with.
+    format: u32,
+    /// The version of cargo-disasm that wrote the entry. Entries from other versions
+    /// might not have the same symbols.
+    version: String,
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+    /// The options that change which symbols are loaded.
+    options: String,
+}
+
+impl SymbolCache {
+    /// Returns the cache entry in `dir` for the binary at `binary_path`
+    /// loaded with `options`.
+    pub fn new(
+        dir: &Path,
+        binary_path: &Path,
+        options: &SearchOptions,
+    ) -> anyhow::Result<SymbolCache> {
+        let binary_path = binary_path
+            .canonicalize()
+            .with_context(|| format!("failed to resolve `{}`", binary_path.display()))?;
+        let metadata = fs::metadata(&binary_path)?;
+
+        let key = CacheKey {
+            format: CACHE_FORMAT_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            path: binary_path,
+            modified: metadata.modified()?,
+            size: metadata.len(),
+            options: format!(
+                "{:?} {} {:?} {:?} {:?} {:?} {}",
+                options.sources,
+                options.auto_sources_threshold,
+                options.dwarf_path,
+                options.dsym_path,
+                options.pdb_path,
+                options.mach_arch,
+                options.dwarf_declarations,
+            ),
+        };
+
+        // An entry is replaced when the binary changes, so the modification
+        // time and size aren't part of the file name.
+        let mut hasher = DefaultHasher::new();
+        key.path.hash(&mut hasher);
+        key.options.hash(&mut hasher);
+        let file_name = key
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let path = dir.join(format!("{}-{:016x}.symbols", file_name, hasher.finish()));
+
+        Ok(SymbolCache { path, key })
+    }
+
+    /// Loads the cached symbols, or returns `None` if there are no cached
+    /// symbols or they are out of date.
+    pub fn load(&self) -> Option<Vec<Symbol>> {
+        match self.try_load() {
+            Ok(symbols) => symbols,
+            Err(err) => {
+                log::debug!(
+                    "failed to load cached symbols from `{}`: {:#}",
+                    self.path.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    fn try_load(&self) -> anyhow::Result<Option<Vec<Symbol>>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut reader = BufReader::new(file);
+
+        // The key is read first so that stale symbols aren't deserialized.
+        let key: CacheKey = bincode::deserialize_from(&mut reader)?;
+        if key != self.key {
+            log::debug!("cached symbols at `{}` are stale", self.path.display());
+            return Ok(None);
+        }
+        Ok(Some(bincode::deserialize_from(&mut reader)?))
+    }
+
+    /// Stores `symbols` in the cache, replacing any symbols that were stored before.
+    pub fn store(&self, symbols: &[Symbol]) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create `{}`", dir.display()))?;
+        }
+
+        // Other processes could be reading the entry, so it is written to
+        // a temporary file and then moved into place.
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = PathBuf::from(temp_path);
+
+        let result = File::create(&temp_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                bincode::serialize_into(&mut writer, &self.key)?;
+                bincode::serialize_into(&mut writer, symbols)?;
+                writer.into_inner().map_err(|err| err.into_error())?;
+                Ok(())
+            })
+            .and_then(|_| fs::rename(&temp_path, &self.path).map_err(anyhow::Error::from));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result.with_context(|| format!("failed to write `{}`", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::disasm::symbol::{Demangle, SymbolSource};
+
+    fn options(sources: &[SymbolSource]) -> SearchOptions<'_> {
+        SearchOptions {
+            sources,
+            auto_sources_threshold: crate::disasm::binary::AUTO_SOURCES_THRESHOLD,
+            demangle: Demangle::Auto,
+            dwarf_path: None,
+            dsym_path: None,
+            pdb_path: None,
+            mach_arch: None,
+            cancel: None,
+            dwarf_declarations: false,
+            symbol_cache: None,
+        }
+    }
+
+    #[test]
+    fn symbols_round_trip_until_options_change() {
+        let dir = std::env::temp_dir().join(format!("disasm-cache-test-{}", std::process::id()));
+        // Any file works as the binary, only its metadata is used.
+        let binary = std::env::current_exe().unwrap();
+
+        let cache = SymbolCache::new(&dir, &binary, &options(&[])).unwrap();
+        assert!(cache.load().is_none());
+
+        let symbols = vec![
+            Symbol::new(
+                "_ZN3foo3bar17h0123456789abcdefE",
+                0x1000,
+                0,
+                16,
+                SymbolSource::Elf,
+            ),
+            Symbol::new_unmangled("baz".into(), 0x1010, 16, 4, SymbolSource::Dwarf),
+        ];
+        cache.store(&symbols).unwrap();
+        let loaded = cache.load().unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].name(), symbols[0].name());
+        assert_eq!(loaded[0].display_name(), "foo::bar");
+        assert_eq!(loaded[1].address_range(), 0x1010..0x1014);
+        assert_eq!(loaded[1].source(), SymbolSource::Dwarf);
+
+        let other = SymbolCache::new(&dir, &binary, &options(&[SymbolSource::Elf])).unwrap();
+        assert!(other.load().is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
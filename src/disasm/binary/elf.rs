@@ -1,9 +1,21 @@
-use super::{Arch, Binary, BinaryData, Bits, Endian, DWARF_SECTIONS};
-use crate::disasm::dwarf::DwarfInfo;
-use crate::disasm::symbol::{Symbol, SymbolSource};
+use super::{
+    Arch, Binary, BinaryData, Bits, Endian, SectionInfo, Segment, DWARF_SECTIONS, ENTRY_SYMBOL_NAME,
+};
+use crate::disasm::dwarf::{DwarfInfo, DwarfSymbolOptions, SplitDwarfRef};
+use crate::disasm::error::DisasmError;
+use crate::disasm::symbol::{Symbol, SymbolBinding, SymbolSource};
 use crate::util;
 use anyhow::Context as _;
-use goblin::elf::Elf;
+use goblin::elf::{Elf, Symtab};
+use goblin::strtab::Strtab;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom as _;
+use std::path::Path;
+
+/// The `e_flags` bit of ARM EABI binaries that marks a big endian binary with
+/// little endian instructions.
+const EF_ARM_BE8: u32 = 0x0080_0000;
 
 pub fn load_arch_info(binary: &mut Binary, elf: &Elf) -> anyhow::Result<()> {
     use goblin::elf::header;
@@ -17,25 +29,148 @@ pub fn load_arch_info(binary: &mut Binary, elf: &Elf) -> anyhow::Result<()> {
             .context("failed to identify ELF endianness")?,
     );
     binary.arch = Arch::from_elf_machine(elf.header.e_machine);
+    binary.be8 = binary.arch == Arch::Arm
+        && binary.endian == Endian::Big
+        && elf.header.e_flags & EF_ARM_BE8 != 0;
 
     log::debug!("object bits   = {}", binary.bits);
     log::debug!("object endian = {}", binary.endian);
+    if binary.be8 {
+        log::debug!("object code   = little-endian (BE8)");
+    }
     log::debug!("object arch   = {}", binary.arch);
 
     Ok(())
 }
 
+/// Creates a symbol for the code from the entry point (`e_entry`) to the end of the
+/// section containing it, or of the segment containing it if there are no sections.
+pub fn entry_symbol(elf: &Elf) -> Option<Symbol> {
+    use goblin::elf::program_header::PT_LOAD;
+    use goblin::elf::section_header::SHT_NOBITS;
+
+    let entry = elf.entry;
+    if entry == 0 {
+        return None;
+    }
+
+    let (offset, size) = elf
+        .section_headers
+        .iter()
+        .filter(|section| section.sh_type != SHT_NOBITS && section.sh_addr != 0)
+        .find(|section| (section.sh_addr..section.sh_addr + section.sh_size).contains(&entry))
+        .map(|section| {
+            let delta = entry - section.sh_addr;
+            (section.sh_offset + delta, section.sh_size - delta)
+        })
+        .or_else(|| {
+            elf.program_headers
+                .iter()
+                .filter(|header| header.p_type == PT_LOAD)
+                .find(|header| (header.p_vaddr..header.p_vaddr + header.p_filesz).contains(&entry))
+                .map(|header| {
+                    let delta = entry - header.p_vaddr;
+                    (header.p_offset + delta, header.p_filesz - delta)
+                })
+        })?;
+
+    Some(Symbol::new_unmangled(
+        ENTRY_SYMBOL_NAME.into(),
+        entry,
+        offset as usize,
+        size as usize,
+        SymbolSource::Elf,
+    ))
+}
+
+/// Returns the `PT_LOAD` segments of the object, which are loaded into memory.
+pub fn load_segments(elf: &Elf) -> Vec<Segment> {
+    use goblin::elf::program_header::PT_LOAD;
+
+    elf.program_headers
+        .iter()
+        .filter(|header| header.p_type == PT_LOAD && header.p_filesz > 0)
+        .map(|header| Segment {
+            address: header.p_vaddr,
+            size: header.p_filesz,
+            offset: header.p_offset as usize,
+        })
+        .collect()
+}
+
+/// Returns the section headers of the object, for `--sections`.
+pub fn load_section_info(elf: &Elf) -> anyhow::Result<Vec<SectionInfo>> {
+    use goblin::elf::section_header::{SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_NULL};
+
+    let mut sections = Vec::new();
+    for section in elf.section_headers.iter() {
+        if section.sh_type == SHT_NULL {
+            continue;
+        }
+        let name = elf
+            .shdr_strtab
+            .get(section.sh_name)
+            .transpose()
+            .context("failed to retrieve ELF section name")?;
+        sections.push(SectionInfo {
+            name: name.unwrap_or("").into(),
+            address: section.sh_addr,
+            offset: section.sh_offset,
+            size: section.sh_size,
+            loaded: section.sh_flags & SHF_ALLOC as u64 != 0,
+            writable: section.sh_flags & SHF_WRITE as u64 != 0,
+            executable: section.sh_flags & SHF_EXECINSTR as u64 != 0,
+        });
+    }
+    Ok(sections)
+}
+
 pub fn load_symbols(elf: &Elf, symbols: &mut Vec<Symbol>) -> anyhow::Result<()> {
-    for sym in elf.syms.iter().filter(|sym| sym.is_function()) {
+    load_symtab_symbols(elf, &elf.syms, &elf.strtab, &[], SymbolSource::Elf, symbols)
+}
+
+/// Loads the functions in the dynamic symbol table (`.dynsym`). This table is kept
+/// when a shared library is stripped, so its exported functions can still be found.
+/// Versioned symbols are named like `memcpy@@GLIBC_2.14`.
+pub fn load_export_symbols(
+    elf: &Elf,
+    data: &BinaryData,
+    symbols: &mut Vec<Symbol>,
+) -> anyhow::Result<()> {
+    let versions = symbol_versions(elf, data);
+    load_symtab_symbols(
+        elf,
+        &elf.dynsyms,
+        &elf.dynstrtab,
+        &versions,
+        SymbolSource::Export,
+        symbols,
+    )
+}
+
+/// Loads the symbols of a symbol table. `versions` contains the version suffix for
+/// each symbol in the table, or is empty if the symbols are not versioned.
+fn load_symtab_symbols(
+    elf: &Elf,
+    symtab: &Symtab,
+    strtab: &Strtab,
+    versions: &[Option<String>],
+    source: SymbolSource,
+    symbols: &mut Vec<Symbol>,
+) -> anyhow::Result<()> {
+    for (index, sym) in symtab
+        .iter()
+        .enumerate()
+        .filter(|(_, sym)| sym.is_function())
+    {
         // FIXME handle symbols with a size of 0 (usually external symbols).
-        if sym.st_size == 0 {
+        if sym.st_size == 0 || sym.is_import() {
             continue;
         }
 
         // FIXME maybe the error here should just be a warning instead. I'm pretty sure it's
         // recoverable :|
-        let sym_name = if let Some(name) = elf
-            .strtab
+        let sym_name = if let Some(name) = strtab
             .get(sym.st_name)
             .transpose()
             .context("failed to get ELF symbol name")?
@@ -63,16 +198,374 @@ pub fn load_symbols(elf: &Elf, symbols: &mut Vec<Symbol>) -> anyhow::Result<()>
         let sym_addr = sym.st_value;
         let sym_offset = (sym_addr - section_addr) + section_offset;
 
-        symbols.push(Symbol::new(
+        let sym_name = match versions.get(index) {
+            Some(Some(version)) => Cow::Owned(format!("{}{}", sym_name, version)),
+            _ => Cow::Borrowed(sym_name),
+        };
+
+        let mut symbol = Symbol::new(
             sym_name,
             sym_addr,
             sym_offset as usize,
             sym.st_size as usize,
+            source,
+        );
+        symbol.set_binding(symbol_binding(sym.st_bind()));
+        symbols.push(symbol);
+    }
+
+    Ok(())
+}
+
+fn symbol_binding(bind: u8) -> SymbolBinding {
+    use goblin::elf::sym::{STB_GLOBAL, STB_GNU_UNIQUE, STB_LOCAL, STB_WEAK};
+
+    match bind {
+        STB_GLOBAL | STB_GNU_UNIQUE => SymbolBinding::Global,
+        STB_WEAK => SymbolBinding::Weak,
+        STB_LOCAL => SymbolBinding::Local,
+        _ => SymbolBinding::Unknown,
+    }
+}
+
+/// Returns the version suffix of each dynamic symbol, or nothing if the versions
+/// could not be loaded.
+fn symbol_versions(elf: &Elf, data: &BinaryData) -> Vec<Option<String>> {
+    load_symbol_versions(elf, data).unwrap_or_else(|err| {
+        log::warn!("failed to load ELF symbol versions: {:#}", err);
+        Vec::new()
+    })
+}
+
+/// Version indices with special meanings in `.gnu.version`.
+const VER_NDX_LOCAL: u16 = 0;
+const VER_NDX_GLOBAL: u16 = 1;
+
+/// Set in a `.gnu.version` entry if the version is not the default version of the symbol.
+const VERSYM_HIDDEN: u16 = 0x8000;
+
+/// Set on the version definition of the file itself, which isn't a symbol version.
+const VER_FLG_BASE: u16 = 0x1;
+
+/// Reads the version of each symbol in the dynamic symbol table from `.gnu.version`,
+/// and the names of the versions from `.gnu.version_d` (versions defined by this
+/// binary) and `.gnu.version_r` (versions required from other binaries).
+///
+/// Returns a suffix for each dynamic symbol: `@@VERSION` for the default version of
+/// a symbol, `@VERSION` for other versions and required versions, or `None`.
+fn load_symbol_versions(elf: &Elf, data: &BinaryData) -> anyhow::Result<Vec<Option<String>>> {
+    use goblin::elf::section_header::{SHT_GNU_VERDEF, SHT_GNU_VERNEED, SHT_GNU_VERSYM};
+
+    let section_data = |sh_type: u32| {
+        elf.section_headers
+            .iter()
+            .find(|section| section.sh_type == sh_type)
+            .map(|section| {
+                (
+                    section_bytes(data, section.sh_offset, section.sh_size),
+                    section.sh_info,
+                )
+            })
+    };
+
+    let versym = if let Some((versym, _)) = section_data(SHT_GNU_VERSYM) {
+        versym.ok_or_else(|| anyhow::anyhow!(".gnu.version is out of bounds"))?
+    } else {
+        return Ok(Vec::new());
+    };
+
+    let read = VersionReader {
+        little_endian: elf.little_endian,
+    };
+
+    // (name, defined by this binary) for each version index.
+    let mut names: Vec<Option<(&str, bool)>> = Vec::new();
+    let mut set_name = |index: u16, name, defined: bool| {
+        let index = index as usize;
+        if names.len() <= index {
+            names.resize(index + 1, None);
+        }
+        names[index] = Some((name, defined));
+    };
+
+    if let Some((verdef, count)) = section_data(SHT_GNU_VERDEF) {
+        let verdef = verdef.ok_or_else(|| anyhow::anyhow!(".gnu.version_d is out of bounds"))?;
+        let mut offset = 0;
+        for _ in 0..count {
+            // Elf_Verdef: vd_version, vd_flags, vd_ndx, vd_cnt, vd_hash, vd_aux, vd_next
+            let flags = read.u16(verdef, offset + 2)?;
+            let index = read.u16(verdef, offset + 4)?;
+            let aux = read.u32(verdef, offset + 12)? as usize;
+            let next = read.u32(verdef, offset + 16)? as usize;
+
+            // The first Elf_Verdaux is the name of the version: vda_name, vda_next
+            if flags & VER_FLG_BASE == 0 {
+                set_name(
+                    index,
+                    version_name(elf, read.u32(verdef, offset + aux)?)?,
+                    true,
+                );
+            }
+
+            if next == 0 {
+                break;
+            }
+            offset += next;
+        }
+    }
+
+    if let Some((verneed, count)) = section_data(SHT_GNU_VERNEED) {
+        let verneed = verneed.ok_or_else(|| anyhow::anyhow!(".gnu.version_r is out of bounds"))?;
+        let mut offset = 0;
+        for _ in 0..count {
+            // Elf_Verneed: vn_version, vn_cnt, vn_file, vn_aux, vn_next
+            let aux_count = read.u16(verneed, offset + 2)?;
+            let mut aux = offset + read.u32(verneed, offset + 8)? as usize;
+            let next = read.u32(verneed, offset + 12)? as usize;
+
+            for _ in 0..aux_count {
+                // Elf_Vernaux: vna_hash, vna_flags, vna_other, vna_name, vna_next
+                let index = read.u16(verneed, aux + 6)?;
+                set_name(
+                    index,
+                    version_name(elf, read.u32(verneed, aux + 8)?)?,
+                    false,
+                );
+
+                let aux_next = read.u32(verneed, aux + 12)? as usize;
+                if aux_next == 0 {
+                    break;
+                }
+                aux += aux_next;
+            }
+
+            if next == 0 {
+                break;
+            }
+            offset += next;
+        }
+    }
+
+    let mut versions = Vec::with_capacity(versym.len() / 2);
+    for entry in versym.chunks_exact(2) {
+        let entry = read.u16(entry, 0)?;
+        let index = entry & !VERSYM_HIDDEN;
+        let name = if index == VER_NDX_LOCAL || index == VER_NDX_GLOBAL {
+            None
+        } else {
+            names.get(index as usize).copied().flatten()
+        };
+
+        versions.push(name.map(|(name, defined)| {
+            if defined && entry & VERSYM_HIDDEN == 0 {
+                format!("@@{}", name)
+            } else {
+                format!("@{}", name)
+            }
+        }));
+    }
+
+    Ok(versions)
+}
+
+fn version_name<'a>(elf: &Elf<'a>, offset: u32) -> anyhow::Result<&'a str> {
+    elf.dynstrtab
+        .get(offset as usize)
+        .transpose()
+        .context("failed to get ELF version name")?
+        .ok_or_else(|| anyhow::anyhow!("ELF version name is out of bounds"))
+}
+
+/// Returns the bytes of a section, or `None` if they are not inside of `data`.
+fn section_bytes(data: &BinaryData, offset: u64, size: u64) -> Option<&[u8]> {
+    let start = usize::try_from(offset).ok()?;
+    let end = start.checked_add(usize::try_from(size).ok()?)?;
+    data.get(start..end)
+}
+
+/// Reads the fields of the ELF version sections in the binary's byte order.
+struct VersionReader {
+    little_endian: bool,
+}
+
+impl VersionReader {
+    fn u16(&self, bytes: &[u8], offset: usize) -> anyhow::Result<u16> {
+        let bytes = bytes
+            .get(offset..offset + 2)
+            .ok_or_else(|| anyhow::anyhow!("ELF version entry is out of bounds"))?;
+        let bytes = [bytes[0], bytes[1]];
+        Ok(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, bytes: &[u8], offset: usize) -> anyhow::Result<u32> {
+        let bytes = bytes
+            .get(offset..offset + 4)
+            .ok_or_else(|| anyhow::anyhow!("ELF version entry is out of bounds"))?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+}
+
+/// Creates `<name>@plt` symbols for the PLT stubs of imported functions. Stubs are
+/// laid out in the same order as the `.rela.plt`/`.rel.plt` relocations, after
+/// the PLT header. Stubs of versioned functions are named like `memcpy@GLIBC_2.14@plt`.
+pub fn load_plt_symbols(
+    elf: &Elf,
+    data: &BinaryData,
+    arch: Arch,
+    symbols: &mut Vec<Symbol>,
+) -> anyhow::Result<()> {
+    // (header size, entry size) of the PLT for each architecture.
+    let (mut header_size, entry_size) = match arch {
+        Arch::X86 | Arch::X86_64 => (16, 16),
+        Arch::Arm => (20, 12),
+        Arch::AArch64 => (32, 16),
+        _ => return Ok(()),
+    };
+
+    // With IBT enabled, the stubs that are called are in `.plt.sec` which has no header.
+    let mut plt = None;
+    for section in elf.section_headers.iter() {
+        let section_name = elf
+            .shdr_strtab
+            .get(section.sh_name)
+            .transpose()
+            .context("failed to retrieve ELF section name")?;
+        match section_name {
+            Some(".plt.sec") => {
+                plt = Some(section);
+                header_size = 0;
+                break;
+            }
+            Some(".plt") => plt = Some(section),
+            _ => {}
+        }
+    }
+
+    let plt = if let Some(plt) = plt {
+        plt
+    } else {
+        return Ok(());
+    };
+    let layout = PltLayout {
+        address: plt.sh_addr,
+        offset: plt.sh_offset,
+        size: plt.sh_size,
+        header_size,
+        entry_size,
+    };
+
+    let versions = symbol_versions(elf, data);
+    let mut imports = Vec::with_capacity(elf.pltrelocs.len());
+    for reloc in elf.pltrelocs.iter() {
+        let name = elf
+            .dynsyms
+            .get(reloc.r_sym)
+            .and_then(|sym| elf.dynstrtab.get(sym.st_name))
+            .transpose()
+            .context("failed to get ELF dynamic symbol name")?;
+        let version = versions
+            .get(reloc.r_sym)
+            .and_then(Option::as_deref)
+            .unwrap_or("");
+        imports.push(name.map(|name| (name, version)));
+    }
+
+    push_plt_symbols(&layout, &imports, symbols);
+    Ok(())
+}
+
+/// Where the PLT stubs are in the binary.
+struct PltLayout {
+    /// The address of the section that has the stubs.
+    address: u64,
+    /// The offset of the section in the binary's data.
+    offset: u64,
+    /// The size of the section in bytes.
+    size: u64,
+    /// The size of the header before the first stub.
+    header_size: u64,
+    /// The size of each stub.
+    entry_size: u64,
+}
+
+/// Names each stub of the PLT after its import, the name and version of the
+/// dynamic symbol of the PLT relocation at the same index. Imports without a name
+/// don't get a symbol.
+fn push_plt_symbols(
+    layout: &PltLayout,
+    imports: &[Option<(&str, &str)>],
+    symbols: &mut Vec<Symbol>,
+) {
+    for (index, import) in imports.iter().enumerate() {
+        let stub_offset = layout.header_size + (index as u64 * layout.entry_size);
+        if stub_offset + layout.entry_size > layout.size {
+            log::warn!("PLT relocation {} does not have a stub in the PLT", index);
+            break;
+        }
+
+        let (name, version) = match import {
+            Some((name, version)) if !name.is_empty() => (name, version),
+            _ => continue,
+        };
+
+        symbols.push(Symbol::new(
+            format!("{}{}@plt", name, version),
+            layout.address + stub_offset,
+            (layout.offset + stub_offset) as usize,
+            layout.entry_size as usize,
             SymbolSource::Elf,
         ));
     }
+}
 
-    Ok(())
+/// Reads the constructor and destructor pointers in `.init_array` and `.fini_array`.
+/// Position independent binaries can leave the entries empty and fill them in with
+/// relative relocations, so the addend of an entry's relocation is used if it has one.
+pub fn load_function_arrays(elf: &Elf, data: &BinaryData) -> Vec<(&'static str, Vec<u64>)> {
+    use goblin::elf::section_header::{SHT_FINI_ARRAY, SHT_INIT_ARRAY};
+
+    let pointer_size = if elf.is_64 { 8 } else { 4 };
+    let addends = elf
+        .dynrelas
+        .iter()
+        .filter_map(|reloc| Some((reloc.r_offset, reloc.r_addend?)))
+        .collect::<HashMap<u64, i64>>();
+
+    let mut arrays = Vec::new();
+    for &(sh_type, name) in &[
+        (SHT_INIT_ARRAY, ".init_array"),
+        (SHT_FINI_ARRAY, ".fini_array"),
+    ] {
+        for section in elf.section_headers.iter() {
+            if section.sh_type != sh_type {
+                continue;
+            }
+            let bytes = match section_bytes(data, section.sh_offset, section.sh_size) {
+                Some(bytes) => bytes,
+                None => continue,
+            };
+            let pointers = super::read_pointers(bytes, pointer_size, elf.little_endian)
+                .enumerate()
+                .map(|(idx, pointer)| {
+                    let address = section.sh_addr + (idx * pointer_size) as u64;
+                    addends
+                        .get(&address)
+                        .map_or(pointer, |&addend| addend as u64)
+                })
+                .collect();
+            arrays.push((name, pointers));
+        }
+    }
+    arrays
 }
 
 pub fn load_dwarf(elf: &Elf, endian: Endian, data: &BinaryData) -> anyhow::Result<Box<DwarfInfo>> {
@@ -87,13 +580,89 @@ pub fn load_dwarf(elf: &Elf, endian: Endian, data: &BinaryData) -> anyhow::Resul
 
     let sup_loader = |_section: gimli::SectionId| Ok(EndianReader::new(data.slice(0..0), endian));
 
-    Ok(Box::new(DwarfInfo::new(loader, sup_loader)?))
+    let mut dwarf = Box::new(DwarfInfo::new(loader, sup_loader)?);
+    let split_refs = dwarf.split_dwarf_refs();
+
+    // FIXME DWARF packages (`.dwp`) require reading the CU/TU index sections
+    //       which our version of gimli does not support yet, so only split
+    //       DWARF objects that are still next to the binary are loaded.
+    let dwp_path = data.path().with_extension("dwp");
+    let missing = split_refs
+        .iter()
+        .any(|split_ref| !split_ref.path().is_file());
+    let packaged = missing && dwp_path.is_file();
+    if packaged {
+        log::warn!(
+            "DWARF packages are not supported, `{}` was not loaded (build with `-Csplit-debuginfo=unpacked` instead)",
+            dwp_path.display()
+        );
+    }
+
+    for split_ref in split_refs {
+        if packaged && !split_ref.path().is_file() {
+            continue;
+        }
+        let split_path = split_ref.path().to_path_buf();
+        if let Err(err) = load_split_dwarf(&mut dwarf, split_ref, endian) {
+            log::warn!(
+                "failed to load split DWARF `{}`: {:?}",
+                split_path.display(),
+                err
+            );
+        }
+    }
+
+    Ok(dwarf)
+}
+
+/// Loads DWARF debug information from a separate ELF file, like one created
+/// by `objcopy --only-keep-debug`.
+pub fn load_separate_dwarf(path: &Path, endian: Endian) -> anyhow::Result<Box<DwarfInfo>> {
+    let data = BinaryData::from_path(path)?;
+    let elf = Elf::parse(&data)
+        .with_context(|| format!("failed to parse DWARF object `{}`", path.display()))?;
+    if !contains_dwarf(&elf) {
+        return Err(DisasmError::MissingDebugInfo(path.to_path_buf()).into());
+    }
+
+    log::debug!("loading DWARF from `{}`", path.display());
+    load_dwarf(&elf, endian, &data)
+}
+
+/// Loads the `.dwo` file referenced by a skeleton unit.
+fn load_split_dwarf(
+    dwarf: &mut DwarfInfo,
+    split_ref: SplitDwarfRef,
+    endian: gimli::RunTimeEndian,
+) -> anyhow::Result<()> {
+    use gimli::EndianReader;
+
+    if !split_ref.path().is_file() {
+        return Err(anyhow::anyhow!("file does not exist"));
+    }
+
+    let dwo_data = BinaryData::from_path(split_ref.path())?;
+    let dwo_elf = Elf::parse(&dwo_data).context("failed to parse split DWARF object")?;
+
+    let loader = |section: gimli::SectionId| {
+        if let Some(name) = section.dwo_name() {
+            section_by_name(&dwo_elf, name, &dwo_data).map(|d| EndianReader::new(d, endian))
+        } else {
+            Ok(EndianReader::new(dwo_data.slice(0..0), endian))
+        }
+    };
+    let sup_loader =
+        |_section: gimli::SectionId| Ok(EndianReader::new(dwo_data.slice(0..0), endian));
+
+    log::debug!("loading split DWARF `{}`", split_ref.path().display());
+    dwarf.add_split_dwarf(split_ref, loader, sup_loader)
 }
 
 pub fn load_dwarf_symbols(
     dwarf: &DwarfInfo,
     elf: &Elf,
     symbols: &mut Vec<Symbol>,
+    options: DwarfSymbolOptions,
 ) -> anyhow::Result<()> {
     let mut sections: Vec<(std::ops::Range<u64>, usize)> = elf
         .section_headers
@@ -120,7 +689,7 @@ pub fn load_dwarf_symbols(
             })
     };
 
-    dwarf.load_symbols(symbols, addr_to_offset)?;
+    dwarf.load_symbols(symbols, addr_to_offset, options)?;
 
     Ok(())
 }
@@ -154,3 +723,63 @@ fn section_by_name(elf: &Elf, name: &str, data: &BinaryData) -> anyhow::Result<B
     }
     Ok(data.slice(0..0))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plt_symbols_are_named_after_imports() {
+        let layout = PltLayout {
+            address: 0x1020,
+            offset: 0x20,
+            size: 16 + 3 * 16,
+            header_size: 16,
+            entry_size: 16,
+        };
+        let imports = [
+            Some(("puts", "")),
+            None,
+            Some(("memcpy", "@GLIBC_2.14")),
+            // There is no stub left in the section for this one.
+            Some(("free", "")),
+        ];
+        let mut symbols = Vec::new();
+        push_plt_symbols(&layout, &imports, &mut symbols);
+
+        let stubs = symbols
+            .iter()
+            .map(|sym| (sym.name(), sym.address(), sym.offset(), sym.size()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stubs,
+            [
+                ("puts@plt", 0x1030, 0x30, 16),
+                ("memcpy@GLIBC_2.14@plt", 0x1050, 0x50, 16),
+            ]
+        );
+    }
+
+    #[test]
+    fn plt_sec_stubs_have_no_header() {
+        let layout = PltLayout {
+            address: 0x1100,
+            offset: 0x100,
+            size: 32,
+            header_size: 0,
+            entry_size: 16,
+        };
+        let mut symbols = Vec::new();
+        push_plt_symbols(
+            &layout,
+            &[Some(("puts", "")), Some(("", "")), Some(("exit", ""))],
+            &mut symbols,
+        );
+
+        let stubs = symbols
+            .iter()
+            .map(|sym| (sym.name(), sym.address()))
+            .collect::<Vec<_>>();
+        assert_eq!(stubs, [("puts@plt", 0x1100)]);
+    }
+}
@@ -1,10 +1,16 @@
-use super::{Arch, Binary, BinaryData, Bits, Endian, DWARF_SECTIONS};
-use crate::disasm::dwarf::DwarfInfo;
+use super::{
+    Arch, Binary, BinaryData, Bits, Endian, SectionInfo, Segment, DWARF_SECTIONS, ENTRY_SYMBOL_NAME,
+};
+use crate::disasm::dwarf::{DwarfInfo, DwarfSymbolOptions};
+use crate::disasm::error::DisasmError;
 use crate::disasm::symbol::{Symbol, SymbolSource};
 use crate::util;
 use anyhow::Context as _;
+use goblin::mach::fat::FatArch;
+use goblin::mach::load_command::CommandVariant;
 use goblin::mach::segment::Section;
-use goblin::mach::{Mach, MachO};
+use goblin::mach::symbols::Nlist;
+use goblin::mach::{Mach, MachO, MultiArch};
 use std::path::{Path, PathBuf};
 
 pub fn load_arch_info(binary: &mut Binary, mach: &MachO) -> anyhow::Result<()> {
@@ -29,11 +35,48 @@ pub fn load_arch_info(binary: &mut Binary, mach: &MachO) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Creates a symbol for the code from the entry point (`LC_MAIN` or `LC_UNIXTHREAD`)
+/// to the end of the section containing it.
+pub fn entry_symbol(mach: &MachO, sections: &[Section]) -> Option<Symbol> {
+    let entry = mach.entry;
+    if entry == 0 {
+        return None;
+    }
+
+    let section = sections
+        .iter()
+        .find(|section| (section.addr..section.addr + section.size).contains(&entry))?;
+    let delta = entry - section.addr;
+    Some(Symbol::new_unmangled(
+        ENTRY_SYMBOL_NAME.into(),
+        entry,
+        section.offset as usize + delta as usize,
+        (section.size - delta) as usize,
+        SymbolSource::Mach,
+    ))
+}
+
 pub fn load_symbols(
     mach: &MachO,
     sections: &[Section],
     symbols: &mut Vec<Symbol>,
 ) -> anyhow::Result<()> {
+    let mut stabs = Vec::new();
+    let mut symbols_it = mach.symbols();
+    while let Some(Ok(entry)) = symbols_it.next() {
+        stabs.push(entry);
+    }
+    load_stab_symbols(stabs, sections, symbols);
+    Ok(())
+}
+
+/// Creates function symbols from the `N_FUN` stabs in a Mach-O symbol table.
+/// Entries that aren't stabs are skipped.
+fn load_stab_symbols<'a>(
+    entries: impl IntoIterator<Item = (&'a str, Nlist)>,
+    sections: &[Section],
+    symbols: &mut Vec<Symbol>,
+) {
     use goblin::mach::symbols;
 
     // The starting index for Mach symbols in the `symbols` vector.
@@ -43,9 +86,24 @@ pub fn load_symbols(
     // This will be used for figuring out where symbols end.
     let mut symbol_addresses = Vec::<u64>::with_capacity(32);
 
-    let mut symbols_it = mach.symbols();
-    while let Some(Ok((sym_name, sym))) = symbols_it.next() {
-        if sym.n_sect == symbols::NO_SECT as usize || !sym.is_stab() {
+    // The index in `symbols` of the last function whose end stab hasn't been seen yet.
+    let mut open_function: Option<usize> = None;
+
+    for (sym_name, sym) in entries {
+        if !sym.is_stab() {
+            continue;
+        }
+
+        // The `N_FUN` stab at the start of a function is followed by an `N_FUN` stab
+        // without a name whose value is the size of the function.
+        if sym.n_type == MACH_TYPE_FUNC && sym_name.is_empty() {
+            if let Some(idx) = open_function.take() {
+                symbols[idx].set_size(sym.n_value as usize);
+            }
+            continue;
+        }
+
+        if sym.n_sect == symbols::NO_SECT as usize {
             continue;
         }
 
@@ -62,11 +120,12 @@ pub fn load_symbols(
             continue;
         };
 
+        open_function = Some(symbols.len());
         symbols.push(Symbol::new(
             sym_name,
             sym_addr,
             sym_offset as usize,
-            0, // this is fixed later
+            0, // this is fixed later if there is no end stab
             SymbolSource::Mach,
         ));
     }
@@ -74,8 +133,13 @@ pub fn load_symbols(
     symbol_addresses.sort_unstable();
     symbol_addresses.dedup();
 
-    // Figure out where symbols end by using the starting address of the next symbol.
+    // Figure out where symbols without an end stab end by using the starting address
+    // of the next symbol.
     for symbol in &mut symbols[mach_symbols_idx..] {
+        if symbol.size() != 0 {
+            continue;
+        }
+
         if let Ok(idx) = symbol_addresses.binary_search(&symbol.address()) {
             if let Some(next_addr) = symbol_addresses.get(idx + 1) {
                 symbol.set_size((next_addr - symbol.address()) as usize);
@@ -84,16 +148,61 @@ pub fn load_symbols(
         };
         symbol.set_address(0);
     }
+}
 
-    Ok(())
+/// Selects the slice of a fat Mach-O binary matching `arch`. If no architecture
+/// is requested, the host architecture is preferred, falling back to the first slice.
+pub fn select_fat_arch(multi: &MultiArch, arch: Option<Arch>) -> anyhow::Result<(usize, FatArch)> {
+    let fat_arches = multi
+        .arches()
+        .context("failed to read architectures from fat Mach binary")?;
+    let slice_arches = fat_arches
+        .iter()
+        .map(|fat_arch| Arch::from_mach_cpu_types(fat_arch.cputype, fat_arch.cpusubtype))
+        .collect::<Vec<Arch>>();
+
+    log::info!(
+        "fat Mach binary contains slices: {}",
+        slice_arches
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+
+    let wanted = arch.unwrap_or_else(Arch::host);
+    let index = match slice_arches.iter().position(|&a| a == wanted) {
+        Some(index) => index,
+        None if arch.is_some() => {
+            return Err(anyhow::anyhow!(
+                "fat Mach binary does not contain a slice for `{}`",
+                wanted
+            ))
+        }
+        None if fat_arches.is_empty() => {
+            return Err(anyhow::anyhow!(
+                "fat Mach binary does not contain any slices"
+            ))
+        }
+        None => 0,
+    };
+
+    log::debug!(
+        "selected {} slice from fat Mach binary",
+        slice_arches[index]
+    );
+    Ok((index, fat_arches[index]))
 }
 
 pub fn load_dwarf(
+    mach: &MachO,
     sections: &[Section],
+    arch: Arch,
     endian: Endian,
     data: &BinaryData,
+    dsym_path: Option<&Path>,
 ) -> anyhow::Result<Option<Box<DwarfInfo>>> {
-    if let dwarf @ Some(_) = load_dsym_dwarf(data)? {
+    if let dwarf @ Some(_) = load_dsym_dwarf(mach, arch, data, dsym_path)? {
         return Ok(dwarf);
     }
 
@@ -111,16 +220,30 @@ pub fn load_dwarf(
     Ok(Some(Box::new(DwarfInfo::new(loader, sup_loader)?)))
 }
 
-fn load_dsym_dwarf(data: &BinaryData) -> anyhow::Result<Option<Box<DwarfInfo>>> {
-    let dsym_directory = if let Some(d) = find_dsym_directory(data.path()) {
-        d
+/// Loads DWARF debug information from a dSYM bundle. If `dsym_path` is `None`
+/// the dSYM directory is searched for next to the executable, otherwise it may
+/// either be the dSYM directory or the DWARF object file inside of it.
+fn load_dsym_dwarf(
+    executable: &MachO,
+    arch: Arch,
+    data: &BinaryData,
+    dsym_path: Option<&Path>,
+) -> anyhow::Result<Option<Box<DwarfInfo>>> {
+    let explicit = dsym_path.is_some();
+    let dsym_path = if let Some(p) = dsym_path
+        .map(Path::to_path_buf)
+        .or_else(|| find_dsym_directory(data.path()))
+    {
+        p
     } else {
         return Ok(None);
     };
 
-    log::trace!("found dSYM directory: {}", dsym_directory.display());
-    let object_path = {
-        let mut o_path = dsym_directory;
+    let object_path = if dsym_path.is_file() {
+        dsym_path
+    } else {
+        log::trace!("found dSYM directory: {}", dsym_path.display());
+        let mut o_path = dsym_path;
         o_path.push("Contents");
         o_path.push("Resources");
         o_path.push("DWARF");
@@ -133,6 +256,9 @@ fn load_dsym_dwarf(data: &BinaryData) -> anyhow::Result<Option<Box<DwarfInfo>>>
     };
 
     if !object_path.is_file() {
+        if explicit {
+            return Err(DisasmError::MissingDebugInfo(object_path).into());
+        }
         log::trace!(
             "did not find dSYM DWARF object file at expected path: {}",
             object_path.display()
@@ -148,13 +274,35 @@ fn load_dsym_dwarf(data: &BinaryData) -> anyhow::Result<Option<Box<DwarfInfo>>>
     let data = BinaryData::from_path(&object_path).context("failed to load Mach-O DWARF binary")?;
     let mach = Mach::parse(&data)
         .with_context(|| format!("failed to parse Mach-O binary {}", object_path.display()))?;
-    let mach = match mach {
-        goblin::mach::Mach::Fat(multi) => multi
-            .get(0)
-            .context("failed to get first object from fat Mach binary")?,
-        goblin::mach::Mach::Binary(obj) => obj,
+    let (mach, data) = match mach {
+        goblin::mach::Mach::Fat(multi) => {
+            let (index, fat_arch) = select_fat_arch(&multi, Some(arch))?;
+            let obj = multi
+                .get(index)
+                .context("failed to get object from fat Mach binary")?;
+            let start = fat_arch.offset as usize;
+            (obj, data.slice(start..start + fat_arch.size as usize))
+        }
+        goblin::mach::Mach::Binary(obj) => (obj, data.clone()),
     };
 
+    // A dSYM from a different build has addresses that do not match the
+    // executable, so one that was found automatically is ignored. One that was
+    // passed explicitly is still used because the user asked for it.
+    if let (Some(expected), Some(found)) = (uuid(executable), uuid(&mach)) {
+        if expected != found {
+            log::warn!(
+                "UUID of dSYM {} ({}) does not match the UUID of the binary ({})",
+                object_path.display(),
+                UuidDisplay(&found),
+                UuidDisplay(&expected),
+            );
+            if !explicit {
+                return Ok(None);
+            }
+        }
+    }
+
     let sections = load_sections(&mach)?;
 
     let endian = if mach.little_endian {
@@ -177,6 +325,7 @@ pub fn load_dwarf_symbols(
     dwarf: &DwarfInfo,
     sections: &[Section],
     symbols: &mut Vec<Symbol>,
+    options: DwarfSymbolOptions,
 ) -> anyhow::Result<()> {
     let addr_to_offset = move |addr| {
         sections
@@ -186,10 +335,23 @@ pub fn load_dwarf_symbols(
             .ok()
             .map(|idx| (addr - sections[idx].addr) as usize + sections[idx].offset as usize)
     };
-    dwarf.load_symbols(symbols, addr_to_offset)?;
+    dwarf.load_symbols(symbols, addr_to_offset, options)?;
     Ok(())
 }
 
+/// Returns the segments of the object that are loaded from the file.
+pub fn load_segments(mach: &MachO) -> Vec<Segment> {
+    mach.segments
+        .iter()
+        .filter(|segment| segment.filesize > 0)
+        .map(|segment| Segment {
+            address: segment.vmaddr,
+            size: segment.filesize,
+            offset: segment.fileoff as usize,
+        })
+        .collect()
+}
+
 pub fn load_sections(mach: &MachO) -> anyhow::Result<Vec<Section>> {
     let mut sections: Vec<Section> = Vec::new();
     for segment in mach.segments.iter() {
@@ -201,6 +363,86 @@ pub fn load_sections(mach: &MachO) -> anyhow::Result<Vec<Section>> {
     Ok(sections)
 }
 
+/// Describes the `sections` from [`load_sections`], named like `__TEXT,__text`,
+/// for `--sections`. A section is writable if its segment is.
+pub fn load_section_info(mach: &MachO, sections: &[Section]) -> Vec<SectionInfo> {
+    use goblin::mach::constants::{
+        S_ATTR_PURE_INSTRUCTIONS, S_ATTR_SOME_INSTRUCTIONS, VM_PROT_WRITE,
+    };
+
+    sections
+        .iter()
+        .map(|section| {
+            let segname = section.segname().unwrap_or("");
+            let writable = mach.segments.iter().any(|segment| {
+                segment.name().ok() == Some(segname) && segment.initprot & VM_PROT_WRITE != 0
+            });
+            SectionInfo {
+                name: format!("{},{}", segname, section.name().unwrap_or("")).into(),
+                address: section.addr,
+                offset: section.offset as u64,
+                size: section.size,
+                loaded: true,
+                writable,
+                executable: section.flags & (S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS)
+                    != 0,
+            }
+        })
+        .collect()
+}
+
+/// Reads the constructor and destructor pointers in the `__mod_init_func` and
+/// `__mod_term_func` sections. Only plain pointers are read, the pointers of binaries
+/// with chained fixups don't point into a segment and are ignored later.
+pub fn load_function_arrays(
+    mach: &MachO,
+    sections: &[Section],
+    data: &BinaryData,
+) -> Vec<(&'static str, Vec<u64>)> {
+    let pointer_size = if mach.is_64 { 8 } else { 4 };
+
+    let mut arrays = Vec::new();
+    for section in sections {
+        let name = match section.name() {
+            Ok("__mod_init_func") => "__mod_init_func",
+            Ok("__mod_term_func") => "__mod_term_func",
+            _ => continue,
+        };
+        let start = section.offset as usize;
+        let bytes = match data.get(start..start.saturating_add(section.size as usize)) {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+        let pointers = super::read_pointers(bytes, pointer_size, mach.little_endian).collect();
+        arrays.push((name, pointers));
+    }
+    arrays
+}
+
+/// Returns the UUID from the `LC_UUID` load command of a Mach-O object.
+fn uuid(mach: &MachO) -> Option<[u8; 16]> {
+    mach.load_commands
+        .iter()
+        .find_map(|command| match command.command {
+            CommandVariant::Uuid(ref uuid) => Some(uuid.uuid),
+            _ => None,
+        })
+}
+
+struct UuidDisplay<'a>(&'a [u8; 16]);
+
+impl std::fmt::Display for UuidDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (idx, byte) in self.0.iter().enumerate() {
+            if idx == 4 || idx == 6 || idx == 8 || idx == 10 {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
 /// Find the dSYM directory relative to an executable.
 fn find_dsym_directory(executable_path: &Path) -> Option<PathBuf> {
     let executable_dir = executable_path.parent()?;
@@ -269,3 +511,64 @@ const MACH_DWARF_SECTIONS: &[&str] = &[
     "__debug_ranges",
     "__debug_rnglists",
 ];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const N_SO: u8 = 0x64;
+
+    fn stab(n_type: u8, n_sect: usize, n_value: u64) -> Nlist {
+        Nlist {
+            n_strx: 0,
+            n_type,
+            n_sect,
+            n_desc: 0,
+            n_value,
+        }
+    }
+
+    fn text_section() -> Section {
+        Section {
+            addr: 0x1000,
+            size: 0x100,
+            offset: 0x400,
+            ..Section::default()
+        }
+    }
+
+    #[test]
+    fn test_end_stab_sets_function_size() {
+        let entries = vec![
+            ("_first", stab(MACH_TYPE_FUNC, 1, 0x1000)),
+            ("", stab(MACH_TYPE_FUNC, 0, 0x18)),
+            ("_second", stab(MACH_TYPE_FUNC, 1, 0x1040)),
+            ("", stab(MACH_TYPE_FUNC, 0, 0x20)),
+        ];
+        let mut symbols = Vec::new();
+        load_stab_symbols(entries, &[text_section()], &mut symbols);
+
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols[0].name(), "_first");
+        assert_eq!(symbols[0].offset(), 0x400);
+        assert_eq!(symbols[0].size(), 0x18);
+        assert_eq!(symbols[1].name(), "_second");
+        assert_eq!(symbols[1].offset(), 0x440);
+        assert_eq!(symbols[1].size(), 0x20);
+    }
+
+    #[test]
+    fn test_missing_end_stab_uses_next_address() {
+        let entries = vec![
+            ("_first", stab(MACH_TYPE_FUNC, 1, 0x1000)),
+            ("_second", stab(MACH_TYPE_FUNC, 1, 0x1040)),
+            ("", stab(MACH_TYPE_FUNC, 0, 0x20)),
+            ("end.o", stab(N_SO, 1, 0x1060)),
+        ];
+        let mut symbols = Vec::new();
+        load_stab_symbols(entries, &[text_section()], &mut symbols);
+
+        assert_eq!(symbols[0].size(), 0x40);
+        assert_eq!(symbols[1].size(), 0x20);
+    }
+}
@@ -1,27 +1,34 @@
+mod cache;
 mod elf;
 mod mach;
 mod pe;
 
-use super::dwarf::DwarfInfo;
-use super::pdb::PDBInfo;
-use super::strmatch::{distance, Tokenizer};
-use super::symbol::{Symbol, SymbolSource};
+use super::dwarf::{DwarfInfo, DwarfSymbolOptions};
+use super::error::DisasmError;
+use super::source::SourceLocation;
+use super::strmatch::{match_score, Tokenizer};
+use super::symbol::{Demangle, Symbol, SymbolKind, SymbolSource};
 use crate::util;
 use anyhow::Context as _;
+use cache::SymbolCache;
 
 use goblin::{archive::Archive, elf::Elf, mach::MachO, pe::PE, Object};
 use memmap::{Mmap, MmapOptions};
+use regex::Regex;
+use std::collections::{BTreeSet, HashMap};
 use std::convert::TryFrom as _;
 use std::fmt;
 use std::fs::File;
 use std::io::{self, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
-/// Threshold for using all available sources when symbol sources is 'auto'.
-/// While there are less than `AUTO_SOURCES_THRESHOLD` symbols loaded
-/// and symsrc is `auto`, the binary may keep loading more sources.
-const AUTO_SOURCES_THRESHOLD: usize = 128 * 1024;
+/// The name of the symbol created for the entry point when it isn't covered by a symbol.
+const ENTRY_SYMBOL_NAME: &str = "entry";
+
+/// The default for [`SearchOptions::auto_sources_threshold`].
+pub const AUTO_SOURCES_THRESHOLD: usize = 128 * 1024;
 
 pub struct Binary {
     /// Shared binary data. This must be pinned because it is referred to
@@ -30,19 +37,92 @@ pub struct Binary {
     /// DWARF debugging information that was found.
     dwarf: Option<Box<DwarfInfo>>,
 
-    /// PDB debugging information that was found.
-    pdb: Option<Box<PDBInfo>>,
+    /// The data of the PDB that was found. The reader of the `pdb` crate can't be
+    /// sent between threads, so it is only opened while loading symbols and is
+    /// dropped on the same thread afterwards.
+    pdb: Option<BinaryData>,
 
     arch: Arch,
     endian: Endian,
     bits: Bits,
 
+    /// True for big endian ARM binaries that use the BE8 scheme (ARMv6 and later),
+    /// where data is big endian but instructions are still little endian. Older
+    /// BE32 binaries have big endian instructions as well.
+    be8: bool,
+
+    /// The address that the binary was loaded at. This has been added to the
+    /// address of every symbol.
+    base: u64,
+
     /// A vector of symbols that are sorted by their address in ascending order.
     symbols: Vec<Symbol>,
+
+    /// The index in `symbols` of the function with each raw name, for
+    /// [`Binary::find_symbol_exact`].
+    names: HashMap<Box<str>, usize>,
+
+    /// A symbol for the code starting at the entry point of the binary, which
+    /// is not in `symbols`.
+    entry: Option<Symbol>,
+
+    /// The parts of the file that are loaded into memory, for finding the bytes
+    /// at an address.
+    segments: Vec<Segment>,
+
+    /// The sections from the binary's headers, for `--sections`.
+    sections: Vec<SectionInfo>,
+
+    /// How long the phases of loading the binary took, for `--timings`.
+    load_timings: LoadTimings,
+}
+
+/// How long [`Binary::new`] spent in each phase of loading a binary.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct LoadTimings {
+    /// Parsing the object file and loading its symbols.
+    pub parse: std::time::Duration,
+    /// Sorting the symbols by address. This is zero for symbols from the cache.
+    pub sort: std::time::Duration,
+}
+
+/// A section of a binary in a form that is the same for every object format.
+#[derive(Debug, Clone)]
+pub struct SectionInfo {
+    /// The name of the section. Mach-O sections are prefixed with their segment,
+    /// like `__TEXT,__text`.
+    pub name: Box<str>,
+    /// The address that the section is loaded at, which is rebased with the binary.
+    pub address: u64,
+    /// The offset of the section in the file.
+    pub offset: u64,
+    pub size: u64,
+    /// True if the section is loaded into memory. Sections like the ELF debug
+    /// information sections are only in the file.
+    pub loaded: bool,
+    pub writable: bool,
+    /// True if the section contains instructions.
+    pub executable: bool,
+}
+
+/// A range of addresses that is loaded from the file of a binary.
+#[derive(Debug, Copy, Clone)]
+struct Segment {
+    /// The address from the binary's headers, which isn't rebased.
+    address: u64,
+    size: u64,
+    offset: usize,
+}
+
+// A `Binary` is shared between the threads that disassemble its symbols.
+#[allow(dead_code)]
+fn assert_binary_is_send_and_sync() {
+    fn assert<T: Send + Sync>() {}
+    assert::<Binary>();
 }
 
 impl Binary {
-    pub fn new(data: BinaryData, options: SearchOptions) -> anyhow::Result<Binary> {
+    pub fn new(data: BinaryData, options: SearchOptions) -> Result<Binary, DisasmError> {
         let mut binary = Binary {
             data,
             dwarf: None,
@@ -51,27 +131,402 @@ impl Binary {
             arch: Arch::Unknown,
             endian: Endian::Unknown,
             bits: Bits::Unknown,
+            be8: false,
+
+            base: 0,
 
             symbols: Vec::new(),
+            names: HashMap::new(),
+            entry: None,
+            segments: Vec::new(),
+            sections: Vec::new(),
+            load_timings: LoadTimings::default(),
         };
 
-        binary.parse_object(options).map(|_| {
-            let symbol_sort_timer = std::time::Instant::now();
-            binary.symbols.sort_unstable_by(|lhs, rhs| {
-                lhs.address()
-                    .cmp(&rhs.address())
-                    .then(lhs.end_address().cmp(&rhs.end_address()))
-            });
-            log::trace!(
-                "sorted {} symbols in {}",
-                binary.symbols.len(),
-                util::DurationDisplay(symbol_sort_timer.elapsed())
+        let cache = options.symbol_cache.and_then(|dir| {
+            SymbolCache::new(dir, binary.data.path(), &options)
+                .map_err(|err| log::debug!("symbol cache is unavailable: {:#}", err))
+                .ok()
+        });
+        let cached_symbols = cache.as_ref().and_then(SymbolCache::load);
+
+        let demangle = options.demangle;
+        let load_symbols = cached_symbols.is_none();
+        let parse_timer = std::time::Instant::now();
+        binary.parse_object(options, load_symbols)?;
+        binary.load_timings.parse = parse_timer.elapsed();
+        if let Some(symbols) = cached_symbols {
+            log::debug!("loaded {} symbols from the symbol cache", symbols.len());
+            binary.symbols = symbols;
+            binary.demangle_symbols(demangle);
+            binary.index_symbol_names();
+            return Ok(binary);
+        }
+
+        binary.demangle_symbols(demangle);
+
+        let symbol_sort_timer = std::time::Instant::now();
+        sort_symbols(&mut binary.symbols);
+        binary.load_timings.sort = symbol_sort_timer.elapsed();
+        log::trace!(
+            "sorted {} symbols in {}",
+            binary.symbols.len(),
+            util::DurationDisplay(binary.load_timings.sort)
+        );
+
+        let symbol_count = binary.symbols.len();
+        binary.symbols = dedup_symbols(std::mem::take(&mut binary.symbols));
+        log::debug!(
+            "removed {} duplicate symbols",
+            symbol_count - binary.symbols.len()
+        );
+
+        if log::log_enabled!(log::Level::Debug) {
+            let overlaps = log_overlapping_symbols(&binary.symbols);
+            log::debug!("found {} overlapping symbols", overlaps);
+        }
+        binary.index_symbol_names();
+
+        if let Some(cache) = cache {
+            if let Err(err) = cache.store(&binary.symbols) {
+                log::warn!("failed to cache symbols: {:#}", err);
+            }
+        }
+
+        Ok(binary)
+    }
+
+    /// Creates a binary from a raw image without an object file container, such
+    /// as a ROM. It has no symbols, only an entry symbol at address 0 that covers
+    /// the whole image. [`Binary::set_base`] moves it to the image's load address.
+    pub fn raw(data: BinaryData, arch: Arch) -> Binary {
+        let entry = Symbol::new_unmangled(
+            ENTRY_SYMBOL_NAME.into(),
+            0,
+            0,
+            data.len(),
+            SymbolSource::Raw,
+        );
+        let segment = Segment {
+            address: 0,
+            size: data.len() as u64,
+            offset: 0,
+        };
+
+        Binary {
+            data,
+            dwarf: None,
+            pdb: None,
+
+            arch,
+            endian: Endian::Unknown,
+            bits: Bits::Unknown,
+            be8: false,
+
+            base: 0,
+
+            symbols: Vec::new(),
+            names: HashMap::new(),
+            entry: Some(entry),
+            segments: vec![segment],
+            sections: Vec::new(),
+            load_timings: LoadTimings::default(),
+        }
+    }
+
+    /// Returns true if this binary is a raw image created by [`Binary::raw`].
+    fn is_raw(&self) -> bool {
+        self.entry
+            .as_ref()
+            .is_some_and(|entry| entry.source() == SymbolSource::Raw)
+    }
+
+    /// Returns the segment containing `address`, an address from the binary's headers
+    /// that isn't rebased.
+    fn segment_at(&self, address: u64) -> Option<&Segment> {
+        self.segments
+            .iter()
+            .find(|segment| address >= segment.address && address - segment.address < segment.size)
+    }
+
+    /// The number of bytes in all of the binary's segments.
+    fn loaded_size(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.size).sum()
+    }
+
+    /// Returns the bytes from `address`, an address from the binary's headers that
+    /// isn't rebased, to the end of the segment containing it. This is how data
+    /// that code refers to by its absolute address is read, e.g. jump tables.
+    pub fn linked_bytes(&self, address: u64) -> Option<&[u8]> {
+        let segment = self.segments.iter().find(|segment| {
+            address >= segment.address && address - segment.address < segment.size
+        })?;
+        let start = segment.offset + (address - segment.address) as usize;
+        let end = segment.offset.saturating_add(segment.size as usize);
+        self.data.get(start..std::cmp::min(end, self.data.len()))
+    }
+
+    /// Replaces the symbols and debug information of this binary with the ones from
+    /// `companion`, an unstripped build of the same program (e.g. the ELF of a flashed
+    /// image). Symbols are matched to the bytes of this binary by their address, so both
+    /// must be linked at the same addresses. A raw image is placed at the address that it
+    /// was rebased to. Symbols that run past the end of their segment in this binary are
+    /// cut short. Returns the number of symbols that are outside of this binary and
+    /// were dropped.
+    pub fn import_symbols(&mut self, companion: Binary) -> usize {
+        if self.is_raw() {
+            // A raw image has no addresses of its own, so the address that it was
+            // rebased to becomes the address that it was linked at.
+            for segment in self.segments.iter_mut() {
+                segment.address = segment.address.wrapping_add(self.base);
+            }
+            self.base = 0;
+        } else if let (Some(entry), Some(companion_entry)) =
+            (self.entry_point(), companion.entry_point())
+        {
+            let entry = entry.wrapping_sub(self.base);
+            if entry != companion_entry {
+                log::warn!(
+                    "the entry point of the symbol file (0x{:x}) is not the entry point of the binary (0x{:x})",
+                    companion_entry,
+                    entry
+                );
+            }
+        }
+
+        // Loaded sections like `.bss` can make the sizes differ a bit, but not by
+        // more than a factor of two.
+        let (size, companion_size) = (self.loaded_size(), companion.loaded_size());
+        if size / 2 > companion_size || companion_size / 2 > size {
+            log::warn!(
+                "the symbol file has {} bytes of segments but the binary has {}",
+                companion_size,
+                size
+            );
+        }
+
+        if companion.arch != self.arch {
+            log::warn!(
+                "the symbol file is for {} but the binary is {}",
+                companion.arch,
+                self.arch
+            );
+        }
+
+        let symbol_count = companion.symbols.len();
+        let mut symbols = Vec::with_capacity(symbol_count);
+        let mut clamped = 0;
+        for mut sym in companion.symbols {
+            // Declarations don't have any bytes to find.
+            if sym.kind() != SymbolKind::Declaration {
+                let segment = match self.segment_at(sym.address()) {
+                    Some(segment) => segment,
+                    None => continue,
+                };
+                let offset = sym.address() - segment.address;
+                sym.set_offset(segment.offset + offset as usize);
+
+                // The bytes after the segment aren't the rest of the symbol.
+                let available = segment.size - offset;
+                if sym.size() as u64 > available {
+                    sym.set_size(available as usize);
+                    clamped += 1;
+                }
+            }
+            sym.set_address(sym.address().wrapping_add(self.base));
+            symbols.push(sym);
+        }
+        if clamped > 0 {
+            log::warn!(
+                "{} symbols from the symbol file run past the end of their segment and were cut short",
+                clamped
             );
+        }
+        let dropped = symbol_count - symbols.len();
+        self.symbols = symbols;
+        self.index_symbol_names();
+
+        if companion.dwarf.is_some() {
+            self.dwarf = companion.dwarf;
+        }
+        if companion.pdb.is_some() {
+            self.pdb = companion.pdb;
+        }
+
+        dropped
+    }
+
+    /// Rebases the binary so that it starts at the runtime load address `base`
+    /// (e.g. from `/proc/<pid>/maps` for a position independent executable).
+    /// `base` is added to the address of every symbol, so addresses that are
+    /// passed to and returned from this binary are runtime addresses from then on.
+    pub fn set_base(&mut self, base: u64) {
+        let delta = base.wrapping_sub(self.base);
+        for sym in self.symbols.iter_mut().chain(self.entry.as_mut()) {
+            sym.set_address(sym.address().wrapping_add(delta));
+        }
+        for section in self.sections.iter_mut().filter(|section| section.loaded) {
+            section.address = section.address.wrapping_add(delta);
+        }
+        self.base = base;
+    }
+
+    /// The runtime load address that the binary was rebased to.
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Adds a function symbol named `name[N]` for each function in `array`, an array of
+    /// constructors or destructors like `.init_array`, that no symbol starts at. These
+    /// are often local or anonymous functions. Nothing records their sizes, so each
+    /// one ends at the next function or at the end of its segment.
+    fn add_function_array_symbols(&mut self, name: &str, array: &[u64], source: SymbolSource) {
+        // The lowest bit of a pointer to a Thumb function is set.
+        let thumb_mask = if self.arch == Arch::Arm { !1 } else { !0 };
+        let known = self
+            .symbols
+            .iter()
+            .filter(|sym| sym.kind() == SymbolKind::Function)
+            .map(Symbol::address)
+            .collect::<BTreeSet<u64>>();
+        let mut starts = known.clone();
+        starts.extend(array.iter().map(|&pointer| pointer & thumb_mask));
+
+        for (idx, &pointer) in array.iter().enumerate() {
+            let address = pointer & thumb_mask;
+            if address == 0 || known.contains(&address) {
+                continue;
+            }
+            let segment = match self.segments.iter().find(|segment| {
+                address >= segment.address && address - segment.address < segment.size
+            }) {
+                Some(segment) => segment,
+                None => continue,
+            };
+
+            let segment_end = segment.address + segment.size;
+            let end = starts
+                .range(address + 1..)
+                .next()
+                .map_or(segment_end, |&next| std::cmp::min(next, segment_end));
+            self.symbols.push(Symbol::new_unmangled(
+                format!("{}[{}]", name, idx),
+                address,
+                segment.offset + (address - segment.address) as usize,
+                (end - address) as usize,
+                source,
+            ));
+        }
+    }
+
+    /// Indexes the raw names of the function symbols. If several functions have
+    /// the same name, the one with the lowest address is found.
+    fn index_symbol_names(&mut self) {
+        self.names.clear();
+        for (idx, sym) in self.symbols.iter().enumerate() {
+            if sym.kind() == SymbolKind::Function {
+                self.names.entry(sym.name().into()).or_insert(idx);
+            }
+        }
+    }
+
+    /// Sets the demangler of every symbol and demangles their names in parallel.
+    /// Searching and sorting the symbols read all of the display names, so demangling
+    /// them up front on every core is faster than demangling each one when it is read.
+    fn demangle_symbols(&mut self, demangle: Demangle) {
+        use rayon::prelude::*;
+
+        self.symbols
+            .iter_mut()
+            .for_each(|sym| sym.set_demangle(demangle));
+        if demangle == Demangle::None {
+            return;
+        }
+
+        let demangle_timer = std::time::Instant::now();
+        self.symbols.par_iter().for_each(|sym| {
+            sym.display_name();
+        });
+        log::trace!(
+            "demangled {} symbols in {}",
+            self.symbols.len(),
+            util::DurationDisplay(demangle_timer.elapsed())
+        );
+    }
+
+    /// The address of the entry point from the binary's header.
+    pub fn entry_point(&self) -> Option<u64> {
+        self.entry.as_ref().map(Symbol::address)
+    }
+
+    /// Returns a symbol for the code from the entry point to the end of the section
+    /// containing it, for binaries without a symbol that covers the entry point.
+    pub fn entry_symbol(&self) -> Option<&Symbol> {
+        self.entry.as_ref()
+    }
+
+    /// The sections from the binary's headers in the order that they are listed.
+    /// A raw image doesn't have any.
+    pub fn sections(&self) -> &[SectionInfo] {
+        &self.sections
+    }
 
-            binary
+    /// Returns true if `address`, which is rebased, is in a section that is loaded and
+    /// isn't writable. Binaries without sections, like raw images, can't be checked,
+    /// so every address in them is treated as read-only.
+    pub fn is_read_only(&self, address: u64) -> bool {
+        if self.sections.is_empty() {
+            return true;
+        }
+
+        self.sections.iter().any(|section| {
+            section.loaded
+                && !section.writable
+                && address >= section.address
+                && address - section.address < section.size
         })
     }
 
+    /// How long loading this binary took. A raw image isn't parsed, so its
+    /// timings are zero.
+    pub fn load_timings(&self) -> LoadTimings {
+        self.load_timings
+    }
+
+    /// Returns all of the symbols in this binary sorted by their address
+    /// in ascending order.
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Returns the symbols with a starting address within `range`, sorted
+    /// by their address in ascending order.
+    pub fn symbols_in_range(&self, range: std::ops::Range<u64>) -> &[Symbol] {
+        let start = self
+            .symbols
+            .partition_point(|sym| sym.address() < range.start);
+        let end = self.symbols[start..].partition_point(|sym| sym.address() < range.end) + start;
+        &self.symbols[start..end]
+    }
+
+    /// Returns the other function symbols that cover exactly the same range as
+    /// `symbol`, e.g. weak symbols and functions merged by identical code folding.
+    pub fn aliases<'s>(&'s self, symbol: &Symbol) -> Vec<&'s Symbol> {
+        if symbol.kind() != SymbolKind::Function {
+            return Vec::new();
+        }
+
+        self.symbols_in_range(symbol.address()..symbol.address().saturating_add(1))
+            .iter()
+            .filter(|sym| {
+                sym.kind() == SymbolKind::Function
+                    && sym.address_range() == symbol.address_range()
+                    && !std::ptr::eq(*sym, symbol)
+                    && sym.name() != symbol.name()
+            })
+            .collect()
+    }
+
     /// Returns a symbol (and offset) for an address.
     pub fn symbolicate(&self, addr: u64) -> Option<(&Symbol, u64)> {
         let mut idx = self
@@ -79,8 +534,10 @@ impl Binary {
             .binary_search_by(|probe| util::cmp_range_to_idx(&probe.address_range(), addr))
             .ok()?;
 
-        // We might have duplicates of a symbol (e.g. from DWARF and ELF), so we want
-        // to scan backwards until we find the one with the highest priority.
+        // We might have duplicates of a symbol (e.g. from DWARF and ELF, or weak and
+        // global aliases), so we want to scan backwards until we find the one with the
+        // highest priority. Symbols with the same range are sorted by their source
+        // and then by their binding.
         while idx > 0 {
             if self.symbols[idx - 1].address_range().contains(&addr) {
                 idx -= 1;
@@ -92,24 +549,46 @@ impl Binary {
         self.symbols.get(idx).map(|sym| (sym, addr - sym.address()))
     }
 
-    // /// Returns an iterator of symbols matching the given `name` string
-    // /// and their calculated "distance" from the desired symbol name.
-    // pub fn fuzzy_list_symbols<'s, 'n: 's>(
-    //     &'s self,
-    //     name: &'n str,
-    // ) -> impl Iterator<Item = (u32, &'s Symbol)> + 's {
-    //     let tokens = Tokenizer::new(name).collect::<Vec<&str>>();
-    //     self.symbols.iter().filter_map(move |sym| {
-    //         Some((
-    //             distance(
-    //                 tokens.iter().copied(),
-    //                 Tokenizer::new(&sym.name()),
-    //                 u32::MAX,
-    //             )?,
-    //             sym,
-    //         ))
-    //     })
-    // }
+    /// Returns an iterator of symbols matching the given `name` string
+    /// and their score from [`match_score`], lower scores are better matches.
+    pub fn fuzzy_list_symbols<'s, 'n: 's>(
+        &'s self,
+        name: &'n str,
+    ) -> impl Iterator<Item = (u32, &'s Symbol)> + 's {
+        let tokens = Tokenizer::new(name).collect::<Vec<&str>>();
+        self.symbols
+            .iter()
+            .filter_map(move |sym| Some((match_score(&tokens, sym.display_name(), u32::MAX)?, sym)))
+    }
+
+    /// Returns an iterator of symbols with names matching the given regular expression.
+    pub fn list_symbols_regex<'s>(
+        &'s self,
+        regex: &'s Regex,
+    ) -> impl Iterator<Item = &'s Symbol> + 's {
+        self.symbols
+            .iter()
+            .filter(move |sym| regex.is_match(sym.display_name()))
+    }
+
+    /// Finds a symbol whose raw (mangled) name is exactly `name`. If there is no
+    /// such symbol, a symbol with the demangled name `name` is returned instead,
+    /// and then a symbol named `name` without its ELF version (`memcpy@@GLIBC_2.14`).
+    pub fn find_symbol_exact<'s>(&'s self, name: &str) -> Option<&'s Symbol> {
+        if let Some(&idx) = self.names.get(name) {
+            return Some(&self.symbols[idx]);
+        }
+
+        let functions = || {
+            self.symbols
+                .iter()
+                .filter(|sym| sym.kind() == SymbolKind::Function)
+        };
+
+        functions()
+            .find(|sym| sym.display_name() == name)
+            .or_else(|| functions().find(|sym| sym.bare_name() == name))
+    }
 
     pub fn fuzzy_find_symbol<'s>(&'s self, name: &str) -> Option<&'s Symbol> {
         let tokens = Tokenizer::new(name).collect::<Vec<&str>>();
@@ -119,12 +598,9 @@ impl Binary {
         let symbol = self
             .symbols
             .iter()
+            .filter(|sym| sym.kind() == SymbolKind::Function)
             .filter_map(|sym| {
-                let dist = distance(
-                    tokens.iter().copied(),
-                    Tokenizer::new(&sym.name()),
-                    smallest_distance,
-                )?;
+                let dist = match_score(&tokens, sym.display_name(), smallest_distance)?;
 
                 if dist < smallest_distance {
                     smallest_distance = dist;
@@ -136,9 +612,10 @@ impl Binary {
                 lhs.0
                     .cmp(&rhs.0)
                     .then_with(|| lhs.1.source().cmp(&rhs.1.source()))
+                    .then_with(|| lhs.1.binding().cmp(&rhs.1.binding()))
                     .then_with(|| lhs.1.address().cmp(&rhs.1.address()))
                     .then_with(|| lhs.1.offset().cmp(&rhs.1.offset()))
-                    .then_with(|| lhs.1.name().cmp(&rhs.1.name()))
+                    .then_with(|| lhs.1.display_name().cmp(rhs.1.display_name()))
             })
             .map(|(_, sym)| sym);
 
@@ -158,53 +635,121 @@ impl Binary {
         self.arch
     }
 
+    /// The byte order of the data in the binary.
     pub fn endian(&self) -> Endian {
         self.endian
     }
 
-    fn parse_object(&mut self, options: SearchOptions) -> anyhow::Result<()> {
+    /// The byte order of the instructions in the binary, which is only different
+    /// from [`Binary::endian`] for ARM BE8 binaries.
+    pub fn code_endian(&self) -> Endian {
+        if self.be8 && self.arch == Arch::Arm {
+            Endian::Little
+        } else {
+            self.endian
+        }
+    }
+
+    pub fn bits(&self) -> Bits {
+        self.bits
+    }
+
+    /// Disassembles the binary as `arch` instead of the architecture that was
+    /// detected from its headers.
+    pub fn set_arch(&mut self, arch: Arch) {
+        self.arch = arch;
+        if arch.bits() != Bits::Unknown {
+            self.bits = arch.bits();
+        }
+    }
+
+    /// The path that the binary was loaded from.
+    pub fn path(&self) -> &Path {
+        self.data.path()
+    }
+
+    /// Returns true if DWARF debug information was found for the binary.
+    pub fn has_dwarf(&self) -> bool {
+        self.dwarf.is_some()
+    }
+
+    /// Returns true if a PDB was found for the binary.
+    pub fn has_pdb(&self) -> bool {
+        self.pdb.is_some()
+    }
+
+    /// Parses the object and loads its debug information. Its symbols are only
+    /// gathered if `load_symbols` is true.
+    fn parse_object(&mut self, options: SearchOptions, load_symbols: bool) -> anyhow::Result<()> {
         let data = self.data.clone();
-        match Object::parse(&data).context("failed to parse object")? {
-            Object::Elf(elf) => self.parse_elf_object(&elf, options),
-            Object::PE(pe) => self.parse_pe_object(&pe, options),
+        match Object::parse(&data).map_err(DisasmError::Parse)? {
+            Object::Elf(elf) => self.parse_elf_object(&elf, options, load_symbols),
+            Object::PE(pe) => self.parse_pe_object(&pe, options, load_symbols),
             Object::Mach(mach) => match mach {
-                goblin::mach::Mach::Fat(multi) => self.parse_mach_object(
-                    &multi
-                        .get(0)
-                        .context("failed to get first object from fat Mach binary")?,
-                    options,
-                ),
-                goblin::mach::Mach::Binary(obj) => self.parse_mach_object(&obj, options),
+                goblin::mach::Mach::Fat(multi) => {
+                    let (index, fat_arch) = mach::select_fat_arch(&multi, options.mach_arch)?;
+                    let obj = multi
+                        .get(index)
+                        .context("failed to get object from fat Mach binary")?;
+
+                    // Offsets in the selected object are relative to the start of its slice.
+                    let start = fat_arch.offset as usize;
+                    self.data = self.data.slice(start..start + fat_arch.size as usize);
+                    self.parse_mach_object(&obj, options, load_symbols)
+                }
+                goblin::mach::Mach::Binary(obj) => {
+                    self.parse_mach_object(&obj, options, load_symbols)
+                }
             },
             Object::Archive(archive) => self.parse_archive_object(&archive),
-            Object::Unknown(magic) => Err(anyhow::anyhow!(
-                "failed to parse object with magic value 0x{:X}",
-                magic
-            )),
+            Object::Unknown(magic) => {
+                Err(DisasmError::Parse(goblin::error::Error::BadMagic(magic)).into())
+            }
         }
     }
 
-    fn parse_elf_object(&mut self, elf: &Elf, options: SearchOptions) -> anyhow::Result<()> {
+    fn parse_elf_object(
+        &mut self,
+        elf: &Elf,
+        options: SearchOptions,
+        load_symbols: bool,
+    ) -> anyhow::Result<()> {
         elf::load_arch_info(self, elf)?;
+        self.entry = elf::entry_symbol(elf);
+        self.segments = elf::load_segments(elf);
+        self.sections = elf::load_section_info(elf)?;
 
         let load_all_symbols_timer = std::time::Instant::now();
         let mut load_elf_symbols = false;
+        let mut load_export_symbols = false;
         let mut load_dwarf_symbols = options.sources.is_empty(); // `auto` makes this true
         options.sources.iter().for_each(|source| match source {
             SymbolSource::Elf => load_elf_symbols = true,
+            SymbolSource::Export => load_export_symbols = true,
             SymbolSource::Dwarf => load_dwarf_symbols = true,
             _ => {}
         });
 
-        if elf::contains_dwarf(elf) {
-            let dwarf = elf::load_dwarf(elf, self.endian, &self.data)?;
-            if load_dwarf_symbols {
+        let dwarf = match options.dwarf_path {
+            Some(path) => Some(elf::load_separate_dwarf(path, self.endian)?),
+            None if elf::contains_dwarf(elf) => {
+                Some(elf::load_dwarf(elf, self.endian, &self.data)?)
+            }
+            None => None,
+        };
+        if let Some(dwarf) = dwarf {
+            if load_symbols && load_dwarf_symbols {
                 log::info!("retrieving symbols from DWARF debug information");
                 let symbols_count_before = self.symbols.len();
                 let load_symbols_timer = std::time::Instant::now();
 
-                elf::load_dwarf_symbols(&dwarf, elf, &mut self.symbols)
-                    .context("error while gather DWARF symbols")?;
+                elf::load_dwarf_symbols(
+                    &dwarf,
+                    elf,
+                    &mut self.symbols,
+                    options.dwarf_symbol_options(),
+                )
+                .context("error while gather DWARF symbols")?;
 
                 log::trace!(
                     "found {} symbols in DWARF debug information in {}",
@@ -215,11 +760,22 @@ impl Binary {
             self.dwarf = Some(dwarf);
         }
 
+        // PLT stubs don't have symbols of their own, so they are named
+        // using the imported symbols even if there are plenty of symbols.
+        // They don't count as found symbols for `auto`, a binary that only has
+        // stubs still needs its symbol table.
+        let mut plt_symbols_count = 0;
+        if load_symbols && (load_elf_symbols || options.sources.is_empty()) {
+            let symbols_count_before = self.symbols.len();
+            elf::load_plt_symbols(elf, &self.data, self.arch, &mut self.symbols)
+                .context("error while gathering ELF PLT symbols")?;
+            plt_symbols_count = self.symbols.len() - symbols_count_before;
+        }
+
         // If we're using `auto` for the symbol source and no symbols are found.
-        load_elf_symbols |=
-            options.sources.is_empty() && self.symbols.len() < AUTO_SOURCES_THRESHOLD;
+        load_elf_symbols |= options.auto_loads_more(self.symbols.len() - plt_symbols_count);
 
-        if load_elf_symbols {
+        if load_symbols && load_elf_symbols {
             log::info!("retrieving symbols from ELF object");
             let symbols_count_before = self.symbols.len();
             let load_symbols_timer = std::time::Instant::now();
@@ -232,6 +788,31 @@ impl Binary {
             );
         }
 
+        // Stripped shared libraries only have their dynamic symbol table left. Duplicates
+        // of `.symtab` symbols are removed after all of the symbols are loaded.
+        load_export_symbols |= options.auto_loads_more(self.symbols.len() - plt_symbols_count);
+
+        if load_symbols && load_export_symbols {
+            log::info!("retrieving symbols from ELF dynamic symbol table");
+            let symbols_count_before = self.symbols.len();
+            let load_symbols_timer = std::time::Instant::now();
+            elf::load_export_symbols(elf, &self.data, &mut self.symbols)
+                .context("error while gathering ELF dynamic symbols")?;
+            log::trace!(
+                "found {} symbols in ELF dynamic symbol table in {}",
+                self.symbols.len() - symbols_count_before,
+                util::DurationDisplay(load_symbols_timer.elapsed())
+            );
+        }
+
+        // Static constructors are often anonymous, so the ones without a symbol are
+        // named after their position in `.init_array` once every symbol is known.
+        if load_symbols && (load_elf_symbols || options.sources.is_empty()) {
+            for (name, array) in elf::load_function_arrays(elf, &self.data) {
+                self.add_function_array_symbols(name, &array, SymbolSource::Elf);
+            }
+        }
+
         log::debug!(
             "found {} total symbols in {}",
             self.symbols.len(),
@@ -241,7 +822,12 @@ impl Binary {
         Ok(())
     }
 
-    fn parse_mach_object(&mut self, mach: &MachO, options: SearchOptions) -> anyhow::Result<()> {
+    fn parse_mach_object(
+        &mut self,
+        mach: &MachO,
+        options: SearchOptions,
+        load_symbols: bool,
+    ) -> anyhow::Result<()> {
         mach::load_arch_info(self, mach)?;
 
         let load_all_symbols_timer = std::time::Instant::now();
@@ -254,14 +840,30 @@ impl Binary {
         });
 
         let sections = mach::load_sections(mach)?;
-
-        if let Some(dwarf) = mach::load_dwarf(&sections, self.endian, &self.data)? {
-            if load_dwarf_symbols {
+        self.entry = mach::entry_symbol(mach, &sections);
+        self.segments = mach::load_segments(mach);
+        self.sections = mach::load_section_info(mach, &sections);
+
+        if let Some(dwarf) = mach::load_dwarf(
+            mach,
+            &sections,
+            self.arch,
+            self.endian,
+            &self.data,
+            // A DWARF object file is loaded the same way as the one inside of a dSYM.
+            options.dwarf_path.or(options.dsym_path),
+        )? {
+            if load_symbols && load_dwarf_symbols {
                 log::info!("retrieving symbols from DWARF debug information");
                 let symbols_count_before = self.symbols.len();
                 let load_symbols_timer = std::time::Instant::now();
-                mach::load_dwarf_symbols(&dwarf, &sections, &mut self.symbols)
-                    .context("error while gathering DWARF symbols")?;
+                mach::load_dwarf_symbols(
+                    &dwarf,
+                    &sections,
+                    &mut self.symbols,
+                    options.dwarf_symbol_options(),
+                )
+                .context("error while gathering DWARF symbols")?;
                 log::trace!(
                     "found {} symbols in DWARF debug information in {}",
                     self.symbols.len() - symbols_count_before,
@@ -271,11 +873,13 @@ impl Binary {
             self.dwarf = Some(dwarf);
         }
 
+        // FIXME load the export trie as `SymbolSource::Export` symbols. It doesn't have
+        //       symbol sizes, so they would have to be inferred from the next symbol.
+
         // If we're using `auto` for the symbol source and no symbols are found.
-        load_mach_symbols |=
-            options.sources.is_empty() && self.symbols.len() < AUTO_SOURCES_THRESHOLD;
+        load_mach_symbols |= options.auto_loads_more(self.symbols.len());
 
-        if load_mach_symbols {
+        if load_symbols && load_mach_symbols {
             log::info!("retrieving symbols from Mach-O object");
             let symbols_count_before = self.symbols.len();
             let load_symbols_timer = std::time::Instant::now();
@@ -288,6 +892,14 @@ impl Binary {
             );
         }
 
+        // Static constructors are often anonymous, so the ones without a symbol are
+        // named after their position in `__mod_init_func` once every symbol is known.
+        if load_symbols && (load_mach_symbols || options.sources.is_empty()) {
+            for (name, array) in mach::load_function_arrays(mach, &sections, &self.data) {
+                self.add_function_array_symbols(name, &array, SymbolSource::Mach);
+            }
+        }
+
         log::debug!(
             "found {} total symbols in {}",
             self.symbols.len(),
@@ -297,8 +909,16 @@ impl Binary {
         Ok(())
     }
 
-    fn parse_pe_object(&mut self, pe: &PE, options: SearchOptions) -> anyhow::Result<()> {
+    fn parse_pe_object(
+        &mut self,
+        pe: &PE,
+        options: SearchOptions,
+        load_symbols: bool,
+    ) -> anyhow::Result<()> {
         pe::load_arch_info(self, pe)?;
+        self.entry = pe::entry_symbol(pe);
+        self.segments = pe::load_segments(pe);
+        self.sections = pe::load_section_info(pe)?;
 
         let load_all_symbols_timer = std::time::Instant::now();
         let mut load_pe_symbols = false;
@@ -311,14 +931,19 @@ impl Binary {
             _ => {}
         });
 
-        if let Some(pdb_path) =
-            pe::find_pdb_path(pe, self.data.path()).context("error while searching for PDB")?
-        {
+        // A PDB passed with `--pdb-path` replaces the one that the binary refers to.
+        let pdb_path = match options.pdb_path {
+            Some(path) => Some(path.to_path_buf()),
+            None => {
+                pe::find_pdb_path(pe, self.data.path()).context("error while searching for PDB")?
+            }
+        };
+        if let Some(pdb_path) = pdb_path {
             log::debug!("found PDB at `{}`", pdb_path.display());
             let pdb_data =
                 BinaryData::from_path(pdb_path).context("error while loading PDB data")?;
-            let mut pdb = pe::load_pdb(pdb_data)?;
-            if load_pdb_symbols {
+            let mut pdb = pe::load_pdb(pdb_data.clone())?;
+            if load_symbols && load_pdb_symbols {
                 log::info!("retrieving symbols from PDB debug information");
                 let symbols_count_before = self.symbols.len();
                 let load_symbols_timer = std::time::Instant::now();
@@ -330,22 +955,26 @@ impl Binary {
                     util::DurationDisplay(load_symbols_timer.elapsed())
                 );
             }
-            self.pdb = Some(pdb);
+            self.pdb = Some(pdb_data);
         }
 
         if pe::contains_dwarf(pe) {
             let dwarf = pe::load_dwarf(pe, self.endian, &self.data)?;
             // If we're using `auto` for the symbol source and no symbols are found.
-            load_dwarf_symbols |=
-                options.sources.is_empty() && self.symbols.len() < AUTO_SOURCES_THRESHOLD;
+            load_dwarf_symbols |= options.auto_loads_more(self.symbols.len());
 
-            if load_dwarf_symbols {
+            if load_symbols && load_dwarf_symbols {
                 let symbols_count_before = self.symbols.len();
                 let load_symbols_timer = std::time::Instant::now();
                 log::info!("retrieving symbols from DWARF debug information");
 
-                pe::load_dwarf_symbols(&dwarf, pe, &mut self.symbols)
-                    .context("error while gather DWARF symbols")?;
+                pe::load_dwarf_symbols(
+                    &dwarf,
+                    pe,
+                    &mut self.symbols,
+                    options.dwarf_symbol_options(),
+                )
+                .context("error while gather DWARF symbols")?;
 
                 log::trace!(
                     "found {} symbols in DWARF debug information in {}",
@@ -357,10 +986,9 @@ impl Binary {
         }
 
         // If we're using `auto` for the symbol source and no symbols are found.
-        load_pe_symbols |=
-            options.sources.is_empty() && self.symbols.len() < AUTO_SOURCES_THRESHOLD;
+        load_pe_symbols |= options.auto_loads_more(self.symbols.len());
 
-        if load_pe_symbols {
+        if load_symbols && load_pe_symbols {
             log::info!("retrieving symbols from PE/COFF object");
             let symbols_count_before = self.symbols.len();
             let load_symbols_timer = std::time::Instant::now();
@@ -388,7 +1016,7 @@ impl Binary {
         ))
     }
 
-    pub fn load_line_information(&mut self) -> anyhow::Result<()> {
+    pub fn load_line_information(&mut self) -> Result<(), DisasmError> {
         if let Some(ref mut dwarf) = self.dwarf {
             dwarf.ensure_compilation_units()?;
         }
@@ -399,9 +1027,10 @@ impl Binary {
     pub fn addr2line(
         &self,
         addr: u64,
-    ) -> anyhow::Result<Option<impl '_ + Iterator<Item = (&Path, u32)>>> {
+    ) -> Result<Option<impl '_ + Iterator<Item = SourceLocation<'_>>>, DisasmError> {
+        // Debug information uses the addresses from the binary, not runtime addresses.
         if let Some(ref dwarf) = self.dwarf {
-            return dwarf.addr2line(addr);
+            return Ok(dwarf.addr2line(addr.wrapping_sub(self.base))?);
         }
 
         Ok(None)
@@ -423,30 +1052,53 @@ pub struct BinaryData {
     range: std::ops::Range<usize>,
 
     /// The current offset of the binary data that is being read.
+    /// This is relative to the start of `range`.
     offset: usize,
 
     inner: Arc<BinaryDataInner>,
 }
 
 impl BinaryData {
-    pub fn from_path<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, DisasmError> {
         Self::from_path_inner(path.as_ref())
     }
 
-    fn from_path_inner(path: &Path) -> anyhow::Result<Self> {
-        let file = File::open(path)
-            .with_context(|| format!("failed to open file at path `{}`", path.display()))?;
-        let path = PathBuf::from(path);
+    fn from_path_inner(path: &Path) -> Result<Self, DisasmError> {
+        let io_error = |source| DisasmError::Io {
+            path: PathBuf::from(path),
+            source,
+        };
+        let file = File::open(path).map_err(io_error)?;
+        let mmap = unsafe { MmapOptions::new().map(&file) }.map_err(io_error)?;
+
+        Ok(BinaryData {
+            range: 0..mmap.len(),
+            offset: 0,
+            inner: Arc::new(BinaryDataInner {
+                mmap,
+                path: PathBuf::from(path),
+            }),
+        })
+    }
+
+    /// Copies `bytes` into anonymous memory, for tests that need a binary
+    /// without writing it to a file first.
+    #[cfg(test)]
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        // Mapping zero bytes fails, the range still makes the data empty.
+        let mut mmap = memmap::MmapMut::map_anon(std::cmp::max(bytes.len(), 1))
+            .expect("failed to map anonymous memory");
+        mmap[..bytes.len()].copy_from_slice(bytes);
 
-        unsafe {
-            MmapOptions::new()
-                .map(&file)
-                .map(|mmap| BinaryData {
-                    range: 0..mmap.len(),
-                    offset: 0,
-                    inner: Arc::new(BinaryDataInner { mmap, path }),
-                })
-                .map_err(|err| err.into())
+        BinaryData {
+            range: 0..bytes.len(),
+            offset: 0,
+            inner: Arc::new(BinaryDataInner {
+                mmap: mmap
+                    .make_read_only()
+                    .expect("failed to protect anonymous memory"),
+                path: PathBuf::new(),
+            }),
         }
     }
 
@@ -456,6 +1108,8 @@ impl BinaryData {
         &self.inner.path
     }
 
+    /// Returns a view of `range` within this binary data. `Read::read` starts reading
+    /// from the beginning of the returned slice and never reads past its end.
     pub fn slice<R>(&self, range: R) -> BinaryData
     where
         R: std::ops::RangeBounds<usize>,
@@ -474,12 +1128,9 @@ impl BinaryData {
             Bound::Unbounded => self.range.end,
         };
 
-        // advance the offset so that `Read::read` is consistent.
-        let offset = self.offset + (start - self.range.start);
-
         BinaryData {
             range: start..end,
-            offset,
+            offset: 0,
             inner: self.inner.clone(),
         }
     }
@@ -503,14 +1154,13 @@ impl std::ops::Deref for BinaryData {
 
 impl Read for BinaryData {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut slice: &[u8] = &*self.inner.mmap;
+        let slice: &[u8] = self;
 
-        let len = std::cmp::min(buf.len(), slice.len() - self.offset);
+        let len = std::cmp::min(buf.len(), slice.len().saturating_sub(self.offset));
         if len == 0 {
             return Ok(0);
         }
-        slice = &slice[self.offset..(self.offset + len)];
-        (&mut buf[..len]).copy_from_slice(slice);
+        buf[..len].copy_from_slice(&slice[self.offset..(self.offset + len)]);
 
         self.offset += len;
         Ok(len)
@@ -544,6 +1194,18 @@ pub enum Arch {
     X86_64,
     Arm,
     AArch64,
+    PowerPc,
+    PowerPc64,
+    S390x,
+    Sparc,
+    /// SPARC V9, which is also used by 32-bit SPARC V8+ binaries.
+    Sparc64,
+    /// MIPS, which is only disassembled when it is selected with `--arch`.
+    Mips,
+    Mips64,
+    RiscV,
+    /// The MOS 6502 and its derivatives, which are only disassembled from raw images.
+    Mos65xx,
 }
 
 impl Arch {
@@ -555,6 +1217,12 @@ impl Arch {
             header::EM_X86_64 => Arch::X86_64,
             header::EM_ARM => Arch::Arm,
             header::EM_AARCH64 => Arch::AArch64,
+            header::EM_PPC => Arch::PowerPc,
+            header::EM_PPC64 => Arch::PowerPc64,
+            header::EM_S390 => Arch::S390x,
+            header::EM_SPARC => Arch::Sparc,
+            header::EM_SPARC32PLUS | header::EM_SPARCV9 => Arch::Sparc64,
+            header::EM_RISCV => Arch::RiscV,
             _ => Arch::Unknown,
         }
     }
@@ -568,10 +1236,88 @@ impl Arch {
             cputype::CPU_TYPE_ARM64_32 => Arch::AArch64,
             cputype::CPU_TYPE_X86 => Arch::X86,
             cputype::CPU_TYPE_X86_64 => Arch::X86_64,
+            cputype::CPU_TYPE_POWERPC => Arch::PowerPc,
+            cputype::CPU_TYPE_POWERPC64 => Arch::PowerPc64,
             _ => Arch::Unknown,
         }
     }
 
+    /// The architecture that this program was compiled for.
+    pub fn host() -> Arch {
+        if cfg!(target_arch = "x86") {
+            Arch::X86
+        } else if cfg!(target_arch = "x86_64") {
+            Arch::X86_64
+        } else if cfg!(target_arch = "arm") {
+            Arch::Arm
+        } else if cfg!(target_arch = "aarch64") {
+            Arch::AArch64
+        } else if cfg!(target_arch = "powerpc") {
+            Arch::PowerPc
+        } else if cfg!(target_arch = "powerpc64") {
+            Arch::PowerPc64
+        } else if cfg!(target_arch = "s390x") {
+            Arch::S390x
+        } else if cfg!(target_arch = "sparc") {
+            Arch::Sparc
+        } else if cfg!(target_arch = "sparc64") {
+            Arch::Sparc64
+        } else if cfg!(target_arch = "mips") {
+            Arch::Mips
+        } else if cfg!(target_arch = "mips64") {
+            Arch::Mips64
+        } else if cfg!(any(target_arch = "riscv32", target_arch = "riscv64")) {
+            Arch::RiscV
+        } else {
+            Arch::Unknown
+        }
+    }
+
+    /// The alignment of every instruction for architectures whose instructions
+    /// must be aligned, or `None` if instructions can start at any byte.
+    pub fn instruction_alignment(self) -> Option<u64> {
+        match self {
+            // ARM mode only, Thumb instructions are aligned to 2 bytes. Thumb functions
+            // have odd addresses and are not disassembled.
+            Arch::Arm => Some(4),
+            Arch::AArch64 | Arch::PowerPc | Arch::PowerPc64 => Some(4),
+            Arch::Sparc | Arch::Sparc64 | Arch::Mips | Arch::Mips64 => Some(4),
+            Arch::S390x | Arch::RiscV => Some(2),
+            Arch::Unknown | Arch::X86 | Arch::X86_64 | Arch::Mos65xx => None,
+        }
+    }
+
+    /// Returns the variant of this architecture with the given word size, like `x86_64`
+    /// for `x86` with 64 bits. Architectures with only one word size are returned as is.
+    pub fn with_bits(self, bits: Bits) -> Arch {
+        match (self, bits) {
+            (Arch::X86 | Arch::X86_64, Bits::Bits32) => Arch::X86,
+            (Arch::X86 | Arch::X86_64, Bits::Bits64) => Arch::X86_64,
+            (Arch::Arm | Arch::AArch64, Bits::Bits32) => Arch::Arm,
+            (Arch::Arm | Arch::AArch64, Bits::Bits64) => Arch::AArch64,
+            (Arch::PowerPc | Arch::PowerPc64, Bits::Bits32) => Arch::PowerPc,
+            (Arch::PowerPc | Arch::PowerPc64, Bits::Bits64) => Arch::PowerPc64,
+            (Arch::Sparc | Arch::Sparc64, Bits::Bits32) => Arch::Sparc,
+            (Arch::Sparc | Arch::Sparc64, Bits::Bits64) => Arch::Sparc64,
+            (Arch::Mips | Arch::Mips64, Bits::Bits32) => Arch::Mips,
+            (Arch::Mips | Arch::Mips64, Bits::Bits64) => Arch::Mips64,
+            (arch, _) => arch,
+        }
+    }
+
+    /// The word size of this architecture, or [`Bits::Unknown`] if it has none
+    /// that is known.
+    pub fn bits(self) -> Bits {
+        match self {
+            Arch::X86 | Arch::Arm | Arch::PowerPc | Arch::Sparc | Arch::Mips => Bits::Bits32,
+            Arch::X86_64 | Arch::AArch64 | Arch::PowerPc64 | Arch::Sparc64 | Arch::Mips64 => {
+                Bits::Bits64
+            }
+            Arch::S390x => Bits::Bits64,
+            Arch::Unknown | Arch::RiscV | Arch::Mos65xx => Bits::Unknown,
+        }
+    }
+
     fn from_coff_machine(machine: u16) -> Arch {
         use goblin::pe::header;
 
@@ -580,6 +1326,7 @@ impl Arch {
             header::COFF_MACHINE_X86_64 => Arch::X86_64,
             header::COFF_MACHINE_ARM => Arch::Arm,
             header::COFF_MACHINE_ARM64 => Arch::AArch64,
+            header::COFF_MACHINE_POWERPC => Arch::PowerPc,
             _ => Arch::Unknown,
         }
     }
@@ -593,11 +1340,57 @@ impl fmt::Display for Arch {
             Arch::X86_64 => "x86_64",
             Arch::Arm => "arm",
             Arch::AArch64 => "arm64",
+            Arch::PowerPc => "ppc",
+            Arch::PowerPc64 => "ppc64",
+            Arch::S390x => "s390x",
+            Arch::Sparc => "sparc",
+            Arch::Sparc64 => "sparc64",
+            Arch::Mips => "mips",
+            Arch::Mips64 => "mips64",
+            Arch::RiscV => "riscv",
+            Arch::Mos65xx => "mos6502",
         };
         write!(f, "{}", t)
     }
 }
 
+impl std::str::FromStr for Arch {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("x86") || s.eq_ignore_ascii_case("i386") {
+            Ok(Arch::X86)
+        } else if s.eq_ignore_ascii_case("x86_64") || s.eq_ignore_ascii_case("x86-64") {
+            Ok(Arch::X86_64)
+        } else if s.eq_ignore_ascii_case("arm") {
+            Ok(Arch::Arm)
+        } else if s.eq_ignore_ascii_case("arm64") || s.eq_ignore_ascii_case("aarch64") {
+            Ok(Arch::AArch64)
+        } else if s.eq_ignore_ascii_case("ppc") || s.eq_ignore_ascii_case("powerpc") {
+            Ok(Arch::PowerPc)
+        } else if s.eq_ignore_ascii_case("ppc64") || s.eq_ignore_ascii_case("powerpc64") {
+            Ok(Arch::PowerPc64)
+        } else if s.eq_ignore_ascii_case("s390x") {
+            Ok(Arch::S390x)
+        } else if s.eq_ignore_ascii_case("sparc") {
+            Ok(Arch::Sparc)
+        } else if s.eq_ignore_ascii_case("sparc64") || s.eq_ignore_ascii_case("sparcv9") {
+            Ok(Arch::Sparc64)
+        } else if s.eq_ignore_ascii_case("mips") {
+            Ok(Arch::Mips)
+        } else if s.eq_ignore_ascii_case("mips64") {
+            Ok(Arch::Mips64)
+        } else if s.eq_ignore_ascii_case("mos6502")
+            || s.eq_ignore_ascii_case("6502")
+            || s.eq_ignore_ascii_case("mos65xx")
+        {
+            Ok(Arch::Mos65xx)
+        } else {
+            Err("invalid architecture")
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Bits {
     Unknown,
@@ -686,17 +1479,509 @@ const DWARF_SECTIONS: &[&str] = &[
     ".debug_rnglists",
 ];
 
+#[derive(Copy, Clone)]
 pub struct SearchOptions<'a> {
+    /// The sources that symbols are loaded from. If this is empty (`auto`), the
+    /// debug information is loaded first and then the object's symbol tables,
+    /// until `auto_sources_threshold` symbols are loaded.
     pub sources: &'a [SymbolSource],
 
-    /// Path to an object file containing DWARF debug information.
-    /// Used for ELF and Mach-O object files.
+    /// With `auto` sources, more sources are loaded while there are fewer than
+    /// this many symbols.
+    pub auto_sources_threshold: usize,
+
+    /// The demangler used for symbol names.
+    pub demangle: Demangle,
+
+    /// Path to an object file containing DWARF debug information, which is used
+    /// instead of the binary's own. Used for ELF and Mach-O object files.
     pub dwarf_path: Option<&'a Path>,
 
-    /// The path to the dSYM directory.
-    /// Used for Mach-O object files.
+    /// The path to the dSYM directory, or the DWARF object file inside of it.
+    /// Used for Mach-O object files instead of searching next to the binary.
     pub dsym_path: Option<&'a Path>,
 
-    /// Path to a PDB file used for PE object files.
+    /// Path to a PDB file used for PE object files instead of the one that
+    /// the binary refers to.
     pub pdb_path: Option<&'a Path>,
+
+    /// The architecture of the slice to use from a fat Mach-O binary.
+    /// If this is `None`, the host architecture is used when it is present.
+    pub mach_arch: Option<Arch>,
+
+    /// When this is set while symbols are being loaded from DWARF debug
+    /// information, loading stops early and [`Binary::new`] returns an error.
+    pub cancel: Option<&'a AtomicBool>,
+
+    /// Also load DWARF subprograms without an address range (declarations and
+    /// functions that were only inlined) as [`SymbolKind::Declaration`] symbols.
+    pub dwarf_declarations: bool,
+
+    /// The directory that the symbols of binaries are cached in. Symbols
+    /// are not cached if this is `None`.
+    pub symbol_cache: Option<&'a Path>,
+}
+
+impl SearchOptions<'_> {
+    /// Returns true if sources are `auto` and another source should be loaded
+    /// after `symbol_count` symbols were found.
+    fn auto_loads_more(&self, symbol_count: usize) -> bool {
+        self.sources.is_empty() && symbol_count < self.auto_sources_threshold
+    }
+
+    fn dwarf_symbol_options(&self) -> DwarfSymbolOptions<'_> {
+        DwarfSymbolOptions {
+            cancel: self.cancel,
+            declarations: self.dwarf_declarations,
+        }
+    }
+}
+
+/// Reads the pointers of `size` (4 or 8) bytes in `bytes`.
+pub fn read_pointers(
+    bytes: &[u8],
+    size: usize,
+    little_endian: bool,
+) -> impl Iterator<Item = u64> + '_ {
+    bytes.chunks_exact(size).map(move |chunk| {
+        let mut buf = [0u8; 8];
+        if little_endian {
+            buf[..size].copy_from_slice(chunk);
+            u64::from_le_bytes(buf)
+        } else {
+            buf[8 - size..].copy_from_slice(chunk);
+            u64::from_be_bytes(buf)
+        }
+    })
+}
+
+/// Sorts symbols by their range. Symbols with the same range are sorted from the
+/// most to the least preferred, by their source and then by their binding, so
+/// that the first one is the name that is shown for the range.
+fn sort_symbols(symbols: &mut [Symbol]) {
+    symbols.sort_unstable_by(|lhs, rhs| {
+        lhs.address()
+            .cmp(&rhs.address())
+            .then(lhs.end_address().cmp(&rhs.end_address()))
+            .then(lhs.source().cmp(&rhs.source()))
+            .then(lhs.binding().cmp(&rhs.binding()))
+            .then_with(|| lhs.name().cmp(rhs.name()))
+    });
+}
+
+/// Removes function symbols that cover the same range as a symbol from a higher
+/// priority source (see [`SOURCE_PRIORITY`](crate::disasm::symbol::SOURCE_PRIORITY)).
+/// The sources of the removed symbols are recorded on the symbol that is kept.
+/// Symbols from the same source are aliases and are all kept.
+///
+/// `symbols` must be sorted by address, end address and then source.
+fn dedup_symbols(symbols: Vec<Symbol>) -> Vec<Symbol> {
+    let mut deduped: Vec<Symbol> = Vec::with_capacity(symbols.len());
+    // The index of the first (highest priority) symbol with the current range.
+    let mut best = 0;
+
+    for sym in symbols {
+        if let Some(kept) = deduped.get_mut(best) {
+            let duplicate = kept.kind() == SymbolKind::Function
+                && sym.kind() == SymbolKind::Function
+                && kept.address_range() == sym.address_range();
+
+            if duplicate && kept.source() != sym.source() {
+                kept.merge_source(sym.source());
+                continue;
+            } else if !duplicate {
+                best = deduped.len();
+            }
+        }
+        deduped.push(sym);
+    }
+
+    deduped
+}
+
+/// Logs function symbols that overlap another function symbol without covering the
+/// same range, which usually means that one of their sizes is wrong. Returns the
+/// number of overlapping symbols.
+///
+/// `symbols` must be sorted by address.
+fn log_overlapping_symbols(symbols: &[Symbol]) -> usize {
+    let mut overlaps = 0;
+    // The function symbol that reaches the furthest so far.
+    let mut furthest: Option<&Symbol> = None;
+
+    for sym in symbols
+        .iter()
+        .filter(|sym| sym.kind() == SymbolKind::Function && sym.size() > 0)
+    {
+        if let Some(prev) = furthest {
+            if sym.address() < prev.end_address() && sym.address_range() != prev.address_range() {
+                log::debug!(
+                    "symbol {} (0x{:x}..0x{:x}) overlaps {} (0x{:x}..0x{:x})",
+                    sym.display_name(),
+                    sym.address(),
+                    sym.end_address(),
+                    prev.display_name(),
+                    prev.address(),
+                    prev.end_address()
+                );
+                overlaps += 1;
+            }
+
+            if sym.end_address() <= prev.end_address() {
+                continue;
+            }
+        }
+        furthest = Some(sym);
+    }
+
+    overlaps
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn imported_symbols_are_clamped_to_their_segment() {
+        let mut binary = Binary::raw(BinaryData::from_bytes(&[0; 0x100]), Arch::X86_64);
+        let mut companion = Binary::raw(BinaryData::from_bytes(&[0; 0x100]), Arch::X86_64);
+        companion.symbols = vec![
+            Symbol::new_unmangled("inside".into(), 0x10, 0, 0x20, SymbolSource::Elf),
+            Symbol::new_unmangled("straddles".into(), 0xf0, 0, 0x40, SymbolSource::Elf),
+            Symbol::new_unmangled("outside".into(), 0x200, 0, 0x10, SymbolSource::Elf),
+        ];
+
+        assert_eq!(binary.import_symbols(companion), 1);
+        let symbols = binary
+            .symbols()
+            .iter()
+            .map(|sym| (sym.name(), sym.offset(), sym.size()))
+            .collect::<Vec<_>>();
+        assert_eq!(symbols, [("inside", 0x10, 0x20), ("straddles", 0xf0, 0x10)]);
+    }
+
+    #[test]
+    fn fuzzy_find_prefers_last_path_component() {
+        let mut binary = Binary::raw(BinaryData::from_bytes(&[0; 16]), Arch::X86_64);
+        binary.symbols = [
+            "my_pushdown",
+            "foo::push::bar",
+            "alloc::vec::Vec::push",
+            "core::arch::x86::push",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(idx, &name)| {
+            Symbol::new_unmangled(name.into(), idx as u64 * 4, idx * 4, 4, SymbolSource::Elf)
+        })
+        .collect();
+
+        let find = |name| binary.fuzzy_find_symbol(name).map(Symbol::display_name);
+        assert_eq!(find("push"), Some("alloc::vec::Vec::push"));
+        assert_eq!(find("x86::push"), Some("core::arch::x86::push"));
+        assert_eq!(find("bar"), Some("foo::push::bar"));
+        assert_eq!(find("pushdown"), Some("my_pushdown"));
+        assert_eq!(find("pop"), None);
+    }
+
+    #[test]
+    fn global_symbols_are_preferred_over_weak_and_local() {
+        use crate::disasm::symbol::SymbolBinding;
+
+        // Aliases with the same name only differ in their binding.
+        let mut binary = Binary::raw(BinaryData::from_bytes(&[0; 16]), Arch::X86_64);
+        binary.symbols = [
+            SymbolBinding::Local,
+            SymbolBinding::Weak,
+            SymbolBinding::Global,
+        ]
+        .iter()
+        .map(|&binding| {
+            let mut sym = Symbol::new_unmangled("push".into(), 0, 0, 16, SymbolSource::Elf);
+            sym.set_binding(binding);
+            sym
+        })
+        .collect();
+        sort_symbols(&mut binary.symbols);
+
+        let bindings = binary
+            .symbols()
+            .iter()
+            .map(Symbol::binding)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bindings,
+            [
+                SymbolBinding::Global,
+                SymbolBinding::Weak,
+                SymbolBinding::Local
+            ]
+        );
+        assert_eq!(
+            binary.symbolicate(8).map(|(sym, _)| sym.binding()),
+            Some(SymbolBinding::Global)
+        );
+        assert_eq!(
+            binary.fuzzy_find_symbol("push").map(Symbol::binding),
+            Some(SymbolBinding::Global)
+        );
+    }
+
+    #[test]
+    fn find_symbol_exact_by_raw_name() {
+        let mut binary = Binary::raw(BinaryData::from_bytes(&[0; 64]), Arch::X86_64);
+        binary.symbols = vec![
+            Symbol::new(
+                "_ZN3foo3bar17h1111111111111111E",
+                0x00,
+                0x00,
+                16,
+                SymbolSource::Elf,
+            ),
+            Symbol::new(
+                "_ZN3foo3bar17h2222222222222222E",
+                0x10,
+                0x10,
+                16,
+                SymbolSource::Elf,
+            ),
+            Symbol::new(
+                "_ZN3foo3bar17h1111111111111111E",
+                0x20,
+                0x20,
+                16,
+                SymbolSource::Elf,
+            ),
+        ];
+        binary.index_symbol_names();
+
+        let find = |name| binary.find_symbol_exact(name).map(Symbol::address);
+        assert_eq!(find("_ZN3foo3bar17h2222222222222222E"), Some(0x10));
+        assert_eq!(find("_ZN3foo3bar17h1111111111111111E"), Some(0x00));
+        assert_eq!(find("_ZN3foo3bar17h3333333333333333E"), None);
+    }
+
+    #[test]
+    fn import_symbols_into_raw_image() {
+        let mut image = Binary::raw(BinaryData::from_bytes(&[0; 16]), Arch::Arm);
+        image.set_base(0x8000);
+
+        let mut companion = Binary::raw(BinaryData::from_bytes(&[0; 64]), Arch::Arm);
+        companion.symbols = vec![
+            Symbol::new_unmangled("foo".into(), 0x8004, 0x24, 4, SymbolSource::Elf),
+            Symbol::new_unmangled("bar".into(), 0x9000, 0x30, 4, SymbolSource::Elf),
+        ];
+
+        assert_eq!(image.import_symbols(companion), 1);
+        assert_eq!(image.symbols().len(), 1);
+        assert_eq!(image.symbols()[0].address(), 0x8004);
+        assert_eq!(image.symbols()[0].offset(), 4);
+        assert_eq!(image.entry_point(), Some(0x8000));
+    }
+
+    #[test]
+    fn function_array_symbols_end_at_next_function() {
+        let mut binary = Binary::raw(BinaryData::from_bytes(&[0; 64]), Arch::Arm);
+        binary.symbols = vec![Symbol::new_unmangled(
+            "main".into(),
+            0x20,
+            0x20,
+            0x10,
+            SymbolSource::Elf,
+        )];
+
+        // A Thumb constructor, a constructor that has a symbol and one outside of the binary.
+        binary.add_function_array_symbols(
+            ".init_array",
+            &[0x11, 0x20, 0x1000, 0x30],
+            SymbolSource::Elf,
+        );
+        let symbols = binary
+            .symbols()
+            .iter()
+            .map(|sym| (sym.display_name(), sym.address(), sym.size()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            [
+                ("main", 0x20, 0x10),
+                (".init_array[0]", 0x10, 0x10),
+                (".init_array[3]", 0x30, 0x10),
+            ]
+        );
+    }
+
+    #[test]
+    fn linked_bytes_ignores_base() {
+        let mut binary = Binary::raw(
+            BinaryData::from_bytes(&[0, 1, 2, 3, 4, 5, 6, 7]),
+            Arch::X86_64,
+        );
+        binary.set_base(0x1000);
+        assert_eq!(binary.linked_bytes(6), Some(&[6, 7][..]));
+        assert_eq!(binary.linked_bytes(8), None);
+        assert_eq!(binary.linked_bytes(0x1006), None);
+    }
+
+    #[test]
+    fn set_base_moves_loaded_sections() {
+        let mut binary = Binary::raw(BinaryData::from_bytes(&[0; 16]), Arch::X86_64);
+        let section = |name: &str, address, loaded| SectionInfo {
+            name: name.into(),
+            address,
+            offset: 0,
+            size: 8,
+            loaded,
+            writable: false,
+            executable: loaded,
+        };
+        binary.sections = vec![
+            section(".text", 0x1000, true),
+            section(".debug_info", 0, false),
+        ];
+
+        binary.set_base(0x5555_0000);
+        assert_eq!(binary.sections()[0].address, 0x5555_1000);
+        assert_eq!(binary.sections()[1].address, 0);
+    }
+
+    #[test]
+    fn read_only_sections() {
+        let mut binary = Binary::raw(BinaryData::from_bytes(&[0; 16]), Arch::X86_64);
+        // Nothing can be checked without sections.
+        assert!(binary.is_read_only(0x2000));
+
+        let section = |name: &str, address, loaded, writable| SectionInfo {
+            name: name.into(),
+            address,
+            offset: 0,
+            size: 8,
+            loaded,
+            writable,
+            executable: false,
+        };
+        binary.sections = vec![
+            section(".rodata", 0x1000, true, false),
+            section(".data", 0x2000, true, true),
+            section(".debug_info", 0x3000, false, false),
+        ];
+        assert!(binary.is_read_only(0x1004));
+        assert!(!binary.is_read_only(0x1008));
+        assert!(!binary.is_read_only(0x2000));
+        assert!(!binary.is_read_only(0x3000));
+    }
+
+    /// A big endian 32-bit ARM ELF header without sections or segments. The EABI
+    /// version in `e_flags` is 5, `be8` sets `EF_ARM_BE8`.
+    fn arm_be_elf_header(be8: bool) -> Vec<u8> {
+        let mut header = vec![0x7f, b'E', b'L', b'F', 1, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        header.extend_from_slice(&2u16.to_be_bytes()); // e_type = ET_EXEC
+        header.extend_from_slice(&40u16.to_be_bytes()); // e_machine = EM_ARM
+        header.extend_from_slice(&1u32.to_be_bytes()); // e_version
+        header.extend_from_slice(&0x8000u32.to_be_bytes()); // e_entry
+        header.extend_from_slice(&0u32.to_be_bytes()); // e_phoff
+        header.extend_from_slice(&0u32.to_be_bytes()); // e_shoff
+        let flags = 0x0500_0000 | if be8 { 0x0080_0000u32 } else { 0 };
+        header.extend_from_slice(&flags.to_be_bytes()); // e_flags
+        header.extend_from_slice(&52u16.to_be_bytes()); // e_ehsize
+        header.extend_from_slice(&32u16.to_be_bytes()); // e_phentsize
+        header.extend_from_slice(&0u16.to_be_bytes()); // e_phnum
+        header.extend_from_slice(&40u16.to_be_bytes()); // e_shentsize
+        header.extend_from_slice(&0u16.to_be_bytes()); // e_shnum
+        header.extend_from_slice(&0u16.to_be_bytes()); // e_shstrndx
+        header
+    }
+
+    #[test]
+    fn arm_be8_code_is_little_endian() {
+        for &(be8, code_endian) in &[(true, Endian::Little), (false, Endian::Big)] {
+            let header = arm_be_elf_header(be8);
+            let elf = Elf::parse(&header).expect("failed to parse ELF header");
+            let mut binary = Binary::raw(BinaryData::from_bytes(&header), Arch::Unknown);
+            elf::load_arch_info(&mut binary, &elf).expect("failed to load arch info");
+
+            assert_eq!(binary.arch(), Arch::Arm);
+            assert_eq!(binary.endian(), Endian::Big);
+            assert_eq!(binary.code_endian(), code_endian);
+        }
+    }
+
+    #[test]
+    fn arch_with_bits() {
+        assert_eq!(Arch::X86.with_bits(Bits::Bits64), Arch::X86_64);
+        assert_eq!(Arch::X86_64.with_bits(Bits::Bits32), Arch::X86);
+        assert_eq!(Arch::AArch64.with_bits(Bits::Bits64), Arch::AArch64);
+        assert_eq!(Arch::Mips.with_bits(Bits::Bits64), Arch::Mips64);
+        assert_eq!(Arch::S390x.with_bits(Bits::Bits32), Arch::S390x);
+        assert_eq!(Arch::PowerPc64.with_bits(Bits::Unknown), Arch::PowerPc64);
+    }
+
+    #[test]
+    fn dedup_symbols_keeps_highest_priority_source() {
+        let symbols = vec![
+            Symbol::new("foo", 0x1000, 0x100, 16, SymbolSource::Dwarf),
+            Symbol::new("foo", 0x1000, 0x100, 16, SymbolSource::Elf),
+            Symbol::new("foo_alias", 0x1000, 0x100, 16, SymbolSource::Elf),
+            Symbol::new("bar", 0x1000, 0x100, 32, SymbolSource::Elf),
+            Symbol::new("baz", 0x1020, 0x120, 16, SymbolSource::Pdb),
+            Symbol::new("baz", 0x1020, 0x120, 16, SymbolSource::Pe),
+        ];
+
+        let deduped = dedup_symbols(symbols);
+        let names = deduped.iter().map(|sym| sym.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["foo", "bar", "baz"]);
+        assert_eq!(deduped[0].source(), SymbolSource::Dwarf);
+        assert_eq!(deduped[0].merged_sources(), [SymbolSource::Elf]);
+        assert!(deduped[1].merged_sources().is_empty());
+        assert_eq!(deduped[2].merged_sources(), [SymbolSource::Pe]);
+    }
+
+    #[test]
+    fn log_overlapping_symbols_ignores_aliases() {
+        let symbols = vec![
+            Symbol::new("foo", 0x1000, 0x100, 16, SymbolSource::Elf),
+            Symbol::new("foo_alias", 0x1000, 0x100, 16, SymbolSource::Elf),
+            Symbol::new("bar", 0x1008, 0x108, 16, SymbolSource::Elf),
+            Symbol::new("baz", 0x1010, 0x110, 4, SymbolSource::Elf),
+            Symbol::new("qux", 0x1020, 0x120, 16, SymbolSource::Elf),
+        ];
+        assert_eq!(log_overlapping_symbols(&symbols), 2);
+    }
+
+    #[test]
+    fn read_stops_at_slice_end() {
+        let bytes = (0u8..32).collect::<Vec<u8>>();
+        let data = BinaryData::from_bytes(&bytes);
+
+        let mut slice = data.slice(8..16);
+        let mut buf = [0u8; 32];
+        assert_eq!(slice.read(&mut buf).unwrap(), 8);
+        assert_eq!(&buf[..8], &bytes[8..16]);
+        assert_eq!(slice.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn read_nested_slice_after_seek() {
+        let bytes = (0u8..32).collect::<Vec<u8>>();
+        let data = BinaryData::from_bytes(&bytes);
+
+        let mut outer = data.slice(4..28);
+        outer.seek(SeekFrom::Start(10)).unwrap();
+
+        let mut inner = outer.slice(2..6);
+        assert_eq!(&*inner, &bytes[6..10]);
+
+        let mut buf = [0u8; 8];
+        assert_eq!(inner.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], &bytes[6..10]);
+
+        inner.seek(SeekFrom::Start(3)).unwrap();
+        assert_eq!(inner.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], bytes[9]);
+
+        inner.seek(SeekFrom::End(-2)).unwrap();
+        assert_eq!(inner.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], &bytes[8..10]);
+        assert_eq!(inner.read(&mut buf).unwrap(), 0);
+    }
 }
@@ -1,5 +1,7 @@
-use super::{Arch, Binary, BinaryData, Bits, Endian, DWARF_SECTIONS};
-use crate::disasm::dwarf::DwarfInfo;
+use super::{
+    Arch, Binary, BinaryData, Bits, Endian, SectionInfo, Segment, DWARF_SECTIONS, ENTRY_SYMBOL_NAME,
+};
+use crate::disasm::dwarf::{DwarfInfo, DwarfSymbolOptions};
 use crate::disasm::pdb::PDBInfo;
 use crate::disasm::symbol::{Symbol, SymbolSource};
 use crate::util;
@@ -21,6 +23,69 @@ pub fn load_arch_info(binary: &mut Binary, pe: &PE) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Creates a symbol for the code from the entry point (`AddressOfEntryPoint`) to the
+/// end of the section containing it.
+pub fn entry_symbol(pe: &PE) -> Option<Symbol> {
+    let entry = pe.entry as u32;
+    if entry == 0 {
+        return None;
+    }
+
+    let section = pe.sections.iter().find(|section| {
+        (section.virtual_address..section.virtual_address + section.size_of_raw_data)
+            .contains(&entry)
+    })?;
+    let delta = entry - section.virtual_address;
+    Some(Symbol::new_unmangled(
+        ENTRY_SYMBOL_NAME.into(),
+        pe.image_base as u64 + entry as u64,
+        (section.pointer_to_raw_data + delta) as usize,
+        (section.size_of_raw_data - delta) as usize,
+        SymbolSource::Pe,
+    ))
+}
+
+/// Returns the sections of the image as the segments that are loaded into memory.
+pub fn load_segments(pe: &PE) -> Vec<Segment> {
+    pe.sections
+        .iter()
+        .filter(|section| section.size_of_raw_data > 0)
+        .map(|section| Segment {
+            address: pe.image_base as u64 + section.virtual_address as u64,
+            size: section.size_of_raw_data as u64,
+            offset: section.pointer_to_raw_data as usize,
+        })
+        .collect()
+}
+
+/// Returns the section table of the image, for `--sections`.
+pub fn load_section_info(pe: &PE) -> anyhow::Result<Vec<SectionInfo>> {
+    use goblin::pe::section_table::{IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_WRITE};
+
+    let mut sections = Vec::new();
+    for section in pe.sections.iter() {
+        let name = section
+            .name()
+            .context("failed to retrieve PE section name")?;
+        // Object files don't have a virtual size.
+        let size = if section.virtual_size > 0 {
+            section.virtual_size
+        } else {
+            section.size_of_raw_data
+        };
+        sections.push(SectionInfo {
+            name: name.into(),
+            address: pe.image_base as u64 + section.virtual_address as u64,
+            offset: section.pointer_to_raw_data as u64,
+            size: size as u64,
+            loaded: true,
+            writable: section.characteristics & IMAGE_SCN_MEM_WRITE != 0,
+            executable: section.characteristics & IMAGE_SCN_MEM_EXECUTE != 0,
+        });
+    }
+    Ok(sections)
+}
+
 pub fn load_symbols(pe: &PE, data: &BinaryData, symbols: &mut Vec<Symbol>) -> anyhow::Result<()> {
     use goblin::pe;
 
@@ -140,6 +205,7 @@ pub fn load_dwarf_symbols(
     dwarf: &DwarfInfo,
     pe: &PE,
     symbols: &mut Vec<Symbol>,
+    options: DwarfSymbolOptions,
 ) -> anyhow::Result<()> {
     let mut sections: Vec<(std::ops::Range<u64>, usize)> = pe
         .sections
@@ -165,7 +231,7 @@ pub fn load_dwarf_symbols(
             })
     };
 
-    dwarf.load_symbols(symbols, addr_to_offset)?;
+    dwarf.load_symbols(symbols, addr_to_offset, options)?;
 
     Ok(())
 }
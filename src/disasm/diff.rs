@@ -0,0 +1,376 @@
+use super::{DisasmLine, Disassembly, Jump};
+use std::borrow::Cow;
+
+/// A line in the diff of two disassemblies.
+#[derive(Copy, Clone)]
+pub enum DiffLine<'d> {
+    /// The instruction is the same in both disassemblies.
+    Same(&'d DisasmLine, &'d DisasmLine),
+    /// The instruction was replaced by a different instruction.
+    Changed(&'d DisasmLine, &'d DisasmLine),
+    /// The instruction is only in the old disassembly.
+    Removed(&'d DisasmLine),
+    /// The instruction is only in the new disassembly.
+    Added(&'d DisasmLine),
+}
+
+/// Diffs the instructions of two disassemblies. Instructions are matched on their
+/// mnemonic and operands, so instructions that only moved to a different address
+/// are still considered the same. See [`Operands`] for how addresses in the operands
+/// are ignored.
+pub fn diff<'d>(old: &'d Disassembly, new: &'d Disassembly) -> Vec<DiffLine<'d>> {
+    let old = old.lines();
+    let new = new.lines();
+    let old_keys = old.iter().map(InsnKey::new).collect::<Vec<_>>();
+    let new_keys = new.iter().map(InsnKey::new).collect::<Vec<_>>();
+    let edits = myers(&old_keys, &new_keys, |lhs, rhs| lhs == rhs);
+
+    let mut lines = Vec::with_capacity(edits.len());
+    let mut idx = 0;
+    while idx < edits.len() {
+        if let Edit::Equal(o, n) = edits[idx] {
+            lines.push(DiffLine::Same(&old[o], &new[n]));
+            idx += 1;
+            continue;
+        }
+
+        // Pair up the instructions of a run of removals and insertions so that
+        // an instruction that was replaced shows up as changed.
+        let run_end = edits[idx..]
+            .iter()
+            .position(|edit| matches!(edit, Edit::Equal(..)))
+            .map(|len| idx + len)
+            .unwrap_or_else(|| edits.len());
+        let removed = edits[idx..run_end].iter().filter_map(|edit| match *edit {
+            Edit::Delete(o) => Some(o),
+            _ => None,
+        });
+        let mut added = edits[idx..run_end].iter().filter_map(|edit| match *edit {
+            Edit::Insert(n) => Some(n),
+            _ => None,
+        });
+
+        for o in removed {
+            if let Some(n) = added.next() {
+                lines.push(DiffLine::Changed(&old[o], &new[n]));
+            } else {
+                lines.push(DiffLine::Removed(&old[o]));
+            }
+        }
+        lines.extend(added.map(|n| DiffLine::Added(&new[n])));
+
+        idx = run_end;
+    }
+
+    lines
+}
+
+/// The parts of an instruction that are compared by [`diff`].
+#[derive(Debug, PartialEq, Eq)]
+struct InsnKey<'d> {
+    mnemonic: &'d str,
+    operands: Operands<'d>,
+}
+
+impl<'d> InsnKey<'d> {
+    fn new(line: &'d DisasmLine) -> Self {
+        InsnKey {
+            mnemonic: line.mnemonic(),
+            operands: Operands::new(line),
+        }
+    }
+}
+
+/// The operands of an instruction with the addresses that change when code moves
+/// taken out.
+#[derive(Debug, PartialEq, Eq)]
+enum Operands<'d> {
+    /// A jump inside of the symbol, compared by the index of the line that it
+    /// jumps to instead of by its address.
+    Internal(usize),
+    /// The operand text. The numbers in the operands of jumps that weren't
+    /// symbolicated and of PC-relative memory operands are masked out.
+    Text(Cow<'d, str>),
+}
+
+impl<'d> Operands<'d> {
+    fn new(line: &'d DisasmLine) -> Self {
+        if let Jump::Internal(target) = line.jump() {
+            return Operands::Internal(target);
+        }
+
+        let operands = line.operands();
+        let pc_relative = operands
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|word| matches!(word, "rip" | "eip" | "pc"));
+        let raw_jump = line.jump().is_external() && !line.is_symbolicated_jump();
+        if pc_relative || raw_jump {
+            Operands::Text(Cow::Owned(mask_numbers(operands)))
+        } else {
+            Operands::Text(Cow::Borrowed(operands))
+        }
+    }
+}
+
+/// Replaces each number in `text` with `?`. Digits that are part of a name, like
+/// the `8` of `r8`, are kept.
+fn mask_numbers(text: &str) -> String {
+    let mut masked = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    let mut prev: Option<char> = None;
+    while let Some((idx, ch)) = chars.next() {
+        let in_name = matches!(prev, Some(p) if p.is_ascii_alphanumeric() || p == '_');
+        if ch.is_ascii_digit() && !in_name {
+            let hex = text[idx..].starts_with("0x") || text[idx..].starts_with("0X");
+            if hex {
+                chars.next();
+            }
+            while let Some(&(_, next)) = chars.peek() {
+                if next.is_ascii_digit() || (hex && next.is_ascii_hexdigit()) {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            masked.push('?');
+            prev = Some('?');
+        } else {
+            masked.push(ch);
+            prev = Some(ch);
+        }
+    }
+    masked
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Edit {
+    /// `old[.0]` is the same as `new[.1]`.
+    Equal(usize, usize),
+    /// `old[.0]` was removed.
+    Delete(usize),
+    /// `new[.0]` was inserted.
+    Insert(usize),
+}
+
+/// Finds the shortest edit script that turns `old` into `new` using Myers'
+/// diff algorithm. The common prefix and suffix are stripped first because
+/// the trace of the search grows quadratically with the number of differences.
+fn myers<T, F>(old: &[T], new: &[T], eq: F) -> Vec<Edit>
+where
+    F: Fn(&T, &T) -> bool,
+{
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(o, n)| eq(o, n))
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| eq(o, n))
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut edits = (0..prefix)
+        .map(|idx| Edit::Equal(idx, idx))
+        .collect::<Vec<_>>();
+    edits.extend(
+        myers_middle(old_mid, new_mid, &eq)
+            .into_iter()
+            .map(|edit| match edit {
+                Edit::Equal(o, n) => Edit::Equal(o + prefix, n + prefix),
+                Edit::Delete(o) => Edit::Delete(o + prefix),
+                Edit::Insert(n) => Edit::Insert(n + prefix),
+            }),
+    );
+    edits.extend(
+        (0..suffix).map(|idx| Edit::Equal(old.len() - suffix + idx, new.len() - suffix + idx)),
+    );
+    edits
+}
+
+fn myers_middle<T, F>(old: &[T], new: &[T], eq: &F) -> Vec<Edit>
+where
+    F: Fn(&T, &T) -> bool,
+{
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = n + m;
+
+    // `v[k + offset]` is the furthest x reached on diagonal k = x - y.
+    let offset = max + 1;
+    let mut v = vec![0isize; (2 * max + 3) as usize];
+    // The part of `v` that was used for each number of edits `d`, for backtracking.
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let down =
+                k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]);
+            let mut x = if down {
+                v[(k + 1 + offset) as usize]
+            } else {
+                v[(k - 1 + offset) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && eq(&old[x as usize], &new[y as usize]) {
+                x += 1;
+                y += 1;
+            }
+            v[(k + offset) as usize] = x;
+
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        // The trace for `d` starts at diagonal `-d - 1`.
+        let get = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+
+        let prev_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = get(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Equal((x - 1) as usize, (y - 1) as usize));
+            x -= 1;
+            y -= 1;
+        }
+
+        if d > 0 {
+            if x == prev_x {
+                edits.push(Edit::Insert((y - 1) as usize));
+            } else {
+                edits.push(Edit::Delete((x - 1) as usize));
+            }
+        }
+
+        x = prev_x;
+        y = prev_y;
+    }
+
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::disasm::test::insn;
+    use capstone::Syntax;
+
+    fn edits(old: &str, new: &str) -> Vec<Edit> {
+        let old = old.chars().collect::<Vec<_>>();
+        let new = new.chars().collect::<Vec<_>>();
+        myers(&old, &new, |lhs, rhs| lhs == rhs)
+    }
+
+    /// Applies the edits to `old` and checks that the result is `new`.
+    fn apply(old: &str, new: &str) -> usize {
+        let old_chars = old.chars().collect::<Vec<_>>();
+        let new_chars = new.chars().collect::<Vec<_>>();
+        let mut result = Vec::new();
+        let mut changes = 0;
+        for edit in edits(old, new) {
+            match edit {
+                Edit::Equal(o, n) => {
+                    assert_eq!(old_chars[o], new_chars[n]);
+                    result.push(old_chars[o]);
+                }
+                Edit::Delete(_) => changes += 1,
+                Edit::Insert(n) => {
+                    result.push(new_chars[n]);
+                    changes += 1;
+                }
+            }
+        }
+        assert_eq!(result, new_chars);
+        changes
+    }
+
+    #[test]
+    fn myers_finds_shortest_edit_script() {
+        assert_eq!(apply("ABCABBA", "CBABAC"), 5);
+        assert_eq!(apply("abc", "abc"), 0);
+        assert_eq!(apply("", "abc"), 3);
+        assert_eq!(apply("abc", ""), 3);
+        assert_eq!(apply("", ""), 0);
+        assert_eq!(apply("xabcx", "xabdcx"), 1);
+    }
+
+    #[test]
+    fn myers_keeps_common_prefix_and_suffix() {
+        assert_eq!(
+            edits("ab", "axb"),
+            [Edit::Equal(0, 0), Edit::Insert(1), Edit::Equal(1, 2)]
+        );
+    }
+
+    #[test]
+    fn mask_numbers_keeps_names() {
+        assert_eq!(
+            mask_numbers("qword ptr [rip + 0x2f3a]"),
+            "qword ptr [rip + ?]"
+        );
+        assert_eq!(mask_numbers("0x10(%rip), %r8"), "?(%rip), %r8");
+        assert_eq!(mask_numbers("x1, [pc, #16]"), "x1, [pc, #?]");
+    }
+
+    #[test]
+    fn diff_ignores_moved_addresses() {
+        let disassembly = |lines: Vec<DisasmLine>| {
+            let mut disassembly = Disassembly::new(Syntax::Intel);
+            for line in lines {
+                disassembly.push_line(line);
+            }
+            disassembly
+        };
+        let jump = |address, operands: &str, target| DisasmLine {
+            jump: Jump::Internal(target),
+            ..insn(address, "jmp", operands)
+        };
+        let call = |address, operands: &str, target| DisasmLine {
+            jump: Jump::External(target),
+            ..insn(address, "call", operands)
+        };
+
+        let old = disassembly(vec![
+            insn(0x1000, "lea", "rax, [rip + 0x100]"),
+            call(0x1007, "0x3000", 0x3000),
+            jump(0x100c, "0x1011", 4),
+            insn(0x100e, "mov", "eax, 1"),
+            insn(0x1011, "ret", ""),
+        ]);
+        let new = disassembly(vec![
+            insn(0x2000, "lea", "rax, [rip + 0x200]"),
+            call(0x2007, "0x5000", 0x5000),
+            jump(0x200c, "0x2011", 4),
+            insn(0x200e, "mov", "eax, 2"),
+            insn(0x2011, "ret", ""),
+        ]);
+
+        let lines = diff(&old, &new);
+        assert_eq!(lines.len(), 5);
+        for (idx, line) in lines.iter().enumerate() {
+            match line {
+                DiffLine::Changed(..) => assert_eq!(idx, 3),
+                DiffLine::Same(..) => assert_ne!(idx, 3),
+                _ => panic!("unexpected added or removed line {}", idx),
+            }
+        }
+    }
+}
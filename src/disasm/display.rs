@@ -1,10 +1,23 @@
-use super::Disassembly;
+use super::{DisasmLine, Disassembly};
 
 pub fn measure(disassembly: &Disassembly) -> DisasmDisplayMeasure {
+    measure_relative(disassembly, 0)
+}
+
+/// Measures a disassembly whose addresses are displayed as offsets from `base`,
+/// which are shorter than the addresses themselves.
+pub fn measure_relative(disassembly: &Disassembly, base: u64) -> DisasmDisplayMeasure {
+    measure_lines(disassembly.lines(), base)
+}
+
+/// Measures some of the lines of a disassembly, like the lines of one of the
+/// symbols that were appended to it, as offsets from `base`.
+pub fn measure_lines(lines: &[DisasmLine], base: u64) -> DisasmDisplayMeasure {
     let mut measure = DisasmDisplayMeasure::default();
 
-    for line in disassembly.lines() {
-        measure.max_address = std::cmp::max(measure.max_address, line.address());
+    for line in lines {
+        measure.max_address =
+            std::cmp::max(measure.max_address, line.address().saturating_sub(base));
         measure.max_mnemonic_len =
             std::cmp::max(measure.max_mnemonic_len, line.mnemonic().len() as u16);
         measure.max_operands_len =
@@ -41,6 +54,18 @@ impl DisasmDisplayMeasure {
         ((64 - self.max_address.leading_zeros()) as f64 / 4.0).ceil() as usize
     }
 
+    /// Returns the maximum address width in decimal characters.
+    #[inline]
+    pub fn max_address_width_dec(&self) -> usize {
+        let mut address = self.max_address;
+        let mut width = 0;
+        while address > 0 {
+            address /= 10;
+            width += 1;
+        }
+        width
+    }
+
     /// Returns the maximum width of of bytes that need to be displayed
     /// in hexidecimal characters. An optional `spacing` can be passed
     /// to indicate the number of space characters that will be between each byte.
@@ -52,6 +77,12 @@ impl DisasmDisplayMeasure {
         (self.max_bytes_count as usize * 2) + (self.max_bytes_count as usize - 1) * spacing
     }
 
+    /// Returns the maximum number of bytes in a single line of the table.
+    #[inline]
+    pub fn max_bytes_count(&self) -> usize {
+        self.max_bytes_count as usize
+    }
+
     #[inline]
     pub fn max_mnemonic_len(&self) -> usize {
         self.max_mnemonic_len as usize
@@ -67,3 +98,109 @@ impl DisasmDisplayMeasure {
         self.max_comments_len as usize
     }
 }
+
+/// How addresses are displayed.
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub enum AddressFormat {
+    /// Lowercase hexadecimal without a prefix (`4011a0`).
+    #[default]
+    Hex,
+    /// Lowercase hexadecimal with a `0x` prefix (`0x4011a0`).
+    HexPrefixed,
+    /// Uppercase hexadecimal without a prefix (`4011A0`).
+    UpperHex,
+    /// Decimal (`4198816`).
+    Dec,
+}
+
+impl AddressFormat {
+    /// Returns the width of the widest address in a disassembly, or of the widest
+    /// offset if the addresses are `relative`.
+    pub fn max_width(self, measure: &DisasmDisplayMeasure, relative: bool) -> usize {
+        if relative {
+            // Offsets are always prefixed with `+`, and hex offsets with `+0x`.
+            return match self {
+                AddressFormat::Dec => measure.max_address_width_dec().max(1) + 1,
+                _ => measure.max_address_width_hex().max(1) + 3,
+            };
+        }
+
+        match self {
+            AddressFormat::Hex | AddressFormat::UpperHex => measure.max_address_width_hex(),
+            AddressFormat::HexPrefixed => measure.max_address_width_hex() + 2,
+            AddressFormat::Dec => measure.max_address_width_dec(),
+        }
+    }
+
+    pub fn display(self, address: u64) -> Address {
+        Address {
+            address,
+            format: self,
+            relative: false,
+        }
+    }
+
+    /// Writes `address` for a comment. Hex addresses always have a `0x` prefix there,
+    /// so that they can't be mistaken for decimal numbers.
+    pub fn comment(self, address: u64) -> String {
+        match self {
+            AddressFormat::Hex | AddressFormat::HexPrefixed => format!("0x{:x}", address),
+            AddressFormat::UpperHex => format!("0x{:X}", address),
+            AddressFormat::Dec => address.to_string(),
+        }
+    }
+
+    /// Displays `address` as an offset from `base` like `+0x1f`, the way that
+    /// jumps inside of a symbol are written, or as an address if there is no base.
+    pub fn display_from(self, address: u64, base: Option<u64>) -> Address {
+        match base {
+            Some(base) => Address {
+                address: address.wrapping_sub(base),
+                format: self,
+                relative: true,
+            },
+            None => self.display(address),
+        }
+    }
+}
+
+impl std::str::FromStr for AddressFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // `hex` and `HEX` are told apart by their case.
+        match s {
+            "hex" => Ok(AddressFormat::Hex),
+            "hex-prefixed" => Ok(AddressFormat::HexPrefixed),
+            "HEX" => Ok(AddressFormat::UpperHex),
+            "dec" => Ok(AddressFormat::Dec),
+            _ => Err("invalid address format"),
+        }
+    }
+}
+
+/// Displays an address in an [`AddressFormat`]. This respects the width
+/// and alignment of the formatter.
+pub struct Address {
+    address: u64,
+    format: AddressFormat,
+    /// The address is an offset from the start of a symbol.
+    relative: bool,
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let address = match self.format {
+            AddressFormat::Hex | AddressFormat::HexPrefixed if self.relative => {
+                format!("+0x{:x}", self.address)
+            }
+            AddressFormat::UpperHex if self.relative => format!("+0x{:X}", self.address),
+            AddressFormat::Dec if self.relative => format!("+{}", self.address),
+            AddressFormat::Hex => format!("{:x}", self.address),
+            AddressFormat::HexPrefixed => format!("0x{:x}", self.address),
+            AddressFormat::UpperHex => format!("{:X}", self.address),
+            AddressFormat::Dec => self.address.to_string(),
+        };
+        f.pad(&address)
+    }
+}
@@ -1,25 +1,52 @@
 use crate::disasm::binary::BinaryData;
-use crate::disasm::symbol::{Symbol, SymbolSource};
+use crate::disasm::error::DisasmError;
+use crate::disasm::source::SourceLocation;
+use crate::disasm::symbol::{Symbol, SymbolKind, SymbolSource};
 use crate::util;
 use anyhow::Context as _;
 use gimli::{read::EndianReader, Dwarf, RunTimeEndian};
-use once_cell::unsync::OnceCell;
+use once_cell::sync::OnceCell;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 pub type BinaryDataReader = EndianReader<RunTimeEndian, BinaryData>;
 
 /// Maps an address range to a compilation unit index.
 type UnitRange = (Range<u64>, usize);
 
+/// Options for loading symbols from DWARF debug information.
+#[derive(Copy, Clone)]
+pub struct DwarfSymbolOptions<'a> {
+    /// Checked between compilation units, loading stops when this is set.
+    pub cancel: Option<&'a AtomicBool>,
+
+    /// Also load subprograms that don't have an address range.
+    pub declarations: bool,
+}
+
 pub struct DwarfInfo {
     dwarf: Dwarf<BinaryDataReader>,
 
+    /// Split DWARF objects (`.dwo` files) that were loaded for skeleton
+    /// units in `dwarf`.
+    split_dwarf: Vec<SplitDwarf>,
+
     compilation_unit_ranges: Vec<UnitRange>,
     compilation_units: Vec<LazyCompilationUnit>,
     compilation_units_initialized: bool,
 }
 
+// Line tables are loaded lazily from `addr2line`, which may be called from
+// several threads at once.
+#[allow(dead_code)]
+fn assert_dwarf_is_send_and_sync() {
+    fn assert<T: Send + Sync>() {}
+    assert::<BinaryDataReader>();
+    assert::<Dwarf<BinaryDataReader>>();
+    assert::<DwarfInfo>();
+}
+
 impl DwarfInfo {
     pub fn new<L, S>(loader: L, sup_loader: S) -> anyhow::Result<DwarfInfo>
     where
@@ -28,6 +55,7 @@ impl DwarfInfo {
     {
         Ok(DwarfInfo {
             dwarf: gimli::Dwarf::load(loader, sup_loader)?,
+            split_dwarf: Vec::new(),
 
             compilation_unit_ranges: Vec::new(),
             compilation_units: Vec::new(),
@@ -35,18 +63,12 @@ impl DwarfInfo {
         })
     }
 
-    /// Loads DWARF symbols into the given output vector.
-    pub fn load_symbols<F>(
-        &self,
-        symbols: &mut Vec<Symbol>,
-        addr_to_offset: F,
-    ) -> anyhow::Result<()>
-    where
-        F: Send + Sync + Fn(u64) -> Option<usize>,
-    {
+    /// Finds the skeleton units whose debugging information was moved into
+    /// split DWARF objects (`.dwo` files).
+    pub fn split_dwarf_refs(&self) -> Vec<SplitDwarfRef> {
+        let mut refs = Vec::new();
         let mut unit_headers = self.dwarf.units();
 
-        let mut units = Vec::new();
         while let Some(unit_header) = match unit_headers.next() {
             Ok(maybe_unit_header) => maybe_unit_header,
             Err(err) => {
@@ -54,39 +76,166 @@ impl DwarfInfo {
                 None
             }
         } {
-            if let Ok(unit) = self.dwarf.unit(unit_header) {
-                units.push(unit);
+            let unit = if let Ok(unit) = self.dwarf.unit(unit_header) {
+                unit
             } else {
                 continue;
+            };
+
+            match Self::split_dwarf_path(&self.dwarf, &unit) {
+                Ok(Some(path)) => refs.push(SplitDwarfRef {
+                    path,
+                    skeleton: unit,
+                }),
+                Ok(None) => {}
+                Err(err) => log::debug!("soft error while reading DWARF skeleton unit: {}", err),
+            }
+        }
+
+        refs
+    }
+
+    /// Returns the path of the split DWARF object referenced by a unit
+    /// if it is a skeleton unit.
+    fn split_dwarf_path(
+        dwarf: &Dwarf<BinaryDataReader>,
+        unit: &gimli::Unit<BinaryDataReader>,
+    ) -> Result<Option<PathBuf>, gimli::Error> {
+        let mut entries = unit.entries_raw(None)?;
+        let abbrev = match entries.read_abbreviation()? {
+            Some(abbrev)
+                if abbrev.tag() == gimli::DW_TAG_compile_unit
+                    || abbrev.tag() == gimli::DW_TAG_skeleton_unit =>
+            {
+                abbrev
+            }
+            _ => return Ok(None),
+        };
+
+        let mut dwo_name = None;
+        for spec in abbrev.attributes() {
+            let attr = entries.read_attribute(*spec)?;
+            if attr.name() == gimli::DW_AT_dwo_name || attr.name() == gimli::DW_AT_GNU_dwo_name {
+                dwo_name = Some(dwarf.attr_string(unit, attr.value())?);
             }
         }
 
+        let dwo_name = match dwo_name.as_ref().map(|n| std::str::from_utf8(n.bytes())) {
+            Some(Ok(dwo_name)) => Path::new(dwo_name),
+            _ => return Ok(None),
+        };
+
+        let mut path = PathBuf::new();
+        if let Some(Ok(comp_dir)) = unit
+            .comp_dir
+            .as_ref()
+            .map(|d| std::str::from_utf8(d.bytes()))
+        {
+            path.push(comp_dir);
+        }
+        path.push(dwo_name);
+        Ok(Some(path))
+    }
+
+    /// Loads the split DWARF object referenced by a skeleton unit. `loader` should
+    /// load the `.dwo` sections of the split DWARF object.
+    pub fn add_split_dwarf<L, S>(
+        &mut self,
+        split_ref: SplitDwarfRef,
+        loader: L,
+        sup_loader: S,
+    ) -> anyhow::Result<()>
+    where
+        L: Fn(gimli::SectionId) -> anyhow::Result<BinaryDataReader>,
+        S: Fn(gimli::SectionId) -> anyhow::Result<BinaryDataReader>,
+    {
+        let mut dwarf = gimli::Dwarf::load(loader, sup_loader)?;
+        dwarf.file_type = gimli::DwarfFileType::Dwo;
+
+        // The `.debug_addr` section never lives in a split DWARF object.
+        dwarf.debug_addr = self.dwarf.debug_addr.clone();
+
+        self.split_dwarf.push(SplitDwarf {
+            dwarf,
+            skeleton: split_ref.skeleton,
+        });
+        Ok(())
+    }
+
+    /// Loads the DWARF symbols of every compilation unit into `symbols`. If
+    /// `options.cancel` is set while this is running, the remaining compilation units
+    /// are skipped and an error is returned.
+    pub fn load_symbols<F>(
+        &self,
+        symbols: &mut Vec<Symbol>,
+        addr_to_offset: F,
+        options: DwarfSymbolOptions,
+    ) -> anyhow::Result<()>
+    where
+        F: Send + Sync + Fn(u64) -> Option<usize>,
+    {
+        let mut units = Vec::new();
+        Self::collect_units(&self.dwarf, None, &mut units);
+        for split in self.split_dwarf.iter() {
+            Self::collect_units(&split.dwarf, Some(&split.skeleton), &mut units);
+        }
+
         use rayon::prelude::*;
 
         log::debug!(
             "processing {} DWARF compilation units using rayon",
             units.len()
         );
+        let units_count = units.len();
+        let progress_step = std::cmp::max(units_count / 10, 1);
+        let units_processed = AtomicUsize::new(0);
+        let is_cancelled = || {
+            options
+                .cancel
+                .map(|c| c.load(Ordering::Relaxed))
+                .unwrap_or(false)
+        };
+
         let (result_send, result_recv) =
             std::sync::mpsc::sync_channel::<Result<(), anyhow::Error>>(units.len());
-        let dwarf = &self.dwarf;
-        symbols.par_extend(units.par_iter().flat_map(move |unit| {
+        symbols.par_extend(units.par_iter().flat_map(|&(dwarf, ref unit)| {
             let mut name_chain = NameChain::new();
             let mut symbols = Vec::with_capacity(32);
+
+            if is_cancelled() {
+                return symbols;
+            }
+
             result_send
                 .send(
                     Self::load_symbols_from_unit(
                         dwarf,
-                        &unit,
+                        unit,
                         &mut symbols,
                         &addr_to_offset,
                         &mut name_chain,
+                        options.declarations,
                     )
                     .context("failed to load symbols from compilation unit"),
                 )
                 .expect("receiver should be available");
+
+            let processed = units_processed.fetch_add(1, Ordering::Relaxed) + 1;
+            if processed % progress_step == 0 {
+                log::info!(
+                    "processed {}/{} DWARF compilation units",
+                    processed,
+                    units_count
+                );
+            }
+
             symbols
         }));
+        drop(result_send);
+
+        if is_cancelled() {
+            return Err(DisasmError::Cancelled.into());
+        }
 
         // Handle any errors that we encountered while gathering symbols
         for result in result_recv {
@@ -96,12 +245,41 @@ impl DwarfInfo {
         Ok(())
     }
 
+    /// Collects all of the units in `dwarf` along with the DWARF object that they
+    /// belong to. Units from a split DWARF object take their relocated attributes
+    /// from their `skeleton` unit.
+    fn collect_units<'d>(
+        dwarf: &'d Dwarf<BinaryDataReader>,
+        skeleton: Option<&gimli::Unit<BinaryDataReader>>,
+        units: &mut Vec<(&'d Dwarf<BinaryDataReader>, gimli::Unit<BinaryDataReader>)>,
+    ) {
+        let mut unit_headers = dwarf.units();
+
+        while let Some(unit_header) = match unit_headers.next() {
+            Ok(maybe_unit_header) => maybe_unit_header,
+            Err(err) => {
+                log::debug!("soft error while reading DWARF compilation units: {}", err);
+                None
+            }
+        } {
+            if let Ok(mut unit) = dwarf.unit(unit_header) {
+                if let Some(skeleton) = skeleton {
+                    unit.copy_relocated_attributes(skeleton);
+                }
+                units.push((dwarf, unit));
+            } else {
+                continue;
+            }
+        }
+    }
+
     fn load_symbols_from_unit<F>(
         dwarf: &Dwarf<BinaryDataReader>,
         unit: &gimli::Unit<BinaryDataReader>,
         symbols: &mut Vec<Symbol>,
         addr_to_offset: &F,
         name_chain: &mut NameChain,
+        declarations: bool,
     ) -> Result<(), gimli::Error>
     where
         F: Fn(u64) -> Option<usize>,
@@ -127,6 +305,7 @@ impl DwarfInfo {
                     &dwarf,
                     addr_to_offset,
                     name_chain,
+                    declarations,
                 )? {
                     symbols.push(symbol);
                 }
@@ -166,6 +345,7 @@ impl DwarfInfo {
         dwarf: &Dwarf<BinaryDataReader>,
         addr_to_offset: &F,
         name_chain: &mut NameChain,
+        declarations: bool,
     ) -> Result<Option<Symbol>, gimli::Error>
     where
         F: Fn(u64) -> Option<usize>,
@@ -175,6 +355,8 @@ impl DwarfInfo {
         let mut name = None;
         let mut linkage_name = false;
         let mut end_is_offset = false;
+        let mut decl_file = None;
+        let mut decl_line = None;
 
         for spec in attributes {
             let attr = entries.read_attribute(*spec)?;
@@ -200,47 +382,65 @@ impl DwarfInfo {
                     linkage_name = false;
                     name = Some(dwarf.attr_string(unit, attr.value())?)
                 }
+                gimli::DW_AT_decl_file => decl_file = attr.udata_value(),
+                gimli::DW_AT_decl_line => decl_line = attr.udata_value(),
                 _ => continue,
             }
         }
 
-        if let (Some(start), Some(mut end), Some(name)) = (start, end, name) {
-            if end_is_offset {
-                end += start;
-            }
-            let end = end; // FREEZE!
-
-            if let Some(off) = addr_to_offset(start) {
-                let len = (end - start) as usize;
-
-                if linkage_name {
-                    if let Ok(name) = std::str::from_utf8(name.bytes()) {
-                        Ok(Some(Symbol::new(
-                            name.to_string(),
-                            start,
-                            off,
-                            len,
-                            SymbolSource::Dwarf,
-                        )))
-                    } else {
-                        Ok(None)
-                    }
+        let name = if let Some(name) = name {
+            name
+        } else {
+            return Ok(None);
+        };
+
+        let (start, len, off, kind) = match (start, end) {
+            (Some(start), Some(end)) => {
+                let end = if end_is_offset { start + end } else { end };
+                if let Some(off) = addr_to_offset(start) {
+                    (start, (end - start) as usize, off, SymbolKind::Function)
                 } else {
-                    name_chain.push(name);
-                    Ok(Some(Symbol::new_unmangled(
-                        name_chain.combine("::"),
-                        start,
-                        off,
-                        len,
-                        SymbolSource::Dwarf,
-                    )))
+                    return Ok(None);
                 }
+            }
+
+            // Subprograms without an address range are declarations or
+            // functions that only exist inlined into other functions.
+            _ if declarations => (0, 0, 0, SymbolKind::Declaration),
+            _ => return Ok(None),
+        };
+
+        let mut symbol = if linkage_name {
+            if let Ok(name) = std::str::from_utf8(name.bytes()) {
+                Symbol::new(name.to_string(), start, off, len, SymbolSource::Dwarf)
             } else {
-                Ok(None)
+                return Ok(None);
             }
         } else {
-            Ok(None)
+            name_chain.push(name);
+            Symbol::new_unmangled(
+                name_chain.combine("::"),
+                start,
+                off,
+                len,
+                SymbolSource::Dwarf,
+            )
+        };
+
+        symbol.set_kind(kind);
+
+        if let (Some(file), Some(line), Some(program)) =
+            (decl_file, decl_line, unit.line_program.as_ref())
+        {
+            let header = program.header();
+            if let Some(file) = header.file(file) {
+                if let Some(path) = file_entry_path(dwarf, unit, header, file)? {
+                    symbol.set_decl_location(path, line as u32);
+                }
+            }
         }
+
+        Ok(Some(symbol))
     }
 
     /// This will load the compilation units and their addresses ranges
@@ -313,8 +513,14 @@ impl DwarfInfo {
     ) -> Result<(), gimli::Error> {
         let mut entries = unit.entries_raw(None)?;
 
+        // Skeleton units still own the line program for split DWARF.
         let abbrev = match entries.read_abbreviation()? {
-            Some(abbrev) if abbrev.tag() == gimli::DW_TAG_compile_unit => abbrev,
+            Some(abbrev)
+                if abbrev.tag() == gimli::DW_TAG_compile_unit
+                    || abbrev.tag() == gimli::DW_TAG_skeleton_unit =>
+            {
+                abbrev
+            }
             _ => return Ok(()),
         };
 
@@ -372,10 +578,13 @@ impl DwarfInfo {
         Ok(())
     }
 
+    /// Returns the source locations for `addr`. The line table of the address's
+    /// compilation unit is loaded the first time it is needed; this can safely
+    /// happen on several threads at once.
     pub fn addr2line(
         &self,
         addr: u64,
-    ) -> anyhow::Result<Option<impl '_ + Iterator<Item = (&Path, u32)>>> {
+    ) -> anyhow::Result<Option<impl '_ + Iterator<Item = SourceLocation<'_>>>> {
         let range_idx = if let Ok(idx) = self
             .compilation_unit_ranges
             .binary_search_by(|&(ref probe, _)| util::cmp_range_to_idx(probe, addr))
@@ -391,6 +600,25 @@ impl DwarfInfo {
     }
 }
 
+/// A skeleton unit that refers to a split DWARF object.
+pub struct SplitDwarfRef {
+    path: PathBuf,
+    skeleton: gimli::Unit<BinaryDataReader>,
+}
+
+impl SplitDwarfRef {
+    /// The path of the split DWARF object (`.dwo` file).
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// A split DWARF object and the skeleton unit that refers to it.
+struct SplitDwarf {
+    dwarf: Dwarf<BinaryDataReader>,
+    skeleton: gimli::Unit<BinaryDataReader>,
+}
+
 pub struct LazyCompilationUnit {
     unit: gimli::Unit<BinaryDataReader>,
 
@@ -440,7 +668,6 @@ impl LazyCompilationUnit {
         let mut lines = Vec::new();
 
         let mut seq_start_addr = 0;
-        let mut seq_prev_addr = 0;
 
         while let Some((_, row)) = rows.next_row()? {
             let address = row.address();
@@ -463,24 +690,27 @@ impl LazyCompilationUnit {
             let file = row.file_index() as usize;
             let line = row.line().unwrap_or(0) as u32;
 
-            if !lines.is_empty() {
-                if seq_prev_addr == address {
-                    let last_line = lines.last_mut().unwrap();
-                    last_line.file = file as usize;
-                    last_line.line = line;
+            // Only one row is kept for each address. Later rows replace earlier ones,
+            // except that a row which starts a statement is not replaced by one that
+            // doesn't.
+            match lines.last_mut() {
+                Some(last) if last.addr == address => {
+                    if row.is_stmt() || !last.is_stmt {
+                        last.file = file;
+                        last.line = line;
+                        last.is_stmt = row.is_stmt();
+                    }
                     continue;
-                } else {
-                    seq_prev_addr = address;
                 }
-            } else {
-                seq_start_addr = address;
-                seq_prev_addr = address;
+                Some(_) => {}
+                None => seq_start_addr = address,
             }
 
             lines.push(Line {
                 addr: address,
                 file,
                 line,
+                is_stmt: row.is_stmt(),
             });
         }
 
@@ -490,35 +720,92 @@ impl LazyCompilationUnit {
         let header = inc_line_program.header();
         let mut idx = 0;
         while let Some(file) = header.file(idx) {
-            let mut path = PathBuf::new();
-
-            if let Some(directory) = file.directory(&header) {
-                let directory_raw = dwarf.attr_string(&self.unit, directory)?;
-
-                if let Ok(directory) = std::str::from_utf8(directory_raw.bytes()) {
-                    path.push(directory);
-                }
-            }
-
-            let file_path_raw = dwarf.attr_string(&self.unit, file.path_name())?;
-            if let Ok(file_path) = std::str::from_utf8(file_path_raw.bytes()) {
-                path.push(file_path);
+            if let Some(path) = file_entry_path(dwarf, &self.unit, header, file)? {
                 files.push(path);
             }
-
             idx += 1;
         }
 
         Ok(Lines {
             sequences: sequences.into_boxed_slice(),
             files: files.into_boxed_slice(),
+            inlined_calls: self.load_inlined_calls(dwarf)?.into_boxed_slice(),
         })
     }
+
+    /// Finds the address ranges of the inlined subroutines in the unit and the
+    /// locations that they were inlined at.
+    fn load_inlined_calls(
+        &self,
+        dwarf: &Dwarf<BinaryDataReader>,
+    ) -> Result<Vec<InlinedCall>, gimli::Error> {
+        let mut calls = Vec::new();
+        let mut depth = 0;
+        let mut entries = self.unit.entries();
+
+        while let Some((delta, entry)) = entries.next_dfs()? {
+            depth += delta;
+            if entry.tag() != gimli::DW_TAG_inlined_subroutine {
+                continue;
+            }
+
+            let file = match entry.attr_value(gimli::DW_AT_call_file)? {
+                Some(gimli::AttributeValue::FileIndex(file)) => file as usize,
+                _ => continue,
+            };
+            let line = entry
+                .attr(gimli::DW_AT_call_line)?
+                .and_then(|attr| attr.udata_value())
+                .unwrap_or(0) as u32;
+
+            let mut ranges = dwarf.die_ranges(&self.unit, entry)?;
+            while let Some(range) = ranges.next()? {
+                calls.push(InlinedCall {
+                    range: range.begin..range.end,
+                    depth,
+                    file,
+                    line,
+                });
+            }
+        }
+
+        calls.sort_unstable_by_key(|call| call.range.start);
+        Ok(calls)
+    }
+}
+
+/// Returns the path of a file in the line program of `unit`, or `None` if
+/// its name is not valid UTF-8.
+fn file_entry_path(
+    dwarf: &Dwarf<BinaryDataReader>,
+    unit: &gimli::Unit<BinaryDataReader>,
+    header: &gimli::LineProgramHeader<BinaryDataReader>,
+    file: &gimli::FileEntry<BinaryDataReader>,
+) -> Result<Option<PathBuf>, gimli::Error> {
+    let mut path = PathBuf::new();
+
+    if let Some(directory) = file.directory(header) {
+        let directory_raw = dwarf.attr_string(unit, directory)?;
+
+        if let Ok(directory) = std::str::from_utf8(directory_raw.bytes()) {
+            path.push(directory);
+        }
+    }
+
+    let file_path_raw = dwarf.attr_string(unit, file.path_name())?;
+    if let Ok(file_path) = std::str::from_utf8(file_path_raw.bytes()) {
+        path.push(file_path);
+        Ok(Some(path))
+    } else {
+        Ok(None)
+    }
 }
 
 struct Lines {
     sequences: Box<[Sequence]>,
     files: Box<[PathBuf]>,
+    /// Sorted by the start of their ranges.
+    inlined_calls: Box<[InlinedCall]>,
 }
 
 impl Lines {
@@ -526,38 +813,47 @@ impl Lines {
         Lines {
             sequences: Box::new([] as [Sequence; 0]),
             files: Box::new([] as [PathBuf; 0]),
+            inlined_calls: Box::new([] as [InlinedCall; 0]),
         }
     }
 
-    fn lines_for_addr(&self, addr: u64) -> Option<impl '_ + Iterator<Item = (&Path, u32)>> {
-        let map_line = move |line: &Line| (self.files[line.file].as_path(), line.line);
-
+    /// Returns the locations of `addr`, innermost first. The first location is the
+    /// one from the line table, and it is followed by the locations that each of the
+    /// inlined subroutines containing `addr` was called from, from the most deeply
+    /// nested one outwards. The call sites are only looked up once they're needed.
+    fn lines_for_addr(&self, addr: u64) -> Option<impl '_ + Iterator<Item = SourceLocation<'_>>> {
         let sequence = self
             .sequences
             .binary_search_by(|probe| util::cmp_range_to_idx(&probe.range, addr))
             .ok()
             .and_then(|seq_idx| self.sequences.get(seq_idx))?;
-
-        if let Ok(idx) = sequence
+        let idx = sequence
             .lines
             .binary_search_by(|probe| probe.addr.cmp(&addr))
-        {
-            let mut range = idx..(idx + 1);
-
-            // Find the first line with the address
-            while range.start > 0 && sequence.lines[range.start - 1].addr == addr {
-                range.start -= 1;
-            }
+            .ok()?;
+        let line = &sequence.lines[idx];
+        let innermost = self.location(line.file, line.line)?;
+
+        let call_sites = std::iter::once_with(move || {
+            let end = self
+                .inlined_calls
+                .partition_point(|call| call.range.start <= addr);
+            let mut calls = self.inlined_calls[..end]
+                .iter()
+                .filter(|call| call.range.contains(&addr))
+                .collect::<Vec<_>>();
+            calls.sort_by_key(|call| std::cmp::Reverse(call.depth));
+            calls
+        })
+        .flatten()
+        .filter_map(move |call| self.location(call.file, call.line));
 
-            // Find the final line with the address.
-            while range.end < sequence.lines.len() && sequence.lines[range.end].addr == addr {
-                range.end += 1;
-            }
+        Some(std::iter::once(innermost).chain(call_sites))
+    }
 
-            Some((&sequence.lines[range] as &[Line]).iter().map(map_line))
-        } else {
-            None
-        }
+    fn location(&self, file: usize, line: u32) -> Option<SourceLocation<'_>> {
+        let path = self.files.get(file)?;
+        Some(SourceLocation::new(path.as_path(), line))
     }
 }
 
@@ -572,6 +868,19 @@ struct Line {
     addr: u64,
     file: usize,
     line: u32,
+    /// The row is the start of a statement.
+    is_stmt: bool,
+}
+
+/// The addresses of a subroutine that was inlined, and the location of the call
+/// that it was inlined for.
+struct InlinedCall {
+    range: Range<u64>,
+    /// The depth of the subroutine's entry in the unit. Subroutines that were
+    /// inlined into other inlined subroutines are deeper.
+    depth: isize,
+    file: usize,
+    line: u32,
 }
 
 struct NameChain {
@@ -647,3 +956,119 @@ impl NameChain {
         self.depth = 0;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Loads DWARF with a single compilation unit whose root entry has the string
+    /// attributes `attrs`.
+    fn dwarf_with_unit(attrs: &[(gimli::DwAt, &str)]) -> DwarfInfo {
+        let mut abbrev = vec![1, gimli::DW_TAG_compile_unit.0 as u8, 0];
+        for &(name, _) in attrs {
+            let mut name = name.0;
+            // ULEB128
+            loop {
+                let byte = (name & 0x7f) as u8;
+                name >>= 7;
+                if name == 0 {
+                    abbrev.push(byte);
+                    break;
+                }
+                abbrev.push(byte | 0x80);
+            }
+            abbrev.push(gimli::DW_FORM_string.0 as u8);
+        }
+        abbrev.extend_from_slice(&[0, 0, 0]);
+
+        // A DWARF 4 unit header without the length, and the root entry.
+        let mut unit = vec![4, 0, 0, 0, 0, 0, 8, 1];
+        for &(_, value) in attrs {
+            unit.extend_from_slice(value.as_bytes());
+            unit.push(0);
+        }
+        let mut info = (unit.len() as u32).to_le_bytes().to_vec();
+        info.extend_from_slice(&unit);
+
+        let (info, abbrev) = (
+            BinaryData::from_bytes(&info),
+            BinaryData::from_bytes(&abbrev),
+        );
+        let loader = |section: gimli::SectionId| {
+            let data = match section {
+                gimli::SectionId::DebugInfo => info.clone(),
+                gimli::SectionId::DebugAbbrev => abbrev.clone(),
+                _ => info.slice(0..0),
+            };
+            Ok(EndianReader::new(data, RunTimeEndian::Little))
+        };
+        let sup_loader = |_| Ok(EndianReader::new(info.slice(0..0), RunTimeEndian::Little));
+        DwarfInfo::new(loader, sup_loader).unwrap()
+    }
+
+    #[test]
+    fn split_dwarf_refs_of_skeleton_units() {
+        let dwarf = dwarf_with_unit(&[
+            (gimli::DW_AT_GNU_dwo_name, "foo.dwo"),
+            (gimli::DW_AT_comp_dir, "/build"),
+        ]);
+        let paths = dwarf
+            .split_dwarf_refs()
+            .iter()
+            .map(|split_ref| split_ref.path().to_path_buf())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [PathBuf::from("/build/foo.dwo")]);
+
+        let dwarf = dwarf_with_unit(&[(gimli::DW_AT_dwo_name, "bar.dwo")]);
+        let paths = dwarf
+            .split_dwarf_refs()
+            .iter()
+            .map(|split_ref| split_ref.path().to_path_buf())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [PathBuf::from("bar.dwo")]);
+
+        // A unit that wasn't split is not a skeleton unit.
+        let dwarf = dwarf_with_unit(&[(gimli::DW_AT_comp_dir, "/build")]);
+        assert!(dwarf.split_dwarf_refs().is_empty());
+    }
+
+    #[test]
+    fn lines_for_addr_follows_inlined_calls() {
+        let line = |addr, line| Line {
+            addr,
+            file: 1,
+            line,
+            is_stmt: true,
+        };
+        let call = |range, depth, line| InlinedCall {
+            range,
+            depth,
+            file: 0,
+            line,
+        };
+        let lines = Lines {
+            sequences: Box::new([Sequence {
+                range: 0x1000..0x1040,
+                lines: Box::new([line(0x1000, 1), line(0x1010, 2), line(0x1020, 3)]),
+            }]),
+            files: Box::new([PathBuf::from("caller.rs"), PathBuf::from("callee.rs")]),
+            inlined_calls: Box::new([call(0x1010..0x1030, 1, 10), call(0x1010..0x1018, 2, 20)]),
+        };
+        let locations = |addr| {
+            lines
+                .lines_for_addr(addr)
+                .into_iter()
+                .flatten()
+                .map(|location| (location.path().to_str().unwrap(), location.line()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(locations(0x1000), [("callee.rs", 1)]);
+        assert_eq!(
+            locations(0x1010),
+            [("callee.rs", 2), ("caller.rs", 20), ("caller.rs", 10)]
+        );
+        assert_eq!(locations(0x1020), [("callee.rs", 3), ("caller.rs", 10)]);
+        assert!(locations(0x1008).is_empty());
+    }
+}
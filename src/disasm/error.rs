@@ -0,0 +1,61 @@
+use super::binary::Arch;
+use std::path::PathBuf;
+
+/// The errors returned by the disassembler.
+///
+/// Errors without a variant of their own (e.g. invalid debug information)
+/// are returned as [`DisasmError::Other`].
+#[derive(Debug, thiserror::Error)]
+pub enum DisasmError {
+    /// The binary's architecture is unknown or can't be disassembled by
+    /// this build of Capstone.
+    #[error("unknown or unsupported binary architecture `{0}`")]
+    UnsupportedArch(Arch),
+
+    /// No symbol matched the name that was searched for.
+    #[error("no symbol matching `{0}` was found")]
+    SymbolNotFound(String),
+
+    /// The binary is not an object that can be parsed.
+    #[error("failed to parse object")]
+    Parse(#[source] goblin::error::Error),
+
+    #[error("failed to initialize Capstone")]
+    Capstone(#[source] capstone::Error),
+
+    #[error("failed to read `{}`", .path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// Debug information that was requested explicitly was not found.
+    #[error("did not find debug information at `{}`", .0.display())]
+    MissingDebugInfo(PathBuf),
+
+    /// An instruction group was named that the binary's architecture doesn't have.
+    #[error("unknown instruction group `{name}`, the groups are: {}", .groups.join(", "))]
+    UnknownGroup { name: String, groups: Vec<String> },
+
+    /// Loading symbols was cancelled through [`SearchOptions::cancel`](super::binary::SearchOptions::cancel).
+    #[error("loading symbols was cancelled")]
+    Cancelled,
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for DisasmError {
+    /// Internally errors are passed around as [`anyhow::Error`], so a typed error
+    /// that was raised further down is recovered here instead of being wrapped.
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast_ref::<DisasmError>() {
+            // Unwrapping `Other` would drop any context that was added after it.
+            Some(DisasmError::Other(_)) | None => DisasmError::Other(err),
+            Some(_) => err
+                .downcast::<DisasmError>()
+                .expect("error should be a DisasmError"),
+        }
+    }
+}
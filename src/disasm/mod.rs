@@ -1,143 +1,1265 @@
 pub mod binary;
+pub mod diff;
 pub mod display;
 pub mod source;
 pub mod symbol;
 
 mod anal;
 mod dwarf;
+mod error;
 mod pdb;
 pub mod strmatch;
 
-pub use self::anal::Jump;
+pub use self::anal::{Jump, OperandKind};
 use self::binary::Binary;
+use self::display::AddressFormat;
+pub use self::error::DisasmError;
 use self::symbol::Symbol;
 use anyhow::Context as _;
-use capstone::Capstone;
-use source::SourceLoader;
+use capstone::{Capstone, InsnGroup, Syntax};
+use source::{SourceLine, SourceLoader, SourceMappings};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::ops::Range;
+use std::path::Path;
 
-pub fn disasm(binary: &Binary, symbol: &Symbol, load_source: bool) -> anyhow::Result<Disassembly> {
+/// The mnemonic used for lines containing bytes that are not instructions.
+/// This is the same as the mnemonic Capstone uses in SKIPDATA mode.
+const DATA_MNEMONIC: &str = ".byte";
+
+/// The number of bytes in each line created by [`disasm_data`].
+const DATA_LINE_LEN: usize = 16;
+
+/// The minimum number of instructions in a prologue or epilogue that is folded.
+const MIN_FOLD_LEN: usize = 2;
+
+/// The number of instructions at the start of a symbol that are searched for the
+/// allocation of its stack frame.
+const FRAME_SCAN_LEN: usize = 16;
+
+/// The maximum number of entries that are read from the jump table of a switch.
+const MAX_JUMP_TABLE_LEN: usize = 1024;
+
+/// The comment on instructions in the delay slot of a branch.
+const DELAY_SLOT_COMMENT: &str = "delay slot";
+
+/// The comment on instructions that are landing pads for indirect jumps and calls.
+const LANDING_PAD_COMMENT: &str = "CET landing pad";
+
+/// Options for [`disasm`].
+#[derive(Clone, Default)]
+pub struct DisasmOptions {
+    /// Only disassemble the instructions starting at or after this address.
+    pub start_at: Option<u64>,
+
+    /// Only disassemble the instructions starting before this address.
+    pub stop_at: Option<u64>,
+
+    /// Load the source lines for each instruction.
+    pub load_source: bool,
+
+    /// Which source lines are loaded for an instruction that is mapped to more
+    /// than one source location.
+    pub source_mappings: SourceMappings,
+
+    /// Comment each instruction with the condition flags that it accesses.
+    pub show_flags: bool,
+
+    /// Describe how each instruction is encoded.
+    pub show_encoding: bool,
+
+    /// Comment each instruction with the characters of its immediate operands that
+    /// are printable ASCII.
+    pub ascii_immediates: bool,
+
+    /// The maximum number of instructions that will be disassembled. This guards
+    /// against symbols with bogus sizes.
+    pub max_instructions: Option<usize>,
+
+    /// Only disassemble this many instructions from the start of the window. Unlike
+    /// `max_instructions` this is expected to cut the symbol short, so the rest of
+    /// the symbol isn't shown as data.
+    pub count: Option<usize>,
+
+    /// The assembly syntax, or Capstone's default syntax for the architecture if
+    /// this is `None`.
+    pub syntax: Option<Syntax>,
+
+    /// Keep the addresses of jump targets in the operands and add the symbol of
+    /// the target as a comment, instead of the other way around.
+    pub raw_targets: bool,
+
+    /// Replace the addresses of jump targets with their symbols and find the lines
+    /// that jumps inside of the symbol go to. Without this the operands are the ones
+    /// that Capstone printed, which is faster for large dumps.
+    pub symbolicate_jumps: bool,
+
+    /// Write the targets of jumps inside of the symbol as offsets from its start
+    /// (`+0x20`) and leave out the addresses of symbolicated targets, so that the
+    /// output doesn't depend on where the symbol was linked.
+    pub relative_targets: bool,
+
+    /// How the addresses of symbolicated jump targets are written in their comments.
+    pub address_format: AddressFormat,
+
+    /// How the instructions of a symbol are found.
+    pub traversal: Traversal,
+
+    /// Find the kind of each operand so that they can be colored separately.
+    pub rich_operands: bool,
+
+    /// Find the standard prologue and epilogues of the symbol so that they can
+    /// be folded into a single line.
+    pub fold_prologue: bool,
+
+    /// Find the padding at the end of the symbol so that it can be folded into
+    /// a single line.
+    pub trim_padding: bool,
+}
+
+/// The strategy used to find the instructions of a symbol.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Traversal {
+    /// Every instruction from the start of the symbol to its end is disassembled
+    /// (linear sweep). Data inside of the function desynchronizes the disassembly.
+    #[default]
+    Linear,
+    /// Only the instructions that are reachable from the start of the symbol by
+    /// following branches and falling through are disassembled (recursive traversal).
+    /// The bytes in between are displayed as data.
+    Recursive,
+}
+
+impl std::str::FromStr for Traversal {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("linear") {
+            Ok(Traversal::Linear)
+        } else if s.eq_ignore_ascii_case("recursive") {
+            Ok(Traversal::Recursive)
+        } else {
+            Err("invalid traversal strategy")
+        }
+    }
+}
+
+/// Disassembles `symbol`. Only the instructions that start inside of the window
+/// given by the options are disassembled, jumps are still resolved against the
+/// entire symbol.
+pub fn disasm(
+    binary: &Binary,
+    symbol: &Symbol,
+    options: &DisasmOptions,
+) -> Result<Disassembly, DisasmError> {
     let disasm_timer = std::time::Instant::now();
-    let caps = capstone_for_binary(binary)?;
-    let mut disassembly = Disassembly::new();
-    let source_loader = if load_source {
+    let (caps, syntax, window) = prepare_disasm(binary, symbol, options)?;
+    if !caps.details_enabled()
+        && (options.show_flags || options.show_encoding || options.ascii_immediates)
+    {
+        log::warn!(
+            "flags, encodings and immediates cannot be shown without Capstone instruction details"
+        );
+    }
+    let mut disassembly = Disassembly::new(syntax);
+    let source_loader = if options.load_source {
         Some(SourceLoader::new())
     } else {
         None
     };
-    disasm_symbol_lines(&caps, binary, symbol, source_loader, &mut disassembly)?;
+
+    let traversal = match options.traversal {
+        Traversal::Recursive if !anal::supports_control_flow(&caps) => {
+            log::warn!(
+                "recursive traversal is not supported for {} binaries, using linear sweep instead",
+                binary.arch()
+            );
+            Traversal::Linear
+        }
+        traversal => traversal,
+    };
+    match traversal {
+        Traversal::Linear => disasm_symbol_lines(
+            &caps,
+            binary,
+            symbol,
+            window,
+            source_loader,
+            options,
+            &mut disassembly,
+        )?,
+        Traversal::Recursive => disasm_reachable_lines(
+            &caps,
+            binary,
+            symbol,
+            window,
+            source_loader,
+            options,
+            &mut disassembly,
+        )?,
+    }
+    comment_address_refs(caps.arch(), binary, &mut disassembly);
+    disassembly.frame_size = find_frame_size(&caps, binary, symbol);
+    if options.fold_prologue {
+        // The prologue is only at the start of the symbol, not at the start of the window.
+        let at_entry = disassembly.lines.first().map(DisasmLine::address) == Some(symbol.address());
+        disassembly.folds = find_frame_folds(caps.arch(), &disassembly, at_entry);
+    }
+    // A disassembly that was cut short by `count` doesn't end with the symbol's padding.
+    let cut_short = options
+        .count
+        .is_some_and(|count| disassembly.instruction_count() >= count);
+    if options.trim_padding && !cut_short {
+        let padding = find_padding_fold(caps.arch(), &disassembly);
+        disassembly.folds.extend(padding);
+    }
     log::trace!(
         "disassembled symbol {} in {}",
-        symbol.name(),
+        symbol.display_name(),
         crate::util::DurationDisplay(disasm_timer.elapsed())
     );
     Ok(disassembly)
 }
 
+/// Creates a disassembly that displays the `len` bytes at `address` as data, e.g.
+/// for the padding between two symbols. `offset` is the offset of the bytes in the
+/// binary's data.
+pub fn disasm_data(binary: &Binary, address: u64, offset: usize, len: usize) -> Disassembly {
+    let data = binary.data();
+    let end = std::cmp::min(offset.saturating_add(len), data.len());
+    let bytes = &data[std::cmp::min(offset, end)..end];
+
+    let mut disassembly = Disassembly::new(Syntax::default());
+    for (idx, chunk) in bytes.chunks(DATA_LINE_LEN).enumerate() {
+        let line_address = address + (idx * DATA_LINE_LEN) as u64;
+        disassembly.push_line(bytes_line(line_address, chunk));
+    }
+    disassembly
+}
+
+/// Disassembles each of `symbols` in parallel. The disassemblies are returned
+/// in the same order as `symbols`.
+pub fn disasm_symbols(
+    binary: &Binary,
+    symbols: &[&Symbol],
+    options: &DisasmOptions,
+) -> Vec<Result<Disassembly, DisasmError>> {
+    use rayon::prelude::*;
+
+    // Capstone handles can't be shared between threads so `disasm` opens one
+    // for each symbol, which is cheap compared to disassembling the symbol.
+    symbols
+        .par_iter()
+        .map(|symbol| disasm(binary, symbol, options))
+        .collect()
+}
+
+/// Returns the functions that are called from the disassembly of `symbol`, in the
+/// order that they are first called. Recursive calls are ignored.
+pub fn callees<'b>(
+    binary: &'b Binary,
+    symbol: &Symbol,
+    disassembly: &Disassembly,
+) -> Vec<&'b Symbol> {
+    let mut callees: Vec<&Symbol> = Vec::new();
+    for line in disassembly.into_iter().filter(|line| line.is_call()) {
+        let target = match line.jump() {
+            Jump::External(target) if !symbol.address_range().contains(&target) => target,
+            _ => continue,
+        };
+
+        if let Some((callee, 0)) = binary.symbolicate(target) {
+            if !callees.iter().any(|&known| std::ptr::eq(known, callee)) {
+                callees.push(callee);
+            }
+        }
+    }
+    callees
+}
+
+/// Disassembles `symbol` lazily, one line at a time, instead of collecting all of
+/// the lines into a [`Disassembly`] first. The lines are read from
+/// [`DisasmStream::lines`].
+///
+/// Jumps to other symbols are symbolicated as each line is disassembled, but a
+/// line cannot know the index of a line that hasn't been disassembled yet, so jumps
+/// inside of `symbol` are left as [`Jump::External`] instead of [`Jump::Internal`].
+/// The symbol is always disassembled with a linear sweep and nothing is folded.
+pub fn disasm_streaming<'b>(
+    binary: &'b Binary,
+    symbol: &'b Symbol,
+    options: &DisasmOptions,
+) -> Result<DisasmStream<'b>, DisasmError> {
+    let (caps, syntax, window) = prepare_disasm(binary, symbol, options)?;
+    Ok(DisasmStream {
+        caps,
+        syntax,
+        binary,
+        symbol,
+        source_loader: if options.load_source {
+            Some(SourceLoader::new())
+        } else {
+            None
+        },
+        options: options.clone(),
+        window,
+    })
+}
+
+/// The Capstone instance and state for disassembling a symbol lazily. See
+/// [`disasm_streaming`].
+pub struct DisasmStream<'b> {
+    caps: Capstone,
+    syntax: Syntax,
+    binary: &'b Binary,
+    symbol: &'b Symbol,
+    source_loader: Option<SourceLoader>,
+    options: DisasmOptions,
+    window: Range<u64>,
+}
+
+impl DisasmStream<'_> {
+    /// Returns an iterator that disassembles the lines of the symbol as it is
+    /// advanced. Each call starts over from the start of the window.
+    pub fn lines(&mut self) -> impl Iterator<Item = Result<DisasmLine, DisasmError>> + '_ {
+        let caps = &self.caps;
+        let syntax = self.syntax;
+        let binary = self.binary;
+        let symbol = self.symbol;
+        let source_loader = &mut self.source_loader;
+        let options = &self.options;
+        let window = self.window.clone();
+
+        let end = symbol.clamped_range(binary.data().len()).end;
+        let start = std::cmp::min(
+            symbol.offset() + (window.start - symbol.address()) as usize,
+            end,
+        );
+        let mut insns = caps.disasm_iter(&binary.data()[start..end], window.start);
+        let mut decoded_end = window.start;
+        let mut count = 0;
+        let mut in_delay_slot = false;
+        let mut jump_tables = anal::JumpTableFinder::default();
+        let mut done = false;
+
+        std::iter::from_fn(move || {
+            if done || options.count == Some(count) {
+                return None;
+            }
+
+            if options.max_instructions == Some(count) {
+                log::warn!(
+                    "stopped disassembling {} after {} instructions",
+                    symbol.display_name(),
+                    count
+                );
+                done = true;
+                return None;
+            }
+
+            let insn = match insns.next() {
+                Some(Ok(insn)) if insn.address() < window.end => insn,
+                Some(Ok(_)) => {
+                    done = true;
+                    return None;
+                }
+                Some(Err(err)) => {
+                    done = true;
+                    return Some(Err(anyhow::Error::new(err)
+                        .context("failed to disassemble instruction")
+                        .into()));
+                }
+                // Capstone stops at the first sequence of bytes that it can't decode,
+                // so whatever is left over is displayed as data.
+                None => {
+                    done = true;
+                    if decoded_end < window.end {
+                        return Some(Ok(data_line(binary, symbol, decoded_end..window.end)));
+                    }
+                    return None;
+                }
+            };
+            decoded_end = insn.address() + insn.size() as u64;
+            count += 1;
+
+            let line = disasm_line(caps, syntax, binary, insn, source_loader.as_mut(), options)
+                .map(|mut line| {
+                    if let Some(table) = jump_tables.find(insn, caps) {
+                        add_jump_table(binary, symbol, table, options, &mut line);
+                    }
+                    if in_delay_slot {
+                        line.prepend_comment(DELAY_SLOT_COMMENT);
+                    }
+                    if options.symbolicate_jumps {
+                        symbolicate_jump(binary, symbol, &mut line, options);
+                    }
+                    line
+                })
+                .map_err(DisasmError::from);
+            in_delay_slot = anal::has_delay_slot(insn, caps);
+            if !anal::falls_through(insn, caps, binary.code_endian()) {
+                jump_tables.reset();
+            }
+            if line.is_err() {
+                done = true;
+            }
+            Some(line)
+        })
+    }
+}
+
+/// Creates a Capstone instance for disassembling `symbol` and returns it with
+/// the syntax that it prints and the window of addresses that should be disassembled.
+fn prepare_disasm(
+    binary: &Binary,
+    symbol: &Symbol,
+    options: &DisasmOptions,
+) -> anyhow::Result<(Capstone, Syntax, Range<u64>)> {
+    let (caps, syntax) = capstone_for_binary(binary, options.syntax)?;
+
+    let symbol_range = symbol.address_range();
+    let window =
+        options.start_at.unwrap_or(symbol_range.start)..options.stop_at.unwrap_or(symbol_range.end);
+    if !symbol_range.contains(&window.start) || window.end <= window.start {
+        return Err(anyhow::anyhow!(
+            "0x{:x}..0x{:x} is not a valid range inside of symbol {} (0x{:x}..0x{:x})",
+            window.start,
+            window.end,
+            symbol.display_name(),
+            symbol_range.start,
+            symbol_range.end
+        ));
+    }
+    // The symbol can extend past the end of the binary if its size is bogus.
+    let clamped = symbol.clamped_range(binary.data().len());
+    if clamped.len() != symbol.size() {
+        log::warn!(
+            "symbol {} extends past the end of the binary, only {} of its {} bytes will be disassembled",
+            symbol.display_name(),
+            clamped.len(),
+            symbol.size()
+        );
+    }
+
+    let start = if let Some(alignment) = binary.arch().instruction_alignment() {
+        aligned_start(binary, symbol, window.start, alignment)?
+    } else {
+        instruction_boundary(&caps, binary, symbol, window.start)
+    };
+    let window = start..std::cmp::min(window.end, symbol_range.end);
+    Ok((caps, syntax, window))
+}
+
+/// Returns the first address at or after `address` that an instruction can start at
+/// on an architecture with aligned instructions. Capstone only produces garbage for
+/// misaligned instructions, which usually means that the symbol's address is wrong.
+/// Thumb functions are refused, since rounding them up would decode Thumb code as ARM.
+fn aligned_start(
+    binary: &Binary,
+    symbol: &Symbol,
+    address: u64,
+    alignment: u64,
+) -> anyhow::Result<u64> {
+    if binary.arch() == binary::Arch::Arm && symbol.address() & 1 != 0 {
+        return Err(anyhow::anyhow!(
+            "symbol {} at 0x{:x} is a Thumb function, only ARM mode can be disassembled",
+            symbol.display_name(),
+            symbol.address()
+        ));
+    }
+    if symbol.address() % alignment != 0 {
+        log::warn!(
+            "symbol {} at 0x{:x} is not aligned to the {} byte instructions of {}",
+            symbol.display_name(),
+            symbol.address(),
+            alignment,
+            binary.arch()
+        );
+    }
+
+    let aligned = match address % alignment {
+        0 => address,
+        misalignment => address.saturating_add(alignment - misalignment),
+    };
+    if aligned != address {
+        log::warn!(
+            "starting at 0x{:x} instead of 0x{:x} because instructions must be aligned to {} bytes",
+            aligned,
+            address,
+            alignment
+        );
+    }
+
+    if aligned >= symbol.end_address() {
+        return Err(anyhow::anyhow!(
+            "symbol {} does not contain any aligned instructions after 0x{:x}",
+            symbol.display_name(),
+            address
+        ));
+    }
+    Ok(aligned)
+}
+
+/// Finds the size of the stack frame that the prologue of `symbol` allocates by adding
+/// up the stack allocations before its first branch or call. This is the space for
+/// locals and spills and doesn't include registers that are pushed on x86.
+fn find_frame_size(caps: &Capstone, binary: &Binary, symbol: &Symbol) -> Option<u64> {
+    let code = &binary.data()[symbol.clamped_range(binary.data().len())];
+    let insns = caps
+        .disasm_count(code, symbol.address(), FRAME_SCAN_LEN)
+        .ok()?;
+    let endian = binary.code_endian();
+    let size: u64 = insns
+        .iter()
+        .take_while(|insn| {
+            anal::falls_through(insn, caps, endian)
+                && !anal::identify_jump_target(insn, caps, endian).is_external()
+                && !anal::is_call(insn, caps, endian)
+        })
+        .filter_map(|insn| anal::stack_allocation(insn, caps))
+        .sum();
+
+    if size > 0 {
+        Some(size)
+    } else {
+        None
+    }
+}
+
+/// Returns the address of the first instruction in `symbol` that starts at or after
+/// `address`, so that disassembly doesn't begin in the middle of an instruction.
+fn instruction_boundary(caps: &Capstone, binary: &Binary, symbol: &Symbol, address: u64) -> u64 {
+    let code = &binary.data()[symbol.clamped_range(binary.data().len())];
+    let mut boundary = symbol.address();
+
+    while boundary < address {
+        let offset = (boundary - symbol.address()) as usize;
+        if let Some(len) = code
+            .get(offset..)
+            .and_then(|code| caps.insn_length(code, boundary))
+        {
+            boundary += len as u64;
+        } else {
+            log::warn!(
+                "failed to find an instruction boundary before 0x{:x}, starting there instead",
+                address
+            );
+            return address;
+        }
+    }
+
+    boundary
+}
+
 fn disasm_symbol_lines(
     caps: &Capstone,
     binary: &Binary,
     symbol: &Symbol,
+    window: Range<u64>,
     mut source_loader: Option<SourceLoader>,
+    options: &DisasmOptions,
     disassembly: &mut Disassembly,
 ) -> anyhow::Result<()> {
-    for insn in caps.disasm_iter(
-        &binary.data()[symbol.offset()..symbol.end()],
-        symbol.address(),
-    ) {
+    let end = symbol.clamped_range(binary.data().len()).end;
+    let start = std::cmp::min(
+        symbol.offset() + (window.start - symbol.address()) as usize,
+        end,
+    );
+    let mut decoded_end = window.start;
+    let mut in_delay_slot = false;
+    let mut jump_tables = anal::JumpTableFinder::default();
+    for insn in caps.disasm_iter(&binary.data()[start..end], window.start) {
         let insn = insn.context("failed to disassemble instruction")?;
-        let jump = anal::identify_jump_target(insn, caps);
-
-        let mut source_lines = Vec::new();
-        if let Some(ref mut source_loader) = source_loader {
-            source_loader
-                .load_lines(
-                    binary.addr2line(insn.address())?.iter_mut().flatten(),
-                    &mut source_lines,
-                )
-                .context("error while loading sources for line")?;
-        }
-        let source_lines = if source_lines.is_empty() {
-            None
-        } else {
-            Some(source_lines.into_boxed_slice())
-        };
+        if insn.address() >= window.end {
+            decoded_end = window.end;
+            break;
+        }
 
-        let line = DisasmLine {
-            address: insn.address(),
-            mnemonic: insn.mnemonic().into(),
-            operands: insn.operands().into(),
-            comments: None,
-            bytes: insn.bytes().to_vec().into_boxed_slice(),
-            source_lines,
-            jump,
-            is_symbolicated_jump: false,
-        };
+        if options.count == Some(disassembly.instruction_count()) {
+            decoded_end = window.end;
+            break;
+        }
+
+        if options.max_instructions == Some(disassembly.instruction_count()) {
+            log::warn!(
+                "stopped disassembling {} after {} instructions",
+                symbol.display_name(),
+                disassembly.instruction_count()
+            );
+            decoded_end = window.end;
+            break;
+        }
+
+        decoded_end = insn.address() + insn.size() as u64;
+        let mut line = disasm_line(
+            caps,
+            disassembly.syntax,
+            binary,
+            insn,
+            source_loader.as_mut(),
+            options,
+        )?;
+        if let Some(table) = jump_tables.find(insn, caps) {
+            add_jump_table(binary, symbol, table, options, &mut line);
+        }
+        if in_delay_slot {
+            line.prepend_comment(DELAY_SLOT_COMMENT);
+        }
+        in_delay_slot = anal::has_delay_slot(insn, caps);
+        if !anal::falls_through(insn, caps, binary.code_endian()) {
+            jump_tables.reset();
+        }
+        disassembly.push_line(line);
+    }
+
+    // Capstone stops at the first sequence of bytes that it can't decode,
+    // so whatever is left over is displayed as data.
+    if decoded_end < window.end {
+        disassembly.push_line(data_line(binary, symbol, decoded_end..window.end));
+    }
+
+    if options.symbolicate_jumps {
+        symbolicate_and_internalize_jumps(binary, symbol, options, disassembly);
+    }
+    Ok(())
+}
+
+/// Disassembles the instructions in `window` that are reachable from its start by
+/// following jumps inside of the window and falling through to the next instruction.
+/// Landing pads for indirect jumps and calls in the bytes that were not reached are
+/// also disassembled from. The bytes that were still not reached are displayed as data.
+fn disasm_reachable_lines(
+    caps: &Capstone,
+    binary: &Binary,
+    symbol: &Symbol,
+    window: Range<u64>,
+    mut source_loader: Option<SourceLoader>,
+    options: &DisasmOptions,
+    disassembly: &mut Disassembly,
+) -> anyhow::Result<()> {
+    // Instructions can't run past the end of the window.
+    let end = std::cmp::min(
+        symbol.offset() + (window.end - symbol.address()) as usize,
+        symbol.clamped_range(binary.data().len()).end,
+    );
+    let mut decoded: BTreeMap<u64, DisasmLine> = BTreeMap::new();
+    let mut worklist = vec![window.start];
+    let mut seen_landing_pads = BTreeSet::new();
+
+    'traversal: loop {
+        while let Some(mut address) = worklist.pop() {
+            let mut jump_tables = anal::JumpTableFinder::default();
+            while window.contains(&address) && !decoded.contains_key(&address) {
+                // A jump into the middle of an instruction that was already decoded.
+                if let Some((_, prev)) = decoded.range(..address).next_back() {
+                    if prev.contains_addr(address) {
+                        log::debug!(
+                            "0x{:x} overlaps the instruction at 0x{:x}",
+                            address,
+                            prev.address
+                        );
+                        break;
+                    }
+                }
+
+                if options.max_instructions == Some(decoded.len()) {
+                    log::warn!(
+                        "stopped disassembling {} after {} instructions",
+                        symbol.display_name(),
+                        decoded.len()
+                    );
+                    break 'traversal;
+                }
+
+                let start =
+                    std::cmp::min(symbol.offset() + (address - symbol.address()) as usize, end);
+                let insns = caps
+                    .disasm_count(&binary.data()[start..end], address, 1)
+                    .context("failed to disassemble instruction")?;
+                let insn = if let Some(insn) = insns.iter().next() {
+                    insn
+                } else {
+                    break;
+                };
+                let next = address + insn.size() as u64;
+
+                if let Some((&following, _)) = decoded.range(address..next).next() {
+                    log::debug!(
+                        "0x{:x} overlaps the instruction at 0x{:x}",
+                        address,
+                        following
+                    );
+                    break;
+                }
+
+                let mut line = disasm_line(
+                    caps,
+                    disassembly.syntax,
+                    binary,
+                    insn,
+                    source_loader.as_mut(),
+                    options,
+                )?;
+                if let Some(table) = jump_tables.find(insn, caps) {
+                    add_jump_table(binary, symbol, table, options, &mut line);
+                }
+                if let Jump::External(target) = line.jump {
+                    if window.contains(&target) {
+                        worklist.push(target);
+                    }
+                }
+                // The cases of a switch are only reached through its jump table.
+                worklist.extend(
+                    line.jump_table
+                        .iter()
+                        .copied()
+                        .filter(|target| window.contains(target)),
+                );
+                decoded.insert(address, line);
+
+                if !anal::falls_through(insn, caps, binary.code_endian()) {
+                    break;
+                }
+                address = next;
+            }
+        }
+
+        // Code that is only reached by indirect jumps and calls starts with a landing pad.
+        worklist = unreached_landing_pads(caps, binary, symbol, &window, &decoded);
+        worklist.retain(|&pad| seen_landing_pads.insert(pad));
+        if worklist.is_empty() {
+            break;
+        }
+    }
+
+    // `count` keeps the first instructions by address instead of the first ones that
+    // were reached, so the whole window is traversed first. The bytes after the last
+    // instruction that is kept aren't data since some of them were reached.
+    let mut cut_short = false;
+    if let Some(&first_dropped) = options.count.and_then(|count| decoded.keys().nth(count)) {
+        decoded.split_off(&first_dropped);
+        cut_short = true;
+    }
+
+    let mut gap_start = window.start;
+    for (address, line) in decoded {
+        if gap_start < address {
+            disassembly.push_line(data_line(binary, symbol, gap_start..address));
+        }
+        gap_start = address + line.bytes.len() as u64;
         disassembly.push_line(line);
     }
-    symbolicate_and_internalize_jumps(binary, symbol, disassembly);
+    if gap_start < window.end && !cut_short {
+        disassembly.push_line(data_line(binary, symbol, gap_start..window.end));
+    }
+
+    if options.symbolicate_jumps {
+        symbolicate_and_internalize_jumps(binary, symbol, options, disassembly);
+    }
     Ok(())
 }
 
+/// Finds the landing pads in the bytes of `window` that are not part of a decoded
+/// instruction, in reverse order so that they are popped from a worklist in order.
+fn unreached_landing_pads(
+    caps: &Capstone,
+    binary: &Binary,
+    symbol: &Symbol,
+    window: &Range<u64>,
+    decoded: &BTreeMap<u64, DisasmLine>,
+) -> Vec<u64> {
+    let end = symbol.clamped_range(binary.data().len()).end;
+    let offset_of =
+        |address: u64| std::cmp::min(symbol.offset() + (address - symbol.address()) as usize, end);
+
+    let mut pads = Vec::new();
+    let mut gap_start = window.start;
+    let gap_ends = decoded
+        .values()
+        .map(|line| (line.address, line.address + line.bytes.len() as u64))
+        .chain(std::iter::once((window.end, window.end)));
+    for (gap_end, next_start) in gap_ends {
+        if gap_start < gap_end {
+            let code = &binary.data()[offset_of(gap_start)..offset_of(gap_end)];
+            pads.extend(anal::find_landing_pads(caps.arch(), code, gap_start));
+        }
+        gap_start = std::cmp::max(gap_start, next_start);
+    }
+    pads.reverse();
+    pads
+}
+
+/// Finds the standard prologue at the start of a disassembly if `at_entry` is set,
+/// and the standard epilogue before each return.
+fn find_frame_folds(arch: capstone::Arch, disassembly: &Disassembly, at_entry: bool) -> Vec<Fold> {
+    let lines = &disassembly.lines;
+    let syntax = disassembly.syntax;
+    let mut folds = Vec::new();
+
+    if at_entry {
+        let len = lines
+            .iter()
+            .take_while(|line| anal::is_prologue_line(arch, syntax, line))
+            .count();
+        if len >= MIN_FOLD_LEN {
+            folds.push(Fold {
+                lines: 0..len,
+                kind: FoldKind::Prologue,
+            });
+        }
+    }
+
+    // An epilogue never overlaps the prologue or reaches back past the previous return.
+    let mut search_start = folds.last().map_or(0, |fold| fold.lines.end);
+    for (idx, line) in lines.iter().enumerate().skip(search_start) {
+        if !anal::is_return(arch, line) {
+            continue;
+        }
+
+        let start = lines[search_start..idx]
+            .iter()
+            .rposition(|line| !anal::is_epilogue_line(arch, syntax, line))
+            .map_or(search_start, |pos| search_start + pos + 1);
+        if idx - start >= MIN_FOLD_LEN {
+            folds.push(Fold {
+                lines: start..idx,
+                kind: FoldKind::Epilogue,
+            });
+        }
+        search_start = idx + 1;
+    }
+
+    folds
+}
+
+/// Finds the run of padding lines at the end of a disassembly. A disassembly
+/// that is only padding isn't folded.
+fn find_padding_fold(arch: capstone::Arch, disassembly: &Disassembly) -> Option<Fold> {
+    let lines = &disassembly.lines;
+    let start = lines
+        .iter()
+        .rposition(|line| !anal::is_padding(arch, line))
+        .map(|pos| pos + 1)?;
+
+    // The padding never reaches into an epilogue because returns aren't padding.
+    if start < lines.len() {
+        Some(Fold {
+            lines: start..lines.len(),
+            kind: FoldKind::Padding,
+        })
+    } else {
+        None
+    }
+}
+
+/// Comments the lines that compute an address from several instructions (e.g. ARM64's
+/// `adrp` and `add`) with the symbol at the address, or the address if there is no symbol.
+fn comment_address_refs(arch: capstone::Arch, binary: &Binary, disassembly: &mut Disassembly) {
+    for (idx, address) in anal::address_refs(arch, &disassembly.lines) {
+        let comment = address_comment(binary, address);
+        disassembly.lines[idx].prepend_comment(&comment);
+    }
+}
+
+/// Describes `address` as a symbol and an offset into it, or as a number if no
+/// symbol contains it.
+fn address_comment(binary: &Binary, address: u64) -> String {
+    match binary.symbolicate(address) {
+        Some((symbol, 0)) => symbol.display_name().to_string(),
+        Some((symbol, offset)) => format!("{}+0x{:x}", symbol.display_name(), offset),
+        None => format!("0x{:x}", address),
+    }
+}
+
+/// Reads the targets of a switch from its jump table, which has to be in read-only
+/// data. The table doesn't record its length, so entries are read until one doesn't
+/// point into `symbol`, because the cases of a switch are in the same function.
+fn jump_table_targets(binary: &Binary, symbol: &Symbol, table: anal::JumpTable) -> Vec<u64> {
+    let (linked, rebased) = if table.relative {
+        (table.address.wrapping_sub(binary.base()), table.address)
+    } else {
+        (table.address, table.address.wrapping_add(binary.base()))
+    };
+    if !binary.is_read_only(rebased) {
+        return Vec::new();
+    }
+    let bytes = match binary.linked_bytes(linked) {
+        Some(bytes) => bytes,
+        None => return Vec::new(),
+    };
+    let range = symbol.address()..symbol.end_address();
+
+    let little_endian = binary.endian() != binary::Endian::Big;
+    binary::read_pointers(bytes, table.entry_size, little_endian)
+        .take(MAX_JUMP_TABLE_LEN)
+        .map(|entry| {
+            if table.relative {
+                rebased.wrapping_add(entry as u32 as i32 as u64)
+            } else {
+                entry.wrapping_add(binary.base())
+            }
+        })
+        .take_while(|target| range.contains(target))
+        .collect()
+}
+
+/// Lists the cases of a switch, each target only once, in the order of the table.
+fn jump_table_comment(symbol: &Symbol, targets: &[u64], options: &DisasmOptions) -> String {
+    let mut seen = BTreeSet::new();
+    let cases = targets
+        .iter()
+        .filter(|&&target| seen.insert(target))
+        // The targets are always inside of the symbol.
+        .map(|&target| internal_target(symbol, target, options))
+        .collect::<Vec<String>>();
+    format!("switch -> {}", cases.join(", "))
+}
+
+/// Adds the cases of the switch that jumps through `table` to its line.
+fn add_jump_table(
+    binary: &Binary,
+    symbol: &Symbol,
+    table: anal::JumpTable,
+    options: &DisasmOptions,
+    line: &mut DisasmLine,
+) {
+    let targets = jump_table_targets(binary, symbol, table);
+    if !targets.is_empty() {
+        line.prepend_comment(&jump_table_comment(symbol, &targets, options));
+        line.jump_table = targets.into_boxed_slice();
+    }
+}
+
+/// Creates the line for a single instruction. Jumps and jump tables are not
+/// symbolicated yet.
+fn disasm_line(
+    caps: &Capstone,
+    syntax: Syntax,
+    binary: &Binary,
+    insn: &capstone::Insn,
+    source_loader: Option<&mut SourceLoader>,
+    options: &DisasmOptions,
+) -> anyhow::Result<DisasmLine> {
+    let jump = anal::identify_jump_target(insn, caps, binary.code_endian());
+    let is_call = anal::is_call(insn, caps, binary.code_endian());
+    let is_landing_pad = anal::is_landing_pad(insn, caps);
+    let groups = caps
+        .try_details(insn)
+        .map(|details| Box::from(details.groups()))
+        .unwrap_or_default();
+
+    let mut source_lines = Vec::new();
+    if let Some(source_loader) = source_loader {
+        let locations = binary.addr2line(insn.address())?.into_iter().flatten();
+        source_loader
+            .load_lines(options.source_mappings.select(locations), &mut source_lines)
+            .context("error while loading sources for line")?;
+    }
+    let source_lines = if source_lines.is_empty() {
+        None
+    } else {
+        Some(source_lines.into_boxed_slice())
+    };
+
+    let mut comments = Vec::new();
+    if is_landing_pad {
+        comments.push(LANDING_PAD_COMMENT.to_string());
+    }
+    if options.ascii_immediates {
+        comments.extend(anal::immediate_chars_comment(insn, caps));
+    }
+    if options.show_flags {
+        comments.extend(anal::flags_comment(insn, caps));
+    }
+    let comments = if comments.is_empty() {
+        None
+    } else {
+        Some(comments.join(", "))
+    };
+
+    let encoding = if options.show_encoding {
+        anal::encoding_comment(insn, caps)
+    } else {
+        None
+    };
+
+    let operand_spans = if options.rich_operands {
+        let kinds = anal::operand_kinds(insn, caps);
+        operand_spans(insn.operands(), kinds.as_deref(), syntax)
+    } else {
+        Vec::new()
+    };
+
+    Ok(DisasmLine {
+        address: insn.address(),
+        mnemonic: insn.mnemonic().into(),
+        operands: insn.operands().into(),
+        operand_spans: operand_spans.into_boxed_slice(),
+        comments: comments.map(String::into_boxed_str),
+        encoding: encoding.map(String::into_boxed_str),
+        bytes: insn.bytes().to_vec().into_boxed_slice(),
+        source_lines,
+        jump,
+        is_call,
+        is_landing_pad,
+        groups,
+        jump_table: Box::new([]),
+        is_symbolicated_jump: false,
+    })
+}
+
+/// Splits an operand string into the byte ranges of its operands and finds their kinds.
+///
+/// The operand string doesn't always match Capstone's list of operands (e.g. implicit
+/// operands aren't printed), so the `kinds` from the instruction details are only
+/// used if there is one for each printed operand. Otherwise the kind of each operand
+/// is guessed from how it looks. The `kinds` are in Intel order, so they are
+/// reversed for AT&T syntax, which prints the operands the other way around.
+fn operand_spans(
+    operands: &str,
+    kinds: Option<&[OperandKind]>,
+    syntax: Syntax,
+) -> Vec<(Range<usize>, OperandKind)> {
+    let mut ranges = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (idx, ch) in operands.char_indices() {
+        match ch {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                ranges.push(start..idx);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    ranges.push(start..operands.len());
+
+    // Leading and trailing whitespace isn't part of an operand.
+    let ranges = ranges
+        .into_iter()
+        .map(|range| {
+            let text = &operands[range.clone()];
+            let leading = text.len() - text.trim_start().len();
+            let trailing = text.len() - text.trim_end().len();
+            range.start + leading..range.end - trailing
+        })
+        .filter(|range| !range.is_empty())
+        .collect::<Vec<_>>();
+
+    match kinds {
+        Some(kinds) if kinds.len() == ranges.len() && syntax == Syntax::Att => ranges
+            .into_iter()
+            .zip(kinds.iter().rev().copied())
+            .collect(),
+        Some(kinds) if kinds.len() == ranges.len() => {
+            ranges.into_iter().zip(kinds.iter().copied()).collect()
+        }
+        _ => ranges
+            .into_iter()
+            .map(|range| {
+                let kind = guess_operand_kind(&operands[range.clone()]);
+                (range, kind)
+            })
+            .collect(),
+    }
+}
+
+/// Guesses the kind of an operand from its text.
+fn guess_operand_kind(operand: &str) -> OperandKind {
+    if operand.contains(['[', '(']) {
+        OperandKind::Memory
+    } else if operand.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '$' | '#' | '-')) {
+        OperandKind::Immediate
+    } else {
+        OperandKind::Register
+    }
+}
+
+/// Creates a line for the bytes of `symbol` in `range` that are not instructions.
+fn data_line(binary: &Binary, symbol: &Symbol, range: Range<u64>) -> DisasmLine {
+    let end = symbol.clamped_range(binary.data().len()).end;
+    let data_start = symbol.offset() + (range.start - symbol.address()) as usize;
+    let data_end = symbol.offset() + (range.end - symbol.address()) as usize;
+    let bytes = &binary.data()[std::cmp::min(data_start, end)..std::cmp::min(data_end, end)];
+    bytes_line(range.start, bytes)
+}
+
+/// Creates a line for `bytes` at `address` that are not instructions.
+fn bytes_line(address: u64, bytes: &[u8]) -> DisasmLine {
+    DisasmLine {
+        address,
+        mnemonic: DATA_MNEMONIC.into(),
+        operands: byte_directive_operands(bytes).into(),
+        operand_spans: Box::new([]),
+        comments: None,
+        encoding: None,
+        bytes: bytes.to_vec().into_boxed_slice(),
+        source_lines: None,
+        jump: Jump::None,
+        is_symbolicated_jump: false,
+        is_call: false,
+        is_landing_pad: false,
+        groups: Box::new([]),
+        jump_table: Box::new([]),
+    }
+}
+
 fn symbolicate_and_internalize_jumps(
     binary: &Binary,
     symbol: &Symbol,
+    options: &DisasmOptions,
     disassembly: &mut Disassembly,
 ) {
     for idx in 0..disassembly.lines.len() {
-        let jump_addr = if let Jump::External(addr) = disassembly.lines[idx].jump {
-            addr
-        } else {
-            continue;
+        let jump_addr = match symbolicate_jump(binary, symbol, &mut disassembly.lines[idx], options)
+        {
+            Some(addr) if symbol.address_range().contains(&addr) => addr,
+            _ => continue,
         };
 
-        // This is an internal jump, so we can skip the more
-        // expensive symbolication step.
-        if symbol.address_range().contains(&jump_addr) {
-            disassembly.lines[idx].operands =
-                format!("{}+0x{:x}", symbol.name(), jump_addr - symbol.address()).into();
-            disassembly.lines[idx].comments = Some(format!("0x{:x}", jump_addr).into());
-            disassembly.lines[idx].is_symbolicated_jump = true;
+        if let Some(index) = disassembly.find_line_by_address(jump_addr) {
+            disassembly.lines[idx].jump = Jump::Internal(index);
+        }
+    }
+}
+
+/// Writes an address inside of `symbol` the way that both jumps and the cases of a
+/// switch show it: as an offset with `relative_targets`, or as the symbol and offset.
+fn internal_target(symbol: &Symbol, address: u64, options: &DisasmOptions) -> String {
+    let offset = address - symbol.address();
+    if options.relative_targets {
+        format!("+0x{:x}", offset)
+    } else {
+        format!("{}+0x{:x}", symbol.display_name(), offset)
+    }
+}
 
-            if let Some(index) = disassembly
-                .lines
-                .iter()
-                .position(|l| l.contains_addr(jump_addr))
-            {
-                disassembly.lines[idx].jump = Jump::Internal(index);
-            }
-        } else if let Some((symbol, offset)) = binary.symbolicate(jump_addr) {
-            if offset == 0 {
-                disassembly.lines[idx].operands = symbol.name().into();
-            } else {
-                disassembly.lines[idx].operands =
-                    format!("{}+0x{:x}", symbol.name(), offset).into();
-            }
-            disassembly.lines[idx].comments = Some(format!("0x{:x}", jump_addr).into());
-            disassembly.lines[idx].is_symbolicated_jump = true;
+/// Replaces the operands of a line that jumps to an external address with the
+/// symbol and offset of the target, or adds them as a comment if `raw_targets`
+/// is set. Returns the address of the target if the line was symbolicated.
+fn symbolicate_jump(
+    binary: &Binary,
+    symbol: &Symbol,
+    line: &mut DisasmLine,
+    options: &DisasmOptions,
+) -> Option<u64> {
+    let jump_addr = if let Jump::External(addr) = line.jump {
+        addr
+    } else {
+        return None;
+    };
+
+    // This is an internal jump, so we can skip the more
+    // expensive symbolication step.
+    let target = if symbol.address_range().contains(&jump_addr) {
+        internal_target(symbol, jump_addr, options)
+    } else if let Some((symbol, offset)) = binary.symbolicate(jump_addr) {
+        if offset == 0 {
+            symbol.display_name().into()
+        } else {
+            format!("{}+0x{:x}", symbol.display_name(), offset)
         }
+    } else {
+        return None;
+    };
+
+    if options.raw_targets {
+        line.prepend_comment(&format!("-> {}", target));
+    } else {
+        line.operands = target.into();
+        line.operand_spans = Box::new([]);
+        if !options.relative_targets {
+            line.prepend_comment(&options.address_format.comment(jump_addr));
+        }
+    }
+    line.is_symbolicated_jump = true;
+    Some(jump_addr)
+}
+
+/// Finds the Capstone instruction group named `name` for the architecture of
+/// `binary`, ignoring case. Dashes in `name` match the underscores of names like
+/// `branch_relative`.
+pub fn find_group(binary: &Binary, name: &str) -> anyhow::Result<InsnGroup> {
+    let (caps, _) = capstone_for_binary(binary, None)?;
+    let matches = |group: &str| {
+        group.len() == name.len()
+            && group
+                .bytes()
+                .zip(name.bytes())
+                .all(|(g, n)| g.eq_ignore_ascii_case(&n) || (g == b'_' && n == b'-'))
+    };
+
+    if let Some(group) = caps.groups().find(|&group| matches(caps.group_name(group))) {
+        return Ok(group);
+    }
+    let groups = caps
+        .groups()
+        .map(|group| caps.group_name(group).replace('_', "-"))
+        .collect();
+    Err(DisasmError::UnknownGroup {
+        name: name.to_string(),
+        groups,
+    }
+    .into())
+}
+
+/// Returns the syntax that the instructions of `binary` are printed with when
+/// `syntax` is requested, which for x86 falls back to another syntax if this build
+/// of Capstone can't print it. The fallback is logged here, so checking this once
+/// and disassembling with the result keeps [`disasm`] from logging it for every symbol.
+pub fn select_syntax(
+    binary: &Binary,
+    syntax: Option<Syntax>,
+) -> Result<Option<Syntax>, DisasmError> {
+    if syntax.is_none() {
+        return Ok(None);
     }
+    let (_, selected) = capstone_for_binary(binary, syntax)?;
+    Ok(Some(selected))
 }
 
-/// Creates a Capstone instance for the binary.
-fn capstone_for_binary(binary: &Binary) -> anyhow::Result<Capstone> {
+/// Creates a Capstone instance for the binary that prints `syntax`, and returns it
+/// with the syntax that it prints. x86 falls back to a syntax that this build of
+/// Capstone supports (see [`Capstone::open_x86_best_effort`]), other architectures
+/// fail if they don't support `syntax`.
+fn capstone_for_binary(
+    binary: &Binary,
+    syntax: Option<Syntax>,
+) -> anyhow::Result<(Capstone, Syntax)> {
     use binary::Arch as BinArch;
     use capstone::{Arch as CapArch, Mode};
 
     let capstone_arch = match binary.arch() {
-        BinArch::Unknown => {
-            return Err(anyhow::anyhow!(
-                "unknown or unsupported binary architecture"
-            ))
-        }
+        BinArch::Unknown => return Err(DisasmError::UnsupportedArch(binary.arch()).into()),
         BinArch::X86 => CapArch::X86,
         BinArch::X86_64 => CapArch::X86,
         BinArch::Arm => CapArch::Arm,
         BinArch::AArch64 => CapArch::Arm64,
+        BinArch::PowerPc => CapArch::PowerPc,
+        BinArch::PowerPc64 => CapArch::PowerPc,
+        BinArch::S390x => CapArch::SystemZ,
+        BinArch::Sparc | BinArch::Sparc64 => CapArch::Sparc,
+        BinArch::Mips | BinArch::Mips64 => CapArch::Mips,
+        BinArch::Mos65xx => CapArch::Mos65xx,
+
+        // FIXME wire this up to `CapArch::RiscV` once the bundled Capstone is upgraded.
+        BinArch::RiscV => return Err(DisasmError::UnsupportedArch(binary.arch()).into()),
     };
 
     let mut mode = Mode::empty();
 
-    match binary.endian() {
+    // ARM BE8 binaries have big endian data but little endian instructions.
+    match binary.code_endian() {
+        // s390x is always big endian, and Capstone only supports big endian SPARC.
+        _ if matches!(
+            binary.arch(),
+            BinArch::S390x | BinArch::Sparc | BinArch::Sparc64
+        ) =>
+        {
+            mode |= Mode::BigEndian
+        }
         binary::Endian::Little => mode |= Mode::LittleEndian,
         binary::Endian::Big => mode |= Mode::BigEndian,
         #[cfg(target_endian = "little")]
@@ -146,24 +1268,185 @@ fn capstone_for_binary(binary: &Binary) -> anyhow::Result<Capstone> {
         binary::Endian::Unknown => mode |= Mode::BigEndian,
     }
 
-    if binary.arch() == BinArch::X86_64 {
-        mode |= Mode::Bits64;
+    match binary.arch() {
+        BinArch::X86_64 | BinArch::PowerPc64 => mode |= Mode::Bits64,
+        BinArch::PowerPc => mode |= Mode::Bits32,
+        BinArch::Sparc64 => mode |= Mode::V9,
+        BinArch::Mips => mode |= Mode::Mips32,
+        BinArch::Mips64 => mode |= Mode::Mips64,
+        _ => {}
     }
 
-    let mut caps = Capstone::open(capstone_arch, mode).context("failed to initialize Capstone")?;
-    caps.set_details_enabled(true)
-        .context("failed to enable Capstone detail mode")?;
+    let (mut caps, syntax) = match syntax {
+        Some(preferred) if capstone_arch == CapArch::X86 => {
+            let (caps, selected) =
+                Capstone::open_x86_best_effort(mode, preferred).map_err(DisasmError::Capstone)?;
+            if selected != preferred {
+                log::warn!(
+                    "{:?} syntax is not available in this build of Capstone, using {:?} syntax instead",
+                    preferred,
+                    selected
+                );
+            }
+            (caps, selected)
+        }
+        Some(syntax) => {
+            let mut caps = Capstone::open(capstone_arch, mode).map_err(DisasmError::Capstone)?;
+            if !caps.supports_syntax(syntax) {
+                return Err(anyhow::anyhow!(
+                    "{:?} syntax is not supported for {} binaries",
+                    syntax,
+                    binary.arch()
+                ));
+            }
+            caps.set_syntax(syntax)
+                .context("failed to set Capstone syntax")?;
+            (caps, syntax)
+        }
+        None => (
+            Capstone::open(capstone_arch, mode).map_err(DisasmError::Capstone)?,
+            Syntax::Default,
+        ),
+    };
+    // A diet build of Capstone has no instruction details, so the analysis
+    // that depends on them is skipped instead.
+    if capstone::supports(capstone::SupportQuery::Diet) {
+        log::debug!("Capstone was built in diet mode, instruction details are not available");
+    } else {
+        caps.set_details_enabled(true)
+            .context("failed to enable Capstone detail mode")?;
+    }
 
-    Ok(caps)
+    Ok((caps, syntax))
 }
 
 pub struct Disassembly {
     lines: Vec<DisasmLine>,
+
+    /// The syntax that the instructions were printed with.
+    syntax: Syntax,
+
+    /// Runs of lines that can be folded into a single line when printed, sorted
+    /// by their first line.
+    folds: Vec<Fold>,
+
+    /// The first line of each symbol that was appended with [`Disassembly::append_symbol`],
+    /// sorted by their first line.
+    boundaries: Vec<SymbolBoundary>,
+
+    /// The size of the stack frame that the prologue of the symbol allocates, if it
+    /// could be found.
+    frame_size: Option<u64>,
 }
 
 impl Disassembly {
-    fn new() -> Disassembly {
-        Disassembly { lines: Vec::new() }
+    /// Creates an empty disassembly, which the disassembly of several symbols can be
+    /// appended to with [`Disassembly::append_symbol`].
+    pub fn new(syntax: Syntax) -> Disassembly {
+        Disassembly {
+            lines: Vec::new(),
+            syntax,
+            folds: Vec::new(),
+            boundaries: Vec::new(),
+            frame_size: None,
+        }
+    }
+
+    /// Appends the lines of `other`, the disassembly of `symbol`, and records where
+    /// they start so that a header can be printed before them. Internal jumps and
+    /// folds keep pointing at the lines of `other`. Jumps between the symbols stay
+    /// external jumps until [`Disassembly::link_symbols`] is called.
+    ///
+    /// Returns the boundary of `symbol`, which the rest of its header can be added to.
+    pub fn append_symbol(&mut self, symbol: &Symbol, other: Disassembly) -> &mut SymbolBoundary {
+        let boundary = self.boundaries.len();
+        self.append(
+            SymbolBoundary {
+                name: Some(symbol.display_name().into()),
+                address: symbol.address(),
+                line: self.lines.len(),
+                decl: symbol
+                    .decl_location()
+                    .map(|(path, line)| (path.into(), line)),
+                caller: None,
+                aliases: Box::new([]),
+                frame_size: other.frame_size,
+            },
+            other,
+        );
+        &mut self.boundaries[boundary]
+    }
+
+    /// Appends `other`, the disassembly of bytes between two symbols that don't
+    /// belong to either of them.
+    pub fn append_data(&mut self, other: Disassembly) {
+        self.append(
+            SymbolBoundary {
+                name: None,
+                address: other.lines.first().map_or(0, |line| line.address),
+                line: self.lines.len(),
+                decl: None,
+                caller: None,
+                aliases: Box::new([]),
+                frame_size: None,
+            },
+            other,
+        );
+    }
+
+    fn append(&mut self, boundary: SymbolBoundary, other: Disassembly) {
+        let start = self.lines.len();
+        self.boundaries.push(boundary);
+        self.boundaries
+            .extend(other.boundaries.into_iter().map(|boundary| SymbolBoundary {
+                line: boundary.line + start,
+                ..boundary
+            }));
+        self.folds.extend(other.folds.into_iter().map(|fold| Fold {
+            lines: fold.lines.start + start..fold.lines.end + start,
+            ..fold
+        }));
+        self.lines.extend(other.lines.into_iter().map(|mut line| {
+            if let Jump::Internal(index) = line.jump {
+                line.jump = Jump::Internal(index + start);
+            }
+            line
+        }));
+    }
+
+    /// Turns the jumps from one of the appended symbols to an instruction of another
+    /// into internal jumps to the line of that instruction. This is done once after
+    /// the last symbol was appended, because it sorts every line by its address.
+    pub fn link_symbols(&mut self) {
+        let mut instructions = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !line.is_data())
+            .map(|(idx, line)| (line.address, idx))
+            .collect::<Vec<(u64, usize)>>();
+        instructions.sort_unstable();
+
+        for line in self.lines.iter_mut() {
+            if let Jump::External(target) = line.jump {
+                if let Ok(found) =
+                    instructions.binary_search_by_key(&target, |&(address, _)| address)
+                {
+                    line.jump = Jump::Internal(instructions[found].1);
+                }
+            }
+        }
+    }
+
+    /// The syntax that the instructions were printed with.
+    pub fn syntax(&self) -> Syntax {
+        self.syntax
+    }
+
+    /// The first line of each symbol that was appended with [`Disassembly::append_symbol`]
+    /// and of the data appended with [`Disassembly::append_data`].
+    pub fn boundaries(&self) -> &[SymbolBoundary] {
+        &self.boundaries
     }
 
     fn push_line(&mut self, line: DisasmLine) {
@@ -173,16 +1456,297 @@ impl Disassembly {
     pub fn lines(&self) -> &[DisasmLine] {
         &*self.lines
     }
+
+    /// The number of lines, including the lines of data.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The prologue and epilogues found with [`DisasmOptions::fold_prologue`].
+    pub fn folds(&self) -> &[Fold] {
+        &self.folds
+    }
+
+    /// The number of bytes in all of the lines, including lines of data.
+    pub fn byte_size(&self) -> u64 {
+        self.lines.iter().map(|line| line.bytes.len() as u64).sum()
+    }
+
+    /// The number of lines that are instructions and not data.
+    pub fn instruction_count(&self) -> usize {
+        self.lines.iter().filter(|line| !line.is_data()).count()
+    }
+
+    /// The size of the stack frame that the prologue of the symbol allocates, if it
+    /// could be found.
+    pub fn frame_size(&self) -> Option<u64> {
+        self.frame_size
+    }
+
+    /// The number of instructions that are landing pads for indirect jumps and calls.
+    pub fn landing_pad_count(&self) -> usize {
+        self.lines.iter().filter(|line| line.is_landing_pad).count()
+    }
+
+    /// The addresses from the start of the first line to the end of the last line.
+    /// This is empty if there are no lines.
+    pub fn address_range(&self) -> Range<u64> {
+        match (self.lines.first(), self.lines.last()) {
+            (Some(first), Some(last)) => first.address..last.address + last.bytes.len() as u64,
+            _ => 0..0,
+        }
+    }
+
+    /// Returns the index of the line containing `addr`. Lines are disassembled
+    /// sequentially, so the lines of each symbol are sorted by address and this is
+    /// a binary search for each symbol.
+    pub fn find_line_by_address(&self, addr: u64) -> Option<usize> {
+        let starts = std::iter::once(0).chain(self.boundaries.iter().map(|b| b.line));
+        let ends = self
+            .boundaries
+            .iter()
+            .map(|b| b.line)
+            .chain(std::iter::once(self.lines.len()));
+        starts
+            .zip(ends)
+            .filter(|(start, end)| start < end)
+            .find_map(|(start, end)| {
+                Self::find_line_in(&self.lines[start..end], addr).map(|idx| start + idx)
+            })
+    }
+
+    fn find_line_in(lines: &[DisasmLine], addr: u64) -> Option<usize> {
+        lines
+            .binary_search_by(|line| {
+                if addr < line.address {
+                    std::cmp::Ordering::Greater
+                } else if line.contains_addr(addr) {
+                    std::cmp::Ordering::Equal
+                } else {
+                    std::cmp::Ordering::Less
+                }
+            })
+            .ok()
+    }
+
+    /// Writes this disassembly as assembly source that can be reassembled by
+    /// the GNU assembler. Internal jump targets get `.Llabel_N` labels and
+    /// branches to them reference those labels instead of symbol offsets.
+    pub fn to_gas_asm(&self, name: &str, arch: binary::Arch) -> String {
+        let mut targets = self
+            .lines
+            .iter()
+            .filter_map(|line| match line.jump {
+                Jump::Internal(idx) => Some(idx),
+                _ => None,
+            })
+            .collect::<Vec<usize>>();
+        targets.sort_unstable();
+        targets.dedup();
+        let label = |idx: usize| targets.binary_search(&idx).ok();
+
+        let mut asm = String::new();
+        if matches!(arch, binary::Arch::X86 | binary::Arch::X86_64) {
+            if self.syntax == Syntax::Att {
+                asm.push_str("\t.att_syntax\n");
+            } else {
+                asm.push_str("\t.intel_syntax noprefix\n");
+            }
+        }
+        asm.push_str("\t.text\n");
+        let _ = writeln!(asm, "\"{}\":", name.replace('"', "\\\""));
+
+        for (idx, line) in self.lines.iter().enumerate() {
+            if let Some(n) = label(idx) {
+                let _ = writeln!(asm, ".Llabel_{}:", n);
+            }
+
+            if line.is_data() {
+                let _ = writeln!(
+                    asm,
+                    "\t{} {}",
+                    DATA_MNEMONIC,
+                    byte_directive_operands(&line.bytes)
+                );
+                continue;
+            }
+
+            let _ = match line.jump {
+                Jump::Internal(target) => match label(target) {
+                    Some(n) => writeln!(asm, "\t{} .Llabel_{}", line.mnemonic, n),
+                    None => writeln!(asm, "\t{} {}", line.mnemonic, line.operands),
+                },
+
+                // Symbolicated operands aren't valid assembly, so the address is used instead.
+                Jump::External(addr) if line.is_symbolicated_jump => {
+                    writeln!(asm, "\t{} 0x{:x}", line.mnemonic, addr)
+                }
+
+                _ if line.operands.is_empty() => writeln!(asm, "\t{}", line.mnemonic),
+                _ => writeln!(asm, "\t{} {}", line.mnemonic, line.operands),
+            };
+        }
+
+        asm
+    }
+}
+
+impl std::ops::Index<usize> for Disassembly {
+    type Output = DisasmLine;
+
+    fn index(&self, idx: usize) -> &DisasmLine {
+        &self.lines[idx]
+    }
+}
+
+impl<'a> IntoIterator for &'a Disassembly {
+    type Item = &'a DisasmLine;
+    type IntoIter = std::slice::Iter<'a, DisasmLine>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.lines.iter()
+    }
+}
+
+/// A run of lines in a [`Disassembly`] that can be folded into a single line.
+pub struct Fold {
+    lines: Range<usize>,
+    kind: FoldKind,
+}
+
+/// Where the lines of a symbol start in a [`Disassembly`] that several symbols
+/// were appended to, and what is shown in the header before them.
+pub struct SymbolBoundary {
+    /// The display name of the symbol, or `None` for data between two symbols.
+    name: Option<Box<str>>,
+    address: u64,
+    line: usize,
+    /// The file and line that the symbol was declared at.
+    decl: Option<(Box<Path>, u32)>,
+    /// The name of the function that called the symbol, for `--follow-calls`.
+    caller: Option<Box<str>>,
+    /// The names of the symbols that share the symbol's code.
+    aliases: Box<[Box<str>]>,
+    frame_size: Option<u64>,
+}
+
+impl SymbolBoundary {
+    /// The display name of the symbol, or `None` if the lines are data between
+    /// two symbols.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn decl_location(&self) -> Option<(&Path, u32)> {
+        self.decl.as_ref().map(|(path, line)| (&**path, *line))
+    }
+
+    pub fn caller(&self) -> Option<&str> {
+        self.caller.as_deref()
+    }
+
+    pub fn set_caller(&mut self, caller: &Symbol) -> &mut Self {
+        self.caller = Some(caller.display_name().into());
+        self
+    }
+
+    pub fn aliases(&self) -> impl Iterator<Item = &str> {
+        self.aliases.iter().map(|alias| &**alias)
+    }
+
+    pub fn set_aliases(&mut self, aliases: &[&Symbol]) -> &mut Self {
+        self.aliases = aliases
+            .iter()
+            .map(|alias| alias.display_name().into())
+            .collect();
+        self
+    }
+
+    /// The size of the stack frame that the prologue of the symbol allocates, if it
+    /// could be found.
+    pub fn frame_size(&self) -> Option<u64> {
+        self.frame_size
+    }
+
+    /// The address of the symbol.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// The index of the first line of the symbol.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl Fold {
+    /// The indices of the folded lines.
+    pub fn lines(&self) -> Range<usize> {
+        self.lines.clone()
+    }
+
+    pub fn kind(&self) -> FoldKind {
+        self.kind
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FoldKind {
+    /// The instructions at the start of a function that save registers and set up
+    /// its stack frame.
+    Prologue,
+    /// The instructions before a return that restore the registers saved by the prologue.
+    Epilogue,
+    /// The `int3`, `nop` or zero bytes after the last instruction of a function.
+    Padding,
+}
+
+impl FoldKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            FoldKind::Prologue => "prologue",
+            FoldKind::Epilogue => "epilogue",
+            FoldKind::Padding => "padding",
+        }
+    }
+}
+
+/// Formats bytes as the operands of a `.byte` directive.
+fn byte_directive_operands(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("0x{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(", ")
 }
 
 pub struct DisasmLine {
     address: u64,
     mnemonic: Box<str>,
     operands: Box<str>,
+    /// The byte ranges of the operands in `operands` and their kinds. This is empty
+    /// unless [`DisasmOptions::rich_operands`] is set.
+    operand_spans: Box<[(Range<usize>, OperandKind)]>,
     comments: Option<Box<str>>,
+    encoding: Option<Box<str>>,
     bytes: Box<[u8]>,
-    source_lines: Option<Box<[Box<str>]>>,
+    source_lines: Option<Box<[SourceLine]>>,
     jump: Jump,
+    /// True if the instruction calls a function.
+    is_call: bool,
+    /// True if the instruction is a landing pad for indirect jumps and calls.
+    is_landing_pad: bool,
+    /// The Capstone groups that the instruction is in, e.g. `jump` or `privilege`.
+    /// This is empty without instruction details.
+    groups: Box<[InsnGroup]>,
+    /// The addresses in the jump table that the instruction jumps through, if it
+    /// is the indirect jump of a switch.
+    jump_table: Box<[u64]>,
     is_symbolicated_jump: bool,
 }
 
@@ -195,6 +1759,14 @@ impl DisasmLine {
         self.address
     }
 
+    /// Adds a comment before any existing comments on this line.
+    fn prepend_comment(&mut self, comment: &str) {
+        self.comments = Some(match self.comments.take() {
+            Some(existing) => format!("{}, {}", comment, existing).into(),
+            None => comment.into(),
+        });
+    }
+
     pub fn mnemonic(&self) -> &str {
         &*self.mnemonic
     }
@@ -203,15 +1775,34 @@ impl DisasmLine {
         &*self.operands
     }
 
+    /// Returns the kind of the operand containing the byte at `offset` in the operands,
+    /// or `None` if the operand kinds are unknown or the byte is between operands.
+    pub fn operand_kind_at(&self, offset: usize) -> Option<OperandKind> {
+        self.operand_spans
+            .iter()
+            .find(|(range, _)| range.contains(&offset))
+            .map(|&(_, kind)| kind)
+    }
+
+    /// Returns true if the kinds of this line's operands are known.
+    pub fn has_operand_kinds(&self) -> bool {
+        !self.operand_spans.is_empty()
+    }
+
     pub fn comments(&self) -> &str {
         self.comments.as_deref().unwrap_or("")
     }
 
+    /// A description of how this line's instruction is encoded.
+    pub fn encoding(&self) -> &str {
+        self.encoding.as_deref().unwrap_or("")
+    }
+
     pub fn bytes(&self) -> &[u8] {
         &*self.bytes
     }
 
-    pub fn source_lines(&self) -> &[Box<str>] {
+    pub fn source_lines(&self) -> &[SourceLine] {
         self.source_lines.as_deref().unwrap_or(&[])
     }
 
@@ -219,7 +1810,672 @@ impl DisasmLine {
         self.jump
     }
 
+    pub fn is_call(&self) -> bool {
+        self.is_call
+    }
+
+    pub fn is_landing_pad(&self) -> bool {
+        self.is_landing_pad
+    }
+
+    /// Returns true if the instruction is in `group`, see [`find_group`].
+    pub fn in_group(&self, group: InsnGroup) -> bool {
+        self.groups.contains(&group)
+    }
+
     pub fn is_symbolicated_jump(&self) -> bool {
         self.is_symbolicated_jump
     }
+
+    /// Returns true if this line contains bytes that are not an instruction.
+    pub fn is_data(&self) -> bool {
+        &*self.mnemonic == DATA_MNEMONIC
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::disasm::binary::{Arch, BinaryData};
+    use crate::disasm::symbol::SymbolSource;
+
+    pub(super) fn line(address: u64, len: usize) -> DisasmLine {
+        DisasmLine {
+            address,
+            mnemonic: "nop".into(),
+            operands: "".into(),
+            operand_spans: Box::new([]),
+            comments: None,
+            encoding: None,
+            bytes: vec![0x90; len].into_boxed_slice(),
+            source_lines: None,
+            jump: Jump::None,
+            is_symbolicated_jump: false,
+            is_call: false,
+            is_landing_pad: false,
+            groups: Box::new([]),
+            jump_table: Box::new([]),
+        }
+    }
+
+    pub(super) fn insn(address: u64, mnemonic: &str, operands: &str) -> DisasmLine {
+        DisasmLine {
+            mnemonic: mnemonic.into(),
+            operands: operands.into(),
+            ..line(address, 1)
+        }
+    }
+
+    #[test]
+    fn find_frame_folds_x86() {
+        let mut disassembly = Disassembly::new(Syntax::Intel);
+        for (idx, &(mnemonic, operands)) in [
+            ("push", "rbp"),
+            ("mov", "rbp, rsp"),
+            ("push", "rbx"),
+            ("sub", "rsp, 0x18"),
+            ("mov", "rbx, rdi"),
+            ("add", "rsp, 0x18"),
+            ("pop", "rbx"),
+            ("pop", "rbp"),
+            ("ret", ""),
+        ]
+        .iter()
+        .enumerate()
+        {
+            disassembly.push_line(insn(0x1000 + idx as u64, mnemonic, operands));
+        }
+
+        let folds = find_frame_folds(capstone::Arch::X86, &disassembly, true);
+        assert_eq!(folds.len(), 2);
+        assert_eq!(
+            (folds[0].lines(), folds[0].kind()),
+            (0..4, FoldKind::Prologue)
+        );
+        assert_eq!(
+            (folds[1].lines(), folds[1].kind()),
+            (5..8, FoldKind::Epilogue)
+        );
+
+        // Without the start of the symbol only the epilogue is found.
+        let folds = find_frame_folds(capstone::Arch::X86, &disassembly, false);
+        assert_eq!(folds.len(), 1);
+        assert_eq!(folds[0].lines(), 5..8);
+    }
+
+    #[test]
+    fn relative_jump_table_targets() {
+        // The table at 0x10 holds offsets from itself to 0x4 and 0x8, and then
+        // to an address after the function, which ends it.
+        let mut bytes = vec![0; 16];
+        for offset in [-12i32, -8, -12, 32].iter() {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+        let binary = Binary::raw(BinaryData::from_bytes(&bytes), Arch::X86_64);
+        let symbol = Symbol::new_unmangled("foo".into(), 0, 0, 16, SymbolSource::Elf);
+
+        let table = anal::JumpTable {
+            address: 0x10,
+            entry_size: 4,
+            relative: true,
+        };
+        assert_eq!(jump_table_targets(&binary, &symbol, table), [0x4, 0x8, 0x4]);
+    }
+
+    #[test]
+    fn thumb_symbols_are_refused() {
+        // Two Thumb `nop`s, which would be decoded as one ARM instruction.
+        let binary = Binary::raw(
+            BinaryData::from_bytes(&[0, 0, 0x00, 0xbf, 0x00, 0xbf, 0, 0]),
+            Arch::Arm,
+        );
+        // The address of a Thumb function has its lowest bit set.
+        let thumb = Symbol::new_unmangled("thumb".into(), 3, 2, 4, SymbolSource::Elf);
+        match disasm(&binary, &thumb, &DisasmOptions::default()) {
+            Err(err) => assert!(err.to_string().contains("Thumb function"), "{}", err),
+            Ok(_) => panic!("disassembled a Thumb function as ARM"),
+        }
+
+        let arm = Symbol::new_unmangled("arm".into(), 4, 4, 4, SymbolSource::Elf);
+        assert!(disasm(&binary, &arm, &DisasmOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn capstone_opens_for_every_cli_arch() {
+        // The architectures that `--arch` accepts.
+        let names = [
+            "x86", "x86_64", "arm", "arm64", "ppc", "ppc64", "s390x", "sparc", "sparc64", "mips",
+            "mips64", "mos6502",
+        ];
+        for name in names.iter() {
+            let arch = name.parse::<Arch>().unwrap();
+            let binary = Binary::raw(BinaryData::from_bytes(&[0; 16]), arch);
+            if let Err(err) = capstone_for_binary(&binary, None) {
+                panic!("failed to open Capstone for {}: {:?}", name, err);
+            }
+        }
+
+        // There is no disassembler for RISC-V in the bundled Capstone.
+        assert!("riscv".parse::<Arch>().is_err());
+    }
+
+    #[test]
+    fn relative_internal_jump_targets() {
+        let binary = Binary::raw(BinaryData::from_bytes(&[0; 16]), Arch::X86_64);
+        let symbol = Symbol::new_unmangled("foo".into(), 0x1000, 0, 16, SymbolSource::Elf);
+
+        let jump = || DisasmLine {
+            jump: Jump::External(0x1008),
+            ..insn(0x1000, "jmp", "0x1008")
+        };
+        let mut absolute = jump();
+        symbolicate_jump(&binary, &symbol, &mut absolute, &DisasmOptions::default());
+        assert_eq!(absolute.operands(), "foo+0x8");
+        assert_eq!(absolute.comments(), "0x1008");
+
+        let options = DisasmOptions {
+            relative_targets: true,
+            ..DisasmOptions::default()
+        };
+        let mut relative = jump();
+        assert_eq!(
+            symbolicate_jump(&binary, &symbol, &mut relative, &options),
+            Some(0x1008)
+        );
+        assert_eq!(relative.operands(), "+0x8");
+        assert_eq!(relative.comments(), "");
+    }
+
+    #[test]
+    fn jump_comments_use_address_format() {
+        let binary = Binary::raw(BinaryData::from_bytes(&[0; 16]), Arch::X86_64);
+        let symbol = Symbol::new_unmangled("foo".into(), 0xabc0, 0, 16, SymbolSource::Elf);
+
+        let comment = |address_format| {
+            let options = DisasmOptions {
+                address_format,
+                ..DisasmOptions::default()
+            };
+            let mut line = DisasmLine {
+                jump: Jump::External(0xabc8),
+                ..insn(0xabc0, "jmp", "0xabc8")
+            };
+            symbolicate_jump(&binary, &symbol, &mut line, &options);
+            line.comments().to_string()
+        };
+        assert_eq!(comment(AddressFormat::Hex), "0xabc8");
+        assert_eq!(comment(AddressFormat::HexPrefixed), "0xabc8");
+        assert_eq!(comment(AddressFormat::UpperHex), "0xABC8");
+        assert_eq!(comment(AddressFormat::Dec), "43976");
+    }
+
+    #[test]
+    fn switch_cases_match_jump_targets() {
+        let binary = Binary::raw(BinaryData::from_bytes(&[0; 16]), Arch::X86_64);
+        let symbol = Symbol::new_unmangled("foo".into(), 0x1000, 0, 16, SymbolSource::Elf);
+
+        for &relative_targets in [false, true].iter() {
+            let options = DisasmOptions {
+                relative_targets,
+                ..DisasmOptions::default()
+            };
+            let mut jump = DisasmLine {
+                jump: Jump::External(0x1008),
+                ..insn(0x1000, "jmp", "0x1008")
+            };
+            symbolicate_jump(&binary, &symbol, &mut jump, &options);
+            assert_eq!(
+                jump_table_comment(&symbol, &[0x1008, 0x1008], &options),
+                format!("switch -> {}", jump.operands())
+            );
+        }
+    }
+
+    #[test]
+    fn count_stops_without_data() {
+        // push rbp; mov rbp, rsp; pop rbp; ret; int3; int3
+        let code = [0x55, 0x48, 0x89, 0xe5, 0x5d, 0xc3, 0xcc, 0xcc];
+        let binary = Binary::raw(BinaryData::from_bytes(&code), Arch::X86_64);
+        let symbol = binary.entry_symbol().unwrap();
+
+        let options = DisasmOptions {
+            count: Some(2),
+            ..DisasmOptions::default()
+        };
+        for &traversal in [Traversal::Linear, Traversal::Recursive].iter() {
+            let options = DisasmOptions {
+                traversal,
+                ..options.clone()
+            };
+            let disassembly = disasm(&binary, symbol, &options).unwrap();
+            let mnemonics = disassembly
+                .lines()
+                .iter()
+                .map(DisasmLine::mnemonic)
+                .collect::<Vec<_>>();
+            assert_eq!(mnemonics, ["push", "mov"]);
+        }
+
+        let full = disasm(&binary, symbol, &DisasmOptions::default()).unwrap();
+        assert_eq!(full.instruction_count(), 6);
+    }
+
+    #[test]
+    fn recursive_count_keeps_first_instructions_by_address() {
+        // jmp 0x4; nop; nop; je 0x2; ret
+        // The nops are reached last, from the jump back to 0x2.
+        let code = [0xeb, 0x02, 0x90, 0x90, 0x74, 0xfc, 0xc3];
+        let binary = Binary::raw(BinaryData::from_bytes(&code), Arch::X86_64);
+        let symbol = binary.entry_symbol().unwrap();
+
+        let options = DisasmOptions {
+            count: Some(3),
+            traversal: Traversal::Recursive,
+            ..DisasmOptions::default()
+        };
+        let disassembly = disasm(&binary, symbol, &options).unwrap();
+        let lines = disassembly
+            .lines()
+            .iter()
+            .map(|line| (line.address(), line.mnemonic()))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [(0, "jmp"), (2, "nop"), (3, "nop")]);
+    }
+
+    /// The address and mnemonic of each line of `symbol` with recursive traversal.
+    fn reachable_lines(
+        binary: &Binary,
+        symbol: &Symbol,
+        stop_at: Option<u64>,
+    ) -> Vec<(u64, String)> {
+        let options = DisasmOptions {
+            stop_at,
+            traversal: Traversal::Recursive,
+            ..DisasmOptions::default()
+        };
+        disasm(binary, symbol, &options)
+            .unwrap()
+            .lines()
+            .iter()
+            .map(|line| (line.address(), line.mnemonic().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn recursive_traversal_follows_branches() {
+        // je 0x4; ret; (unreachable byte); nop; ret
+        let code = [0x74, 0x02, 0xc3, 0xff, 0x90, 0xc3];
+        let binary = Binary::raw(BinaryData::from_bytes(&code), Arch::X86_64);
+        let symbol = binary.entry_symbol().unwrap();
+
+        // The conditional jump is followed and falls through to the `ret`.
+        assert_eq!(
+            reachable_lines(&binary, symbol, None),
+            [
+                (0, "je".to_string()),
+                (2, "ret".to_string()),
+                (3, DATA_MNEMONIC.to_string()),
+                (4, "nop".to_string()),
+                (5, "ret".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn recursive_traversal_stops_at_unconditional_jumps() {
+        // jmp 0x3; (unreachable byte); ret
+        let code = [0xeb, 0x01, 0xff, 0xc3];
+        let binary = Binary::raw(BinaryData::from_bytes(&code), Arch::X86_64);
+        let symbol = binary.entry_symbol().unwrap();
+
+        assert_eq!(
+            reachable_lines(&binary, symbol, None),
+            [
+                (0, "jmp".to_string()),
+                (2, DATA_MNEMONIC.to_string()),
+                (3, "ret".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn recursive_traversal_ignores_targets_outside_of_the_symbol() {
+        // jmp 0x10; nop, in a symbol that is 3 bytes long
+        let mut code = vec![0xeb, 0x0e, 0x90];
+        code.resize(0x20, 0x90);
+        let binary = Binary::raw(BinaryData::from_bytes(&code), Arch::X86_64);
+        let symbol = Symbol::new_unmangled("foo".into(), 0, 0, 3, SymbolSource::Elf);
+
+        assert_eq!(
+            reachable_lines(&binary, &symbol, None),
+            [(0, "jmp".to_string()), (2, DATA_MNEMONIC.to_string())]
+        );
+    }
+
+    #[test]
+    fn recursive_traversal_stays_inside_of_the_window() {
+        // nop; nop; mov eax, 1; ret, but the window ends in the middle of the `mov`.
+        let code = [0x90, 0x90, 0xb8, 0x01, 0x00, 0x00, 0x00, 0xc3];
+        let binary = Binary::raw(BinaryData::from_bytes(&code), Arch::X86_64);
+        let symbol = binary.entry_symbol().unwrap();
+
+        assert_eq!(
+            reachable_lines(&binary, symbol, Some(3)),
+            [
+                (0, "nop".to_string()),
+                (1, "nop".to_string()),
+                (2, DATA_MNEMONIC.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn streaming_matches_disasm() {
+        // xor eax, eax; je 0x6; nop; nop; ret; followed by bytes that aren't an instruction
+        let code = [0x31, 0xc0, 0x74, 0x02, 0x90, 0x90, 0xc3, 0xff, 0xff];
+        let binary = Binary::raw(BinaryData::from_bytes(&code), Arch::X86_64);
+        let symbol = binary.entry_symbol().unwrap();
+
+        for &symbolicate_jumps in [false, true].iter() {
+            let options = DisasmOptions {
+                symbolicate_jumps,
+                ..DisasmOptions::default()
+            };
+            let disassembly = disasm(&binary, symbol, &options).unwrap();
+            let mut stream = disasm_streaming(&binary, symbol, &options).unwrap();
+            let streamed = stream.lines().collect::<Result<Vec<_>, _>>().unwrap();
+
+            assert_eq!(streamed.len(), disassembly.len());
+            for (line, expected) in streamed.iter().zip(disassembly.lines()) {
+                assert_eq!(line.address(), expected.address());
+                assert_eq!(line.bytes(), expected.bytes());
+                assert_eq!(line.mnemonic(), expected.mnemonic());
+                // Jumps inside of the symbol are only resolved to lines by `disasm`.
+                match expected.jump() {
+                    Jump::Internal(idx) => {
+                        let target = disassembly.lines()[idx].address();
+                        assert_eq!(line.jump(), Jump::External(target));
+                    }
+                    jump => {
+                        assert_eq!(line.jump(), jump);
+                        assert_eq!(line.operands(), expected.operands());
+                        assert_eq!(line.comments(), expected.comments());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn select_syntax_keeps_available_syntax() {
+        let binary = Binary::raw(BinaryData::from_bytes(&[0x90]), Arch::X86_64);
+        assert_eq!(select_syntax(&binary, None).unwrap(), None);
+        assert_eq!(
+            select_syntax(&binary, Some(Syntax::Intel)).unwrap(),
+            Some(Syntax::Intel)
+        );
+    }
+
+    #[test]
+    fn find_padding_fold_x86() {
+        let mut disassembly = Disassembly::new(Syntax::Intel);
+        disassembly.push_line(DisasmLine {
+            bytes: Box::new([0xc3]),
+            ..insn(0x1000, "ret", "")
+        });
+        for address in 0x1001..0x1004 {
+            disassembly.push_line(DisasmLine {
+                bytes: Box::new([0xcc]),
+                ..insn(address, "int3", "")
+            });
+        }
+        disassembly.push_line(insn(0x1004, "nop", ""));
+
+        let fold = find_padding_fold(capstone::Arch::X86, &disassembly).unwrap();
+        assert_eq!((fold.lines(), fold.kind()), (1..5, FoldKind::Padding));
+
+        // A disassembly without padding has nothing to fold.
+        disassembly.lines.truncate(1);
+        assert!(find_padding_fold(capstone::Arch::X86, &disassembly).is_none());
+    }
+
+    #[test]
+    fn operand_spans_split_top_level_operands() {
+        use OperandKind::*;
+
+        let spans = operand_spans(
+            "qword ptr [rbp + rax*8 - 0x10], rcx",
+            Some(&[Memory, Register]),
+            Syntax::Intel,
+        );
+        assert_eq!(spans, [(0..30, Memory), (32..35, Register)]);
+
+        // The kinds are guessed when they don't match the printed operands.
+        let spans = operand_spans("x29, x30, [sp, #-0x10]!", None, Syntax::Intel);
+        assert_eq!(
+            spans,
+            [(0..3, Register), (5..8, Register), (10..23, Memory)]
+        );
+        let spans = operand_spans("$0x10, %rsp", Some(&[Immediate]), Syntax::Att);
+        assert_eq!(spans, [(0..5, Immediate), (7..11, Register)]);
+    }
+
+    #[test]
+    fn operand_spans_att_order() {
+        use OperandKind::*;
+
+        // `mov qword ptr [rbp - 0x10], rcx`, with the kinds in Intel order.
+        let spans = operand_spans("%rcx, -0x10(%rbp)", Some(&[Memory, Register]), Syntax::Att);
+        assert_eq!(spans, [(0..4, Register), (6..17, Memory)]);
+
+        // `add rsp, 0x10`
+        let spans = operand_spans("$0x10, %rsp", Some(&[Register, Immediate]), Syntax::Att);
+        assert_eq!(spans, [(0..5, Immediate), (7..11, Register)]);
+    }
+
+    #[test]
+    fn address_refs_arm64() {
+        let lines = [
+            insn(0x1000, "adrp", "x0, #0x411000"),
+            insn(0x1004, "adrp", "x1, #0x412000"),
+            insn(0x1008, "add", "x0, x0, #0x123"),
+            insn(0x100c, "ldr", "x2, [x1, #0x18]"),
+            insn(0x1010, "ldr", "w3, [x1]"),
+            // x0 holds the full address now, not the page.
+            insn(0x1014, "ldr", "x4, [x0, #8]"),
+            DisasmLine {
+                jump: Jump::External(0x1020),
+                ..insn(0x1018, "b", "#0x1020")
+            },
+            insn(0x101c, "ldr", "x5, [x1, #-0x10]"),
+        ];
+        assert_eq!(
+            anal::address_refs(capstone::Arch::Arm64, &lines),
+            [(2, 0x411123), (3, 0x412018), (4, 0x412000)]
+        );
+    }
+
+    #[test]
+    fn address_refs_arm64_writeback() {
+        let lines = [
+            insn(0x1000, "adrp", "x1, #0x412000"),
+            // Pre-index, x1 is 0x412010 afterwards.
+            insn(0x1004, "ldr", "x2, [x1, #0x10]!"),
+            insn(0x1008, "ldr", "x3, [x1, #8]"),
+            // Post-index, x1 is 0x412018 afterwards.
+            insn(0x100c, "ldr", "x4, [x1], #8"),
+            insn(0x1010, "ldr", "x5, [x1]"),
+        ];
+        assert_eq!(
+            anal::address_refs(capstone::Arch::Arm64, &lines),
+            [(1, 0x412010), (2, 0x412018), (3, 0x412010), (4, 0x412018)]
+        );
+    }
+
+    #[test]
+    fn address_refs_arm64_w_register_clears_page() {
+        let lines = [
+            insn(0x1000, "adrp", "x0, #0x411000"),
+            insn(0x1004, "adrp", "x1, #0x412000"),
+            insn(0x1008, "mov", "w0, #1"),
+            insn(0x100c, "ldr", "x2, [x0, #8]"),
+            insn(0x1010, "ldr", "w1, [x1, #8]"),
+            insn(0x1014, "ldr", "x3, [x1]"),
+        ];
+        assert_eq!(
+            anal::address_refs(capstone::Arch::Arm64, &lines),
+            [(4, 0x412008)]
+        );
+    }
+
+    #[test]
+    fn find_line_by_address_matches_linear_scan() {
+        let mut disassembly = Disassembly::new(Syntax::default());
+        let mut address = 0x1000;
+        for len in [1, 3, 7, 2, 15, 4, 1, 1, 5, 6].iter() {
+            disassembly.push_line(line(address, *len));
+            address += *len as u64;
+        }
+
+        for addr in 0xff0..address + 0x10 {
+            assert_eq!(
+                disassembly.find_line_by_address(addr),
+                disassembly.lines.iter().position(|l| l.contains_addr(addr)),
+                "address 0x{:x}",
+                addr
+            );
+        }
+    }
+
+    #[test]
+    fn disassembly_totals() {
+        let mut disassembly = Disassembly::new(Syntax::default());
+        assert_eq!(disassembly.byte_size(), 0);
+        assert_eq!(disassembly.instruction_count(), 0);
+        assert!(disassembly.address_range().is_empty());
+
+        disassembly.push_line(line(0x1000, 4));
+        disassembly.push_line(line(0x1004, 1));
+        disassembly.push_line(bytes_line(0x1005, &[0x00, 0x00, 0x00]));
+        assert_eq!(disassembly.byte_size(), 8);
+        assert_eq!(disassembly.instruction_count(), 2);
+        assert_eq!(disassembly.address_range(), 0x1000..0x1008);
+        assert_eq!(disassembly.landing_pad_count(), 0);
+
+        disassembly.push_line(DisasmLine {
+            mnemonic: "endbr64".into(),
+            is_landing_pad: true,
+            ..line(0x1008, 4)
+        });
+        assert_eq!(disassembly.landing_pad_count(), 1);
+    }
+
+    #[test]
+    fn append_symbol_keeps_jumps_and_boundaries() {
+        let mut first = Disassembly::new(Syntax::Intel);
+        first.push_line(line(0x2000, 2));
+        first.push_line(DisasmLine {
+            jump: Jump::Internal(0),
+            ..line(0x2002, 2)
+        });
+        // Tail calls into the second symbol.
+        first.push_line(DisasmLine {
+            jump: Jump::External(0x1004),
+            ..line(0x2004, 2)
+        });
+
+        let mut second = Disassembly::new(Syntax::Intel);
+        second.push_line(line(0x1000, 4));
+        second.push_line(DisasmLine {
+            jump: Jump::Internal(0),
+            ..line(0x1004, 2)
+        });
+        second.folds.push(Fold {
+            lines: 0..1,
+            kind: FoldKind::Prologue,
+        });
+
+        let mut merged = Disassembly::new(Syntax::Intel);
+        merged.append_symbol(
+            &Symbol::new_unmangled("first".into(), 0x2000, 0, 4, SymbolSource::Elf),
+            first,
+        );
+        merged.append_symbol(
+            &Symbol::new_unmangled("second".into(), 0x1000, 0, 6, SymbolSource::Elf),
+            second,
+        );
+
+        let mut data = Disassembly::new(Syntax::Intel);
+        data.push_line(bytes_line(0x1006, &[0xcc, 0xcc]));
+        merged.append_data(data);
+
+        let boundaries = merged
+            .boundaries()
+            .iter()
+            .map(|b| (b.name(), b.line()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            boundaries,
+            [(Some("first"), 0), (Some("second"), 3), (None, 5)]
+        );
+        assert_eq!(merged.len(), 6);
+        let addresses = (&merged)
+            .into_iter()
+            .map(DisasmLine::address)
+            .collect::<Vec<u64>>();
+        assert_eq!(addresses, [0x2000, 0x2002, 0x2004, 0x1000, 0x1004, 0x1006]);
+        assert!(matches!(merged[1].jump(), Jump::Internal(0)));
+        assert!(matches!(merged[4].jump(), Jump::Internal(3)));
+        assert_eq!(merged.folds()[0].lines(), 3..4);
+
+        // The symbols aren't in address order, each one is searched separately.
+        assert_eq!(merged.find_line_by_address(0x2002), Some(1));
+        assert_eq!(merged.find_line_by_address(0x1005), Some(4));
+        assert_eq!(merged.find_line_by_address(0x3000), None);
+
+        assert!(matches!(merged[2].jump(), Jump::External(0x1004)));
+        merged.link_symbols();
+        assert!(matches!(merged[2].jump(), Jump::Internal(4)));
+    }
+
+    #[test]
+    fn find_group_ignores_case_and_dashes() {
+        let binary = Binary::raw(BinaryData::from_bytes(&[0; 16]), Arch::X86_64);
+        let find = |name| find_group(&binary, name).ok();
+        assert!(find("jump") == Some(InsnGroup::JUMP));
+        assert!(find("JUMP") == Some(InsnGroup::JUMP));
+        assert!(find("branch-relative") == Some(InsnGroup::BRANCH_RELATIVE));
+        assert!(find("branch_relative") == Some(InsnGroup::BRANCH_RELATIVE));
+        assert!(find("jum").is_none());
+
+        // The error lists the groups.
+        match find_group(&binary, "jmp") {
+            Ok(_) => panic!("`jmp` isn't a group"),
+            Err(err) => assert!(err.to_string().contains("branch-relative")),
+        }
+
+        let line = DisasmLine {
+            groups: vec![InsnGroup::JUMP, InsnGroup::BRANCH_RELATIVE].into_boxed_slice(),
+            ..line(0x1000, 2)
+        };
+        assert!(line.in_group(InsnGroup::JUMP));
+        assert!(!line.in_group(InsnGroup::CALL));
+    }
+
+    #[test]
+    fn find_landing_pads_x86() {
+        // endbr64; push rbp; ud2; endbr32
+        let code = [
+            0xf3, 0x0f, 0x1e, 0xfa, 0x55, 0x0f, 0x0b, 0xf3, 0x0f, 0x1e, 0xfb,
+        ];
+        assert_eq!(
+            anal::find_landing_pads(capstone::Arch::X86, &code, 0x1000),
+            vec![0x1000, 0x1007]
+        );
+        assert_eq!(
+            anal::find_landing_pads(capstone::Arch::X86, &code[1..], 0x1001),
+            vec![0x1007]
+        );
+        assert!(anal::find_landing_pads(capstone::Arch::Arm64, &code, 0x1000).is_empty());
+    }
 }
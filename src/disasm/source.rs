@@ -4,13 +4,111 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A line of source code.
+pub struct SourceLine {
+    path: Arc<Path>,
+    line: u32,
+    text: Box<str>,
+}
+
+impl SourceLine {
+    /// The path of the source file as it was recorded in the debug information.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The 1-based line number of this line in its source file.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// A source location of an address: either the line from the line table, or the
+/// call site of an inlined subroutine that contains the address.
+#[derive(Copy, Clone)]
+pub struct SourceLocation<'a> {
+    path: &'a Path,
+    line: u32,
+}
+
+impl<'a> SourceLocation<'a> {
+    pub(crate) fn new(path: &'a Path, line: u32) -> Self {
+        SourceLocation { path, line }
+    }
+
+    pub fn path(&self) -> &'a Path {
+        self.path
+    }
+
+    /// The 1-based line number, or 0 if the location has no line.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+}
+
+/// Which of the source locations of an address are shown when it is in inlined
+/// code, which is mapped to a line in the inlined function and to each of the
+/// calls that it was inlined through.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SourceMappings {
+    /// Only the innermost location, which is the line in the inlined function.
+    #[default]
+    Innermost,
+    /// Only the outermost location, which is the line in the function that the
+    /// code was inlined into.
+    Outermost,
+    /// Every location, outermost first.
+    All,
+}
+
+impl SourceMappings {
+    /// Returns the locations that are shown out of `locations`, which are the
+    /// locations of one address ordered from the innermost to the outermost.
+    pub fn select<'a, I>(self, locations: I) -> Vec<SourceLocation<'a>>
+    where
+        I: IntoIterator<Item = SourceLocation<'a>>,
+    {
+        let mut locations = locations.into_iter();
+        match self {
+            SourceMappings::Innermost => locations.next().into_iter().collect(),
+            SourceMappings::Outermost => locations.last().into_iter().collect(),
+            SourceMappings::All => {
+                let mut locations = locations.collect::<Vec<_>>();
+                locations.reverse();
+                locations
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for SourceMappings {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("innermost") {
+            Ok(SourceMappings::Innermost)
+        } else if s.eq_ignore_ascii_case("outermost") {
+            Ok(SourceMappings::Outermost)
+        } else if s.eq_ignore_ascii_case("all") {
+            Ok(SourceMappings::All)
+        } else {
+            Err("invalid source mappings")
+        }
+    }
+}
 
 pub struct SourceLoader {
     // FIXME implement this
     // /// A map of paths that to not exist to their corresponding
     // /// existing paths. (e.g. an absolute Windows path to a path on a Linux OS).
     // path_mapper: HashMap<PathBuf, PathBuf>,
-    cache: HashMap<PathBuf, Option<LineCache>>,
+    cache: HashMap<PathBuf, (Arc<Path>, Option<LineCache>)>,
 }
 
 impl SourceLoader {
@@ -21,27 +119,35 @@ impl SourceLoader {
         }
     }
 
-    pub fn load_lines<'p, I>(&mut self, lines: I, output: &mut Vec<Box<str>>) -> anyhow::Result<()>
+    pub fn load_lines<'p, I>(
+        &mut self,
+        lines: I,
+        output: &mut Vec<SourceLine>,
+    ) -> anyhow::Result<()>
     where
-        I: Iterator<Item = (&'p Path, u32)>,
+        I: IntoIterator<Item = SourceLocation<'p>>,
     {
         use std::collections::hash_map::Entry;
-        for (path, line) in lines {
-            let cache = match self.cache.entry(path.into()) {
+        for location in lines {
+            let (path, line) = (location.path(), location.line());
+            let (shared_path, cache) = match self.cache.entry(path.into()) {
                 Entry::Occupied(o) => o.into_mut(),
                 Entry::Vacant(v) => {
-                    if !path.exists() {
-                        v.insert(None)
+                    let cache = if !path.exists() {
+                        None
                     } else {
-                        v.insert(Some(
-                            LineCache::new(path).context("error loading line cache")?,
-                        ))
-                    }
+                        Some(LineCache::new(path).context("error loading line cache")?)
+                    };
+                    v.insert((Arc::from(path), cache))
                 }
             };
 
             if let Some(line_str) = cache.as_mut().and_then(|cache| cache.line(line)) {
-                output.push(line_str.into());
+                output.push(SourceLine {
+                    path: Arc::clone(shared_path),
+                    line,
+                    text: line_str.into(),
+                });
             }
         }
         Ok(())
@@ -111,3 +217,34 @@ impl LineCache {
         self.offsets.push(self.current as u32);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn select_source_mappings() {
+        let callee = Path::new("callee.rs");
+        let caller = Path::new("caller.rs");
+        let locations = [
+            SourceLocation::new(callee, 20),
+            SourceLocation::new(callee, 5),
+            SourceLocation::new(caller, 10),
+        ];
+        let lines = |mappings: SourceMappings| {
+            mappings
+                .select(locations.iter().copied())
+                .iter()
+                .map(|location| (location.path(), location.line()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(lines(SourceMappings::Innermost), [(callee, 20)]);
+        assert_eq!(lines(SourceMappings::Outermost), [(caller, 10)]);
+        assert_eq!(
+            lines(SourceMappings::All),
+            [(caller, 10), (callee, 5), (callee, 20)]
+        );
+        assert!(SourceMappings::Innermost.select(None).is_empty());
+    }
+}
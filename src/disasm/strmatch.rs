@@ -1,7 +1,9 @@
+use std::ops::Range;
 use std::str::Chars;
 
 pub struct Tokenizer<'a> {
     source: Chars<'a>,
+    source_len: usize,
     normalize_whitespace: bool,
 }
 
@@ -9,6 +11,7 @@ impl<'a> Tokenizer<'a> {
     pub fn new(source: &'a str) -> Tokenizer<'a> {
         Tokenizer {
             source: source.chars(),
+            source_len: source.len(),
             normalize_whitespace: true,
         }
     }
@@ -16,10 +19,26 @@ impl<'a> Tokenizer<'a> {
     pub fn no_whitespace_normalize(source: &'a str) -> Tokenizer<'a> {
         Tokenizer {
             source: source.chars(),
+            source_len: source.len(),
             normalize_whitespace: false,
         }
     }
 
+    /// Returns an iterator over the tokens and the byte offsets in the source that
+    /// they start at. Normalized whitespace tokens start at the first whitespace
+    /// character that they replace.
+    pub fn with_offsets(mut self) -> impl Iterator<Item = (usize, &'a str)> {
+        std::iter::from_fn(move || {
+            let offset = self.offset();
+            Some((offset, self.next()?))
+        })
+    }
+
+    /// The byte offset of the next token in the source.
+    fn offset(&self) -> usize {
+        self.source_len - self.source.as_str().len()
+    }
+
     fn next_char(&mut self) -> Option<char> {
         self.source.next()
     }
@@ -92,15 +111,33 @@ pub fn distance<'lhs, 'rhs, Lhs, Rhs>(lhs: Lhs, rhs: Rhs, max_distance: u32) ->
 where
     Lhs: IntoIterator<Item = &'lhs str>,
     Rhs: IntoIterator<Item = &'rhs str>,
+{
+    align(lhs, rhs, max_distance, |_| {})
+}
+
+/// Like [`distance`], but also calls `on_match` with the index of each token in `rhs`
+/// that a token of `lhs` was matched with. `on_match` may be called for some tokens
+/// before `None` is returned.
+pub fn align<'lhs, 'rhs, Lhs, Rhs, F>(
+    lhs: Lhs,
+    rhs: Rhs,
+    max_distance: u32,
+    mut on_match: F,
+) -> Option<u32>
+where
+    Lhs: IntoIterator<Item = &'lhs str>,
+    Rhs: IntoIterator<Item = &'rhs str>,
+    F: FnMut(usize),
 {
     let mut dist = 0;
-    let mut rhs = rhs.into_iter();
+    let mut rhs = rhs.into_iter().enumerate();
 
     for lhs in lhs {
         loop {
-            let rhs = rhs.next()?;
+            let (idx, rhs) = rhs.next()?;
 
             if lhs == rhs {
+                on_match(idx);
                 break;
             } else {
                 dist += 1;
@@ -113,3 +150,183 @@ where
 
     Some(dist)
 }
+
+/// The score added to names that contain the query, but not at the end of their last
+/// path component.
+const INNER_MATCH_SCORE: u32 = 1 << 16;
+
+/// The score added to names whose last path component only contains the query as
+/// part of an identifier.
+const SUBSTRING_MATCH_SCORE: u32 = 2 << 16;
+
+/// Scores how well `name` matches the tokens of a query, lower is better. Returns `None`
+/// if `name` doesn't match or its score would be greater than `max_score`.
+///
+/// Names whose last path component ends with the query (`push` in `alloc::vec::Vec::push`)
+/// come first, then names that contain the query anywhere else (`foo::push::bar`), and then
+/// names whose last path component contains the query inside of an identifier (`my_pushdown`).
+/// Names in the same group are ordered by their [`distance`] from the query.
+pub fn match_score(query: &[&str], name: &str, max_score: u32) -> Option<u32> {
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let tokens = Tokenizer::new(name).collect::<Vec<&str>>();
+    let last = last_path_component(&tokens);
+
+    let score = match distance(query.iter().copied(), tokens.iter().copied(), max_score) {
+        Some(dist) if tokens[..last.end].ends_with(query) => dist,
+        Some(dist) => INNER_MATCH_SCORE + dist,
+        None if query.len() == 1 => {
+            let component = &tokens[last];
+            let len = component
+                .iter()
+                .find(|token| token.contains(query[0]))?
+                .len();
+            SUBSTRING_MATCH_SCORE + (len - query[0].len()) as u32
+        }
+        None => return None,
+    };
+
+    Some(score).filter(|&score| score <= max_score)
+}
+
+/// Returns the byte ranges of `name` that matched the tokens of a query, for the same
+/// match that [`match_score`] scored. The ranges are in order and don't overlap.
+pub fn match_spans(query: &[&str], name: &str) -> Vec<Range<usize>> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let (offsets, tokens): (Vec<usize>, Vec<&str>) = Tokenizer::new(name).with_offsets().unzip();
+    // Normalized whitespace can be longer than its token, so tokens end where the next one starts.
+    let span = |idx: usize| offsets[idx]..offsets.get(idx + 1).copied().unwrap_or(name.len());
+
+    let mut spans = Vec::new();
+    let query_tokens = query.iter().copied();
+    let matched = align(query_tokens, tokens.iter().copied(), u32::MAX, |idx| {
+        spans.push(span(idx))
+    });
+    if matched.is_some() {
+        return spans;
+    }
+    spans.clear();
+
+    if query.len() == 1 {
+        let substring = last_path_component(&tokens)
+            .find_map(|idx| Some(offsets[idx] + tokens[idx].find(query[0])?));
+        if let Some(start) = substring {
+            spans.push(start..(start + query[0].len()));
+        }
+    }
+
+    spans
+}
+
+/// Returns the range of the tokens in the last path component of a name, not including
+/// its generic parameters or function parameters.
+fn last_path_component(tokens: &[&str]) -> Range<usize> {
+    let mut depth = 0usize;
+    let mut component = 0..tokens.len();
+
+    for (idx, &token) in tokens.iter().enumerate() {
+        match token {
+            "::" if depth == 0 => component = (idx + 1)..tokens.len(),
+            "<" | "(" => {
+                if depth == 0 && component.end == tokens.len() {
+                    component.end = idx;
+                }
+                depth += 1;
+            }
+            ">" | ")" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    component
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn score(query: &str, name: &str) -> Option<u32> {
+        let query = Tokenizer::new(query).collect::<Vec<&str>>();
+        match_score(&query, name, u32::MAX)
+    }
+
+    #[test]
+    fn last_path_component_skips_generics() {
+        let component = |name| {
+            let tokens = Tokenizer::new(name).collect::<Vec<&str>>();
+            tokens[last_path_component(&tokens)].concat()
+        };
+
+        assert_eq!(component("alloc::vec::Vec<T>::push"), "push");
+        assert_eq!(
+            component("<alloc::vec::Vec<T> as core::Clone>::clone"),
+            "clone"
+        );
+        assert_eq!(component("foo::bar(int, char)"), "bar");
+        assert_eq!(component("main"), "main");
+    }
+
+    #[test]
+    fn match_spans_highlight_query_tokens() {
+        let spans = |query, name: &'static str| {
+            let query = Tokenizer::new(query).collect::<Vec<&str>>();
+            match_spans(&query, name)
+                .into_iter()
+                .map(|span| &name[span])
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            spans("Vec::push", "alloc::vec::Vec::push"),
+            ["Vec", "::", "push"]
+        );
+        assert_eq!(spans("push", "my_pushdown"), ["push"]);
+        assert_eq!(
+            spans("as  Clone", "<Foo as   Clone>::clone"),
+            ["as", "   ", "Clone"]
+        );
+        assert!(spans("pop", "alloc::vec::Vec::push").is_empty());
+    }
+
+    #[test]
+    fn match_score_prefers_last_path_component() {
+        let names = [
+            "my_pushdown",
+            "foo::push::bar",
+            "core::arch::x86::push",
+            "alloc::vec::Vec::push",
+        ];
+        let ranked = |query| {
+            let mut matches = names
+                .iter()
+                .filter_map(|&name| Some((score(query, name)?, name)))
+                .collect::<Vec<_>>();
+            matches.sort();
+            matches
+                .into_iter()
+                .map(|(_, name)| name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ranked("push"),
+            [
+                "alloc::vec::Vec::push",
+                "core::arch::x86::push",
+                "foo::push::bar",
+                "my_pushdown",
+            ]
+        );
+        assert_eq!(ranked("x86::push"), ["core::arch::x86::push"]);
+        assert_eq!(ranked("Vec::push"), ["alloc::vec::Vec::push"]);
+        assert_eq!(ranked("bar"), ["foo::push::bar"]);
+        assert_eq!(ranked("pushdown"), ["my_pushdown"]);
+        assert_eq!(ranked("vec"), ["alloc::vec::Vec::push"]);
+        assert!(ranked("pop").is_empty());
+    }
+}
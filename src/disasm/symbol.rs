@@ -1,10 +1,32 @@
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::fmt;
+use std::path::{Path, PathBuf};
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Serialize, Deserialize)]
 pub struct Symbol {
-    /// The demangled name of the symbol.
-    name: String,
+    /// The raw (possibly mangled) name of the symbol.
+    name: Box<str>,
+
+    /// The demangled name of the symbol. This is computed the first time
+    /// that [`Symbol::display_name`] is called and is `None` if the name
+    /// could not be demangled.
+    #[serde(skip)]
+    demangled: OnceCell<Option<Box<str>>>,
+
+    /// The language that the symbol's name was mangled with.
+    lang: SymbolLang,
+
+    /// The demangler used for [`Symbol::display_name`].
+    demangle: Demangle,
+
+    /// What the symbol refers to.
+    kind: SymbolKind,
+
+    /// The ELF binding of the symbol, which decides which of the symbols at the
+    /// same address is preferred.
+    binding: SymbolBinding,
 
     /// The virtual address of the symbol.
     addr: u64,
@@ -16,6 +38,14 @@ pub struct Symbol {
     blen: usize,
     /// Where this symbol is from.
     source: SymbolSource,
+
+    /// The sources of lower priority symbols that covered the same range and
+    /// were merged into this one.
+    merged_sources: Vec<SymbolSource>,
+
+    /// The file and line that the symbol was declared at. This is only
+    /// available for symbols loaded from DWARF.
+    decl: Option<(Box<Path>, u32)>,
 }
 
 impl Symbol {
@@ -27,37 +57,80 @@ impl Symbol {
         source: SymbolSource,
     ) -> Self {
         Symbol {
-            name,
+            name: name.into_boxed_str(),
+            demangled: OnceCell::new(),
+            lang: SymbolLang::Unknown,
+            demangle: Demangle::Auto,
+            kind: SymbolKind::Function,
+            binding: SymbolBinding::Unknown,
             addr,
             bpos,
             blen,
             source,
+            merged_sources: Vec::new(),
+            decl: None,
         }
     }
 
+    /// Creates a new symbol from a mangled name. The name is not demangled
+    /// until [`Symbol::display_name`] is called.
     pub fn new<'a, N>(name: N, addr: u64, bpos: usize, blen: usize, source: SymbolSource) -> Self
     where
         N: Into<Cow<'a, str>>,
     {
-        use cpp_demangle::Symbol as CppSymbol;
-        use rustc_demangle::try_demangle;
-
-        // FIXME demangle C names (e.g. stdcall and fastcall naming conventions).
-        let name = name.into();
-        let demangled_name = try_demangle(&*name)
-            .map(|n| Cow::from(format!("{:#}", n)))
-            .or_else(|_| CppSymbol::new(name.as_bytes()).map(|s| Cow::from(s.to_string())))
-            .unwrap_or(name);
+        let name = name.into().into_owned().into_boxed_str();
+        let lang = SymbolLang::detect(&name);
 
         Symbol {
-            name: demangled_name.into_owned(),
+            name,
+            demangled: OnceCell::new(),
+            lang,
+            demangle: Demangle::Auto,
+            kind: SymbolKind::Function,
+            binding: SymbolBinding::Unknown,
             addr,
             bpos,
             blen,
             source,
+            merged_sources: Vec::new(),
+            decl: None,
         }
     }
 
+    /// Sets the demangler used for [`Symbol::display_name`].
+    pub(crate) fn set_demangle(&mut self, demangle: Demangle) {
+        if self.demangle != demangle {
+            self.demangle = demangle;
+            self.demangled = OnceCell::new();
+        }
+    }
+
+    /// Demangles the name of this symbol, returning `None` if the name
+    /// is not mangled or cannot be demangled.
+    fn demangle_name(&self) -> Option<Box<str>> {
+        use cpp_demangle::Symbol as CppSymbol;
+        use rustc_demangle::try_demangle;
+
+        let lang = match self.demangle {
+            Demangle::None => return None,
+            Demangle::Auto => self.lang,
+            Demangle::Rust => SymbolLang::Rust,
+            Demangle::Cpp => SymbolLang::Cpp,
+        };
+
+        // ELF symbol versions and PLT stubs (`name@plt`) are kept after the demangled name.
+        let (name, suffix) = split_elf_suffix(&self.name);
+
+        // FIXME demangle C names (e.g. stdcall and fastcall naming conventions).
+        let demangled = match lang {
+            SymbolLang::Unknown => None,
+            SymbolLang::Rust => try_demangle(name).ok().map(|n| format!("{:#}", n)),
+            SymbolLang::Cpp => CppSymbol::new(name.as_bytes()).ok().map(|s| s.to_string()),
+        };
+
+        demangled.map(|d| format!("{}{}", d, suffix).into_boxed_str())
+    }
+
     pub fn address(&self) -> u64 {
         self.addr
     }
@@ -76,32 +149,218 @@ impl Symbol {
     }
 
     pub fn end(&self) -> usize {
-        self.bpos + self.blen
+        self.bpos.saturating_add(self.blen)
+    }
+
+    /// Returns the range of bytes of this symbol in a binary that is `data_len`
+    /// bytes long. The range is clamped so that it never goes past the end
+    /// of the binary.
+    pub fn clamped_range(&self, data_len: usize) -> std::ops::Range<usize> {
+        let end = std::cmp::min(self.end(), data_len);
+        std::cmp::min(self.bpos, end)..end
     }
 
-    #[allow(dead_code)]
     pub fn size(&self) -> usize {
         self.blen
     }
 
+    /// The raw (possibly mangled) name of this symbol.
     pub fn name(&self) -> &str {
         &*self.name
     }
 
+    /// The demangled name of this symbol, or the raw name if it can't be demangled.
+    /// The name is only demangled once.
+    pub fn display_name(&self) -> &str {
+        self.demangled
+            .get_or_init(|| self.demangle_name())
+            .as_deref()
+            .unwrap_or(&*self.name)
+    }
+
+    /// The raw name of this symbol without an ELF version or PLT suffix, e.g.
+    /// `memcpy` for `memcpy@@GLIBC_2.14`.
+    pub fn bare_name(&self) -> &str {
+        split_elf_suffix(&self.name).0
+    }
+
     pub fn source(&self) -> SymbolSource {
         self.source
     }
 
+    /// The sources of duplicate symbols that were merged into this one.
+    #[allow(dead_code)]
+    pub fn merged_sources(&self) -> &[SymbolSource] {
+        &self.merged_sources
+    }
+
+    /// Records that a duplicate of this symbol was found in `source`.
+    pub(crate) fn merge_source(&mut self, source: SymbolSource) {
+        if source != self.source && !self.merged_sources.contains(&source) {
+            self.merged_sources.push(source);
+        }
+    }
+
+    /// The file and line that this symbol was declared at, if known.
+    pub fn decl_location(&self) -> Option<(&Path, u32)> {
+        self.decl.as_ref().map(|(path, line)| (&**path, *line))
+    }
+
+    pub(crate) fn set_decl_location(&mut self, path: PathBuf, line: u32) {
+        self.decl = Some((path.into_boxed_path(), line));
+    }
+
+    #[allow(dead_code)]
+    pub fn lang(&self) -> SymbolLang {
+        self.lang
+    }
+
+    pub fn kind(&self) -> SymbolKind {
+        self.kind
+    }
+
+    pub(crate) fn set_kind(&mut self, kind: SymbolKind) {
+        self.kind = kind;
+    }
+
+    pub fn binding(&self) -> SymbolBinding {
+        self.binding
+    }
+
+    pub(crate) fn set_binding(&mut self, binding: SymbolBinding) {
+        self.binding = binding;
+    }
+
     pub(crate) fn set_address(&mut self, new_address: u64) {
         self.addr = new_address;
     }
 
+    pub(crate) fn set_offset(&mut self, new_offset: usize) {
+        self.bpos = new_offset;
+    }
+
     pub(crate) fn set_size(&mut self, new_size: usize) {
         self.blen = new_size;
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+/// What a symbol refers to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SymbolKind {
+    /// A function or other executable code.
+    Function,
+    /// A function that was declared in the debug information without any
+    /// code (e.g. it was only inlined). These have no address or size.
+    Declaration,
+}
+
+/// The binding of an ELF symbol. The variants are sorted from the most to the least
+/// preferred name for an address: a global symbol overrides a weak one, and both
+/// are visible outside of the object file that defined them, unlike a local symbol.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SymbolBinding {
+    /// `STB_GLOBAL`, or `STB_GNU_UNIQUE`.
+    Global,
+    /// `STB_WEAK`.
+    Weak,
+    /// `STB_LOCAL`.
+    Local,
+    /// The symbol isn't from an ELF symbol table.
+    Unknown,
+}
+
+/// The language that a symbol name was mangled with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SymbolLang {
+    /// The name is not mangled or the mangling scheme is not known.
+    Unknown,
+    /// Rust legacy (`_ZN...17h<hash>E`) or v0 (`_R`) mangling.
+    Rust,
+    /// Itanium C++ ABI mangling (`_Z`).
+    Cpp,
+}
+
+impl SymbolLang {
+    /// Guesses the language of a symbol from its mangled name.
+    pub fn detect(name: &str) -> SymbolLang {
+        let name = split_elf_suffix(name).0;
+
+        // Mach-O symbols have an extra leading underscore.
+        let name = if name.starts_with("__Z") || name.starts_with("__R") {
+            &name[1..]
+        } else {
+            name
+        };
+
+        if name.starts_with("_R") || (name.starts_with("_ZN") && has_rust_legacy_hash(name)) {
+            SymbolLang::Rust
+        } else if name.starts_with("_Z") {
+            SymbolLang::Cpp
+        } else {
+            SymbolLang::Unknown
+        }
+    }
+}
+
+/// Splits a name into the symbol name and an ELF version or PLT suffix
+/// starting with `@` (e.g. `memcpy@GLIBC_2.14` or `puts@plt`).
+fn split_elf_suffix(name: &str) -> (&str, &str) {
+    match name.find('@') {
+        Some(idx) if idx > 0 => name.split_at(idx),
+        _ => (name, ""),
+    }
+}
+
+/// Returns true if a `_ZN` name ends with the `17h<16 hex digits>E` hash
+/// that rustc appends to legacy mangled names.
+fn has_rust_legacy_hash(name: &str) -> bool {
+    // LLVM may append suffixes such as `.llvm.1234` to local symbols.
+    let name = name.split('.').next().unwrap_or(name);
+    let name = if let Some(name) = name.strip_suffix('E') {
+        name
+    } else {
+        return false;
+    };
+
+    if name.len() < 19 {
+        return false;
+    }
+    let (prefix, hash) = name.split_at(name.len() - 16);
+    prefix.ends_with("17h") && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Which demangler should be used for symbol names.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Demangle {
+    /// Choose a demangler for each symbol based on its [`SymbolLang`].
+    Auto,
+    /// Demangle every symbol as a Rust symbol.
+    Rust,
+    /// Demangle every symbol as a C++ symbol.
+    Cpp,
+    /// Do not demangle symbols.
+    None,
+}
+
+impl std::str::FromStr for Demangle {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            Ok(Demangle::Auto)
+        } else if s.eq_ignore_ascii_case("rust") {
+            Ok(Demangle::Rust)
+        } else if s.eq_ignore_ascii_case("cpp") || s.eq_ignore_ascii_case("c++") {
+            Ok(Demangle::Cpp)
+        } else if s.eq_ignore_ascii_case("none") {
+            Ok(Demangle::None)
+        } else {
+            Err("invalid demangler")
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum SymbolSource {
     Elf,
@@ -110,14 +369,36 @@ pub enum SymbolSource {
     Archive,
     Dwarf,
     Pdb,
+    /// Dynamic symbol tables, which still contain the exported functions of
+    /// stripped shared libraries (e.g. ELF `.dynsym`).
+    Export,
+    /// Symbols created for raw images, which don't have any symbols of their own.
+    Raw,
 }
 
+/// Symbol sources from the highest to the lowest priority. When several sources
+/// have a symbol for the same range, the symbol from the source that comes first
+/// is kept. Debug information has the most accurate names and sizes, so it comes
+/// before the object file's own symbol tables.
+pub const SOURCE_PRIORITY: [SymbolSource; 8] = [
+    SymbolSource::Dwarf,
+    SymbolSource::Pdb,
+    SymbolSource::Elf,
+    SymbolSource::Mach,
+    SymbolSource::Export,
+    SymbolSource::Archive,
+    SymbolSource::Pe,
+    SymbolSource::Raw,
+];
+
 impl SymbolSource {
+    /// The position of this source in [`SOURCE_PRIORITY`]. Lower values have
+    /// a higher priority.
     pub fn priority(self) -> u8 {
-        match self {
-            SymbolSource::Dwarf | SymbolSource::Pdb => 1,
-            SymbolSource::Elf | SymbolSource::Mach | SymbolSource::Pe | SymbolSource::Archive => 2,
-        }
+        SOURCE_PRIORITY
+            .iter()
+            .position(|&source| source == self)
+            .expect("symbol source missing from SOURCE_PRIORITY") as u8
     }
 }
 
@@ -137,6 +418,8 @@ impl std::str::FromStr for SymbolSource {
             Ok(SymbolSource::Dwarf)
         } else if s.eq_ignore_ascii_case("pdb") {
             Ok(SymbolSource::Pdb)
+        } else if s.eq_ignore_ascii_case("export") {
+            Ok(SymbolSource::Export)
         } else {
             Err("invalid symbol source")
         }
@@ -164,7 +447,81 @@ impl fmt::Display for SymbolSource {
             SymbolSource::Archive => "archive",
             SymbolSource::Dwarf => "dwarf",
             SymbolSource::Pdb => "pdb",
+            SymbolSource::Export => "export",
+            SymbolSource::Raw => "raw",
         };
         write!(f, "{}", t)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn symbol_at(bpos: usize, blen: usize) -> Symbol {
+        Symbol::new_unmangled("test".into(), 0x1000, bpos, blen, SymbolSource::Elf)
+    }
+
+    #[test]
+    fn clamped_range_inside_data() {
+        assert_eq!(symbol_at(16, 32).clamped_range(64), 16..48);
+        assert_eq!(symbol_at(16, 48).clamped_range(64), 16..64);
+    }
+
+    #[test]
+    fn clamped_range_past_end_of_data() {
+        assert_eq!(symbol_at(16, 64).clamped_range(64), 16..64);
+        assert_eq!(symbol_at(64, 16).clamped_range(64), 64..64);
+        assert_eq!(symbol_at(128, 16).clamped_range(64), 64..64);
+        assert_eq!(symbol_at(usize::MAX - 4, 2).clamped_range(64), 64..64);
+    }
+
+    #[test]
+    fn bare_name_strips_versions() {
+        let symbol = |name: &str| Symbol::new(name, 0x1000, 0, 16, SymbolSource::Export);
+        assert_eq!(symbol("memcpy@@GLIBC_2.14").bare_name(), "memcpy");
+        assert_eq!(symbol("memcpy@GLIBC_2.2.5").bare_name(), "memcpy");
+        assert_eq!(symbol("memcpy@GLIBC_2.14@plt").bare_name(), "memcpy");
+        assert_eq!(symbol("memcpy").bare_name(), "memcpy");
+    }
+
+    #[test]
+    fn detect_symbol_lang() {
+        let legacy = "_ZN3foo3bar17h0123456789abcdefE";
+        assert_eq!(SymbolLang::detect(legacy), SymbolLang::Rust);
+        assert_eq!(
+            SymbolLang::detect("_ZN3foo3bar17h0123456789abcdefE.llvm.1234"),
+            SymbolLang::Rust
+        );
+        // Mach-O names have an extra underscore.
+        assert_eq!(
+            SymbolLang::detect(&format!("_{}", legacy)),
+            SymbolLang::Rust
+        );
+        assert_eq!(SymbolLang::detect("_RNvCs1234_3foo3bar"), SymbolLang::Rust);
+        assert_eq!(SymbolLang::detect("__RNvCs1234_3foo3bar"), SymbolLang::Rust);
+
+        assert_eq!(SymbolLang::detect("_ZN3foo3barEv"), SymbolLang::Cpp);
+        assert_eq!(SymbolLang::detect("_Z3fooi"), SymbolLang::Cpp);
+        assert_eq!(SymbolLang::detect("__Z3fooi"), SymbolLang::Cpp);
+        assert_eq!(SymbolLang::detect("_Z3fooi@plt"), SymbolLang::Cpp);
+
+        assert_eq!(SymbolLang::detect("main"), SymbolLang::Unknown);
+        assert_eq!(SymbolLang::detect("memcpy@GLIBC_2.14"), SymbolLang::Unknown);
+        assert_eq!(SymbolLang::detect("_start"), SymbolLang::Unknown);
+    }
+
+    #[test]
+    fn rust_legacy_hash() {
+        assert!(has_rust_legacy_hash("_ZN3foo17h0123456789abcdefE"));
+        assert!(has_rust_legacy_hash("_ZN3foo17h0123456789abcdefE.llvm.42"));
+
+        // The hash has to be 16 hex digits after `17h`, right before the `E`.
+        assert!(!has_rust_legacy_hash("_ZN3foo17h0123456789abcdeE"));
+        assert!(!has_rust_legacy_hash("_ZN3foo17h0123456789abcdegE"));
+        assert!(!has_rust_legacy_hash("_ZN3foo16h0123456789abcdefE"));
+        assert!(!has_rust_legacy_hash("_ZN3foo17h0123456789abcdef"));
+        assert!(!has_rust_legacy_hash("_ZN3foo3barEv"));
+        assert!(!has_rust_legacy_hash("E"));
+    }
+}
@@ -94,6 +94,59 @@ pub fn disasm_test_project_x86_64_unknown_linux_gnu() -> Result<(), Box<dyn Erro
     disassemble_test_project_plat("x86_64-unknown-linux-gnu")
 }
 
+/// `count.bin` is a flat 6502 image: `lda #$01; sta $0200; ldx #$05; dex; bne; rts`.
+#[test]
+pub fn disassemble_raw_mos6502() -> Result<(), Box<dyn Error>> {
+    compile_cargo_disasm();
+
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let rom_dir = manifest_dir.join("assets").join("mos6502");
+
+    let disasm_rom = cargo_disasm_args(
+        &rom_dir,
+        &[
+            "--raw",
+            "--arch",
+            "mos6502",
+            "--base",
+            "0x8000",
+            "count.bin",
+        ],
+    )?;
+    assert_cmd!("disasm count.bin", disasm_rom);
+
+    let stdout = String::from_utf8_lossy(&disasm_rom.stdout);
+    assert!(stdout.contains("8000"), "{}", stdout);
+    assert!(stdout.contains("lda"), "{}", stdout);
+    assert!(stdout.contains("rts"), "{}", stdout);
+
+    Ok(())
+}
+
+#[test]
+pub fn disassemble_all_functions() -> Result<(), Box<dyn Error>> {
+    compile_cargo_disasm();
+
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let test_project_dir = manifest_dir.join("assets").join("pow");
+    let test_project_bin = test_project_dir
+        .join("x86_64-unknown-linux-gnu")
+        .join("debug")
+        .join("pow");
+
+    let disasm_all = cargo_disasm_args(
+        &test_project_dir,
+        &[OsStr::new("--all"), test_project_bin.as_os_str()],
+    )?;
+    assert_cmd!("disasm --all pow", disasm_all);
+
+    let stdout = String::from_utf8_lossy(&disasm_all.stdout);
+    assert!(stdout.contains("pow::my_pow"), "{}", stdout);
+    assert!(stdout.contains("pow::main"), "{}", stdout);
+
+    Ok(())
+}
+
 pub fn disassemble_test_project_plat(platform: &str) -> Result<(), Box<dyn Error>> {
     compile_cargo_disasm();
 
@@ -123,20 +176,20 @@ where
     B: AsRef<OsStr>,
     S: AsRef<OsStr>,
 {
-    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
-    let mut disasm_exec_name = String::from("cargo-disasm");
-    disasm_exec_name.push_str(std::env::consts::EXE_SUFFIX);
-    let disasm_exec = manifest_dir
-        .join("target")
-        .join("debug")
-        .join(&disasm_exec_name);
-    let mut disasm_command = Command::new(disasm_exec);
-    disasm_command.current_dir(disasm_dir);
-    disasm_command.args(&[OsStr::new("-vvv"), symbol.as_ref(), disasm_bin.as_ref()]);
-    disasm_command.output().map_err(|err| err.into())
+    cargo_disasm_args(disasm_dir, &[symbol.as_ref(), disasm_bin.as_ref()])
 }
 
 fn cargo_disasm<P, S>(disasm_dir: P, symbol: S) -> Result<Output, Box<dyn Error>>
+where
+    P: AsRef<Path>,
+    S: AsRef<OsStr>,
+{
+    cargo_disasm_args(disasm_dir, &[symbol])
+}
+
+/// Runs the `cargo-disasm` executable that was built by `compile_cargo_disasm`
+/// in `disasm_dir` with `args`.
+fn cargo_disasm_args<P, S>(disasm_dir: P, args: &[S]) -> Result<Output, Box<dyn Error>>
 where
     P: AsRef<Path>,
     S: AsRef<OsStr>,
@@ -150,7 +203,7 @@ where
         .join(&disasm_exec_name);
     let mut disasm_command = Command::new(disasm_exec);
     disasm_command.current_dir(disasm_dir);
-    disasm_command.args(&[OsStr::new("-vvv"), symbol.as_ref()]);
+    disasm_command.arg("-vvv").args(args);
     disasm_command.output().map_err(|err| err.into())
 }
 